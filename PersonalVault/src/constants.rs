@@ -1,5 +1,6 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program;
+use anchor_spl::token_2022::spl_token_2022::extension::ExtensionType;
 
 /// SOL 相关常量
 pub const SOL_DECIMALS: u8 = 9;
@@ -35,6 +36,13 @@ pub const RAYDIUM_AMM_PROGRAM_ID: Pubkey = solana_program::pubkey!("HWy1jotHpo6U
 #[cfg(not(feature = "devnet"))]
 pub const RAYDIUM_AMM_PROGRAM_ID: Pubkey = solana_program::pubkey!("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
 
+/// Pyth 价格接收程序 ID（PriceUpdateV2 账户的所有者，主网与 devnet 相同）
+pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+
+/// Pyth PriceUpdateV2 账户鉴别器（sha256("account:PriceUpdateV2") 的前 8 字节）
+pub const PYTH_PRICE_UPDATE_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+
 /// Raydium 相关常量
 pub const RAYDIUM_AMM_CONFIG_INDEX: u8 = 0;
 pub const RAYDIUM_AMM_DEFAULT_FEE_RATE: u64 = 2500; // 0.25% = 2500 / 1000000
@@ -63,6 +71,107 @@ pub fn is_using_dex(dex_name: &str) -> bool {
 pub const BYREAL_SWAP_INSTRUCTION_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
 pub const BYREAL_SWAP_V2_INSTRUCTION_DISCRIMINATOR: [u8; 8] = [43, 4, 237, 11, 26, 201, 30, 98];
 
+/// 个人金库账户鉴别器（zero-copy 布局）
+/// 等于 sha256("account:PersonalVaultV2") 的前 8 字节，与旧版 Borsh 布局区分
+pub const PERSONAL_VAULT_DISCRIMINATOR: [u8; 8] = [254, 223, 183, 244, 185, 61, 71, 205];
+
+/// 旧版个人金库账户鉴别器（Borsh 布局）
+/// 等于 sha256("account:PersonalVault") 的前 8 字节
+pub const LEGACY_PERSONAL_VAULT_DISCRIMINATOR: [u8; 8] = [206, 167, 10, 209, 218, 109, 217, 49];
+
+/// 当前金库账户布局版本
+pub const VAULT_LAYOUT_VERSION: u8 = 1;
+
+/// 平均成本的放大倍数（get_position 返回的 average_cost = cost_basis * 10^9 / amount）
+pub const AVERAGE_COST_SCALE: u128 = 1_000_000_000;
+
+/// 金库标签最大字节数
+pub const VAULT_LABEL_LEN: usize = 32;
+
+/// 每个投资者最多登记的金库数量
+pub const MAX_VAULTS_PER_INVESTOR: usize = 16;
+
+/// 每个金库的分仓数量（分仓 0 为默认分仓，存取款都记入默认分仓）
+pub const MAX_VAULT_BUCKETS: usize = 4;
+
+/// 默认分仓编号
+pub const DEFAULT_BUCKET: u8 = 0;
+
+/// 分仓名称最大字节数
+pub const BUCKET_LABEL_LEN: usize = 16;
+
+/// 金库创建时预分配的代币条目数量
+pub const VAULT_INITIAL_TOKEN_CAPACITY: usize = 10;
+
+/// 每个金库默认最多持有的代币种类（全局配置未设置时使用）
+pub const DEFAULT_MAX_VAULT_TOKENS: u16 = 32;
+
+/// 管理员可设置的代币种类上限
+pub const MAX_VAULT_TOKENS_LIMIT: u16 = 200;
+
+/// 每个金库记录的最近交易信号 ID 数量（用于重放保护）
+pub const SIGNAL_HISTORY_SIZE: usize = 64;
+
+/// 交易信号有效期上限（槽位数，约 60 秒，与交易 blockhash 有效期一致）
+/// 信号必须携带有效截止槽位且不超过该上限，保证记录在信号过期前留在信号记录账户中
+pub const MAX_SIGNAL_VALIDITY_SLOTS: u64 = 150;
+
+/// 交易历史单次创建或扩容最多增加的记录数量
+/// 受单条指令账户增长上限（10 KiB）限制，64 条记录约 9 KiB
+pub const TRADE_HISTORY_MAX_GROWTH: usize = 64;
+
+/// 交易历史最多保存的记录数量
+pub const MAX_TRADE_HISTORY_CAPACITY: usize = 512;
+
+/// 每个金库保存的净值快照数量（环形缓冲区）
+pub const SNAPSHOT_HISTORY_SIZE: usize = 64;
+
+/// 预言机配置最多登记的价格源数量
+pub const MAX_PRICE_FEEDS: usize = 32;
+
+/// 默认的最小快照间隔（秒，预言机配置未设置时使用）
+pub const DEFAULT_SNAPSHOT_INTERVAL_SECS: u32 = 3600;
+
+/// 默认的价格最大延迟（秒，预言机配置未设置时使用）
+pub const DEFAULT_MAX_PRICE_AGE_SECS: u32 = 60;
+
+/// 预言机价格置信区间相对价格的上限（基点），超过时拒绝该价格
+pub const MAX_PRICE_CONF_BPS: u64 = 200;
+
+/// 交易策略中最多允许的代币种类
+pub const MAX_POLICY_MINTS: usize = 16;
+
+/// 每个金库最多授权的执行者数量
+pub const MAX_VAULT_EXECUTORS: usize = 8;
+
+/// 交易限额的滚动窗口长度（秒）
+pub const TRADE_LIMIT_WINDOW_SECS: i64 = 86_400;
+
+/// 交易限额按小时分段统计，窗口内共 24 段
+pub const TRADE_LIMIT_SEGMENT_SECS: i64 = 3_600;
+pub const TRADE_LIMIT_SEGMENTS: usize = (TRADE_LIMIT_WINDOW_SECS / TRADE_LIMIT_SEGMENT_SECS) as usize;
+
+/// 全局暂停标志位（GlobalConfig.paused_operations）
+/// 每个改变余额的指令只检查其中一个标志：
+/// - PAUSE_TRADING：send_trade_signal，以及在金库内部转换或划转资产的
+///   wrap_sol、unwrap_sol、move_between_buckets
+/// - PAUSE_DEPOSITS：user_deposit、user_deposit_sol、deposit_for、deposit_sol_for、batch_deposit，
+///   以及把直接转入金库的资产记入账本的 reconcile_balances
+/// - PAUSE_WITHDRAWALS：各取款指令、batch_withdraw、close_vault，
+///   以及把回收的租金转给投资者的 compact_vault
+pub const PAUSE_TRADING: u8 = 1 << 0;
+pub const PAUSE_DEPOSITS: u8 = 1 << 1;
+pub const PAUSE_WITHDRAWALS: u8 = 1 << 2;
+pub const PAUSE_ALL_OPERATIONS: u8 = PAUSE_TRADING | PAUSE_DEPOSITS | PAUSE_WITHDRAWALS;
+
+/// 金库不接受的 Token-2022 mint 扩展
+/// - NonTransferable: 代币无法从金库转出
+/// - PermanentDelegate: 委托人可以随时转走金库中的代币，账本无法保证
+pub const UNSUPPORTED_MINT_EXTENSIONS: [ExtensionType; 2] = [
+    ExtensionType::NonTransferable,
+    ExtensionType::PermanentDelegate,
+];
+
 /// 费率分母常量
 pub const FEE_RATE_DENOMINATOR: u32 = 1_000_000;
 
@@ -153,6 +262,116 @@ pub enum ErrorCode {
     InvalidVaultPda,
     #[msg("数学运算溢出")]
     MathOverflow,
-    #[msg("不支持的池子类型")]
-    InvalidPoolType,
+    #[msg("DEX 程序与路由不匹配")]
+    InvalidDexProgram,
+    #[msg("交易信号已过期")]
+    TradeSignalExpired,
+    #[msg("无效的交易信号 ID")]
+    InvalidSignalId,
+    #[msg("重复的交易信号 ID")]
+    DuplicateSignalId,
+    #[msg("账本余额超过实际持有量")]
+    LedgerInvariantViolation,
+    #[msg("金库代币种类超过上限")]
+    TooManyVaultTokens,
+    #[msg("无效的代币种类上限")]
+    InvalidMaxVaultTokens,
+    #[msg("金库余额槽位已满")]
+    VaultCapacityExceeded,
+    #[msg("代币账户或账本余额不为零")]
+    TokenAccountNotEmpty,
+    #[msg("金库仍有未清算的资产")]
+    VaultNotEmpty,
+    #[msg("不支持的 Token-2022 mint 扩展")]
+    UnsupportedMintExtension,
+    #[msg("金库标签过长")]
+    InvalidVaultLabel,
+    #[msg("投资者金库数量超过上限")]
+    TooManyVaults,
+    #[msg("无效的分仓编号")]
+    InvalidBucket,
+    #[msg("无效的交易历史容量")]
+    InvalidTradeHistoryCapacity,
+    #[msg("无效的交易历史账户")]
+    InvalidTradeHistoryAccount,
+    #[msg("未设置报价代币")]
+    QuoteMintNotSet,
+    #[msg("无效的价格账户")]
+    InvalidPriceAccount,
+    #[msg("缺少代币的价格源")]
+    MissingPriceFeed,
+    #[msg("价格已过期")]
+    StalePrice,
+    #[msg("价格源数量超过上限")]
+    TooManyPriceFeeds,
+    #[msg("距离上次快照时间过短")]
+    SnapshotTooFrequent,
+    #[msg("投资者已暂停该金库的交易")]
+    VaultTradingPaused,
+    #[msg("交易已被全局暂停")]
+    TradingPaused,
+    #[msg("存款已被全局暂停")]
+    DepositsPaused,
+    #[msg("取款已被全局暂停")]
+    WithdrawalsPaused,
+    #[msg("该 DEX 适配器已被暂停")]
+    DexAdapterPaused,
+    #[msg("无效的暂停标志")]
+    InvalidPauseFlags,
+    #[msg("无效的交易策略")]
+    InvalidTradingPolicy,
+    #[msg("交易策略不允许该代币")]
+    TokenNotAllowedByPolicy,
+    #[msg("交易金额超过策略上限")]
+    TradeAmountExceedsPolicy,
+    #[msg("交易金额超过策略允许的余额比例")]
+    TradeAmountExceedsBalanceShare,
+    #[msg("滑点超过策略上限")]
+    SlippageExceedsPolicy,
+    #[msg("交易策略不允许该 DEX 适配器")]
+    DexRouteNotAllowed,
+    #[msg("距离上次交易时间过短")]
+    TradeIntervalTooShort,
+    #[msg("无效的交易限额")]
+    InvalidTradeLimits,
+    #[msg("已达到 24 小时交易次数上限")]
+    DailyTradeCountExceeded,
+    #[msg("已达到 24 小时交易额上限")]
+    DailyVolumeExceeded,
+    #[msg("交易额限额要求交易的一侧为限额计价代币")]
+    TradeNotPricedInLimitMint,
+    #[msg("无效的执行者授权账户")]
+    InvalidVaultExecutors,
+    #[msg("金库授权的执行者数量已达上限")]
+    TooManyVaultExecutors,
+    #[msg("授权过期时间必须晚于当前时间")]
+    InvalidExecutorExpiry,
+    #[msg("执行者未授权")]
+    ExecutorNotFound,
+    #[msg("执行者未获该金库授权")]
+    ExecutorNotAuthorized,
+    #[msg("执行者授权已过期")]
+    ExecutorAuthorizationExpired,
+    #[msg("交易信号必须设置有效截止槽位且不超过有效期上限")]
+    InvalidSignalValidity,
+    #[msg("信号记录已满：最近的信号均未过期，请稍后重试")]
+    SignalRegistryFull,
+    #[msg("无效的信号记录账户")]
+    InvalidSignalRegistryAccount,
+    #[msg("交换账户中的签名者不是金库")]
+    InvalidSwapAuthority,
+    #[msg("交换代币账户不是金库的关联代币账户")]
+    InvalidSwapTokenAccount,
+    #[msg("转账钩子账户缺失或与 ExtraAccountMetaList 不匹配")]
+    InvalidTransferHookAccounts,
+    #[msg("该 DEX 路由只支持 SPL Token 代币账户")]
+    UnsupportedRouteTokenProgram,
+    #[msg("预言机价格的置信区间过宽")]
+    PriceConfidenceTooWide,
+    #[msg("已注册的 DEX 路由会留下账本无法跟踪的挂单或流动性仓位，不能关闭金库")]
+    VaultHasOpenPositions,
+    #[msg("代存款只接受金库已持有或交易策略白名单中的代币")]
+    DepositForMintNotAccepted,
+    #[msg("分仓名称过长")]
+    InvalidBucketLabel,
 }
@@ -2,32 +2,237 @@ pub mod raydium_amm;
 pub mod raydium_clmm;
 
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::AccountMeta;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anchor_spl::token;
+use anchor_spl::token_2022::{self, spl_token_2022};
+use anchor_spl::token_interface::TokenAccount;
+use spl_tlv_account_resolution::state::ExtraAccountMetaList;
+use spl_token_2022::extension::StateWithExtensions;
+use spl_transfer_hook_interface::get_extra_account_metas_address;
+use spl_transfer_hook_interface::instruction::{execute, ExecuteInstruction};
+
+use crate::constants::ErrorCode;
 
 // 重导出 Raydium DEX 类型
 pub use raydium_amm::RaydiumAmmDex;
 pub use raydium_clmm::RaydiumClmmDex;
 
-/// DEX 交换结果
+/// DEX 交换结果（根据金库代币账户余额变化测量）
 #[derive(Debug, Clone)]
 pub struct SwapResult {
+    /// 实际花费的输入代币数量
+    pub amount_in: u64,
+    /// 实际收到的输出代币数量
     pub amount_out: u64,
-    pub fee_amount: u64,
+    /// 交换后金库输入代币账户余额
+    pub input_balance: u64,
+    /// 交换后金库输出代币账户余额
+    pub output_balance: u64,
+}
+
+/// DEX 交换指令中金库相关账户的位置（由各适配器按 DEX 的账户布局给出）
+#[derive(Debug, Clone, Copy)]
+pub struct SwapAccountLayout {
+    /// 交换指令至少需要的账户数量
+    pub min_accounts: usize,
+    /// 金库 PDA（金库代币账户的 owner，CPI 时由金库签名）
+    pub vault_authority: usize,
+    /// 金库输入代币账户
+    pub input_token_account: usize,
+    /// 金库输出代币账户
+    pub output_token_account: usize,
+    /// Token-2022 转账钩子校验所需的账户位置；为 None 时该路由只支持 SPL Token
+    pub transfer_hook: Option<TransferHookLayout>,
+}
+
+/// 交换中两笔代币转账的参与账户位置（用于按 ExtraAccountMetaList 校验转账钩子账户）
+#[derive(Debug, Clone, Copy)]
+pub struct TransferHookLayout {
+    /// 输入代币 mint
+    pub input_mint: usize,
+    /// 输出代币 mint
+    pub output_mint: usize,
+    /// 池子的输入代币账户（金库 -> 池子转账的目标）
+    pub pool_input_vault: usize,
+    /// 池子的输出代币账户（池子 -> 金库转账的来源）
+    pub pool_output_vault: usize,
+    /// 池子输出代币账户的权限（池子 -> 金库转账的签名者）
+    pub pool_authority: usize,
 }
 
 /// DEX 抽象接口
+///
+/// 每个 DEX 适配器实现此 trait，并在 [`DexRoute::adapter`] 中注册。
+/// 新增 DEX 只需要：
+/// 1. 在 `dex/` 下新建模块并实现 `DexInterface`
+/// 2. 在 `DexRoute` 中追加一个变体（只能追加在末尾，保持序列化兼容）
+/// 3. 在 `DexRoute::adapter` 中返回对应的适配器实例
 pub trait DexInterface {
     /// 获取 DEX 名称
     fn name(&self) -> &'static str;
 
-    /// 执行交换
-    /// 注意：程序 ID 应该作为 account_infos 的最后一个账户传递
+    /// DEX 程序 ID（用于校验调用方传入的 dex_program 账户）
+    fn program_id(&self) -> Pubkey;
+
+    /// 金库签名者和金库代币账户在 account_infos 中的位置
+    fn account_layout(&self) -> SwapAccountLayout;
+
+    /// 该 DEX 是否会让金库在交换之外留下挂单或流动性仓位（归 DEX 程序所有的账户）
+    /// 账本只记录代币账户余额，无法跟踪这类仓位；返回 true 的路由启用时 `close_vault` 会被拒绝
+    fn leaves_open_positions(&self) -> bool;
+
+    /// 执行交换（带 PDA 签名）
+    /// account_infos: 直接传递给 DEX 程序的账户列表
+    ///   （Token-2022 转账钩子的额外账户追加在末尾，SwapExecutor 先按 ExtraAccountMetaList 校验，再原样转发给 DEX）
+    /// signer_seeds: vault PDA 签名种子
     fn execute_swap(
         &self,
         amount_in: u64,
         amount_out_minimum: u64,
         slippage_bps: u16,
         account_infos: &[AccountInfo],
-    ) -> Result<SwapResult>;
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()>;
+}
+
+/// DEX 路由
+/// 作为 `send_trade_signal` 的指令参数，Borsh 序列化为 1 字节的变体索引
+/// 注意：变体顺序即链上编码，只能在末尾追加
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DexRoute {
+    /// Raydium AMM V4（传统恒定乘积池子）
+    RaydiumAmmV4,
+    /// Raydium CLMM（集中流动性池子）
+    RaydiumClmm,
+}
+
+/// 已注册的适配器实例
+static RAYDIUM_AMM_DEX: RaydiumAmmDex = RaydiumAmmDex;
+static RAYDIUM_CLMM_DEX: RaydiumClmmDex = RaydiumClmmDex;
+
+impl DexRoute {
+    /// 获取路由对应的 DEX 适配器（唯一的适配器注册点）
+    pub fn adapter(&self) -> &'static dyn DexInterface {
+        match self {
+            DexRoute::RaydiumAmmV4 => &RAYDIUM_AMM_DEX,
+            DexRoute::RaydiumClmm => &RAYDIUM_CLMM_DEX,
+        }
+    }
+
+    /// 全部已注册的路由（新增适配器时同步追加）
+    pub const ALL: [DexRoute; 2] = [DexRoute::RaydiumAmmV4, DexRoute::RaydiumClmm];
+
+    /// 是否有已注册的路由会留下账本无法跟踪的挂单或流动性仓位
+    pub fn any_leaves_open_positions() -> bool {
+        Self::ALL.iter().any(|route| route.adapter().leaves_open_positions())
+    }
+
+    /// 路由在 `GlobalConfig.paused_dex_routes` 中对应的暂停标志位
+    pub fn pause_flag(&self) -> u8 {
+        1 << (*self as u8)
+    }
+}
+
+/// 解析代币账户（SPL Token 或 Token-2022）
+pub fn read_token_account(account: &AccountInfo) -> Result<TokenAccount> {
+    require!(
+        *account.owner == token::ID || *account.owner == token_2022::ID,
+        ErrorCode::InvalidTokenAccount
+    );
+    let data = account.try_borrow_data()?;
+    TokenAccount::try_deserialize(&mut &data[..])
+}
+
+/// 读取金库持有的代币账户余额（校验账户归属与 mint）
+pub fn vault_token_amount(account: &AccountInfo, vault: &Pubkey, mint: &Pubkey) -> Result<u64> {
+    let token_account = read_token_account(account)?;
+    require_keys_eq!(token_account.owner, *vault, ErrorCode::InvalidTokenAccount);
+    require_keys_eq!(token_account.mint, *mint, ErrorCode::InvalidTokenMintAddress);
+    Ok(token_account.amount)
+}
+
+/// 校验代币账户是金库在该 mint 下的关联代币账户（ATA）
+pub fn require_vault_ata(account: &AccountInfo, vault: &Pubkey, mint: &Pubkey) -> Result<()> {
+    let expected = get_associated_token_address_with_program_id(vault, mint, account.owner);
+    require_keys_eq!(account.key(), expected, ErrorCode::InvalidSwapTokenAccount);
+    Ok(())
+}
+
+/// mint 配置的转账钩子程序（SPL Token mint 和未配置钩子的 Token-2022 mint 返回 None）
+pub fn transfer_hook_program_id(mint: &AccountInfo) -> Result<Option<Pubkey>> {
+    if *mint.owner != token_2022::ID {
+        return Ok(None);
+    }
+    let data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    Ok(spl_token_2022::extension::transfer_hook::get_program_id(&mint_state))
+}
+
+/// 校验转账钩子账户：mint 配置了转账钩子时，accounts 中必须包含钩子程序、ExtraAccountMetaList PDA
+/// 以及按 Execute 指令解析出的全部额外账户（需要写入的账户必须可写）；未配置钩子的 mint 直接通过
+pub fn require_transfer_hook_accounts<'info>(
+    mint: &AccountInfo<'info>,
+    source: &AccountInfo<'info>,
+    destination: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    amount: u64,
+    accounts: &[AccountInfo<'info>],
+) -> Result<()> {
+    let Some(hook_program_id) = transfer_hook_program_id(mint)? else {
+        return Ok(());
+    };
+    require!(
+        accounts.iter().any(|info| *info.key == hook_program_id),
+        ErrorCode::InvalidTransferHookAccounts
+    );
+    let validation_key = get_extra_account_metas_address(mint.key, &hook_program_id);
+    let validation_info = accounts
+        .iter()
+        .find(|info| *info.key == validation_key)
+        .ok_or(ErrorCode::InvalidTransferHookAccounts)?;
+    require_keys_eq!(
+        *validation_info.owner,
+        hook_program_id,
+        ErrorCode::InvalidTransferHookAccounts
+    );
+
+    // 按 Token-2022 调用钩子时的方式解析额外账户
+    let mut execute_instruction = execute(
+        &hook_program_id,
+        source.key,
+        mint.key,
+        destination.key,
+        authority.key,
+        amount,
+    );
+    execute_instruction
+        .accounts
+        .push(AccountMeta::new_readonly(validation_key, false));
+    let mut execute_infos = vec![
+        source.clone(),
+        mint.clone(),
+        destination.clone(),
+        authority.clone(),
+        validation_info.clone(),
+    ];
+    ExtraAccountMetaList::add_to_cpi_instruction::<ExecuteInstruction>(
+        &mut execute_instruction,
+        &mut execute_infos,
+        &validation_info.try_borrow_data()?,
+        accounts,
+    )
+    .map_err(|err| {
+        msg!("转账钩子额外账户解析失败: {:?}", err);
+        error!(ErrorCode::InvalidTransferHookAccounts)
+    })?;
+    for (meta, info) in execute_instruction.accounts.iter().zip(&execute_infos).skip(5) {
+        require!(
+            !meta.is_writable || info.is_writable,
+            ErrorCode::InvalidTransferHookAccounts
+        );
+    }
+    Ok(())
 }
 
 /// 通用的交换执行器
@@ -35,54 +240,107 @@ pub struct SwapExecutor;
 
 impl SwapExecutor {
     /// 执行交换（使用 remaining_accounts）
-    /// pool_type: 0 = AMM V4, 1 = CLMM
+    /// route: DEX 路由
+    /// vault: 金库 PDA 地址（输入/输出代币账户的 owner）
     /// signer_seeds: PDA 签名种子（用于 CPI 调用）
+    ///
+    /// 返回值中的输入/输出金额是交换前后金库代币账户的实际余额变化
+    #[allow(clippy::too_many_arguments)]
     pub fn execute_swap_with_remaining_accounts(
         remaining_accounts: &[AccountInfo],
+        vault: &Pubkey,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
         amount_in: u64,
         amount_out_minimum: u64,
         slippage_bps: u16,
-        pool_type: u8,
+        route: DexRoute,
         signer_seeds: &[&[&[u8]]],
     ) -> Result<SwapResult> {
-        msg!("🔄 SwapExecutor: 池子类型 = {}", pool_type);
-        
-        match pool_type {
-            0 => {
-                msg!("✅ 使用 Raydium AMM V4");
-                let dex = raydium_amm::RaydiumAmmDex::new();
-                dex.execute_swap_signed(
+        let dex = route.adapter();
+        msg!("SwapExecutor: 路由 = {:?}", route);
+        msg!("使用 {}", dex.name());
+
+        // 按适配器的账户布局定位金库签名者和金库代币账户
+        let layout = dex.account_layout();
+        require!(
+            remaining_accounts.len() >= layout.min_accounts,
+            ErrorCode::InsufficientAccounts
+        );
+        require_keys_eq!(
+            remaining_accounts[layout.vault_authority].key(),
+            *vault,
+            ErrorCode::InvalidSwapAuthority
+        );
+        let input_account = &remaining_accounts[layout.input_token_account];
+        let output_account = &remaining_accounts[layout.output_token_account];
+
+        // 记录交换前的金库代币余额（只接受金库在输入/输出 mint 下的 ATA）
+        let input_before = vault_token_amount(input_account, vault, token_in)?;
+        let output_before = vault_token_amount(output_account, vault, token_out)?;
+        require_vault_ata(input_account, vault, token_in)?;
+        require_vault_ata(output_account, vault, token_out)?;
+
+        // Token-2022 转账钩子：两笔转账所需的额外账户必须与 mint 的 ExtraAccountMetaList 一致
+        // 池子 -> 金库的转账数量在交换前未知，按最小输出数量解析（钩子账户种子一般不依赖数量）
+        match layout.transfer_hook {
+            Some(hook) => {
+                let input_mint = &remaining_accounts[hook.input_mint];
+                let output_mint = &remaining_accounts[hook.output_mint];
+                require_keys_eq!(input_mint.key(), *token_in, ErrorCode::InvalidTokenMintAddress);
+                require_keys_eq!(output_mint.key(), *token_out, ErrorCode::InvalidTokenMintAddress);
+                require_transfer_hook_accounts(
+                    input_mint,
+                    input_account,
+                    &remaining_accounts[hook.pool_input_vault],
+                    &remaining_accounts[layout.vault_authority],
                     amount_in,
-                    amount_out_minimum,
-                    slippage_bps,
                     remaining_accounts,
-                    signer_seeds,
-                )
-            }
-            1 => {
-                msg!("✅ 使用 Raydium CLMM");
-                let dex = raydium_clmm::RaydiumClmmDex::new();
-                dex.execute_swap_signed(
-                    amount_in,
+                )?;
+                require_transfer_hook_accounts(
+                    output_mint,
+                    &remaining_accounts[hook.pool_output_vault],
+                    output_account,
+                    &remaining_accounts[hook.pool_authority],
                     amount_out_minimum,
-                    slippage_bps,
                     remaining_accounts,
-                    signer_seeds,
-                )
+                )?;
             }
-            _ => {
-                msg!("❌ 不支持的池子类型: {}", pool_type);
-                Err(error!(crate::constants::ErrorCode::InvalidPoolType))
+            None => {
+                require!(
+                    *input_account.owner == token::ID && *output_account.owner == token::ID,
+                    ErrorCode::UnsupportedRouteTokenProgram
+                );
             }
         }
-    }
-}
+        msg!("交换前余额: 输入={}, 输出={}", input_before, output_before);
 
-/// DEX 类型枚举
-#[derive(Debug, Clone, Copy)]
-pub enum DexType {
-    /// Raydium AMM V4（传统恒定乘积池子）
-    RaydiumAmm,
-    /// Raydium CLMM（集中流动性池子）
-    RaydiumClmm,
+        dex.execute_swap(
+            amount_in,
+            amount_out_minimum,
+            slippage_bps,
+            remaining_accounts,
+            signer_seeds,
+        )?;
+
+        // 根据余额变化计算实际的输入/输出金额
+        let input_after = vault_token_amount(input_account, vault, token_in)?;
+        let output_after = vault_token_amount(output_account, vault, token_out)?;
+        msg!("交换后余额: 输入={}, 输出={}", input_after, output_after);
+
+        let spent = input_before
+            .checked_sub(input_after)
+            .ok_or(ErrorCode::SwapExecutionFailed)?;
+        let received = output_after
+            .checked_sub(output_before)
+            .ok_or(ErrorCode::SwapExecutionFailed)?;
+        require!(spent <= amount_in, ErrorCode::SwapExecutionFailed);
+
+        Ok(SwapResult {
+            amount_in: spent,
+            amount_out: received,
+            input_balance: input_after,
+            output_balance: output_after,
+        })
+    }
 }
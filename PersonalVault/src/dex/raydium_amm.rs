@@ -1,9 +1,7 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::instruction::AccountMeta;
-use anchor_lang::solana_program::program::invoke_signed;
-use anchor_spl::token::spl_token;
 
-use super::SwapResult;
+use super::{DexInterface, SwapAccountLayout};
 
 /// # Raydium AMM V4 实现
 /// 
@@ -19,7 +17,10 @@ use super::SwapResult;
 /// - **程序 ID**: `675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8` (mainnet)
 /// 
 /// ### 账户结构
-/// AMM V4 需要 18 个账户（包括 Serum/OpenBook 市场账户）
+/// AMM V4 需要 18 个账户（包括 Serum/OpenBook 市场账户），其中：
+/// - [15] 金库输入代币账户（ATA）
+/// - [16] 金库输出代币账户（ATA）
+/// - [17] 金库 PDA（代币账户 owner，CPI 时签名）
 /// 
 /// ### TypeScript 端职责
 /// - 根据 AMM V4 池子构建正确的 18 个账户列表
@@ -31,30 +32,14 @@ use super::SwapResult;
 /// - 使用单字节指令 ID (9) 构建指令数据
 /// 
 /// ## 使用示例
-/// ```rust
-/// let dex = RaydiumAmmDex::new();
-/// let result = dex.execute_swap(amount_in, amount_out_minimum, slippage_bps, account_infos)?;
+/// ```rust,ignore
+/// let dex = DexRoute::RaydiumAmmV4.adapter();
+/// let result = dex.execute_swap(amount_in, amount_out_minimum, slippage_bps, account_infos, signer_seeds)?;
 /// ```
+#[derive(Default)]
 pub struct RaydiumAmmDex;
 
 impl RaydiumAmmDex {
-    pub fn new() -> Self {
-        Self
-    }
-
-    /// 执行 Raydium AMM V4 交换（带 PDA 签名）
-    pub     fn execute_swap_signed(
-        &self,
-        amount_in: u64,
-        amount_out_minimum: u64,
-        _slippage_bps: u16,
-        account_infos: &[AccountInfo],
-        signer_seeds: &[&[&[u8]]],
-    ) -> Result<SwapResult> {
-        msg!("🔄 执行 Raydium AMM V4 交换（带 PDA 签名）...");
-        self.execute_swap_impl(amount_in, amount_out_minimum, _slippage_bps, account_infos, signer_seeds)
-    }
-
     /// 执行 Raydium AMM V4 交换（内部实现）
     fn execute_swap_impl(
         &self,
@@ -63,7 +48,7 @@ impl RaydiumAmmDex {
         _slippage_bps: u16,
         account_infos: &[AccountInfo],
         signer_seeds: &[&[&[u8]]],
-    ) -> Result<SwapResult> {
+    ) -> Result<()> {
         msg!("🔄 执行 Raydium AMM V4 交换...");
         msg!("输入金额: {}", amount_in);
         msg!("最小输出金额: {}", amount_out_minimum);
@@ -109,12 +94,13 @@ impl RaydiumAmmDex {
         );
         msg!("  总数据大小: {} bytes", instruction_data.len());
 
-        // 🔧 从 account_infos 构建账户元数据
+        // 🔧 从 account_infos 构建账户元数据（金库 PDA 通过 invoke_signed 签名）
+        let vault_authority = self.account_layout().vault_authority;
         let mut account_metas = Vec::new();
         for (i, account_info) in account_infos.iter().enumerate() {
             account_metas.push(AccountMeta {
                 pubkey: *account_info.key,
-                is_signer: account_info.is_signer,
+                is_signer: account_info.is_signer || i == vault_authority,
                 is_writable: account_info.is_writable,
             });
             msg!(
@@ -131,7 +117,7 @@ impl RaydiumAmmDex {
         msg!("✅ 账户元数据数量: {}", account_metas.len());
 
         // 🔧 使用 constants.rs 中定义的 Raydium AMM 程序 ID
-        let program_id = crate::constants::RAYDIUM_AMM_PROGRAM_ID;
+        let program_id = self.program_id();
         msg!("✅ 使用 Raydium AMM V4 程序 ID: {}", program_id);
 
         // 构建指令（使用所有传入的账户）
@@ -167,44 +153,8 @@ impl RaydiumAmmDex {
             Ok(()) => {
                 msg!("✅ Raydium AMM V4 交换执行成功！");
 
-                // 🔧 检测并关闭临时 WSOL 账户
-                // AMM V4 account_infos 结构（从 DEX 账户开始）：
-                // [0] TOKEN_PROGRAM_ID
-                // [1] poolState
-                // [2] poolAuthority
-                // [3] poolOpenOrders
-                // [4] poolTargetOrders
-                // [5] poolVaultA
-                // [6] poolVaultB
-                // [7] marketProgramId
-                // [8] marketId
-                // [9] marketBids
-                // [10] marketAsks
-                // [11] marketEventQueue
-                // [12] marketBaseVault
-                // [13] marketQuoteVault
-                // [14] marketAuthority
-                // [15] userInputTokenAccount  <-- 可能是临时 WSOL 账户
-                // [16] userOutputTokenAccount
-                // [17] raydium_program (已在 TypeScript 端添加)
-                if account_infos.len() > 16 {
-                    // vault PDA 不在这个列表中，需要从更早的位置获取
-                    // 假设 vault PDA 在 account_infos 的开始位置（需要调用者正确传递）
-                    // 实际上，我们需要从外部传入 vault PDA
-                    // 暂时使用一个占位实现
-                    msg!("ℹ️  AMM V4: 暂不支持自动关闭临时 WSOL 账户");
-                    msg!("  需要从外部传入 vault PDA 引用");
-                }
-                
-                // 这里应该解析返回的数据来获取实际的输出金额
-                // 暂时返回一个模拟的结果
-                let amount_out = amount_in; // 简化处理，实际应该从返回数据中解析
-                let fee_amount = amount_in / 1000; // 简化处理，实际应该计算真实费用
-                
-                Ok(SwapResult {
-                    amount_out,
-                    fee_amount,
-                })
+                // 实际输入/输出金额由 SwapExecutor 根据金库代币账户的余额变化测量
+                Ok(())
             }
             Err(err) => {
                 msg!("❌ Raydium AMM V4 交换执行失败: {:?}", err);
@@ -212,78 +162,43 @@ impl RaydiumAmmDex {
             }
         }
     }
+}
 
-    /// 检测是否是临时 WSOL 账户
-    /// 临时账户特征：owner 是 vault PDA，且 mint 是 WSOL
-    fn is_temp_wsol_account<'info>(
-        token_account: &AccountInfo<'info>,
-        vault_pda: &AccountInfo<'info>,
-    ) -> Result<bool> {
-        // 解析代币账户数据
-        let data = token_account.try_borrow_data()?;
-        
-        // Token Account 布局：
-        // - mint: Pubkey (32 bytes, offset 0)
-        // - owner: Pubkey (32 bytes, offset 32)
-        // - amount: u64 (8 bytes, offset 64)
-        
-        if data.len() < 165 {
-            return Ok(false);
-        }
-        
-        // 检查 owner 是否是 vault PDA
-        let owner = &data[32..64];
-        let vault_key_bytes = vault_pda.key().to_bytes();
-        if owner != vault_key_bytes {
-            return Ok(false);
-        }
-        
-        // 检查 mint 是否是 WSOL
-        let mint = &data[0..32];
-        let wsol_mint = crate::constants::WSOL_MINT.to_bytes();
-        if mint != wsol_mint {
-            return Ok(false);
-        }
-        
-        msg!("  ✅ 检测到临时 WSOL 账户（owner = vault, mint = WSOL）");
-        Ok(true)
+impl DexInterface for RaydiumAmmDex {
+    fn name(&self) -> &'static str {
+        "Raydium AMM V4"
     }
 
-    /// 关闭临时 WSOL 账户
-    fn close_temp_wsol_account<'info>(
-        temp_account: &AccountInfo<'info>,
-        destination: &AccountInfo<'info>,
-        authority: &AccountInfo<'info>,
-        signer_seeds: &[&[&[u8]]],
-    ) -> Result<()> {
-        msg!("🔧 开始关闭临时 WSOL 账户...");
-        msg!("  临时账户: {}", temp_account.key());
-        msg!("  租金接收者: {}", destination.key());
-        msg!("  权限账户: {}", authority.key());
-
-        // 构建 CloseAccount 指令
-        let close_ix = spl_token::instruction::close_account(
-            &spl_token::ID,
-            temp_account.key,
-            destination.key,
-            authority.key,
-            &[],
-        )?;
+    fn program_id(&self) -> Pubkey {
+        crate::constants::RAYDIUM_AMM_PROGRAM_ID
+    }
 
-        // 准备账户列表
-        let account_infos = &[
-            temp_account.clone(),
-            destination.clone(),
-            authority.clone(),
-        ];
+    fn account_layout(&self) -> SwapAccountLayout {
+        SwapAccountLayout {
+            min_accounts: 18,
+            vault_authority: 17,
+            input_token_account: 15,
+            output_token_account: 16,
+            // AMM V4 只支持 SPL Token，不转发转账钩子账户
+            transfer_hook: None,
+        }
+    }
 
-        // 使用 PDA 签名执行 CPI
-        invoke_signed(&close_ix, account_infos, signer_seeds)?;
+    /// 单笔原子交换，输出直接进入金库代币账户
+    fn leaves_open_positions(&self) -> bool {
+        false
+    }
 
-        msg!("✅ 临时账户关闭成功");
-        Ok(())
+    /// 执行 Raydium AMM V4 交换（带 PDA 签名）
+    fn execute_swap(
+        &self,
+        amount_in: u64,
+        amount_out_minimum: u64,
+        slippage_bps: u16,
+        account_infos: &[AccountInfo],
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        msg!("🔄 执行 Raydium AMM V4 交换（带 PDA 签名）...");
+        self.execute_swap_impl(amount_in, amount_out_minimum, slippage_bps, account_infos, signer_seeds)
     }
 }
-
-// 注意：不再实现 DexInterface trait，因为我们需要 PDA 签名
-// 直接使用 execute_swap_signed 方法
@@ -1,32 +1,43 @@
-use crate::dex::SwapExecutor;
+use crate::dex::{DexRoute, SwapExecutor, SwapResult};
 use anchor_lang::prelude::*;
 
-/// 执行 DEX 交换（使用当前配置的 DEX）
-/// pool_type: 0 = Raydium AMM V4, 1 = Raydium CLMM
+/// 执行 DEX 交换
+/// route: DEX 路由（决定使用哪个适配器）
+/// vault: 金库 PDA 地址
 /// signer_seeds: PDA 签名种子（用于 CPI 调用）
+#[allow(clippy::too_many_arguments)]
 pub fn execute_dex_swap(
     remaining_accounts: &[AccountInfo],
+    vault: &Pubkey,
+    token_in: &Pubkey,
+    token_out: &Pubkey,
     amount_in: u64,
     amount_out_minimum: u64,
     slippage_bps: u16,
-    pool_type: u8,
+    route: DexRoute,
     signer_seeds: &[&[&[u8]]],
-) -> Result<u64> {
+) -> Result<SwapResult> {
     msg!("🔄 执行 DEX 交换...");
     msg!("输入金额: {}", amount_in);
     msg!("最小输出金额: {}", amount_out_minimum);
-    msg!("池子类型: {} (0=AMM V4, 1=CLMM)", pool_type);
 
-    // 使用新的 DEX 抽象层执行交换
-    let swap_result: crate::SwapResult = SwapExecutor::execute_swap_with_remaining_accounts(
+    // 使用 DEX 抽象层执行交换
+    let swap_result = SwapExecutor::execute_swap_with_remaining_accounts(
         remaining_accounts,
+        vault,
+        token_in,
+        token_out,
         amount_in,
         amount_out_minimum,
         slippage_bps,
-        pool_type,  // ✅ 传递池子类型参数
+        route,
         signer_seeds,  // ✅ 传递 PDA 签名种子
     )?;
 
-    msg!("✅ DEX 交换完成，输出金额: {}", swap_result.amount_out);
-    Ok(swap_result.amount_out)
+    msg!(
+        "DEX 交换完成，实际输入: {}, 实际输出: {}",
+        swap_result.amount_in,
+        swap_result.amount_out
+    );
+    Ok(swap_result)
 }
@@ -1,38 +1,985 @@
-use crate::constants::{ErrorCode, LAMPORTS_PER_SOL, NATIVE_SOL_MINT, WSOL_MINT};
+use crate::constants::{
+    ErrorCode, AVERAGE_COST_SCALE, BUCKET_LABEL_LEN, DEFAULT_BUCKET, LAMPORTS_PER_SOL,
+    MAX_POLICY_MINTS, MAX_PRICE_CONF_BPS, MAX_PRICE_FEEDS, MAX_SIGNAL_VALIDITY_SLOTS,
+    MAX_TRADE_HISTORY_CAPACITY, MAX_VAULTS_PER_INVESTOR, MAX_VAULT_BUCKETS, MAX_VAULT_EXECUTORS,
+    MAX_VAULT_TOKENS_LIMIT, NATIVE_SOL_MINT, PAUSE_ALL_OPERATIONS, PAUSE_DEPOSITS, PAUSE_TRADING,
+    PAUSE_WITHDRAWALS, PERSONAL_VAULT_DISCRIMINATOR, SIGNAL_HISTORY_SIZE, SNAPSHOT_HISTORY_SIZE,
+    TRADE_HISTORY_MAX_GROWTH, UNSUPPORTED_MINT_EXTENSIONS, VAULT_INITIAL_TOKEN_CAPACITY,
+    VAULT_LABEL_LEN, VAULT_LAYOUT_VERSION, WSOL_MINT,
+};
+use crate::dex::{
+    read_token_account, require_transfer_hook_accounts, transfer_hook_program_id, DexRoute,
+};
+use crate::oracle::{quote_value, read_price_update, OraclePrice};
+// 账户状态、参数类型与读写辅助
+use crate::structs::{
+    load_trade_history_mut, load_vault, load_vault_mut, load_vault_slots_mut, vault_index_seed,
+    GlobalConfig, LegacyPersonalVault, NavSnapshot, OracleConfig, PersonalVault, PositionInfo,
+    PriceFeedEntry, SignalRegistry, TokenBalance, TokenTransfer, TradeAllowance, TradeHistory,
+    TradeLimits, TradeRecord, TradingPolicy, TradingPolicyParams, UnpricedTradePolicy,
+    VaultExecutorEntry, VaultExecutors, VaultMut, VaultRegistry, VaultRegistryEntry,
+    VaultSnapshots,
+};
+// 指令账户上下文
 use crate::structs::{
-    BalanceManagerCreatedEvent, CreateBalanceManager, GetBalance, InitializeGlobalConfig,
-    PersonalVault, SendTradeSignal, SetAdmin, SetBot, TokenBalance, TradeSignalEvent, UnwrapSol,
-    UserDeposit, UserDepositEvent, UserDepositSol, UserWithdraw, UserWithdrawEvent,
-    UserWithdrawSol, WrapSol,
+    BatchDeposit, BatchWithdraw, CloseTradeHistory, CloseTradeLimits, CloseTradingPolicy,
+    CloseVault, CloseVaultExecutors, CompactVault, CreateBalanceManager, DepositFor, DepositSolFor,
+    GetBalance, GetPosition, GetTradeAllowance, InitTradeHistory, InitializeGlobalConfig,
+    ManageVaultExecutors, MigrateGlobalConfig, MigrateSignalRegistry, MigrateVault,
+    MoveBetweenBuckets, PauseTrading, ReconcileBalances, RegisterVault, ResizeTradeHistory,
+    ResumeTrading, RevokeVaultExecutor, SendTradeSignal, SetAdmin, SetBot, SetBucketLabel,
+    SetGuardian, SetMaxVaultTokens, SetPauseFlags, SetPriceFeed, SetQuoteMint, SetSnapshotConfig,
+    SetTradeLimits, SetTradingPolicy, SnapshotVault, UnwrapSol, UserDeposit, UserDepositSol,
+    UserWithdraw, UserWithdrawSol, UserWithdrawSolTo, UserWithdrawTo, WrapSol,
+};
+// 事件
+use crate::structs::{
+    BalanceManagerCreatedEvent, BalanceReconciledEvent, BucketTransferEvent, DepositForEvent,
+    EmergencyPauseEvent, ExecutorPreferenceUpdatedEvent, LedgerInvariantViolatedEvent,
+    TradeHistoryResizedEvent, TradeLimitsUpdatedEvent, TradeSignalEvent, TradingPolicyUpdatedEvent,
+    UserDepositEvent, UserWithdrawEvent, VaultClosedEvent, VaultCompactedEvent,
+    VaultExecutorUpdatedEvent, VaultMigratedEvent, VaultSnapshotEvent,
+    VaultTokenAccountClosedEvent, VaultTradingPausedEvent,
 };
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::{
-    program::invoke,
-    system_instruction,
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use anchor_lang::system_program;
+use anchor_spl::associated_token::{self, get_associated_token_address_with_program_id};
+use anchor_spl::token::Token;
+use anchor_spl::token_2022::spl_token_2022::extension::{
+    BaseStateWithExtensions, StateWithExtensions,
 };
-use anchor_spl::token::{self, Transfer};
-
-
-/// 获取代币余额
-pub fn get_token_balance(vault: &PersonalVault, token: Pubkey) -> u64 {
-    for balance in vault.balances.iter() {
-        if balance.token == token {
+use anchor_spl::token_2022::{self, spl_token_2022, Token2022};
+use anchor_spl::token_interface;
+use spl_tlv_account_resolution::state::ExtraAccountMetaList;
+use spl_transfer_hook_interface::get_extra_account_metas_address;
+use spl_transfer_hook_interface::instruction::ExecuteInstruction;
+use spl_type_length_value::state::TlvStateBorrowed;
+
+/// 获取代币在指定分仓中的余额
+pub fn get_bucket_balance(balances: &[TokenBalance], token: Pubkey, bucket: u8) -> u64 {
+    for balance in balances.iter() {
+        if balance.token == token && balance.bucket == bucket {
             return balance.amount;
         }
     }
     0
 }
 
-/// 设置代币余额
-pub fn set_token_balance(vault: &mut PersonalVault, token: Pubkey, amount: u64) {
-    for balance in vault.balances.iter_mut() {
-        if balance.token == token {
+/// 设置代币在指定分仓中的余额
+/// 条目不存在时追加新槽位（需要先调用 ensure_vault_capacity 预留空间）
+pub fn set_bucket_balance(vault: &mut VaultMut, token: Pubkey, bucket: u8, amount: u64) -> Result<()> {
+    for balance in vault.balances_mut().iter_mut() {
+        if balance.token == token && balance.bucket == bucket {
             balance.amount = amount;
-            return;
+            return Ok(());
+        }
+    }
+    // 如果条目不存在，添加新条目
+    vault.push(token, bucket, amount)?;
+    Ok(())
+}
+
+/// 获取代币余额（默认分仓）
+pub fn get_token_balance(balances: &[TokenBalance], token: Pubkey) -> u64 {
+    get_bucket_balance(balances, token, DEFAULT_BUCKET)
+}
+
+/// 设置代币余额（默认分仓）
+pub fn set_token_balance(vault: &mut VaultMut, token: Pubkey, amount: u64) -> Result<()> {
+    set_bucket_balance(vault, token, DEFAULT_BUCKET, amount)
+}
+
+/// 获取代币在所有分仓中的余额合计（与实际持有量比较时使用）
+pub fn get_token_total(balances: &[TokenBalance], token: Pubkey) -> u64 {
+    balances
+        .iter()
+        .filter(|balance| balance.token == token)
+        .fold(0u64, |total, balance| total.saturating_add(balance.amount))
+}
+
+/// 校验分仓编号
+fn validate_bucket(bucket: u8) -> Result<()> {
+    require!((bucket as usize) < MAX_VAULT_BUCKETS, ErrorCode::InvalidBucket);
+    Ok(())
+}
+
+/// 增加账本余额并记录流入，返回更新后的余额
+/// 新代币条目需要先调用 ensure_vault_capacity 预留空间
+fn credit_ledger(
+    vault: &AccountLoader<PersonalVault>,
+    token: Pubkey,
+    amount: u64,
+    quote_mint: Pubkey,
+) -> Result<u64> {
+    let mut vault = load_vault_mut(vault)?;
+    let new_balance = get_token_balance(vault.balances(), token)
+        .checked_add(amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    set_token_balance(&mut vault, token, new_balance)?;
+    record_inflow(&mut vault, token, DEFAULT_BUCKET, amount, quote_mint)?;
+    Ok(new_balance)
+}
+
+/// 减少账本余额并记录流出，返回更新后的余额
+fn debit_ledger(vault: &AccountLoader<PersonalVault>, token: Pubkey, amount: u64) -> Result<u64> {
+    let mut vault = load_vault_mut(vault)?;
+    let new_balance = get_token_balance(vault.balances(), token)
+        .checked_sub(amount)
+        .ok_or(ErrorCode::InsufficientBalance)?;
+    set_token_balance(&mut vault, token, new_balance)?;
+    record_outflow(&mut vault, token, DEFAULT_BUCKET, amount)?;
+    Ok(new_balance)
+}
+
+/// 查找 (代币, 分仓) 对应的账本条目
+fn find_entry_mut<'a>(vault: &'a mut VaultMut, token: Pubkey, bucket: u8) -> Option<&'a mut TokenBalance> {
+    vault
+        .balances_mut()
+        .iter_mut()
+        .find(|balance| balance.token == token && balance.bucket == bucket)
+}
+
+/// 按比例扣减条目的持仓成本，返回扣减的成本
+/// balance_before 为扣减 amount 之前的账本余额；全部取出时同时复位成本未知标记
+fn take_cost(vault: &mut VaultMut, token: Pubkey, bucket: u8, amount: u64, balance_before: u64) -> u64 {
+    match find_entry_mut(vault, token, bucket) {
+        Some(entry) if balance_before > 0 => {
+            let removed = (entry.cost_basis as u128 * amount.min(balance_before) as u128
+                / balance_before as u128) as u64;
+            entry.cost_basis -= removed;
+            if amount >= balance_before {
+                entry.basis_unknown = 0;
+            }
+            removed
+        }
+        _ => 0,
+    }
+}
+
+/// 条目是否含有成本未知的持仓
+fn has_unknown_basis(vault: &VaultMut, token: Pubkey, bucket: u8) -> bool {
+    vault
+        .balances()
+        .iter()
+        .any(|balance| balance.token == token && balance.bucket == bucket && balance.has_unknown_basis())
+}
+
+/// 标记条目含有成本未知的持仓（条目需已存在）
+fn mark_unknown_basis(vault: &mut VaultMut, token: Pubkey, bucket: u8) {
+    if let Some(entry) = find_entry_mut(vault, token, bucket) {
+        entry.basis_unknown = 1;
+    }
+}
+
+/// 按比例把持仓成本从一个条目转到另一个条目（两个条目的账本余额更新之后调用）
+/// 来源条目含有成本未知的持仓时，目标条目同样标记为成本未知
+#[allow(clippy::too_many_arguments)]
+fn move_cost(
+    vault: &mut VaultMut,
+    from_token: Pubkey,
+    from_bucket: u8,
+    to_token: Pubkey,
+    to_bucket: u8,
+    amount: u64,
+    from_balance_before: u64,
+) -> Result<()> {
+    let unknown = has_unknown_basis(vault, from_token, from_bucket);
+    let cost = take_cost(vault, from_token, from_bucket, amount, from_balance_before);
+    add_cost(vault, to_token, to_bucket, cost)?;
+    if unknown {
+        mark_unknown_basis(vault, to_token, to_bucket);
+    }
+    Ok(())
+}
+
+/// 增加条目的持仓成本（条目需已存在）
+fn add_cost(vault: &mut VaultMut, token: Pubkey, bucket: u8, cost: u64) -> Result<()> {
+    if let Some(entry) = find_entry_mut(vault, token, bucket) {
+        entry.cost_basis = entry.cost_basis.checked_add(cost).ok_or(ErrorCode::MathOverflow)?;
+    }
+    Ok(())
+}
+
+/// 记录流入（在账本余额增加之后调用）
+/// 报价代币按面值计入成本；其他代币的存入价格未知，不计成本并把条目标记为成本未知
+fn record_inflow(
+    vault: &mut VaultMut,
+    token: Pubkey,
+    bucket: u8,
+    amount: u64,
+    quote_mint: Pubkey,
+) -> Result<()> {
+    if let Some(entry) = find_entry_mut(vault, token, bucket) {
+        entry.total_deposited = entry.total_deposited.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+    }
+    if token == quote_mint {
+        add_cost(vault, token, bucket, amount)?;
+    } else if amount > 0 {
+        mark_unknown_basis(vault, token, bucket);
+    }
+    Ok(())
+}
+
+/// 记录流出（在账本余额减少之后调用），成本按取出比例扣减
+fn record_outflow(vault: &mut VaultMut, token: Pubkey, bucket: u8, amount: u64) -> Result<()> {
+    let balance_after = get_bucket_balance(vault.balances(), token, bucket);
+    let balance_before = balance_after.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+    take_cost(vault, token, bucket, amount, balance_before);
+    if let Some(entry) = find_entry_mut(vault, token, bucket) {
+        entry.total_withdrawn = entry.total_withdrawn.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+    }
+    Ok(())
+}
+
+/// 记录兑换的成本与已实现盈亏（在两个条目的账本余额更新之后调用），返回本次已实现盈亏
+/// - 换出的成本按花费比例从 token_in 条目扣减
+/// - 换入报价代币时，收到的数量即为价值：已实现盈亏 = 收到数量 - 扣减的成本，记在 token_in 条目上
+/// - 换入其他代币时，扣减的成本原样转入 token_out 条目
+/// - token_in 条目含有成本未知的持仓时不计已实现盈亏（扣减的成本不完整），换入的非报价代币同样标记为成本未知
+#[allow(clippy::too_many_arguments)]
+fn record_trade(
+    vault: &mut VaultMut,
+    token_in: Pubkey,
+    token_out: Pubkey,
+    bucket: u8,
+    amount_in: u64,
+    balance_in_before: u64,
+    amount_out: u64,
+    quote_mint: Pubkey,
+) -> Result<i64> {
+    if token_out != quote_mint {
+        move_cost(vault, token_in, bucket, token_out, bucket, amount_in, balance_in_before)?;
+        return Ok(0);
+    }
+
+    let basis_unknown = has_unknown_basis(vault, token_in, bucket);
+    let cost_removed = take_cost(vault, token_in, bucket, amount_in, balance_in_before);
+    add_cost(vault, token_out, bucket, amount_out)?;
+    if basis_unknown {
+        msg!("换出的持仓含有成本未知的部分，本次不计已实现盈亏");
+        return Ok(0);
+    }
+    let realized = i64::try_from(amount_out as i128 - cost_removed as i128)
+        .map_err(|_| ErrorCode::MathOverflow)?;
+    if let Some(entry) = find_entry_mut(vault, token_in, bucket) {
+        entry.realized_pnl = entry.realized_pnl.checked_add(realized).ok_or(ErrorCode::MathOverflow)?;
+    }
+    Ok(realized)
+}
+
+/// 将可选的金库标签编码为定长字节（不足部分补零）
+fn encode_vault_label(label: Option<String>) -> Result<[u8; VAULT_LABEL_LEN]> {
+    let mut encoded = [0u8; VAULT_LABEL_LEN];
+    if let Some(label) = label {
+        require!(label.len() <= VAULT_LABEL_LEN, ErrorCode::InvalidVaultLabel);
+        encoded[..label.len()].copy_from_slice(label.as_bytes());
+    }
+    Ok(encoded)
+}
+
+/// 在投资者金库登记账户中添加一项，已登记的金库直接返回
+/// 登记账户首次使用时写入投资者和 bump
+fn register_vault_entry(
+    registry: &mut VaultRegistry,
+    investor: Pubkey,
+    bump: u8,
+    entry: VaultRegistryEntry,
+) -> Result<()> {
+    if registry.investor == Pubkey::default() {
+        registry.investor = investor;
+        registry.bump = bump;
+    }
+    if registry.vaults.iter().any(|existing| existing.vault == entry.vault) {
+        msg!("金库已登记: {}", entry.vault);
+        return Ok(());
+    }
+    require!(
+        registry.vaults.len() < MAX_VAULTS_PER_INVESTOR,
+        ErrorCode::TooManyVaults
+    );
+    registry.vaults.push(entry);
+    msg!("已登记金库数量: {}", registry.vaults.len());
+    Ok(())
+}
+
+/// 为新的 (代币, 分仓) 条目预留金库空间
+/// 条目已在列表中时直接返回；否则检查代币种类上限，
+/// 并在槽位表已满时扩容（realloc），新增的租金由 payer 支付
+fn ensure_vault_capacity<'info>(
+    vault: &AccountLoader<'info, PersonalVault>,
+    token: Pubkey,
+    bucket: u8,
+    global_config: &GlobalConfig,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    let token_count = {
+        let vault_data = load_vault(vault)?;
+        if vault_data
+            .balances
+            .iter()
+            .any(|balance| balance.token == token && balance.bucket == bucket)
+        {
+            return Ok(());
+        }
+        vault_data.balances.len() + 1
+    };
+
+    let max_tokens = global_config.effective_max_vault_tokens();
+    if token_count > max_tokens as usize {
+        msg!("代币种类超过上限: {} > {}", token_count, max_tokens);
+        return Err(ErrorCode::TooManyVaultTokens.into());
+    }
+
+    let vault_info = vault.to_account_info();
+    let current_len = vault_info.data_len();
+    if PersonalVault::capacity_for(current_len) >= token_count {
+        return Ok(());
+    }
+    let required_len = PersonalVault::space(token_count);
+
+    // 只补足新增空间的租金，金库中存入的 SOL 不受影响
+    let rent = Rent::get()?;
+    let extra_rent = rent
+        .minimum_balance(required_len)
+        .saturating_sub(rent.minimum_balance(current_len));
+    msg!(
+        "扩容金库: {} -> {} 字节, 新增租金 {} lamports (支付者: {})",
+        current_len,
+        required_len,
+        extra_rent,
+        payer.key()
+    );
+
+    if extra_rent > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                system_program::Transfer {
+                    from: payer.clone(),
+                    to: vault_info.clone(),
+                },
+            ),
+            extra_rent,
+        )?;
+    }
+    vault_info.resize(required_len)?;
+
+    Ok(())
+}
+
+/// 校验 Token-2022 mint 的扩展是否受金库支持
+/// SPL Token mint 没有扩展，直接通过
+fn validate_mint_extensions(mint: &AccountInfo) -> Result<()> {
+    if *mint.owner != token_2022::ID {
+        return Ok(());
+    }
+    let data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    for extension in mint_state.get_extension_types()? {
+        if UNSUPPORTED_MINT_EXTENSIONS.contains(&extension) {
+            msg!("不支持的 mint 扩展: {:?}", extension);
+            return Err(ErrorCode::UnsupportedMintExtension.into());
+        }
+    }
+    Ok(())
+}
+
+/// 执行 transfer_checked，mint 带转账钩子时附加钩子所需的额外账户
+/// hook_accounts 中需要包含 ExtraAccountMetaList PDA、钩子程序以及列表中声明的额外账户，
+/// 转账前按 ExtraAccountMetaList 校验，缺失或不匹配时返回 InvalidTransferHookAccounts；普通 mint 忽略 hook_accounts
+#[allow(clippy::too_many_arguments)]
+fn transfer_checked_with_hook<'info>(
+    token_program_id: &Pubkey,
+    from: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    hook_accounts: &[AccountInfo<'info>],
+    amount: u64,
+    decimals: u8,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    if !hook_accounts.is_empty() {
+        msg!("附加 {} 个转账钩子账户", hook_accounts.len());
+    }
+    require_transfer_hook_accounts(&mint, &from, &to, &authority, amount, hook_accounts)?;
+    spl_token_2022::onchain::invoke_transfer_checked(
+        token_program_id,
+        from,
+        mint,
+        to,
+        authority,
+        hook_accounts,
+        amount,
+        decimals,
+        signer_seeds,
+    )?;
+    Ok(())
+}
+
+/// 关闭金库时 mint 之后附带的转账钩子账户数量，顺序与 spl-token 客户端解析的一致：
+/// [列表中声明的额外账户..., ExtraAccountMetaList PDA, 钩子程序]，未配置钩子的 mint 为 0
+fn transfer_hook_account_count(mint: &AccountInfo, following: &[AccountInfo]) -> Result<usize> {
+    let Some(hook_program_id) = transfer_hook_program_id(mint)? else {
+        return Ok(0);
+    };
+    let validation_key = get_extra_account_metas_address(mint.key, &hook_program_id);
+    let validation_info = following
+        .iter()
+        .find(|info| *info.key == validation_key)
+        .ok_or(ErrorCode::InvalidTransferHookAccounts)?;
+    let data = validation_info.try_borrow_data()?;
+    let extra_account_count = TlvStateBorrowed::unpack(&data)
+        .and_then(|state| {
+            ExtraAccountMetaList::unpack_with_tlv_state::<ExecuteInstruction>(&state)
+                .map(|list| list.data().len())
+        })
+        .map_err(|_| error!(ErrorCode::InvalidTransferHookAccounts))?;
+    require!(
+        following.len() >= extra_account_count + 2
+            && *following[extra_account_count].key == validation_key
+            && *following[extra_account_count + 1].key == hook_program_id,
+        ErrorCode::InvalidTransferHookAccounts
+    );
+    Ok(extra_account_count + 2)
+}
+
+/// 根据代币账户的所有者选择对应的代币程序（SPL Token 或 Token-2022）
+fn select_token_program<'info>(
+    token_account: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    token_2022_program: &Program<'info, Token2022>,
+) -> AccountInfo<'info> {
+    if *token_account.owner == token_2022::ID {
+        token_2022_program.to_account_info()
+    } else {
+        token_program.to_account_info()
+    }
+}
+
+/// mint 是否配置了转账钩子程序
+fn has_transfer_hook(mint: &AccountInfo) -> Result<bool> {
+    Ok(transfer_hook_program_id(mint)?.is_some())
+}
+
+/// 校验批量存取款的参数列表与 remaining_accounts 数量
+/// 每个代币对应 [用户代币账户, 金库代币账户, mint] 一组账户，同一 mint 不能重复出现
+fn validate_batch_transfers(transfers: &[TokenTransfer], accounts: &[AccountInfo]) -> Result<()> {
+    require!(!transfers.is_empty(), ErrorCode::InvalidAmount);
+    require!(
+        accounts.len() == transfers.len() * 3,
+        ErrorCode::InsufficientAccounts
+    );
+    for (index, transfer) in transfers.iter().enumerate() {
+        require!(transfer.amount > 0, ErrorCode::InvalidAmount);
+        require!(
+            !transfers[..index].iter().any(|previous| previous.mint == transfer.mint),
+            ErrorCode::DuplicateAccountAddress
+        );
+    }
+    Ok(())
+}
+
+/// 校验批量存取款中的一组账户 [用户代币账户, 金库代币账户, mint]
+/// 两个代币账户必须是对应 mint 的 ATA；返回 mint 所属的代币程序和小数位
+fn validate_batch_group<'info>(
+    group: &[AccountInfo<'info>],
+    mint: Pubkey,
+    user: Pubkey,
+    vault: Pubkey,
+    token_program: &Program<'info, Token>,
+    token_2022_program: &Program<'info, Token2022>,
+) -> Result<(AccountInfo<'info>, u8)> {
+    let (user_token_info, vault_token_info, mint_info) = (&group[0], &group[1], &group[2]);
+    require_keys_eq!(mint_info.key(), mint, ErrorCode::InvalidTokenMintAddress);
+    require!(
+        *mint_info.owner == Token::id() || *mint_info.owner == token_2022::ID,
+        ErrorCode::InvalidTokenMintAddress
+    );
+    require_keys_eq!(
+        user_token_info.key(),
+        get_associated_token_address_with_program_id(&user, &mint, mint_info.owner),
+        ErrorCode::InvalidTokenAccount
+    );
+    require_keys_eq!(
+        vault_token_info.key(),
+        get_associated_token_address_with_program_id(&vault, &mint, mint_info.owner),
+        ErrorCode::InvalidTokenAccount
+    );
+
+    // 批量指令不转发转账钩子账户，带钩子的 mint 需使用单币种指令
+    if has_transfer_hook(mint_info)? {
+        msg!("mint {} 配置了转账钩子，请使用单币种存取款指令", mint);
+        return Err(ErrorCode::UnsupportedMintExtension.into());
+    }
+
+    let decimals = {
+        let data = mint_info.try_borrow_data()?;
+        token_interface::Mint::try_deserialize(&mut &data[..])?.decimals
+    };
+    Ok((
+        select_token_program(mint_info, token_program, token_2022_program),
+        decimals,
+    ))
+}
+
+/// 金库 PDA 上可用的原生 SOL（扣除租金豁免后的 lamports）
+fn vault_available_lamports(vault_info: &AccountInfo) -> Result<u64> {
+    let rent_exempt = Rent::get()?.minimum_balance(vault_info.data_len());
+    Ok(vault_info.lamports().saturating_sub(rent_exempt))
+}
+
+/// 关闭本程序拥有的附属 PDA，全部 lamports 转给 destination；账户不存在时跳过
+/// 返回是否关闭了账户
+fn close_program_account(account: &AccountInfo, destination: &AccountInfo) -> Result<bool> {
+    if *account.owner != crate::ID || account.lamports() == 0 {
+        return Ok(false);
+    }
+    let lamports = account.lamports();
+    **account.try_borrow_mut_lamports()? = 0;
+    **destination.try_borrow_mut_lamports()? += lamports;
+    account.assign(&system_program::ID);
+    account.resize(0)?;
+    Ok(true)
+}
+
+/// 账本不变量检查：账本余额不能超过实际持有量
+/// 在每个改变余额的指令执行后调用；违反时发出事件并返回错误
+/// （交易回滚，但日志与事件仍保留在失败交易的元数据中，可供监控读取）
+fn check_ledger_invariant(
+    vault: &AccountLoader<PersonalVault>,
+    mint: Pubkey,
+    bucket: u8,
+    actual_amount: u64,
+) -> Result<()> {
+    let vault_data = load_vault(vault)?;
+    let ledger_amount = get_token_total(&vault_data.balances, mint);
+    if ledger_amount > actual_amount {
+        msg!("账本不变量违反: 代币={}, 账本={}, 实际={}", mint, ledger_amount, actual_amount);
+        emit!(LedgerInvariantViolatedEvent {
+            user: vault_data.header.investor,
+            vault: vault.key(),
+            mint,
+            bucket,
+            ledger_amount,
+            actual_amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        return Err(ErrorCode::LedgerInvariantViolation.into());
+    }
+    Ok(())
+}
+
+/// 将实际持有量超出账本的部分记入账本，返回记入的差额
+/// 实际持有量低于账本时不做修改（由账本不变量检查负责报错）
+fn credit_surplus<'info>(
+    ctx: &Context<'_, '_, '_, 'info, ReconcileBalances<'info>>,
+    token: Pubkey,
+    token_account: Pubkey,
+    actual_amount: u64,
+) -> Result<u64> {
+    let ledger_amount = get_token_total(&load_vault(&ctx.accounts.vault)?.balances, token);
+    if actual_amount <= ledger_amount {
+        if actual_amount < ledger_amount {
+            msg!("实际持有量低于账本: 代币={}, 账本={}, 实际={}", token, ledger_amount, actual_amount);
+        }
+        return Ok(0);
+    }
+    let delta = actual_amount - ledger_amount;
+
+    // 新代币需要预留金库空间（必要时扩容，由调用者支付租金）
+    ensure_vault_capacity(
+        &ctx.accounts.vault,
+        token,
+        DEFAULT_BUCKET,
+        &ctx.accounts.global_config,
+        &ctx.accounts.caller.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+    )?;
+    // 差额记入默认分仓
+    let investor = {
+        let mut vault = load_vault_mut(&ctx.accounts.vault)?;
+        let default_balance = get_token_balance(vault.balances(), token)
+            .checked_add(delta)
+            .ok_or(ErrorCode::MathOverflow)?;
+        set_token_balance(&mut vault, token, default_balance)?;
+        record_inflow(&mut vault, token, DEFAULT_BUCKET, delta, ctx.accounts.global_config.quote_mint)?;
+        vault.header.investor
+    };
+    msg!("对账: 代币={}, 账本 {} -> {}, 差额 {}", token, ledger_amount, actual_amount, delta);
+
+    emit!(BalanceReconciledEvent {
+        user: investor,
+        vault: ctx.accounts.vault.key(),
+        caller: ctx.accounts.caller.key(),
+        token,
+        token_account,
+        ledger_amount,
+        actual_amount,
+        delta,
+        timestamp: Clock::get()?.unix_timestamp,
+        bucket: DEFAULT_BUCKET,
+    });
+
+    Ok(delta)
+}
+
+/// 检查信号 ID 是否已执行过
+fn is_signal_recorded(registry: &SignalRegistry, signal_id: u64) -> bool {
+    registry.signal_ids.contains(&signal_id)
+}
+
+/// 记录信号 ID 及其有效截止槽位
+/// 从 next_index 开始寻找空槽或已过期的记录覆盖；未过期的记录不会被覆盖，
+/// 否则被挤出的信号 ID 可在有效期内被再次执行
+fn record_signal_id(
+    registry: &mut SignalRegistry,
+    signal_id: u64,
+    valid_until_slot: u64,
+    current_slot: u64,
+) -> Result<()> {
+    let start = registry.next_index as usize % SIGNAL_HISTORY_SIZE;
+    let index = (0..SIGNAL_HISTORY_SIZE)
+        .map(|offset| (start + offset) % SIGNAL_HISTORY_SIZE)
+        .find(|&i| registry.signal_ids[i] == 0 || registry.expiry_slots[i] < current_slot)
+        .ok_or(error!(ErrorCode::SignalRegistryFull))?;
+    registry.signal_ids[index] = signal_id;
+    registry.expiry_slots[index] = valid_until_slot;
+    registry.next_index = ((index + 1) % SIGNAL_HISTORY_SIZE) as u16;
+    Ok(())
+}
+
+/// 校验交易历史容量：1..=MAX_TRADE_HISTORY_CAPACITY，且每次最多增加 TRADE_HISTORY_MAX_GROWTH 条
+fn validate_trade_history_capacity(old_capacity: usize, new_capacity: usize) -> Result<()> {
+    require!(
+        new_capacity > 0
+            && new_capacity <= MAX_TRADE_HISTORY_CAPACITY
+            && new_capacity <= old_capacity + TRADE_HISTORY_MAX_GROWTH,
+        ErrorCode::InvalidTradeHistoryCapacity
+    );
+    Ok(())
+}
+
+/// 追加一条交易记录到金库的交易历史
+/// 投资者未创建交易历史（账户为空）时跳过，返回是否已记录
+fn append_trade_record(history_info: &AccountInfo, record: TradeRecord) -> Result<bool> {
+    if history_info.data_is_empty() {
+        return Ok(false);
+    }
+    let mut history = load_trade_history_mut(history_info)?;
+    history.push(record);
+    msg!(
+        "交易记录已保存: {}/{} 条, 累计 {} 笔",
+        history.header.record_count,
+        history.capacity(),
+        history.header.total_trades
+    );
+    Ok(true)
+}
+
+/// 查找代币登记的价格源及对应的预言机价格，并校验价格时效
+/// 返回 (代币精度, 价格)
+fn find_oracle_price<'a>(
+    oracle_config: &OracleConfig,
+    prices: &'a [OraclePrice],
+    mint: &Pubkey,
+    now: i64,
+) -> Result<(u8, &'a OraclePrice)> {
+    let Some(feed) = oracle_config.feed_for(mint) else {
+        msg!("代币未登记价格源: {}", mint);
+        return Err(ErrorCode::MissingPriceFeed.into());
+    };
+    let Some(price) = prices.iter().find(|price| price.feed_id == feed.feed_id) else {
+        msg!("缺少代币的价格账户: {}", mint);
+        return Err(ErrorCode::MissingPriceFeed.into());
+    };
+    let age = now.saturating_sub(price.publish_time);
+    if age > oracle_config.effective_max_price_age_secs() as i64 {
+        msg!("价格已过期: {} ({} 秒前)", mint, age);
+        return Err(ErrorCode::StalePrice.into());
+    }
+    // 置信区间相对价格过宽时价格不可靠（价格非正时由 quote_value 拒绝）
+    if price.price > 0 && price.conf as u128 * 10_000 > price.price as u128 * MAX_PRICE_CONF_BPS as u128 {
+        msg!("价格置信区间过宽: {} (价格 {}, 置信区间 {})", mint, price.price, price.conf);
+        return Err(ErrorCode::PriceConfidenceTooWide.into());
+    }
+    Ok((feed.decimals, price))
+}
+
+/// 记录净值快照（环形缓冲区，覆盖最旧的记录）
+fn record_snapshot(snapshots: &mut VaultSnapshots, snapshot: NavSnapshot) {
+    let index = snapshots.next_index as usize % SNAPSHOT_HISTORY_SIZE;
+    snapshots.snapshots[index] = snapshot;
+    snapshots.next_index = ((index + 1) % SNAPSHOT_HISTORY_SIZE) as u16;
+    snapshots.last_snapshot_ts = snapshot.timestamp;
+}
+
+/// 设置金库的交易暂停标志（仅投资者）
+fn set_trading_paused(vault: &AccountLoader<PersonalVault>, user: Pubkey, paused: bool) -> Result<()> {
+    let investor = {
+        let mut vault = vault.load_mut()?;
+        require!(user == vault.investor, ErrorCode::OnlyInvestor);
+        require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+        vault.trading_paused = paused as u8;
+        vault.investor
+    };
+
+    emit!(VaultTradingPausedEvent {
+        user: investor,
+        vault: vault.key(),
+        paused,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// 读取金库的交易策略（投资者未设置时返回 None）
+fn load_trading_policy(policy_info: &AccountInfo) -> Result<Option<TradingPolicy>> {
+    if policy_info.data_is_empty() {
+        return Ok(None);
+    }
+    require_keys_eq!(*policy_info.owner, crate::ID, ErrorCode::InvalidTradingPolicy);
+    let data = policy_info.try_borrow_data()?;
+    Ok(Some(TradingPolicy::try_deserialize(&mut &data[..])?))
+}
+
+/// 代存款只接受金库账本中已有的代币，或投资者在交易策略白名单中列出的代币
+/// 防止任何人存入任意代币的粉尘占满金库代币槽位（无价格源的代币还会让快照失败）
+fn require_deposit_for_mint(
+    vault: &AccountLoader<PersonalVault>,
+    policy_info: &AccountInfo,
+    mint: Pubkey,
+) -> Result<()> {
+    if load_vault(vault)?.balances.iter().any(|balance| balance.token == mint) {
+        return Ok(());
+    }
+    let allowed = load_trading_policy(policy_info)?
+        .is_some_and(|policy| policy.allowed_mints.contains(&mint));
+    require!(allowed, ErrorCode::DepositForMintNotAccepted);
+    Ok(())
+}
+
+/// 写回交易策略账户
+fn store_trading_policy(policy_info: &AccountInfo, policy: &TradingPolicy) -> Result<()> {
+    let mut data = policy_info.try_borrow_mut_data()?;
+    policy.try_serialize(&mut &mut data[..])
+}
+
+/// 按投资者设置的交易策略检查交易信号
+/// balance 为输入代币在该分仓中的余额
+#[allow(clippy::too_many_arguments)]
+fn enforce_trading_policy(
+    policy: &TradingPolicy,
+    token_in: Pubkey,
+    token_out: Pubkey,
+    amount_in: u64,
+    slippage_bps: u16,
+    route: DexRoute,
+    balance: u64,
+    now: i64,
+) -> Result<()> {
+    if !policy.allowed_mints.is_empty() {
+        for token in [token_in, token_out] {
+            if !policy.allowed_mints.contains(&token) {
+                msg!("交易策略不允许代币: {}", token);
+                return Err(ErrorCode::TokenNotAllowedByPolicy.into());
+            }
+        }
+    }
+
+    if policy.max_amount_in > 0 && amount_in > policy.max_amount_in {
+        msg!("交易金额 {} 超过策略上限 {}", amount_in, policy.max_amount_in);
+        return Err(ErrorCode::TradeAmountExceedsPolicy.into());
+    }
+
+    if policy.max_amount_in_bps > 0 {
+        let max_amount = (balance as u128 * policy.max_amount_in_bps as u128 / 10_000) as u64;
+        if amount_in > max_amount {
+            msg!(
+                "交易金额 {} 超过余额的 {} bps ({})",
+                amount_in,
+                policy.max_amount_in_bps,
+                max_amount
+            );
+            return Err(ErrorCode::TradeAmountExceedsBalanceShare.into());
+        }
+    }
+
+    if policy.max_slippage_bps > 0 && slippage_bps > policy.max_slippage_bps {
+        msg!("滑点 {} bps 超过策略上限 {} bps", slippage_bps, policy.max_slippage_bps);
+        return Err(ErrorCode::SlippageExceedsPolicy.into());
+    }
+
+    if policy.allowed_dex_routes != 0 && policy.allowed_dex_routes & route.pause_flag() == 0 {
+        msg!("交易策略不允许 DEX 路由: {:?}", route);
+        return Err(ErrorCode::DexRouteNotAllowed.into());
+    }
+
+    if policy.min_trade_interval_secs > 0 && policy.last_trade_ts != 0 {
+        let elapsed = now.saturating_sub(policy.last_trade_ts);
+        if elapsed < policy.min_trade_interval_secs as i64 {
+            msg!(
+                "距离上次交易 {} 秒，策略要求至少 {} 秒",
+                elapsed,
+                policy.min_trade_interval_secs
+            );
+            return Err(ErrorCode::TradeIntervalTooShort.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// 验证交易执行者
+/// 金库未设置执行者授权时只接受全局机器人和管理员；
+/// 设置后接受未过期的授权执行者，以及投资者允许时的全局机器人和管理员
+fn authorize_trade_executor(
+    executor: Pubkey,
+    global_config: &GlobalConfig,
+    executors_info: &AccountInfo,
+    now: i64,
+) -> Result<()> {
+    let is_global_executor = executor == global_config.bot || executor == global_config.admin;
+
+    if executors_info.data_is_empty() {
+        require!(is_global_executor, ErrorCode::OnlyBotOrAdmin);
+        return Ok(());
+    }
+
+    require_keys_eq!(*executors_info.owner, crate::ID, ErrorCode::InvalidVaultExecutors);
+    let vault_executors = {
+        let data = executors_info.try_borrow_data()?;
+        VaultExecutors::try_deserialize(&mut &data[..])?
+    };
+
+    if is_global_executor && vault_executors.allow_global_executors {
+        msg!("全局执行者（投资者允许）");
+        return Ok(());
+    }
+
+    if let Some(entry) = vault_executors.find_active(&executor, now) {
+        msg!("金库授权的执行者，授权过期时间: {}", entry.expires_at);
+        return Ok(());
+    }
+
+    if vault_executors
+        .executors
+        .iter()
+        .any(|entry| entry.executor == executor)
+    {
+        msg!("执行者 {} 的授权已过期", executor);
+        return Err(ErrorCode::ExecutorAuthorizationExpired.into());
+    }
+
+    msg!("执行者 {} 未获该金库授权", executor);
+    Err(ErrorCode::ExecutorNotAuthorized.into())
+}
+
+/// 首次设置时初始化执行者授权账户（默认继续接受全局机器人和管理员）
+fn init_vault_executors_if_needed(vault_executors: &mut VaultExecutors, vault: Pubkey, bump: u8) {
+    if vault_executors.vault == Pubkey::default() {
+        vault_executors.vault = vault;
+        vault_executors.bump = bump;
+        vault_executors.allow_global_executors = true;
+    }
+}
+
+/// 读取金库的交易限额（投资者未设置时返回 None）
+fn load_trade_limits(limits_info: &AccountInfo) -> Result<Option<TradeLimits>> {
+    if limits_info.data_is_empty() {
+        return Ok(None);
+    }
+    require_keys_eq!(*limits_info.owner, crate::ID, ErrorCode::InvalidTradeLimits);
+    let data = limits_info.try_borrow_data()?;
+    Ok(Some(TradeLimits::try_deserialize(&mut &data[..])?))
+}
+
+/// 写回交易限额账户
+fn store_trade_limits(limits_info: &AccountInfo, limits: &TradeLimits) -> Result<()> {
+    let mut data = limits_info.try_borrow_mut_data()?;
+    limits.try_serialize(&mut &mut data[..])
+}
+
+/// 交易前检查 24 小时交易次数，并确认交易额可以用限额计价代币计量
+/// 输入代币为计价代币时提前检查交易额，避免执行注定失败的兑换
+fn enforce_trade_limits_before_swap(
+    limits: &TradeLimits,
+    token_in: Pubkey,
+    token_out: Pubkey,
+    amount_in: u64,
+    now: i64,
+) -> Result<()> {
+    let (trades_used, volume_used) = limits.usage(now);
+
+    if limits.max_daily_trades > 0 && trades_used >= limits.max_daily_trades {
+        msg!("24 小时内已交易 {} 次，上限 {} 次", trades_used, limits.max_daily_trades);
+        return Err(ErrorCode::DailyTradeCountExceeded.into());
+    }
+
+    if limits.max_daily_volume > 0 {
+        if token_in != limits.quote_mint && token_out != limits.quote_mint {
+            match limits.unpriced_trade_policy {
+                UnpricedTradePolicy::Reject => {
+                    msg!("交易额限额按 {} 计价，交易两侧都不是该代币", limits.quote_mint);
+                    return Err(ErrorCode::TradeNotPricedInLimitMint.into());
+                }
+                UnpricedTradePolicy::CountOnly => {
+                    msg!("交易两侧都不是计价代币 {}，只计入交易次数", limits.quote_mint);
+                }
+            }
+        }
+        if token_in == limits.quote_mint
+            && volume_used.saturating_add(amount_in) > limits.max_daily_volume
+        {
+            msg!(
+                "24 小时交易额 {} + {} 超过上限 {}",
+                volume_used,
+                amount_in,
+                limits.max_daily_volume
+            );
+            return Err(ErrorCode::DailyVolumeExceeded.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// 交易后按实际成交数量计入交易额，超过 24 小时上限时整笔交易回滚
+/// 交易额取计价代币一侧的数量（输入为计价代币取实际花费，输出为计价代币取扣费前的输出）
+fn record_trade_limits_after_swap(
+    limits: &mut TradeLimits,
+    token_in: Pubkey,
+    token_out: Pubkey,
+    amount_in: u64,
+    amount_out: u64,
+    now: i64,
+) -> Result<()> {
+    // 两侧都不是计价代币时只计次数（设置了交易额上限时按 unpriced_trade_policy 在交易前处理）
+    let volume = if token_in == limits.quote_mint {
+        amount_in
+    } else if token_out == limits.quote_mint {
+        amount_out
+    } else {
+        0
+    };
+
+    if limits.max_daily_volume > 0 {
+        let (_, volume_used) = limits.usage(now);
+        if volume_used.saturating_add(volume) > limits.max_daily_volume {
+            msg!(
+                "24 小时交易额 {} + {} 超过上限 {}",
+                volume_used,
+                volume,
+                limits.max_daily_volume
+            );
+            return Err(ErrorCode::DailyVolumeExceeded.into());
         }
     }
-    // 如果代币不存在，添加新条目
-    vault.balances.push(TokenBalance { token, amount });
+
+    limits.record(now, volume);
+    Ok(())
 }
 
 /// 根据滑点计算最小输出金额（简化版本）
@@ -61,6 +1008,7 @@ fn calculate_min_output_amount(amount_in: u64, slippage_bps: u16) -> Result<u64>
 }
 
 /// 个人金库程序指令实现
+#[allow(clippy::module_inception)]
 pub mod instructions {
     use super::*;
 
@@ -87,6 +1035,7 @@ pub mod instructions {
         config.admin = ctx.accounts.admin.key();
         config.bot = bot_address;
         config.is_initialized = true;
+        config.max_vault_tokens = crate::constants::DEFAULT_MAX_VAULT_TOKENS;
 
         msg!("全局配置初始化完成!");
         msg!("配置地址: {}", ctx.accounts.global_config.key());
@@ -94,6 +1043,190 @@ pub mod instructions {
         Ok(())
     }
 
+    /// 迁移全局配置：将旧版账户扩容到当前布局（管理员，新增租金由管理员支付）
+    /// 扩容部分为零，即未设置守护者、未暂停任何操作
+    pub fn migrate_global_config(ctx: Context<MigrateGlobalConfig>) -> Result<()> {
+        msg!("开始迁移全局配置...");
+        msg!("管理员地址: {}", ctx.accounts.admin.key());
+
+        let config_info = ctx.accounts.global_config.to_account_info();
+        require_keys_eq!(*config_info.owner, crate::ID, ErrorCode::InvalidGlobalConfigAccount);
+        {
+            let data = config_info.try_borrow_data()?;
+            require!(
+                data.len() >= 8 + 32 && data[..8] == *GlobalConfig::DISCRIMINATOR,
+                ErrorCode::InvalidGlobalConfigAccount
+            );
+            require!(
+                data[8..40] == ctx.accounts.admin.key().to_bytes(),
+                ErrorCode::Unauthorized
+            );
+        }
+
+        let current_len = config_info.data_len();
+        if current_len >= GlobalConfig::SPACE {
+            msg!("全局配置已是当前布局，无需迁移");
+            return Ok(());
+        }
+
+        let rent = Rent::get()?;
+        let extra_rent = rent
+            .minimum_balance(GlobalConfig::SPACE)
+            .saturating_sub(config_info.lamports());
+        if extra_rent > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.admin.to_account_info(),
+                        to: config_info.clone(),
+                    },
+                ),
+                extra_rent,
+            )?;
+        }
+        config_info.resize(GlobalConfig::SPACE)?;
+
+        msg!(
+            "全局配置扩容: {} -> {} 字节, 新增租金 {} lamports",
+            current_len,
+            GlobalConfig::SPACE,
+            extra_rent
+        );
+        Ok(())
+    }
+
+    /// 迁移信号记录账户：将旧版账户扩容到带有效截止槽位的布局（任何人可调用，调用者支付新增租金）
+    /// 旧记录的截止槽位设为当前槽位加有效期上限，使其至少再保留一个有效期
+    pub fn migrate_signal_registry(ctx: Context<MigrateSignalRegistry>) -> Result<()> {
+        msg!("开始迁移信号记录账户...");
+        msg!("金库地址: {}", ctx.accounts.vault.key());
+
+        let registry_info = ctx.accounts.signal_registry.to_account_info();
+        require_keys_eq!(*registry_info.owner, crate::ID, ErrorCode::InvalidSignalRegistryAccount);
+        let current_len = registry_info.data_len();
+        {
+            let data = registry_info.try_borrow_data()?;
+            require!(
+                current_len >= SignalRegistry::LEGACY_SPACE
+                    && data[..8] == *SignalRegistry::DISCRIMINATOR,
+                ErrorCode::InvalidSignalRegistryAccount
+            );
+        }
+        if current_len >= SignalRegistry::SPACE {
+            msg!("信号记录账户已是当前布局，无需迁移");
+            return Ok(());
+        }
+
+        let rent = Rent::get()?;
+        let extra_rent = rent
+            .minimum_balance(SignalRegistry::SPACE)
+            .saturating_sub(registry_info.lamports());
+        if extra_rent > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: registry_info.clone(),
+                    },
+                ),
+                extra_rent,
+            )?;
+        }
+        registry_info.resize(SignalRegistry::SPACE)?;
+
+        let retain_until = Clock::get()?.slot.saturating_add(MAX_SIGNAL_VALIDITY_SLOTS);
+        {
+            let mut data = registry_info.try_borrow_mut_data()?;
+            let ids = data[8 + 32..8 + 32 + 8 * SIGNAL_HISTORY_SIZE].to_vec();
+            let expiries = &mut data[SignalRegistry::LEGACY_SPACE..SignalRegistry::SPACE];
+            for (id, expiry) in ids.chunks_exact(8).zip(expiries.chunks_exact_mut(8)) {
+                if id.iter().any(|&b| b != 0) {
+                    expiry.copy_from_slice(&retain_until.to_le_bytes());
+                }
+            }
+        }
+
+        msg!(
+            "信号记录账户扩容: {} -> {} 字节, 新增租金 {} lamports",
+            current_len,
+            SignalRegistry::SPACE,
+            extra_rent
+        );
+        Ok(())
+    }
+
+    /// 设置紧急暂停守护者（管理员），传入默认地址表示取消守护者
+    pub fn set_guardian(ctx: Context<SetGuardian>, new_guardian: Pubkey) -> Result<()> {
+        msg!("开始设置守护者...");
+        msg!("管理员地址: {}", ctx.accounts.admin.key());
+        msg!("新守护者地址: {}", new_guardian);
+
+        let config = &mut ctx.accounts.global_config;
+        require!(
+            ctx.accounts.admin.key() == config.admin,
+            ErrorCode::Unauthorized
+        );
+
+        msg!("当前守护者地址: {}", config.guardian);
+        config.guardian = new_guardian;
+
+        msg!("守护者更新完成!");
+        Ok(())
+    }
+
+    /// 设置全局暂停标志（管理员或守护者），同时用于暂停和恢复
+    pub fn set_pause_flags(
+        ctx: Context<SetPauseFlags>,
+        paused_operations: u8,
+        paused_dex_routes: u8,
+    ) -> Result<()> {
+        msg!("开始设置全局暂停标志...");
+        msg!("调用者地址: {}", ctx.accounts.authority.key());
+
+        let config = &mut ctx.accounts.global_config;
+        let authority = ctx.accounts.authority.key();
+        require!(
+            authority == config.admin
+                || (config.guardian != Pubkey::default() && authority == config.guardian),
+            ErrorCode::Unauthorized
+        );
+
+        let known_dex_routes = DexRoute::ALL
+            .iter()
+            .fold(0u8, |flags, route| flags | route.pause_flag());
+        require!(
+            paused_operations & !PAUSE_ALL_OPERATIONS == 0
+                && paused_dex_routes & !known_dex_routes == 0,
+            ErrorCode::InvalidPauseFlags
+        );
+
+        let old_paused_operations = config.paused_operations;
+        let old_paused_dex_routes = config.paused_dex_routes;
+        config.paused_operations = paused_operations;
+        config.paused_dex_routes = paused_dex_routes;
+        msg!(
+            "暂停的操作: {:#04x} -> {:#04x}, 暂停的 DEX 适配器: {:#04x} -> {:#04x}",
+            old_paused_operations,
+            paused_operations,
+            old_paused_dex_routes,
+            paused_dex_routes
+        );
+
+        emit!(EmergencyPauseEvent {
+            authority,
+            old_paused_operations,
+            paused_operations,
+            old_paused_dex_routes,
+            paused_dex_routes,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("全局暂停标志更新完成!");
+        Ok(())
+    }
+
     /// 设置机器人地址
     pub fn set_bot(ctx: Context<SetBot>, new_bot_address: Pubkey) -> Result<()> {
         msg!("开始设置机器人地址...");
@@ -159,10 +1292,138 @@ pub mod instructions {
         Ok(())
     }
 
-    /// 创建余额管理器
-    pub fn create_balance_manager(ctx: Context<CreateBalanceManager>) -> Result<()> {
-        msg!("开始创建余额管理器...");
-        msg!("用户地址: {}", ctx.accounts.user.key());
+    /// 设置每个金库最多持有的代币种类
+    pub fn set_max_vault_tokens(ctx: Context<SetMaxVaultTokens>, max_tokens: u16) -> Result<()> {
+        msg!("开始设置金库代币种类上限...");
+        msg!("管理员地址: {}", ctx.accounts.admin.key());
+        msg!("新上限: {}", max_tokens);
+
+        require!(
+            max_tokens > 0 && max_tokens <= MAX_VAULT_TOKENS_LIMIT,
+            ErrorCode::InvalidMaxVaultTokens
+        );
+
+        let config = &mut ctx.accounts.global_config;
+        require!(
+            ctx.accounts.admin.key() == config.admin,
+            ErrorCode::Unauthorized
+        );
+
+        msg!("当前上限: {}", config.effective_max_vault_tokens());
+
+        config.max_vault_tokens = max_tokens;
+
+        msg!("金库代币种类上限更新完成!");
+
+        Ok(())
+    }
+
+    /// 设置成本与盈亏计价使用的报价代币
+    /// 修改后不会重算已有条目的成本，只影响之后的记账
+    pub fn set_quote_mint(ctx: Context<SetQuoteMint>, quote_mint: Pubkey) -> Result<()> {
+        msg!("开始设置报价代币...");
+        msg!("管理员地址: {}", ctx.accounts.admin.key());
+        msg!("新报价代币: {}", quote_mint);
+
+        let config = &mut ctx.accounts.global_config;
+        require!(
+            ctx.accounts.admin.key() == config.admin,
+            ErrorCode::Unauthorized
+        );
+
+        msg!("当前报价代币: {}", config.quote_mint);
+
+        config.quote_mint = quote_mint;
+
+        msg!("报价代币更新完成!");
+
+        Ok(())
+    }
+
+    /// 登记代币的价格源（管理员）
+    /// feed_id 全零时移除该代币的登记
+    pub fn set_price_feed(ctx: Context<SetPriceFeed>, mint: Pubkey, feed_id: [u8; 32]) -> Result<()> {
+        msg!("开始登记价格源...");
+        msg!("管理员地址: {}", ctx.accounts.admin.key());
+
+        require!(
+            ctx.accounts.admin.key() == ctx.accounts.global_config.admin,
+            ErrorCode::Unauthorized
+        );
+
+        // 精度从 mint 账户读取；原生 SOL 没有 mint，使用 WSOL 的精度
+        let expected_mint = if mint == NATIVE_SOL_MINT { WSOL_MINT } else { mint };
+        require_keys_eq!(
+            ctx.accounts.mint_account.key(),
+            expected_mint,
+            ErrorCode::InvalidTokenMintAddress
+        );
+        let decimals = ctx.accounts.mint_account.decimals;
+        msg!("代币: {}, 精度: {}", mint, decimals);
+
+        let config = &mut ctx.accounts.oracle_config;
+        config.bump = ctx.bumps.oracle_config;
+
+        if feed_id == [0u8; 32] {
+            config.feeds.retain(|feed| feed.mint != mint);
+            msg!("已移除价格源, 剩余 {} 个", config.feeds.len());
+            return Ok(());
+        }
+
+        if let Some(feed) = config.feeds.iter_mut().find(|feed| feed.mint == mint) {
+            feed.feed_id = feed_id;
+            feed.decimals = decimals;
+        } else {
+            require!(config.feeds.len() < MAX_PRICE_FEEDS, ErrorCode::TooManyPriceFeeds);
+            config.feeds.push(PriceFeedEntry {
+                mint,
+                feed_id,
+                decimals,
+            });
+        }
+
+        msg!("价格源登记完成, 共 {} 个", config.feeds.len());
+        Ok(())
+    }
+
+    /// 设置快照间隔和价格最大延迟（管理员，0 表示使用默认值）
+    pub fn set_snapshot_config(
+        ctx: Context<SetSnapshotConfig>,
+        snapshot_interval_secs: u32,
+        max_price_age_secs: u32,
+    ) -> Result<()> {
+        msg!("开始设置快照参数...");
+        msg!("管理员地址: {}", ctx.accounts.admin.key());
+
+        require!(
+            ctx.accounts.admin.key() == ctx.accounts.global_config.admin,
+            ErrorCode::Unauthorized
+        );
+
+        let config = &mut ctx.accounts.oracle_config;
+        config.bump = ctx.bumps.oracle_config;
+        config.snapshot_interval_secs = snapshot_interval_secs;
+        config.max_price_age_secs = max_price_age_secs;
+
+        msg!(
+            "快照间隔: {} 秒, 价格最大延迟: {} 秒",
+            config.effective_snapshot_interval_secs(),
+            config.effective_max_price_age_secs()
+        );
+        Ok(())
+    }
+
+    /// 创建余额管理器
+    pub fn create_balance_manager(
+        ctx: Context<CreateBalanceManager>,
+        vault_index: u16,
+        label: Option<String>,
+    ) -> Result<()> {
+        msg!("开始创建余额管理器...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        msg!("金库编号: {}", vault_index);
+
+        let label = encode_vault_label(label)?;
 
         // 验证全局配置已初始化
         require!(
@@ -170,14 +1431,30 @@ pub mod instructions {
             ErrorCode::GlobalConfigNotInitialized
         );
 
-        let vault = &mut ctx.accounts.vault;
+        let mut vault = ctx.accounts.vault.load_init()?;
 
         // 设置金库数据
         vault.investor = ctx.accounts.user.key();
-        vault.is_initialized = true;
-        vault.is_locked = false; // 初始化重入保护标志
-        vault.balances = Vec::new(); // 初始化余额列表
+        vault.is_initialized = 1;
+        vault.is_locked = 0; // 初始化重入保护标志
+        vault.token_count = 0; // 初始化余额槽位
         vault.bump = ctx.bumps.vault; // 保存 PDA bump
+        vault.layout_version = VAULT_LAYOUT_VERSION;
+        vault.vault_index = vault_index;
+        vault.label = label;
+        drop(vault);
+
+        // 登记到投资者的金库列表
+        register_vault_entry(
+            &mut ctx.accounts.vault_registry,
+            ctx.accounts.user.key(),
+            ctx.bumps.vault_registry,
+            VaultRegistryEntry {
+                vault_index,
+                label,
+                vault: ctx.accounts.vault.key(),
+            },
+        )?;
 
         msg!("余额管理器创建完成!");
         msg!("金库地址: {}", ctx.accounts.vault.key());
@@ -187,27 +1464,485 @@ pub mod instructions {
             vault: ctx.accounts.vault.key(),
             timestamp: Clock::get()?.unix_timestamp,
             timestamp_microseconds: Clock::get()?.unix_timestamp as u64 * 1_000_000, // 转换为微秒
+            vault_index,
         });
 
         Ok(())
     }
 
-    /// 用户存款
-    pub fn user_deposit(ctx: Context<UserDeposit>, amount: u64) -> Result<()> {
-        let vault = &mut ctx.accounts.vault;
+    /// 登记已有金库（登记账户出现之前创建的旧版金库）
+    pub fn register_vault(ctx: Context<RegisterVault>) -> Result<()> {
+        msg!("开始登记金库...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+
+        let entry = {
+            let vault = ctx.accounts.vault.load()?;
+            require!(
+                ctx.accounts.user.key() == vault.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+            VaultRegistryEntry {
+                vault_index: vault.vault_index,
+                label: vault.label,
+                vault: ctx.accounts.vault.key(),
+            }
+        };
+        msg!("金库地址: {}, 编号: {}", entry.vault, entry.vault_index);
+
+        register_vault_entry(
+            &mut ctx.accounts.vault_registry,
+            ctx.accounts.user.key(),
+            ctx.bumps.vault_registry,
+            entry,
+        )?;
+
+        msg!("金库登记完成!");
+        Ok(())
+    }
+
+    /// 将旧版 Borsh 布局的金库迁移为 zero-copy 布局
+    pub fn migrate_vault(ctx: Context<MigrateVault>) -> Result<()> {
+        msg!("开始迁移金库...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        msg!("支付者地址: {}", ctx.accounts.payer.key());
+
+        // 只有投资者或管理员可以迁移
+        require!(
+            ctx.accounts.payer.key() == ctx.accounts.user.key()
+                || ctx.accounts.payer.key() == ctx.accounts.global_config.admin,
+            ErrorCode::Unauthorized
+        );
+
+        let vault_info = ctx.accounts.vault.to_account_info();
+        require_keys_eq!(*vault_info.owner, crate::ID, ErrorCode::InvalidVaultAccount);
+
+        // 读取旧版数据（会校验旧版鉴别器，已迁移的金库在这里失败）
+        let legacy = {
+            let data = vault_info.try_borrow_data()?;
+            LegacyPersonalVault::try_deserialize(&mut &data[..])?
+        };
+        require!(legacy.is_initialized, ErrorCode::VaultNotInitialized);
+        require!(!legacy.is_locked, ErrorCode::ReentrantCall);
+        require_keys_eq!(legacy.investor, ctx.accounts.user.key(), ErrorCode::InvalidVaultAccount);
+
+        msg!("旧版金库代币数量: {}", legacy.balances.len());
+
+        // 扩容到新布局所需的大小，只补足新增空间的租金
+        let token_capacity = legacy.balances.len().max(VAULT_INITIAL_TOKEN_CAPACITY);
+        let current_len = vault_info.data_len();
+        let required_len = PersonalVault::space(token_capacity);
+        if required_len > current_len {
+            let rent = Rent::get()?;
+            let extra_rent = rent
+                .minimum_balance(required_len)
+                .saturating_sub(rent.minimum_balance(current_len));
+            msg!(
+                "扩容金库: {} -> {} 字节, 新增租金 {} lamports",
+                current_len,
+                required_len,
+                extra_rent
+            );
+            if extra_rent > 0 {
+                system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        system_program::Transfer {
+                            from: ctx.accounts.payer.to_account_info(),
+                            to: vault_info.clone(),
+                        },
+                    ),
+                    extra_rent,
+                )?;
+            }
+            vault_info.resize(required_len)?;
+        }
+
+        // 按新布局重写账户数据
+        {
+            let mut data = vault_info.try_borrow_mut_data()?;
+            data.fill(0);
+            data[..8].copy_from_slice(&PERSONAL_VAULT_DISCRIMINATOR);
+        }
+        {
+            let mut vault = load_vault_slots_mut(&vault_info)?;
+            vault.header.investor = legacy.investor;
+            vault.header.is_initialized = 1;
+            vault.header.is_locked = 0;
+            vault.header.bump = legacy.bump;
+            vault.header.layout_version = VAULT_LAYOUT_VERSION;
+            for balance in legacy.balances.iter() {
+                vault.push(balance.token, DEFAULT_BUCKET, balance.amount)?;
+            }
+        }
+
+        msg!("金库迁移完成!");
+
+        emit!(VaultMigratedEvent {
+            user: legacy.investor,
+            vault: vault_info.key(),
+            payer: ctx.accounts.payer.key(),
+            token_count: legacy.balances.len() as u16,
+            layout_version: VAULT_LAYOUT_VERSION,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// 账本对账：将直接转入金库的代币和 SOL 记入账本
+    pub fn reconcile_balances<'info>(
+        ctx: Context<'_, '_, '_, 'info, ReconcileBalances<'info>>,
+    ) -> Result<()> {
+        msg!("开始账本对账...");
+        msg!("调用者地址: {}", ctx.accounts.caller.key());
+        msg!("收到 {} 个代币账户", ctx.remaining_accounts.len());
+
+        // 🚨 全局暂停检查（对账把直接转入的资产记入账本，按存款处理）
+        ctx.accounts.global_config.check_not_paused(PAUSE_DEPOSITS)?;
+
+        let vault_key = ctx.accounts.vault.key();
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+            require!(!vault.is_locked(), ErrorCode::ReentrantCall);
+
+            // 只有投资者或管理员可以对账
+            require!(
+                ctx.accounts.caller.key() == vault.investor
+                    || ctx.accounts.caller.key() == ctx.accounts.global_config.admin,
+                ErrorCode::Unauthorized
+            );
+        }
+
+        // 原生 SOL：金库 PDA 上扣除租金后的 lamports
+        let available_lamports = vault_available_lamports(&ctx.accounts.vault.to_account_info())?;
+        credit_surplus(&ctx, NATIVE_SOL_MINT, vault_key, available_lamports)?;
+
+        // SPL 代币：金库的 ATA
+        for account in ctx.remaining_accounts.iter() {
+            let token_account = read_token_account(account)?;
+            require_keys_eq!(token_account.owner, vault_key, ErrorCode::InvalidTokenAccount);
+            require_keys_eq!(
+                account.key(),
+                get_associated_token_address_with_program_id(
+                    &vault_key,
+                    &token_account.mint,
+                    account.owner
+                ),
+                ErrorCode::InvalidTokenAccount
+            );
+
+            credit_surplus(&ctx, token_account.mint, account.key(), token_account.amount)?;
+        }
+
+        msg!("账本对账完成!");
+        Ok(())
+    }
+
+    /// 压缩金库：移除零余额条目、缩小账户并关闭空的金库代币账户
+    pub fn compact_vault<'info>(ctx: Context<'_, '_, '_, 'info, CompactVault<'info>>) -> Result<()> {
+        msg!("开始压缩金库...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        msg!("收到 {} 个待关闭的代币账户", ctx.remaining_accounts.len());
+
+        // 🚨 全局暂停检查（关闭代币账户并把租金转给投资者，按取款处理）
+        ctx.accounts.global_config.check_not_paused(PAUSE_WITHDRAWALS)?;
+
+        let vault_key = ctx.accounts.vault.key();
+        let vault_info = ctx.accounts.vault.to_account_info();
+        let user_info = ctx.accounts.user.to_account_info();
+        let timestamp = Clock::get()?.unix_timestamp;
+
+        // 移除零余额条目
+        let (investor, bump, removed_entries, token_count) = {
+            let mut vault = load_vault_mut(&ctx.accounts.vault)?;
+            require!(
+                ctx.accounts.user.key() == vault.header.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(vault.header.is_initialized(), ErrorCode::VaultNotInitialized);
+            require!(!vault.header.is_locked(), ErrorCode::ReentrantCall);
+
+            let removed_entries = vault.remove_zero_balances();
+            (
+                vault.header.investor,
+                vault.header.bump,
+                removed_entries,
+                vault.header.token_count,
+            )
+        };
+        msg!("移除零余额条目: {}, 剩余条目: {}", removed_entries, token_count);
+
+        // 缩小账户，多余的租金退还给投资者（金库中存入的 SOL 不受影响）
+        let old_size = vault_info.data_len();
+        let new_size = PersonalVault::space(token_count as usize);
+        let mut rent_reclaimed = 0;
+        if new_size < old_size {
+            let rent = Rent::get()?;
+            rent_reclaimed = rent
+                .minimum_balance(old_size)
+                .saturating_sub(rent.minimum_balance(new_size));
+            vault_info.resize(new_size)?;
+            **vault_info.try_borrow_mut_lamports()? -= rent_reclaimed;
+            **user_info.try_borrow_mut_lamports()? += rent_reclaimed;
+            msg!(
+                "缩小金库: {} -> {} 字节, 退还租金 {} lamports",
+                old_size,
+                new_size,
+                rent_reclaimed
+            );
+        }
+
+        // 关闭空的金库代币账户
+        let index_bytes = ctx.accounts.vault.load()?.vault_index.to_le_bytes();
+        let seeds = &[
+            b"vault".as_ref(),
+            investor.as_ref(),
+            vault_index_seed(&index_bytes),
+            &[bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+        for account in ctx.remaining_accounts.iter() {
+            let token_account = read_token_account(account)?;
+            require_keys_eq!(token_account.owner, vault_key, ErrorCode::InvalidTokenAccount);
+            require!(token_account.amount == 0, ErrorCode::TokenAccountNotEmpty);
+            require!(
+                get_token_total(&load_vault(&ctx.accounts.vault)?.balances, token_account.mint) == 0,
+                ErrorCode::TokenAccountNotEmpty
+            );
+
+            let account_rent = account.lamports();
+            let token_program = select_token_program(
+                account,
+                &ctx.accounts.token_program,
+                &ctx.accounts.token_2022_program,
+            );
+            token_interface::close_account(CpiContext::new_with_signer(
+                token_program,
+                token_interface::CloseAccount {
+                    account: account.clone(),
+                    destination: user_info.clone(),
+                    authority: vault_info.clone(),
+                },
+                signer_seeds,
+            ))?;
+            msg!("关闭代币账户: {} (mint: {})", account.key(), token_account.mint);
+
+            emit!(VaultTokenAccountClosedEvent {
+                user: investor,
+                vault: vault_key,
+                token_account: account.key(),
+                mint: token_account.mint,
+                rent_reclaimed: account_rent,
+                timestamp,
+            });
+        }
+
+        emit!(VaultCompactedEvent {
+            user: investor,
+            vault: vault_key,
+            removed_entries: removed_entries as u16,
+            token_count,
+            old_size: old_size as u64,
+            new_size: new_size as u64,
+            rent_reclaimed,
+            timestamp,
+        });
+
+        msg!("金库压缩完成!");
+        Ok(())
+    }
+
+    /// 关闭金库：将全部代币和 SOL 转回投资者，关闭金库代币账户和金库 PDA
+    pub fn close_vault<'info>(ctx: Context<'_, '_, '_, 'info, CloseVault<'info>>) -> Result<()> {
+        msg!("开始关闭金库...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+
+        // 🚨 全局暂停检查
+        ctx.accounts.global_config.check_not_paused(PAUSE_WITHDRAWALS)?;
+
+        // 挂单和流动性仓位归 DEX 程序所有，不在金库代币账户中，关闭时无法转回
+        require!(!DexRoute::any_leaves_open_positions(), ErrorCode::VaultHasOpenPositions);
+
+        let vault_key = ctx.accounts.vault.key();
+        let vault_info = ctx.accounts.vault.to_account_info();
+        let user_key = ctx.accounts.user.key();
+
+        let (investor, bump) = {
+            let vault = ctx.accounts.vault.load()?;
+            require!(user_key == vault.investor, ErrorCode::OnlyInvestor);
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+            // 执行中的交易会锁定金库，锁定期间不能关闭
+            require!(!vault.is_locked(), ErrorCode::ReentrantCall);
+            (vault.investor, vault.bump)
+        };
+
+        // 代币账户按 [金库代币账户, 投资者代币账户, mint] 一组传入；mint 带转账钩子时紧接着传入
+        // [列表中声明的额外账户..., ExtraAccountMetaList PDA, 钩子程序]
+        let index_bytes = ctx.accounts.vault.load()?.vault_index.to_le_bytes();
+        let seeds = &[
+            b"vault".as_ref(),
+            investor.as_ref(),
+            vault_index_seed(&index_bytes),
+            &[bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let mut swept_mints = Vec::new();
+        let mut token_accounts_closed: u16 = 0;
+        let mut remaining = ctx.remaining_accounts;
+        while !remaining.is_empty() {
+            require!(remaining.len() >= 3, ErrorCode::InsufficientAccounts);
+            let (vault_token_info, user_token_info, mint_info) = (&remaining[0], &remaining[1], &remaining[2]);
+            let hook_account_count = transfer_hook_account_count(mint_info, &remaining[3..])?;
+            let hook_accounts = &remaining[3..3 + hook_account_count];
+            remaining = &remaining[3 + hook_account_count..];
+
+            let vault_token_account = read_token_account(vault_token_info)?;
+            let user_token_account = read_token_account(user_token_info)?;
+            require_keys_eq!(vault_token_account.owner, vault_key, ErrorCode::InvalidTokenAccount);
+            require_keys_eq!(user_token_account.owner, user_key, ErrorCode::InvalidTokenAccount);
+            require_keys_eq!(
+                user_token_account.mint,
+                vault_token_account.mint,
+                ErrorCode::InvalidTokenMintAddress
+            );
+            require_keys_eq!(
+                mint_info.key(),
+                vault_token_account.mint,
+                ErrorCode::InvalidTokenMintAddress
+            );
+            require_keys_eq!(
+                *user_token_info.owner,
+                *vault_token_info.owner,
+                ErrorCode::InvalidTokenAccount
+            );
+            let mint = {
+                let data = mint_info.try_borrow_data()?;
+                token_interface::Mint::try_deserialize(&mut &data[..])?
+            };
+            let token_program = select_token_program(
+                vault_token_info,
+                &ctx.accounts.token_program,
+                &ctx.accounts.token_2022_program,
+            );
+
+            // 转回全部代币
+            if vault_token_account.amount > 0 {
+                transfer_checked_with_hook(
+                    token_program.key,
+                    vault_token_info.clone(),
+                    mint_info.clone(),
+                    user_token_info.clone(),
+                    vault_info.clone(),
+                    hook_accounts,
+                    vault_token_account.amount,
+                    mint.decimals,
+                    signer_seeds,
+                )?;
+            }
+
+            // 关闭金库代币账户，租金退还给投资者
+            token_interface::close_account(CpiContext::new_with_signer(
+                token_program,
+                token_interface::CloseAccount {
+                    account: vault_token_info.clone(),
+                    destination: ctx.accounts.user.to_account_info(),
+                    authority: vault_info.clone(),
+                },
+                signer_seeds,
+            ))?;
+            msg!(
+                "转回 {} (mint: {}) 并关闭代币账户 {}",
+                vault_token_account.amount,
+                vault_token_account.mint,
+                vault_token_info.key()
+            );
+
+            token_accounts_closed += 1;
+            if !swept_mints.contains(&vault_token_account.mint) {
+                swept_mints.push(vault_token_account.mint);
+            }
+        }
+
+        // 账本中所有非零代币都必须已经转回（原生 SOL 随金库关闭一并转回）
+        {
+            let vault = load_vault(&ctx.accounts.vault)?;
+            for balance in vault.balances.iter() {
+                if balance.amount > 0
+                    && balance.token != NATIVE_SOL_MINT
+                    && !swept_mints.contains(&balance.token)
+                {
+                    msg!("代币 {} 仍有余额 {}，未传入对应的代币账户", balance.token, balance.amount);
+                    return Err(ErrorCode::VaultNotEmpty.into());
+                }
+            }
+        }
+
+        // 从投资者的金库列表中移除
+        if let Some(registry) = ctx.accounts.vault_registry.as_mut() {
+            registry.vaults.retain(|entry| entry.vault != vault_key);
+        }
+
+        // 关闭附属 PDA，同一编号重建的金库从空白状态开始
+        let user_info = ctx.accounts.user.to_account_info();
+        for (name, account) in [
+            ("信号记录", ctx.accounts.signal_registry.to_account_info()),
+            ("交易策略", ctx.accounts.trading_policy.to_account_info()),
+            ("交易限额", ctx.accounts.trade_limits.to_account_info()),
+            ("执行者授权", ctx.accounts.vault_executors.to_account_info()),
+            ("交易历史", ctx.accounts.trade_history.to_account_info()),
+            ("金库快照", ctx.accounts.vault_snapshots.to_account_info()),
+        ] {
+            if close_program_account(&account, &user_info)? {
+                msg!("关闭{}账户 {}", name, account.key());
+            }
+        }
+
+        // 金库 PDA 的全部 lamports 由 Anchor 的 close 约束在指令结束时转给投资者
+        let sol_amount = vault_info.lamports();
+        msg!("金库 SOL（含租金）: {} lamports", sol_amount);
+
+        emit!(VaultClosedEvent {
+            user: investor,
+            vault: vault_key,
+            tokens_swept: swept_mints.len() as u16,
+            token_accounts_closed,
+            sol_amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("金库关闭完成!");
+        Ok(())
+    }
 
+    /// 用户存款
+    pub fn user_deposit<'info>(
+        ctx: Context<'_, '_, '_, 'info, UserDeposit<'info>>,
+        amount: u64,
+    ) -> Result<()> {
         msg!("开始用户存款操作...");
         msg!("用户地址: {}", ctx.accounts.user.key());
         msg!("代币地址: {}", ctx.accounts.mint.key());
         msg!("存款金额: {}", amount);
 
+        // 🚨 全局暂停检查
+        ctx.accounts.global_config.check_not_paused(PAUSE_DEPOSITS)?;
+
         // 验证调用者是投资者
-        require!(
-            ctx.accounts.user.key() == vault.investor,
-            ErrorCode::OnlyInvestor
-        );
-        require!(amount > 0, ErrorCode::InvalidAmount);
-        require!(vault.is_initialized, ErrorCode::VaultNotInitialized);
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(
+                ctx.accounts.user.key() == vault.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(amount > 0, ErrorCode::InvalidAmount);
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+        }
+
+        // Token-2022：拒绝不受支持的 mint 扩展
+        validate_mint_extensions(&ctx.accounts.mint.to_account_info())?;
 
         msg!("验证通过，检查用户代币余额...");
 
@@ -220,37 +1955,80 @@ pub mod instructions {
         msg!("用户余额足够，开始转移代币...");
 
         // 实际转移代币：从用户账户转移到金库账户
-        let transfer_instruction = Transfer {
-            from: ctx.accounts.user_token_account.to_account_info(),
-            to: ctx.accounts.vault_token_account.to_account_info(),
-            authority: ctx.accounts.user.to_account_info(),
-        };
-
-        let cpi_ctx = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            transfer_instruction,
-        );
+        // 转账钩子所需的额外账户通过 remaining_accounts 传入
+        let vault_amount_before = ctx.accounts.vault_token_account.amount;
+        transfer_checked_with_hook(
+            &ctx.accounts.token_program.key(),
+            ctx.accounts.user_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.vault_token_account.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            ctx.remaining_accounts,
+            amount,
+            ctx.accounts.mint.decimals,
+            &[],
+        )?;
 
-        token::transfer(cpi_ctx, amount)?;
+        // 按金库实际收到的数量记账（转账手续费扩展的代币会少于 amount）
+        ctx.accounts.vault_token_account.reload()?;
+        let received = ctx
+            .accounts
+            .vault_token_account
+            .amount
+            .checked_sub(vault_amount_before)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(received > 0, ErrorCode::InvalidAmount);
+        if received != amount {
+            msg!("ℹ️  扣除转账手续费后实际到账: {}", received);
+        }
 
         msg!("代币转移成功，更新内部余额记录...");
 
+        // 新代币需要预留金库空间（必要时扩容，由用户支付租金）
+        ensure_vault_capacity(
+            &ctx.accounts.vault,
+            ctx.accounts.mint.key(),
+            DEFAULT_BUCKET,
+            &ctx.accounts.global_config,
+            &ctx.accounts.user.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+
         // 更新内部余额记录
-        let current_balance = get_token_balance(vault, ctx.accounts.mint.key());
-        msg!("当前余额记录: {}", current_balance);
+        {
+            let mut vault = load_vault_mut(&ctx.accounts.vault)?;
+            let current_balance = get_token_balance(vault.balances(), ctx.accounts.mint.key());
+            msg!("当前余额记录: {}", current_balance);
+
+            let new_balance = current_balance.checked_add(received)
+                .ok_or(ErrorCode::MathOverflow)?;
+            set_token_balance(&mut vault, ctx.accounts.mint.key(), new_balance)?;
+            record_inflow(
+                &mut vault,
+                ctx.accounts.mint.key(),
+                DEFAULT_BUCKET,
+                received,
+                ctx.accounts.global_config.quote_mint,
+            )?;
+            msg!("更新后余额记录: {}", new_balance);
+        }
 
-        let new_balance = current_balance.checked_add(amount)
-            .ok_or(ErrorCode::MathOverflow)?;
-        set_token_balance(vault, ctx.accounts.mint.key(), new_balance);
-        msg!("更新后余额记录: {}", new_balance);
+        // 账本不变量检查
+        check_ledger_invariant(
+            &ctx.accounts.vault,
+            ctx.accounts.mint.key(),
+            DEFAULT_BUCKET,
+            ctx.accounts.vault_token_account.amount,
+        )?;
 
         emit!(UserDepositEvent {
             user: ctx.accounts.user.key(),
             token: ctx.accounts.mint.key(),
             asset_metadata: ctx.accounts.mint.key(), // 对应 Aptos 的 Object<Metadata
-            amount,
+            amount: received,
             timestamp: Clock::get()?.unix_timestamp,
             timestamp_microseconds: Clock::get()?.unix_timestamp as u64 * 1_000_000, // 转换为微秒
+            bucket: DEFAULT_BUCKET,
         });
 
         msg!("用户存款操作完成!");
@@ -267,16 +2045,19 @@ pub mod instructions {
             amount as f64 / LAMPORTS_PER_SOL as f64
         );
 
+        // 🚨 全局暂停检查
+        ctx.accounts.global_config.check_not_paused(PAUSE_DEPOSITS)?;
+
         // 验证调用者是投资者
-        require!(
-            ctx.accounts.user.key() == ctx.accounts.vault.investor,
-            ErrorCode::OnlyInvestor
-        );
-        require!(amount > 0, ErrorCode::InvalidAmount);
-        require!(
-            ctx.accounts.vault.is_initialized,
-            ErrorCode::VaultNotInitialized
-        );
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(
+                ctx.accounts.user.key() == vault.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(amount > 0, ErrorCode::InvalidAmount);
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+        }
 
         // 检查用户 SOL 余额是否足够（需要考虑租金豁免）
         let user_balance = ctx.accounts.user.to_account_info().lamports();
@@ -292,33 +2073,51 @@ pub mod instructions {
         msg!("验证通过，开始转移 SOL...");
 
         // 从用户账户转移 SOL 到金库账户
-        let user_key = ctx.accounts.user.key();
-        let vault_key = ctx.accounts.vault.key();
-        let transfer_instruction = system_instruction::transfer(&user_key, &vault_key, amount);
-
-        invoke(
-            &transfer_instruction,
-            &[
-                ctx.accounts.user.to_account_info(),
-                ctx.accounts.vault.to_account_info(),
+        system_program::transfer(
+            CpiContext::new(
                 ctx.accounts.system_program.to_account_info(),
-            ],
+                system_program::Transfer {
+                    from: ctx.accounts.user.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                },
+            ),
+            amount,
         )?;
 
         msg!("SOL 转移成功，更新内部余额记录...");
 
+        // 新代币需要预留金库空间（必要时扩容，由用户支付租金）
+        ensure_vault_capacity(
+            &ctx.accounts.vault,
+            NATIVE_SOL_MINT,
+            DEFAULT_BUCKET,
+            &ctx.accounts.global_config,
+            &ctx.accounts.user.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+
         // 更新内部余额记录（使用特殊的 NATIVE_SOL_MINT 标识）
-        let current_balance = get_token_balance(&ctx.accounts.vault, NATIVE_SOL_MINT);
-        msg!("当前 SOL 余额记录: {}", current_balance);
+        {
+            let mut vault = load_vault_mut(&ctx.accounts.vault)?;
+            let current_balance = get_token_balance(vault.balances(), NATIVE_SOL_MINT);
+            msg!("当前 SOL 余额记录: {}", current_balance);
+
+            let new_balance = current_balance.checked_add(amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+            set_token_balance(&mut vault, NATIVE_SOL_MINT, new_balance)?;
+            record_inflow(
+                &mut vault,
+                NATIVE_SOL_MINT,
+                DEFAULT_BUCKET,
+                amount,
+                ctx.accounts.global_config.quote_mint,
+            )?;
+            msg!("更新后 SOL 余额记录: {}", new_balance);
+        }
 
-        let new_balance = current_balance.checked_add(amount)
-            .ok_or(ErrorCode::MathOverflow)?;
-        set_token_balance(
-            &mut ctx.accounts.vault,
-            NATIVE_SOL_MINT,
-            new_balance,
-        );
-        msg!("更新后 SOL 余额记录: {}", new_balance);
+        // 账本不变量检查
+        let available_lamports = vault_available_lamports(&ctx.accounts.vault.to_account_info())?;
+        check_ledger_invariant(&ctx.accounts.vault, NATIVE_SOL_MINT, DEFAULT_BUCKET, available_lamports)?;
 
         emit!(UserDepositEvent {
             user: ctx.accounts.user.key(),
@@ -327,102 +2126,304 @@ pub mod instructions {
             amount,
             timestamp: Clock::get()?.unix_timestamp,
             timestamp_microseconds: Clock::get()?.unix_timestamp as u64 * 1_000_000,
+            bucket: DEFAULT_BUCKET,
         });
 
         msg!("用户 SOL 存款操作完成!");
         Ok(())
     }
 
-    /// 用户取款
-    pub fn user_withdraw(ctx: Context<UserWithdraw>, amount: u64) -> Result<()> {
-        let vault = &mut ctx.accounts.vault;
-
-        msg!("开始用户取款操作...");
-        msg!("用户地址: {}", ctx.accounts.user.key());
+    /// 代存款：出资人向指定投资者的金库存入代币（只有投资者可以取出）
+    pub fn deposit_for<'info>(
+        ctx: Context<'_, '_, '_, 'info, DepositFor<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        msg!("开始代存款操作...");
+        msg!("出资人地址: {}", ctx.accounts.funder.key());
+        msg!("受益投资者地址: {}", ctx.accounts.beneficiary.key());
         msg!("代币地址: {}", ctx.accounts.mint.key());
-        msg!("取款金额: {}", amount);
-
-        // 验证调用者是投资者
-        require!(
-            ctx.accounts.user.key() == vault.investor,
-            ErrorCode::OnlyInvestor
-        );
-        require!(amount > 0, ErrorCode::InvalidAmount);
-        require!(vault.is_initialized, ErrorCode::VaultNotInitialized);
-
-        // 🔒 重入保护：检查并设置锁定状态
-        require!(!vault.is_locked, ErrorCode::ReentrantCall);
-        vault.is_locked = true;
+        msg!("存款金额: {}", amount);
 
-        msg!("验证通过，检查内部余额记录...");
+        // 🚨 全局暂停检查
+        ctx.accounts.global_config.check_not_paused(PAUSE_DEPOSITS)?;
 
-        // 检查内部余额记录
-        let current_balance = get_token_balance(vault, ctx.accounts.mint.key());
-        msg!("当前余额记录: {}", current_balance);
-        msg!("需要取款金额: {}", amount);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+            require!(
+                ctx.accounts.beneficiary.key() == vault.investor,
+                ErrorCode::InvalidVaultAccount
+            );
+        }
 
-        require!(current_balance >= amount, ErrorCode::InsufficientBalance);
+        // 只接受投资者已持有或已允许的代币
+        require_deposit_for_mint(
+            &ctx.accounts.vault,
+            &ctx.accounts.trading_policy,
+            ctx.accounts.mint.key(),
+        )?;
 
-        msg!("检查金库代币账户实际余额...");
+        // Token-2022：拒绝不受支持的 mint 扩展
+        validate_mint_extensions(&ctx.accounts.mint.to_account_info())?;
 
-        // 检查金库代币账户的实际余额
         require!(
-            ctx.accounts.vault_token_account.amount >= amount,
+            ctx.accounts.funder_token_account.amount >= amount,
             ErrorCode::InsufficientBalance
         );
 
-        msg!("余额充足，先更新内部余额记录防止重入攻击...");
+        // 从出资人账户转移到金库账户（转账钩子账户通过 remaining_accounts 传入）
+        let vault_amount_before = ctx.accounts.vault_token_account.amount;
+        transfer_checked_with_hook(
+            &ctx.accounts.token_program.key(),
+            ctx.accounts.funder_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.vault_token_account.to_account_info(),
+            ctx.accounts.funder.to_account_info(),
+            ctx.remaining_accounts,
+            amount,
+            ctx.accounts.mint.decimals,
+            &[],
+        )?;
 
-        // 🔒 关键安全修复：先更新内部余额记录，防止重入攻击
-        // 这遵循 "Checks-Effects-Interactions" 模式
-        let new_balance = current_balance.checked_sub(amount)
-            .ok_or(ErrorCode::InsufficientBalance)?;
-        set_token_balance(vault, ctx.accounts.mint.key(), new_balance);
-        msg!("更新后余额记录: {}", new_balance);
+        // 按金库实际收到的数量记账
+        ctx.accounts.vault_token_account.reload()?;
+        let received = ctx
+            .accounts
+            .vault_token_account
+            .amount
+            .checked_sub(vault_amount_before)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(received > 0, ErrorCode::InvalidAmount);
+
+        // 新代币需要预留金库空间（必要时扩容，由出资人支付租金）
+        ensure_vault_capacity(
+            &ctx.accounts.vault,
+            ctx.accounts.mint.key(),
+            DEFAULT_BUCKET,
+            &ctx.accounts.global_config,
+            &ctx.accounts.funder.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+        let new_balance = credit_ledger(
+            &ctx.accounts.vault,
+            ctx.accounts.mint.key(),
+            received,
+            ctx.accounts.global_config.quote_mint,
+        )?;
+        msg!("实际到账: {}, 更新后余额记录: {}", received, new_balance);
+
+        // 账本不变量检查
+        check_ledger_invariant(
+            &ctx.accounts.vault,
+            ctx.accounts.mint.key(),
+            DEFAULT_BUCKET,
+            ctx.accounts.vault_token_account.amount,
+        )?;
 
-        msg!("开始转移代币...");
+        let timestamp = Clock::get()?.unix_timestamp;
+        emit!(DepositForEvent {
+            funder: ctx.accounts.funder.key(),
+            beneficiary: ctx.accounts.beneficiary.key(),
+            vault: ctx.accounts.vault.key(),
+            token: ctx.accounts.mint.key(),
+            amount: received,
+            timestamp,
+            timestamp_microseconds: timestamp as u64 * 1_000_000,
+            bucket: DEFAULT_BUCKET,
+        });
 
-        // 创建金库的签名种子
-        msg!("🔐 构建 PDA seeds:");
-        msg!("  - investor: {}", vault.investor);
-        msg!("  - bump: {}", vault.bump);
-        msg!("  - vault PDA 地址: {}", vault.key());
-        
-        let seeds = &[b"vault".as_ref(), vault.investor.as_ref(), &[vault.bump]];
-        let signer_seeds = &[&seeds[..]];
+        msg!("代存款操作完成!");
+        Ok(())
+    }
 
-        // 验证 PDA 是否正确
-        let (derived_vault, derived_bump) = Pubkey::find_program_address(
-            &[b"vault", vault.investor.as_ref()],
-            ctx.program_id
-        );
-        msg!("  - 派生的 vault 地址: {}", derived_vault);
-        msg!("  - 派生的 bump: {}", derived_bump);
-        
-        require!(
-            derived_vault == vault.key(),
-            ErrorCode::InvalidVaultPda
-        );
+    /// 代存 SOL：出资人向指定投资者的金库存入原生 SOL
+    pub fn deposit_sol_for(ctx: Context<DepositSolFor>, amount: u64) -> Result<()> {
+        msg!("开始代存 SOL 操作...");
+        msg!("出资人地址: {}", ctx.accounts.funder.key());
+        msg!("受益投资者地址: {}", ctx.accounts.beneficiary.key());
+        msg!("存款金额: {} lamports", amount);
 
-        // 实际转移代币：从金库账户转移到用户账户
-        let transfer_instruction = Transfer {
-            from: ctx.accounts.vault_token_account.to_account_info(),
-            to: ctx.accounts.user_token_account.to_account_info(),
-            authority: vault.to_account_info(),
-        };
+        // 🚨 全局暂停检查
+        ctx.accounts.global_config.check_not_paused(PAUSE_DEPOSITS)?;
 
-        let cpi_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            transfer_instruction,
-            signer_seeds,
-        );
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+            require!(
+                ctx.accounts.beneficiary.key() == vault.investor,
+                ErrorCode::InvalidVaultAccount
+            );
+        }
+
+        // 只接受投资者已持有或已允许的代币
+        require_deposit_for_mint(&ctx.accounts.vault, &ctx.accounts.trading_policy, NATIVE_SOL_MINT)?;
+
+        // 从出资人账户转移 SOL 到金库账户
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.funder.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        // 新代币需要预留金库空间（必要时扩容，由出资人支付租金）
+        ensure_vault_capacity(
+            &ctx.accounts.vault,
+            NATIVE_SOL_MINT,
+            DEFAULT_BUCKET,
+            &ctx.accounts.global_config,
+            &ctx.accounts.funder.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+        let new_balance = credit_ledger(
+            &ctx.accounts.vault,
+            NATIVE_SOL_MINT,
+            amount,
+            ctx.accounts.global_config.quote_mint,
+        )?;
+        msg!("更新后 SOL 余额记录: {}", new_balance);
+
+        // 账本不变量检查
+        let available_lamports = vault_available_lamports(&ctx.accounts.vault.to_account_info())?;
+        check_ledger_invariant(&ctx.accounts.vault, NATIVE_SOL_MINT, DEFAULT_BUCKET, available_lamports)?;
+
+        let timestamp = Clock::get()?.unix_timestamp;
+        emit!(DepositForEvent {
+            funder: ctx.accounts.funder.key(),
+            beneficiary: ctx.accounts.beneficiary.key(),
+            vault: ctx.accounts.vault.key(),
+            token: NATIVE_SOL_MINT,
+            amount,
+            timestamp,
+            timestamp_microseconds: timestamp as u64 * 1_000_000,
+            bucket: DEFAULT_BUCKET,
+        });
+
+        msg!("代存 SOL 操作完成!");
+        Ok(())
+    }
+
+    /// 用户取款
+    pub fn user_withdraw<'info>(
+        ctx: Context<'_, '_, '_, 'info, UserWithdraw<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        msg!("开始用户取款操作...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        msg!("代币地址: {}", ctx.accounts.mint.key());
+        msg!("取款金额: {}", amount);
+
+        // 🚨 全局暂停检查
+        ctx.accounts.global_config.check_not_paused(PAUSE_WITHDRAWALS)?;
+
+        // 🔒 关键安全修复：先更新内部余额记录，防止重入攻击
+        // 这遵循 "Checks-Effects-Interactions" 模式
+        let (investor, bump) = {
+            let mut vault = load_vault_mut(&ctx.accounts.vault)?;
+
+            // 验证调用者是投资者
+            require!(
+                ctx.accounts.user.key() == vault.header.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(amount > 0, ErrorCode::InvalidAmount);
+            require!(vault.header.is_initialized(), ErrorCode::VaultNotInitialized);
+
+            // 🔒 重入保护：检查并设置锁定状态
+            require!(!vault.header.is_locked(), ErrorCode::ReentrantCall);
+            vault.header.is_locked = 1;
+
+            msg!("验证通过，检查内部余额记录...");
+
+            // 检查内部余额记录
+            let current_balance = get_token_balance(vault.balances(), ctx.accounts.mint.key());
+            msg!("当前余额记录: {}", current_balance);
+            msg!("需要取款金额: {}", amount);
+
+            require!(current_balance >= amount, ErrorCode::InsufficientBalance);
+
+            msg!("检查金库代币账户实际余额...");
+
+            // 检查金库代币账户的实际余额
+            require!(
+                ctx.accounts.vault_token_account.amount >= amount,
+                ErrorCode::InsufficientBalance
+            );
+
+            msg!("余额充足，先更新内部余额记录防止重入攻击...");
+
+            let new_balance = current_balance.checked_sub(amount)
+                .ok_or(ErrorCode::InsufficientBalance)?;
+            set_token_balance(&mut vault, ctx.accounts.mint.key(), new_balance)?;
+            record_outflow(&mut vault, ctx.accounts.mint.key(), DEFAULT_BUCKET, amount)?;
+            msg!("更新后余额记录: {}", new_balance);
+
+            (vault.header.investor, vault.header.bump)
+        };
+
+        msg!("开始转移代币...");
+
+        // 创建金库的签名种子
+        msg!("🔐 构建 PDA seeds:");
+        msg!("  - investor: {}", investor);
+        msg!("  - bump: {}", bump);
+        msg!("  - vault PDA 地址: {}", ctx.accounts.vault.key());
+        
+        let index_bytes = ctx.accounts.vault.load()?.vault_index.to_le_bytes();
+        let seeds = &[
+            b"vault".as_ref(),
+            investor.as_ref(),
+            vault_index_seed(&index_bytes),
+            &[bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        // 验证 PDA 是否正确
+        let (derived_vault, derived_bump) = Pubkey::find_program_address(
+            &[b"vault", investor.as_ref(), vault_index_seed(&index_bytes)],
+            ctx.program_id
+        );
+        msg!("  - 派生的 vault 地址: {}", derived_vault);
+        msg!("  - 派生的 bump: {}", derived_bump);
+        
+        require!(
+            derived_vault == ctx.accounts.vault.key(),
+            ErrorCode::InvalidVaultPda
+        );
 
-        token::transfer(cpi_ctx, amount)?;
+        // 实际转移代币：从金库账户转移到用户账户
+        // 转账钩子所需的额外账户通过 remaining_accounts 传入
+        transfer_checked_with_hook(
+            &ctx.accounts.token_program.key(),
+            ctx.accounts.vault_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.user_token_account.to_account_info(),
+            ctx.accounts.vault.to_account_info(),
+            ctx.remaining_accounts,
+            amount,
+            ctx.accounts.mint.decimals,
+            signer_seeds,
+        )?;
 
         msg!("代币转移成功!");
 
         // 🔒 解锁重入保护
-        vault.is_locked = false;
+        ctx.accounts.vault.load_mut()?.is_locked = 0;
+
+        // 账本不变量检查
+        ctx.accounts.vault_token_account.reload()?;
+        check_ledger_invariant(
+            &ctx.accounts.vault,
+            ctx.accounts.mint.key(),
+            DEFAULT_BUCKET,
+            ctx.accounts.vault_token_account.amount,
+        )?;
 
         emit!(UserWithdrawEvent {
             user: ctx.accounts.user.key(),
@@ -431,6 +2432,8 @@ pub mod instructions {
             amount,
             timestamp: Clock::get()?.unix_timestamp,
             timestamp_microseconds: Clock::get()?.unix_timestamp as u64 * 1_000_000, // 转换为微秒
+            destination: ctx.accounts.user_token_account.key(),
+            bucket: DEFAULT_BUCKET,
         });
 
         msg!("用户取款操作完成!");
@@ -447,119 +2450,1089 @@ pub mod instructions {
             amount as f64 / LAMPORTS_PER_SOL as f64
         );
 
-        // 验证调用者是投资者
+        // 🚨 全局暂停检查
+        ctx.accounts.global_config.check_not_paused(PAUSE_WITHDRAWALS)?;
+
+        // 验证调用者是投资者
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(
+                ctx.accounts.user.key() == vault.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(amount > 0, ErrorCode::InvalidAmount);
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+        }
+
+        // 🔒 重入保护：检查并设置锁定状态
+        {
+            let mut vault = ctx.accounts.vault.load_mut()?;
+            require!(!vault.is_locked(), ErrorCode::ReentrantCall);
+            vault.is_locked = 1;
+        }
+
+        msg!("验证通过，检查内部 SOL 余额记录...");
+
+        // 检查内部余额记录
+        let current_balance = get_token_balance(&load_vault(&ctx.accounts.vault)?.balances, NATIVE_SOL_MINT);
+        msg!("当前 SOL 余额记录: {}", current_balance);
+        msg!("需要取款金额: {}", amount);
+
+        require!(current_balance >= amount, ErrorCode::InsufficientBalance);
+
+        msg!("检查金库账户实际 SOL 余额...");
+
+        // 检查金库账户的实际 SOL 余额
+        let vault_balance = ctx.accounts.vault.to_account_info().lamports();
+        let vault_rent_exempt =
+            Rent::get()?.minimum_balance(ctx.accounts.vault.to_account_info().data_len());
+
+        require!(
+            vault_balance >= amount + vault_rent_exempt,
+            ErrorCode::InsufficientBalance
+        );
+
+        msg!("金库 SOL 余额: {} lamports", vault_balance);
+        msg!("金库需要保留的最小余额: {} lamports", vault_rent_exempt);
+        msg!("余额充足，先更新内部余额记录防止重入攻击...");
+
+        // 🔒 关键安全修复：先更新内部余额记录，防止重入攻击
+        let new_balance = current_balance.checked_sub(amount)
+            .ok_or(ErrorCode::InsufficientBalance)?;
+        {
+            let mut vault = load_vault_mut(&ctx.accounts.vault)?;
+            set_token_balance(&mut vault, NATIVE_SOL_MINT, new_balance)?;
+            record_outflow(&mut vault, NATIVE_SOL_MINT, DEFAULT_BUCKET, amount)?;
+        }
+        msg!("更新后 SOL 余额记录: {}", new_balance);
+
+        msg!("开始转移 SOL...");
+
+        // 从金库账户转移 SOL 到用户账户
+        **ctx
+            .accounts
+            .vault
+            .to_account_info()
+            .try_borrow_mut_lamports()? -= amount;
+        **ctx
+            .accounts
+            .user
+            .to_account_info()
+            .try_borrow_mut_lamports()? += amount;
+
+        msg!("SOL 转移成功!");
+
+        // 🔒 解锁重入保护
+        ctx.accounts.vault.load_mut()?.is_locked = 0;
+
+        // 账本不变量检查
+        let available_lamports = vault_available_lamports(&ctx.accounts.vault.to_account_info())?;
+        check_ledger_invariant(&ctx.accounts.vault, NATIVE_SOL_MINT, DEFAULT_BUCKET, available_lamports)?;
+
+        emit!(UserWithdrawEvent {
+            user: ctx.accounts.user.key(),
+            token: NATIVE_SOL_MINT,
+            asset_metadata: NATIVE_SOL_MINT, // 使用特殊标识符表示原生 SOL
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+            timestamp_microseconds: Clock::get()?.unix_timestamp as u64 * 1_000_000,
+            destination: ctx.accounts.user.key(),
+            bucket: DEFAULT_BUCKET,
+        });
+
+        msg!("用户 SOL 取款操作完成!");
+        Ok(())
+    }
+
+    /// 取款到指定账户
+    /// 收款方的 ATA 不存在时由投资者支付创建
+    pub fn user_withdraw_to<'info>(
+        ctx: Context<'_, '_, '_, 'info, UserWithdrawTo<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        msg!("开始取款到指定账户...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        msg!("收款地址: {}", ctx.accounts.destination_owner.key());
+        msg!("代币地址: {}", ctx.accounts.mint.key());
+        msg!("取款金额: {}", amount);
+
+        // 🚨 全局暂停检查
+        ctx.accounts.global_config.check_not_paused(PAUSE_WITHDRAWALS)?;
+
+        // 🔒 先更新内部余额记录，防止重入攻击
+        let (investor, bump) = {
+            let mut vault = load_vault_mut(&ctx.accounts.vault)?;
+
+            require!(
+                ctx.accounts.user.key() == vault.header.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(amount > 0, ErrorCode::InvalidAmount);
+            require!(vault.header.is_initialized(), ErrorCode::VaultNotInitialized);
+
+            // 🔒 重入保护：检查并设置锁定状态
+            require!(!vault.header.is_locked(), ErrorCode::ReentrantCall);
+            vault.header.is_locked = 1;
+
+            let current_balance = get_token_balance(vault.balances(), ctx.accounts.mint.key());
+            msg!("当前余额记录: {}", current_balance);
+
+            require!(current_balance >= amount, ErrorCode::InsufficientBalance);
+            require!(
+                ctx.accounts.vault_token_account.amount >= amount,
+                ErrorCode::InsufficientBalance
+            );
+
+            let new_balance = current_balance.checked_sub(amount)
+                .ok_or(ErrorCode::InsufficientBalance)?;
+            set_token_balance(&mut vault, ctx.accounts.mint.key(), new_balance)?;
+            record_outflow(&mut vault, ctx.accounts.mint.key(), DEFAULT_BUCKET, amount)?;
+            msg!("更新后余额记录: {}", new_balance);
+
+            (vault.header.investor, vault.header.bump)
+        };
+
+        let index_bytes = ctx.accounts.vault.load()?.vault_index.to_le_bytes();
+        let seeds = &[
+            b"vault".as_ref(),
+            investor.as_ref(),
+            vault_index_seed(&index_bytes),
+            &[bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        // 从金库账户转移到收款账户，转账钩子所需的额外账户通过 remaining_accounts 传入
+        transfer_checked_with_hook(
+            &ctx.accounts.token_program.key(),
+            ctx.accounts.vault_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.destination_token_account.to_account_info(),
+            ctx.accounts.vault.to_account_info(),
+            ctx.remaining_accounts,
+            amount,
+            ctx.accounts.mint.decimals,
+            signer_seeds,
+        )?;
+
+        msg!("代币转移成功!");
+
+        // 🔒 解锁重入保护
+        ctx.accounts.vault.load_mut()?.is_locked = 0;
+
+        // 账本不变量检查
+        ctx.accounts.vault_token_account.reload()?;
+        check_ledger_invariant(
+            &ctx.accounts.vault,
+            ctx.accounts.mint.key(),
+            DEFAULT_BUCKET,
+            ctx.accounts.vault_token_account.amount,
+        )?;
+
+        emit!(UserWithdrawEvent {
+            user: ctx.accounts.user.key(),
+            token: ctx.accounts.mint.key(),
+            asset_metadata: ctx.accounts.mint.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+            timestamp_microseconds: Clock::get()?.unix_timestamp as u64 * 1_000_000,
+            destination: ctx.accounts.destination_token_account.key(),
+            bucket: DEFAULT_BUCKET,
+        });
+
+        msg!("取款到指定账户完成!");
+        Ok(())
+    }
+
+    /// 取出原生 SOL 到指定钱包
+    pub fn user_withdraw_sol_to(ctx: Context<UserWithdrawSolTo>, amount: u64) -> Result<()> {
+        msg!("开始取出 SOL 到指定钱包...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        msg!("收款地址: {}", ctx.accounts.destination.key());
+        msg!(
+            "取款金额: {} lamports ({} SOL)",
+            amount,
+            amount as f64 / LAMPORTS_PER_SOL as f64
+        );
+
+        // 🚨 全局暂停检查
+        ctx.accounts.global_config.check_not_paused(PAUSE_WITHDRAWALS)?;
+
+        {
+            let mut vault = load_vault_mut(&ctx.accounts.vault)?;
+
+            require!(
+                ctx.accounts.user.key() == vault.header.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(amount > 0, ErrorCode::InvalidAmount);
+            require!(vault.header.is_initialized(), ErrorCode::VaultNotInitialized);
+
+            // 🔒 重入保护：检查并设置锁定状态
+            require!(!vault.header.is_locked(), ErrorCode::ReentrantCall);
+            vault.header.is_locked = 1;
+
+            let current_balance = get_token_balance(vault.balances(), NATIVE_SOL_MINT);
+            msg!("当前 SOL 余额记录: {}", current_balance);
+            require!(current_balance >= amount, ErrorCode::InsufficientBalance);
+
+            let new_balance = current_balance.checked_sub(amount)
+                .ok_or(ErrorCode::InsufficientBalance)?;
+            set_token_balance(&mut vault, NATIVE_SOL_MINT, new_balance)?;
+            record_outflow(&mut vault, NATIVE_SOL_MINT, DEFAULT_BUCKET, amount)?;
+            msg!("更新后 SOL 余额记录: {}", new_balance);
+        }
+
+        // 金库必须保留租金豁免所需的最小余额
+        require!(
+            vault_available_lamports(&ctx.accounts.vault.to_account_info())? >= amount,
+            ErrorCode::InsufficientBalance
+        );
+
+        // 从金库账户转移 SOL 到收款钱包
+        **ctx
+            .accounts
+            .vault
+            .to_account_info()
+            .try_borrow_mut_lamports()? -= amount;
+        **ctx
+            .accounts
+            .destination
+            .to_account_info()
+            .try_borrow_mut_lamports()? += amount;
+
+        msg!("SOL 转移成功!");
+
+        // 🔒 解锁重入保护
+        ctx.accounts.vault.load_mut()?.is_locked = 0;
+
+        // 账本不变量检查
+        let available_lamports = vault_available_lamports(&ctx.accounts.vault.to_account_info())?;
+        check_ledger_invariant(&ctx.accounts.vault, NATIVE_SOL_MINT, DEFAULT_BUCKET, available_lamports)?;
+
+        emit!(UserWithdrawEvent {
+            user: ctx.accounts.user.key(),
+            token: NATIVE_SOL_MINT,
+            asset_metadata: NATIVE_SOL_MINT,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+            timestamp_microseconds: Clock::get()?.unix_timestamp as u64 * 1_000_000,
+            destination: ctx.accounts.destination.key(),
+            bucket: DEFAULT_BUCKET,
+        });
+
+        msg!("取出 SOL 到指定钱包完成!");
+        Ok(())
+    }
+
+    /// 批量存款：一次存入多种代币
+    /// 整个批次只加一次重入锁，每种代币发出一个存款事件
+    pub fn batch_deposit<'info>(
+        ctx: Context<'_, '_, '_, 'info, BatchDeposit<'info>>,
+        transfers: Vec<TokenTransfer>,
+    ) -> Result<()> {
+        msg!("开始批量存款操作...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        msg!("代币种类: {}", transfers.len());
+
+        // 🚨 全局暂停检查
+        ctx.accounts.global_config.check_not_paused(PAUSE_DEPOSITS)?;
+
+        let user_key = ctx.accounts.user.key();
+        let vault_key = ctx.accounts.vault.key();
+
+        {
+            let mut vault = ctx.accounts.vault.load_mut()?;
+            require!(user_key == vault.investor, ErrorCode::OnlyInvestor);
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+
+            // 🔒 重入保护：整个批次只加一次锁
+            require!(!vault.is_locked(), ErrorCode::ReentrantCall);
+            vault.is_locked = 1;
+        }
+
+        validate_batch_transfers(&transfers, ctx.remaining_accounts)?;
+
+        for (transfer, group) in transfers.iter().zip(ctx.remaining_accounts.chunks(3)) {
+            let (user_token_info, vault_token_info, mint_info) = (&group[0], &group[1], &group[2]);
+            msg!("存入 {}: {}", transfer.mint, transfer.amount);
+
+            let (token_program, decimals) = validate_batch_group(
+                group,
+                transfer.mint,
+                user_key,
+                vault_key,
+                &ctx.accounts.token_program,
+                &ctx.accounts.token_2022_program,
+            )?;
+            validate_mint_extensions(mint_info)?;
+
+            // 金库代币账户不存在时由用户支付创建
+            if vault_token_info.data_is_empty() {
+                associated_token::create_idempotent(CpiContext::new(
+                    ctx.accounts.associated_token_program.to_account_info(),
+                    associated_token::Create {
+                        payer: ctx.accounts.user.to_account_info(),
+                        associated_token: vault_token_info.clone(),
+                        authority: ctx.accounts.vault.to_account_info(),
+                        mint: mint_info.clone(),
+                        system_program: ctx.accounts.system_program.to_account_info(),
+                        token_program: token_program.clone(),
+                    },
+                ))?;
+                msg!("  创建金库代币账户: {}", vault_token_info.key());
+            }
+
+            require!(
+                read_token_account(user_token_info)?.amount >= transfer.amount,
+                ErrorCode::InsufficientBalance
+            );
+
+            let vault_amount_before = read_token_account(vault_token_info)?.amount;
+            transfer_checked_with_hook(
+                token_program.key,
+                user_token_info.clone(),
+                mint_info.clone(),
+                vault_token_info.clone(),
+                ctx.accounts.user.to_account_info(),
+                &[],
+                transfer.amount,
+                decimals,
+                &[],
+            )?;
+
+            // 按金库实际收到的数量记账（转账手续费扩展的代币会少于 amount）
+            let vault_amount_after = read_token_account(vault_token_info)?.amount;
+            let received = vault_amount_after
+                .checked_sub(vault_amount_before)
+                .ok_or(ErrorCode::MathOverflow)?;
+            require!(received > 0, ErrorCode::InvalidAmount);
+
+            ensure_vault_capacity(
+                &ctx.accounts.vault,
+                transfer.mint,
+                DEFAULT_BUCKET,
+                &ctx.accounts.global_config,
+                &ctx.accounts.user.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+            )?;
+            let new_balance = credit_ledger(
+                &ctx.accounts.vault,
+                transfer.mint,
+                received,
+                ctx.accounts.global_config.quote_mint,
+            )?;
+            msg!("  到账 {}，更新后余额记录: {}", received, new_balance);
+
+            // 账本不变量检查
+            check_ledger_invariant(&ctx.accounts.vault, transfer.mint, DEFAULT_BUCKET, vault_amount_after)?;
+
+            emit!(UserDepositEvent {
+                user: user_key,
+                token: transfer.mint,
+                asset_metadata: transfer.mint,
+                amount: received,
+                timestamp: Clock::get()?.unix_timestamp,
+                timestamp_microseconds: Clock::get()?.unix_timestamp as u64 * 1_000_000,
+                bucket: DEFAULT_BUCKET,
+            });
+        }
+
+        // 🔒 解锁重入保护
+        ctx.accounts.vault.load_mut()?.is_locked = 0;
+
+        msg!("批量存款操作完成!");
+        Ok(())
+    }
+
+    /// 批量取款：一次取出多种代币到投资者自己的 ATA
+    /// 整个批次只加一次重入锁，每种代币发出一个取款事件
+    pub fn batch_withdraw<'info>(
+        ctx: Context<'_, '_, '_, 'info, BatchWithdraw<'info>>,
+        transfers: Vec<TokenTransfer>,
+    ) -> Result<()> {
+        msg!("开始批量取款操作...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        msg!("代币种类: {}", transfers.len());
+
+        // 🚨 全局暂停检查
+        ctx.accounts.global_config.check_not_paused(PAUSE_WITHDRAWALS)?;
+
+        let user_key = ctx.accounts.user.key();
+        let vault_key = ctx.accounts.vault.key();
+        let vault_info = ctx.accounts.vault.to_account_info();
+
+        let (investor, bump) = {
+            let mut vault = ctx.accounts.vault.load_mut()?;
+            require!(user_key == vault.investor, ErrorCode::OnlyInvestor);
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+
+            // 🔒 重入保护：整个批次只加一次锁
+            require!(!vault.is_locked(), ErrorCode::ReentrantCall);
+            vault.is_locked = 1;
+
+            (vault.investor, vault.bump)
+        };
+
+        validate_batch_transfers(&transfers, ctx.remaining_accounts)?;
+
+        let index_bytes = ctx.accounts.vault.load()?.vault_index.to_le_bytes();
+        let seeds = &[
+            b"vault".as_ref(),
+            investor.as_ref(),
+            vault_index_seed(&index_bytes),
+            &[bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        for (transfer, group) in transfers.iter().zip(ctx.remaining_accounts.chunks(3)) {
+            let (user_token_info, vault_token_info, mint_info) = (&group[0], &group[1], &group[2]);
+            msg!("取出 {}: {}", transfer.mint, transfer.amount);
+
+            let (token_program, decimals) = validate_batch_group(
+                group,
+                transfer.mint,
+                user_key,
+                vault_key,
+                &ctx.accounts.token_program,
+                &ctx.accounts.token_2022_program,
+            )?;
+
+            require!(
+                read_token_account(vault_token_info)?.amount >= transfer.amount,
+                ErrorCode::InsufficientBalance
+            );
+
+            // 🔒 先更新内部余额记录，再转账
+            let new_balance = debit_ledger(&ctx.accounts.vault, transfer.mint, transfer.amount)?;
+            msg!("  更新后余额记录: {}", new_balance);
+
+            transfer_checked_with_hook(
+                token_program.key,
+                vault_token_info.clone(),
+                mint_info.clone(),
+                user_token_info.clone(),
+                vault_info.clone(),
+                &[],
+                transfer.amount,
+                decimals,
+                signer_seeds,
+            )?;
+
+            // 账本不变量检查
+            check_ledger_invariant(
+                &ctx.accounts.vault,
+                transfer.mint,
+                DEFAULT_BUCKET,
+                read_token_account(vault_token_info)?.amount,
+            )?;
+
+            emit!(UserWithdrawEvent {
+                user: user_key,
+                token: transfer.mint,
+                asset_metadata: transfer.mint,
+                amount: transfer.amount,
+                timestamp: Clock::get()?.unix_timestamp,
+                timestamp_microseconds: Clock::get()?.unix_timestamp as u64 * 1_000_000,
+                destination: user_token_info.key(),
+                bucket: DEFAULT_BUCKET,
+            });
+        }
+
+        // 🔒 解锁重入保护
+        ctx.accounts.vault.load_mut()?.is_locked = 0;
+
+        msg!("批量取款操作完成!");
+        Ok(())
+    }
+
+    /// 设置分仓名称（对应的策略）
+    pub fn set_bucket_label(ctx: Context<SetBucketLabel>, bucket: u8, label: String) -> Result<()> {
+        msg!("设置分仓名称...");
+        msg!("分仓: {}, 名称: {}", bucket, label);
+
+        validate_bucket(bucket)?;
+        require!(label.len() <= BUCKET_LABEL_LEN, ErrorCode::InvalidBucketLabel);
+
+        let mut vault = ctx.accounts.vault.load_mut()?;
+        require!(
+            ctx.accounts.user.key() == vault.investor,
+            ErrorCode::OnlyInvestor
+        );
+        require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+
+        let mut encoded = [0u8; BUCKET_LABEL_LEN];
+        encoded[..label.len()].copy_from_slice(label.as_bytes());
+        vault.bucket_labels[bucket as usize] = encoded;
+
+        msg!("分仓名称设置完成!");
+        Ok(())
+    }
+
+    /// 设置交易策略（投资者），各项为 0 或空列表时表示不限制
+    pub fn set_trading_policy(
+        ctx: Context<SetTradingPolicy>,
+        params: TradingPolicyParams,
+    ) -> Result<()> {
+        msg!("开始设置交易策略...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(
+                ctx.accounts.user.key() == vault.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+        }
+
+        let known_dex_routes = DexRoute::ALL
+            .iter()
+            .fold(0u8, |flags, route| flags | route.pause_flag());
+        require!(
+            params.allowed_mints.len() <= MAX_POLICY_MINTS
+                && params.max_amount_in_bps <= 10_000
+                && params.max_slippage_bps <= 10_000
+                && params.allowed_dex_routes & !known_dex_routes == 0,
+            ErrorCode::InvalidTradingPolicy
+        );
+        for (index, mint) in params.allowed_mints.iter().enumerate() {
+            require!(
+                !params.allowed_mints[..index].contains(mint),
+                ErrorCode::DuplicateAccountAddress
+            );
+        }
+
+        let policy = &mut ctx.accounts.trading_policy;
+        if policy.vault == Pubkey::default() {
+            // 首次设置，初始化策略账户
+            policy.vault = ctx.accounts.vault.key();
+            policy.bump = ctx.bumps.trading_policy;
+        }
+        policy.allowed_mints = params.allowed_mints;
+        policy.max_amount_in = params.max_amount_in;
+        policy.max_amount_in_bps = params.max_amount_in_bps;
+        policy.max_slippage_bps = params.max_slippage_bps;
+        policy.allowed_dex_routes = params.allowed_dex_routes;
+        policy.min_trade_interval_secs = params.min_trade_interval_secs;
+
+        msg!("允许的代币: {} 种", policy.allowed_mints.len());
+        msg!(
+            "单笔上限: {}, 余额比例上限: {} bps, 滑点上限: {} bps",
+            policy.max_amount_in,
+            policy.max_amount_in_bps,
+            policy.max_slippage_bps
+        );
+        msg!(
+            "允许的 DEX 适配器: {:#04x}, 最小交易间隔: {} 秒",
+            policy.allowed_dex_routes,
+            policy.min_trade_interval_secs
+        );
+
+        emit!(TradingPolicyUpdatedEvent {
+            user: ctx.accounts.user.key(),
+            vault: policy.vault,
+            allowed_mints: policy.allowed_mints.len() as u16,
+            max_amount_in: policy.max_amount_in,
+            max_amount_in_bps: policy.max_amount_in_bps,
+            max_slippage_bps: policy.max_slippage_bps,
+            allowed_dex_routes: policy.allowed_dex_routes,
+            min_trade_interval_secs: policy.min_trade_interval_secs,
+            removed: false,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("交易策略设置完成!");
+        Ok(())
+    }
+
+    /// 移除交易策略（投资者），之后的交易不再受策略限制
+    pub fn close_trading_policy(ctx: Context<CloseTradingPolicy>) -> Result<()> {
+        msg!("移除交易策略...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+
+        require!(
+            ctx.accounts.user.key() == ctx.accounts.vault.load()?.investor,
+            ErrorCode::OnlyInvestor
+        );
+
+        emit!(TradingPolicyUpdatedEvent {
+            user: ctx.accounts.user.key(),
+            vault: ctx.accounts.vault.key(),
+            allowed_mints: 0,
+            max_amount_in: 0,
+            max_amount_in_bps: 0,
+            max_slippage_bps: 0,
+            allowed_dex_routes: 0,
+            min_trade_interval_secs: 0,
+            removed: true,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("交易策略已移除");
+        Ok(())
+    }
+
+    /// 授权执行者（投资者），已授权的执行者更新过期时间
+    /// expires_at 为 None 时永不过期
+    pub fn authorize_vault_executor(
+        ctx: Context<ManageVaultExecutors>,
+        executor: Pubkey,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        msg!("开始授权执行者...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        msg!("执行者地址: {}", executor);
+
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(
+                ctx.accounts.user.key() == vault.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+        }
+        require!(executor != Pubkey::default(), ErrorCode::InvalidAccountAddressFormat);
+
+        let now = Clock::get()?.unix_timestamp;
+        if let Some(expires_at) = expires_at {
+            msg!("授权过期时间: {}", expires_at);
+            require!(expires_at > now, ErrorCode::InvalidExecutorExpiry);
+        }
+        let expires_at = expires_at.unwrap_or(0);
+
+        let vault_key = ctx.accounts.vault.key();
+        let vault_executors = &mut ctx.accounts.vault_executors;
+        init_vault_executors_if_needed(vault_executors, vault_key, ctx.bumps.vault_executors);
+
+        match vault_executors
+            .executors
+            .iter_mut()
+            .find(|entry| entry.executor == executor)
+        {
+            Some(entry) => {
+                msg!("执行者已授权，更新过期时间");
+                entry.expires_at = expires_at;
+            }
+            None => {
+                require!(
+                    vault_executors.executors.len() < MAX_VAULT_EXECUTORS,
+                    ErrorCode::TooManyVaultExecutors
+                );
+                vault_executors.executors.push(VaultExecutorEntry {
+                    executor,
+                    expires_at,
+                });
+            }
+        }
+
+        emit!(VaultExecutorUpdatedEvent {
+            user: ctx.accounts.user.key(),
+            vault: vault_key,
+            executor,
+            expires_at,
+            authorized: true,
+            timestamp: now,
+        });
+
+        msg!("执行者授权完成! 当前授权数量: {}", vault_executors.executors.len());
+        Ok(())
+    }
+
+    /// 撤销执行者授权（投资者）
+    pub fn revoke_vault_executor(ctx: Context<RevokeVaultExecutor>, executor: Pubkey) -> Result<()> {
+        msg!("开始撤销执行者授权...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        msg!("执行者地址: {}", executor);
+
+        require!(
+            ctx.accounts.user.key() == ctx.accounts.vault.load()?.investor,
+            ErrorCode::OnlyInvestor
+        );
+
+        let vault_executors = &mut ctx.accounts.vault_executors;
+        let position = vault_executors
+            .executors
+            .iter()
+            .position(|entry| entry.executor == executor)
+            .ok_or(ErrorCode::ExecutorNotFound)?;
+        vault_executors.executors.remove(position);
+
+        emit!(VaultExecutorUpdatedEvent {
+            user: ctx.accounts.user.key(),
+            vault: ctx.accounts.vault.key(),
+            executor,
+            expires_at: 0,
+            authorized: false,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("执行者授权已撤销! 当前授权数量: {}", vault_executors.executors.len());
+        Ok(())
+    }
+
+    /// 设置是否接受全局机器人和管理员执行交易（投资者）
+    /// 设为 false 后只有金库授权的执行者可以交易
+    pub fn set_executor_preference(
+        ctx: Context<ManageVaultExecutors>,
+        allow_global_executors: bool,
+    ) -> Result<()> {
+        msg!("开始设置执行者偏好...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        msg!("接受全局机器人和管理员: {}", allow_global_executors);
+
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(
+                ctx.accounts.user.key() == vault.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+        }
+
+        let vault_key = ctx.accounts.vault.key();
+        let vault_executors = &mut ctx.accounts.vault_executors;
+        init_vault_executors_if_needed(vault_executors, vault_key, ctx.bumps.vault_executors);
+        vault_executors.allow_global_executors = allow_global_executors;
+
+        if !allow_global_executors && vault_executors.executors.is_empty() {
+            msg!("金库没有授权的执行者，交易信号将全部被拒绝");
+        }
+
+        emit!(ExecutorPreferenceUpdatedEvent {
+            user: ctx.accounts.user.key(),
+            vault: vault_key,
+            allow_global_executors,
+            authorized_executors: vault_executors.executors.len() as u16,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("执行者偏好设置完成!");
+        Ok(())
+    }
+
+    /// 删除执行者授权账户（投资者），之后只接受全局机器人和管理员
+    pub fn close_vault_executors(ctx: Context<CloseVaultExecutors>) -> Result<()> {
+        msg!("删除执行者授权...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+
+        require!(
+            ctx.accounts.user.key() == ctx.accounts.vault.load()?.investor,
+            ErrorCode::OnlyInvestor
+        );
+
+        emit!(ExecutorPreferenceUpdatedEvent {
+            user: ctx.accounts.user.key(),
+            vault: ctx.accounts.vault.key(),
+            allow_global_executors: true,
+            authorized_executors: 0,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("执行者授权已删除");
+        Ok(())
+    }
+
+    /// 设置 24 小时交易限额（投资者），上限为 0 时表示不限制
+    /// 更换计价代币时清空已统计的交易额
+    pub fn set_trade_limits(
+        ctx: Context<SetTradeLimits>,
+        quote_mint: Pubkey,
+        max_daily_trades: u32,
+        max_daily_volume: u64,
+        unpriced_trade_policy: UnpricedTradePolicy,
+    ) -> Result<()> {
+        msg!("开始设置交易限额...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(
+                ctx.accounts.user.key() == vault.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+        }
+
+        require!(
+            max_daily_volume == 0 || quote_mint != Pubkey::default(),
+            ErrorCode::InvalidTradeLimits
+        );
+
+        let limits = &mut ctx.accounts.trade_limits;
+        if limits.vault == Pubkey::default() {
+            // 首次设置，初始化限额账户
+            limits.vault = ctx.accounts.vault.key();
+            limits.bump = ctx.bumps.trade_limits;
+        }
+        if limits.quote_mint != quote_mint {
+            // 旧的交易额按其他代币计价，不能继续累计
+            for segment in limits.segments.iter_mut() {
+                segment.volume = 0;
+            }
+            limits.quote_mint = quote_mint;
+        }
+        limits.max_daily_trades = max_daily_trades;
+        limits.max_daily_volume = max_daily_volume;
+        limits.unpriced_trade_policy = unpriced_trade_policy;
+
+        msg!(
+            "计价代币: {}, 24 小时交易次数上限: {}, 交易额上限: {}, 未计价交易: {:?}",
+            limits.quote_mint,
+            limits.max_daily_trades,
+            limits.max_daily_volume,
+            limits.unpriced_trade_policy
+        );
+
+        emit!(TradeLimitsUpdatedEvent {
+            user: ctx.accounts.user.key(),
+            vault: limits.vault,
+            quote_mint: limits.quote_mint,
+            max_daily_trades,
+            max_daily_volume,
+            unpriced_trade_policy,
+            removed: false,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("交易限额设置完成!");
+        Ok(())
+    }
+
+    /// 移除交易限额（投资者），已统计的交易记录一并删除
+    pub fn close_trade_limits(ctx: Context<CloseTradeLimits>) -> Result<()> {
+        msg!("移除交易限额...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+
         require!(
-            ctx.accounts.user.key() == ctx.accounts.vault.investor,
+            ctx.accounts.user.key() == ctx.accounts.vault.load()?.investor,
             ErrorCode::OnlyInvestor
         );
-        require!(amount > 0, ErrorCode::InvalidAmount);
-        require!(
-            ctx.accounts.vault.is_initialized,
-            ErrorCode::VaultNotInitialized
-        );
-
-        // 🔒 重入保护：检查并设置锁定状态
-        require!(!ctx.accounts.vault.is_locked, ErrorCode::ReentrantCall);
-        ctx.accounts.vault.is_locked = true;
 
-        msg!("验证通过，检查内部 SOL 余额记录...");
+        emit!(TradeLimitsUpdatedEvent {
+            user: ctx.accounts.user.key(),
+            vault: ctx.accounts.vault.key(),
+            quote_mint: ctx.accounts.trade_limits.quote_mint,
+            max_daily_trades: 0,
+            max_daily_volume: 0,
+            unpriced_trade_policy: ctx.accounts.trade_limits.unpriced_trade_policy,
+            removed: true,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-        // 检查内部余额记录
-        let current_balance = get_token_balance(&ctx.accounts.vault, NATIVE_SOL_MINT);
-        msg!("当前 SOL 余额记录: {}", current_balance);
-        msg!("需要取款金额: {}", amount);
+        msg!("交易限额已移除");
+        Ok(())
+    }
 
-        require!(current_balance >= amount, ErrorCode::InsufficientBalance);
+    /// 查询 24 小时交易限额的已用和剩余额度
+    pub fn get_trade_allowance(ctx: Context<GetTradeAllowance>) -> Result<TradeAllowance> {
+        msg!("查询交易限额...");
+
+        let limits = &ctx.accounts.trade_limits;
+        let (trades_used, volume_used) = limits.usage(Clock::get()?.unix_timestamp);
+        let allowance = TradeAllowance {
+            quote_mint: limits.quote_mint,
+            max_daily_trades: limits.max_daily_trades,
+            trades_used,
+            trades_remaining: if limits.max_daily_trades == 0 {
+                u32::MAX
+            } else {
+                limits.max_daily_trades.saturating_sub(trades_used)
+            },
+            max_daily_volume: limits.max_daily_volume,
+            volume_used,
+            volume_remaining: if limits.max_daily_volume == 0 {
+                u64::MAX
+            } else {
+                limits.max_daily_volume.saturating_sub(volume_used)
+            },
+        };
+        msg!(
+            "24 小时内已交易 {} 次（剩余 {}），交易额 {}（剩余 {}）",
+            allowance.trades_used,
+            allowance.trades_remaining,
+            allowance.volume_used,
+            allowance.volume_remaining
+        );
 
-        msg!("检查金库账户实际 SOL 余额...");
+        Ok(allowance)
+    }
 
-        // 检查金库账户的实际 SOL 余额
-        let vault_balance = ctx.accounts.vault.to_account_info().lamports();
-        let vault_rent_exempt =
-            Rent::get()?.minimum_balance(ctx.accounts.vault.to_account_info().data_len());
+    /// 暂停金库交易（投资者），存取款不受影响
+    pub fn pause_trading(ctx: Context<PauseTrading>) -> Result<()> {
+        msg!("暂停金库交易...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        set_trading_paused(&ctx.accounts.vault, ctx.accounts.user.key(), true)?;
+        msg!("⏸️ 金库交易已暂停");
+        Ok(())
+    }
 
-        require!(
-            vault_balance >= amount + vault_rent_exempt,
-            ErrorCode::InsufficientBalance
-        );
+    /// 恢复金库交易（投资者）
+    pub fn resume_trading(ctx: Context<ResumeTrading>) -> Result<()> {
+        msg!("恢复金库交易...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        set_trading_paused(&ctx.accounts.vault, ctx.accounts.user.key(), false)?;
+        msg!("▶️ 金库交易已恢复");
+        Ok(())
+    }
 
-        msg!("金库 SOL 余额: {} lamports", vault_balance);
-        msg!("金库需要保留的最小余额: {} lamports", vault_rent_exempt);
-        msg!("余额充足，先更新内部余额记录防止重入攻击...");
+    /// 在分仓之间划转余额（只改账本，不移动代币）
+    pub fn move_between_buckets(
+        ctx: Context<MoveBetweenBuckets>,
+        token: Pubkey,
+        from_bucket: u8,
+        to_bucket: u8,
+        amount: u64,
+    ) -> Result<()> {
+        msg!("开始分仓划转...");
+        msg!("代币: {}, {} -> {}, 数量: {}", token, from_bucket, to_bucket, amount);
 
-        // 🔒 关键安全修复：先更新内部余额记录，防止重入攻击
-        let new_balance = current_balance.checked_sub(amount)
-            .ok_or(ErrorCode::InsufficientBalance)?;
-        set_token_balance(
-            &mut ctx.accounts.vault,
-            NATIVE_SOL_MINT,
-            new_balance,
-        );
-        msg!("更新后 SOL 余额记录: {}", new_balance);
+        // 🚨 全局暂停检查（分仓划转按交易处理）
+        ctx.accounts.global_config.check_not_paused(PAUSE_TRADING)?;
 
-        msg!("开始转移 SOL...");
+        validate_bucket(from_bucket)?;
+        validate_bucket(to_bucket)?;
+        require!(from_bucket != to_bucket, ErrorCode::InvalidBucket);
+        require!(amount > 0, ErrorCode::InvalidAmount);
 
-        // 从金库账户转移 SOL 到用户账户
-        **ctx
-            .accounts
-            .vault
-            .to_account_info()
-            .try_borrow_mut_lamports()? -= amount;
-        **ctx
-            .accounts
-            .user
-            .to_account_info()
-            .try_borrow_mut_lamports()? += amount;
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(
+                ctx.accounts.user.key() == vault.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+            // 执行中的交易会锁定金库，锁定期间不能划转
+            require!(!vault.is_locked(), ErrorCode::ReentrantCall);
+        }
 
-        msg!("SOL 转移成功!");
+        // 目标分仓可能是新条目（必要时扩容，由投资者支付租金）
+        ensure_vault_capacity(
+            &ctx.accounts.vault,
+            token,
+            to_bucket,
+            &ctx.accounts.global_config,
+            &ctx.accounts.user.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
 
-        // 🔒 解锁重入保护
-        ctx.accounts.vault.is_locked = false;
+        let investor = {
+            let mut vault = load_vault_mut(&ctx.accounts.vault)?;
+            let from_balance_before = get_bucket_balance(vault.balances(), token, from_bucket);
+            let from_balance = from_balance_before
+                .checked_sub(amount)
+                .ok_or(ErrorCode::InsufficientBalance)?;
+            let to_balance = get_bucket_balance(vault.balances(), token, to_bucket)
+                .checked_add(amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+            set_bucket_balance(&mut vault, token, from_bucket, from_balance)?;
+            set_bucket_balance(&mut vault, token, to_bucket, to_balance)?;
+            // 成本随数量按比例转入目标分仓
+            move_cost(&mut vault, token, from_bucket, token, to_bucket, amount, from_balance_before)?;
+            msg!("划转后余额: 分仓 {} = {}, 分仓 {} = {}", from_bucket, from_balance, to_bucket, to_balance);
+            vault.header.investor
+        };
 
-        emit!(UserWithdrawEvent {
-            user: ctx.accounts.user.key(),
-            token: NATIVE_SOL_MINT,
-            asset_metadata: NATIVE_SOL_MINT, // 使用特殊标识符表示原生 SOL
+        emit!(BucketTransferEvent {
+            user: investor,
+            vault: ctx.accounts.vault.key(),
+            token,
+            from_bucket,
+            to_bucket,
             amount,
             timestamp: Clock::get()?.unix_timestamp,
-            timestamp_microseconds: Clock::get()?.unix_timestamp as u64 * 1_000_000,
         });
 
-        msg!("用户 SOL 取款操作完成!");
+        msg!("分仓划转完成!");
         Ok(())
     }
 
     /// 获取代币余额
     pub fn get_balance(ctx: Context<GetBalance>, token: Pubkey) -> Result<u64> {
-        let vault = &ctx.accounts.vault;
         msg!("查询代币余额...");
         msg!("代币地址: {}", token);
 
-        let balance = get_token_balance(vault, token);
+        // 返回所有分仓的合计
+        let balance = get_token_total(&load_vault(&ctx.accounts.vault)?.balances, token);
         msg!("查询到的余额: {}", balance);
 
         Ok(balance)
     }
 
+    /// 查询持仓：累计存取、持仓成本、平均成本和已实现盈亏
+    pub fn get_position(ctx: Context<GetPosition>, token: Pubkey, bucket: u8) -> Result<PositionInfo> {
+        msg!("查询持仓...");
+        msg!("代币地址: {}, 分仓: {}", token, bucket);
+
+        validate_bucket(bucket)?;
+
+        let vault = load_vault(&ctx.accounts.vault)?;
+        let entry = vault
+            .balances
+            .iter()
+            .find(|balance| balance.token == token && balance.bucket == bucket)
+            .copied()
+            .unwrap_or_else(bytemuck::Zeroable::zeroed);
+
+        let average_cost = if entry.amount == 0 {
+            0
+        } else {
+            u64::try_from(entry.cost_basis as u128 * AVERAGE_COST_SCALE / entry.amount as u128)
+                .map_err(|_| ErrorCode::MathOverflow)?
+        };
+
+        let position = PositionInfo {
+            token,
+            bucket,
+            quote_mint: ctx.accounts.global_config.quote_mint,
+            amount: entry.amount,
+            total_deposited: entry.total_deposited,
+            total_withdrawn: entry.total_withdrawn,
+            cost_basis: entry.cost_basis,
+            average_cost,
+            realized_pnl: entry.realized_pnl,
+            basis_unknown: entry.has_unknown_basis(),
+        };
+        msg!(
+            "余额: {}, 成本: {}, 平均成本: {}, 已实现盈亏: {}, 成本未知: {}",
+            position.amount,
+            position.cost_basis,
+            position.average_cost,
+            position.realized_pnl,
+            position.basis_unknown
+        );
+
+        Ok(position)
+    }
+
     /// 将 SOL 包装为 WSOL
     pub fn wrap_sol(ctx: Context<WrapSol>, amount: u64) -> Result<()> {
         msg!("开始 SOL 包装操作...");
         msg!("用户地址: {}", ctx.accounts.user.key());
         msg!("包装金额: {} lamports", amount);
 
+        // 🚨 全局暂停检查（SOL 与 WSOL 之间的转换按交易处理）
+        ctx.accounts.global_config.check_not_paused(PAUSE_TRADING)?;
+
         // 验证调用者是投资者
-        require!(
-            ctx.accounts.user.key() == ctx.accounts.vault.investor,
-            ErrorCode::OnlyInvestor
-        );
-        require!(amount > 0, ErrorCode::InvalidAmount);
-        require!(
-            ctx.accounts.vault.is_initialized,
-            ErrorCode::VaultNotInitialized
-        );
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(
+                ctx.accounts.user.key() == vault.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(amount > 0, ErrorCode::InvalidAmount);
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+        }
+
+        // 🔒 重入保护：检查并设置锁定状态
+        {
+            let mut vault = ctx.accounts.vault.load_mut()?;
+            require!(!vault.is_locked(), ErrorCode::ReentrantCall);
+            vault.is_locked = 1;
+        }
 
         // 检查 SOL 余额是否足够
-        let sol_balance = get_token_balance(&ctx.accounts.vault, NATIVE_SOL_MINT);
+        let sol_balance = get_token_balance(&load_vault(&ctx.accounts.vault)?.balances, NATIVE_SOL_MINT);
         require!(sol_balance >= amount, ErrorCode::InsufficientBalance);
 
         // 检查金库实际 SOL 余额
@@ -574,26 +3547,17 @@ pub mod instructions {
 
         msg!("验证通过，开始包装 SOL...");
 
-        // 创建金库的签名种子
-        let vault_investor = ctx.accounts.vault.investor;
-        let vault_bump = ctx.accounts.vault.bump;
-        let seeds = &[b"vault".as_ref(), vault_investor.as_ref(), &[vault_bump]];
-        let _signer_seeds = &[&seeds[..]];
-
-        // 从金库账户转移 SOL 到 WSOL 账户
-        let vault_key = ctx.accounts.vault.key();
-        let wsol_key = ctx.accounts.wsol_account.key();
-        let transfer_to_wsol_instruction =
-            system_instruction::transfer(&vault_key, &wsol_key, amount);
-
-        invoke(
-            &transfer_to_wsol_instruction,
-            &[
-                ctx.accounts.vault.to_account_info(),
-                ctx.accounts.wsol_account.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-            ],
-        )?;
+        // 从金库账户转移 SOL 到 WSOL 账户（金库归本程序所有，直接划转 lamports）
+        **ctx
+            .accounts
+            .vault
+            .to_account_info()
+            .try_borrow_mut_lamports()? -= amount;
+        **ctx
+            .accounts
+            .wsol_account
+            .to_account_info()
+            .try_borrow_mut_lamports()? += amount;
 
         // 同步 WSOL 账户
         let token_program_key = ctx.accounts.token_program.key();
@@ -613,14 +3577,34 @@ pub mod instructions {
 
         msg!("SOL 包装成功，更新余额记录...");
 
+        // WSOL 条目可能是新增的（必要时扩容，由用户支付租金）
+        ensure_vault_capacity(
+            &ctx.accounts.vault,
+            WSOL_MINT,
+            DEFAULT_BUCKET,
+            &ctx.accounts.global_config,
+            &ctx.accounts.user.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+
         // 更新内部余额记录
-        set_token_balance(
-            &mut ctx.accounts.vault,
-            NATIVE_SOL_MINT,
-            sol_balance - amount,
-        );
-        let wsol_balance = get_token_balance(&ctx.accounts.vault, WSOL_MINT);
-        set_token_balance(&mut ctx.accounts.vault, WSOL_MINT, wsol_balance + amount);
+        {
+            let mut vault = load_vault_mut(&ctx.accounts.vault)?;
+            set_token_balance(&mut vault, NATIVE_SOL_MINT, sol_balance - amount)?;
+            let wsol_balance = get_token_balance(vault.balances(), WSOL_MINT);
+            set_token_balance(&mut vault, WSOL_MINT, wsol_balance + amount)?;
+            // 包装不改变持仓价值，成本随数量转入 WSOL
+            move_cost(&mut vault, NATIVE_SOL_MINT, DEFAULT_BUCKET, WSOL_MINT, DEFAULT_BUCKET, amount, sol_balance)?;
+        }
+
+        // 🔒 解锁重入保护
+        ctx.accounts.vault.load_mut()?.is_locked = 0;
+
+        // 账本不变量检查
+        ctx.accounts.wsol_account.reload()?;
+        check_ledger_invariant(&ctx.accounts.vault, WSOL_MINT, DEFAULT_BUCKET, ctx.accounts.wsol_account.amount)?;
+        let available_lamports = vault_available_lamports(&ctx.accounts.vault.to_account_info())?;
+        check_ledger_invariant(&ctx.accounts.vault, NATIVE_SOL_MINT, DEFAULT_BUCKET, available_lamports)?;
 
         msg!("SOL 包装操作完成!");
         Ok(())
@@ -632,36 +3616,59 @@ pub mod instructions {
         msg!("用户地址: {}", ctx.accounts.user.key());
         msg!("解包装金额: {} lamports", amount);
 
+        // 🚨 全局暂停检查（SOL 与 WSOL 之间的转换按交易处理）
+        ctx.accounts.global_config.check_not_paused(PAUSE_TRADING)?;
+
         // 验证调用者是投资者
-        require!(
-            ctx.accounts.user.key() == ctx.accounts.vault.investor,
-            ErrorCode::OnlyInvestor
-        );
-        require!(amount > 0, ErrorCode::InvalidAmount);
-        require!(
-            ctx.accounts.vault.is_initialized,
-            ErrorCode::VaultNotInitialized
-        );
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(
+                ctx.accounts.user.key() == vault.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(amount > 0, ErrorCode::InvalidAmount);
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+        }
+
+        // 🔒 重入保护：检查并设置锁定状态
+        {
+            let mut vault = ctx.accounts.vault.load_mut()?;
+            require!(!vault.is_locked(), ErrorCode::ReentrantCall);
+            vault.is_locked = 1;
+        }
 
         // 检查 WSOL 余额是否足够
-        let wsol_balance = get_token_balance(&ctx.accounts.vault, WSOL_MINT);
+        let wsol_balance = get_token_balance(&load_vault(&ctx.accounts.vault)?.balances, WSOL_MINT);
         require!(wsol_balance >= amount, ErrorCode::InsufficientBalance);
 
         // 检查 WSOL 账户实际余额
-        require!(
-            ctx.accounts.wsol_account.amount >= amount,
-            ErrorCode::InsufficientBalance
-        );
+        let wsol_held = ctx.accounts.wsol_account.amount;
+        require!(wsol_held >= amount, ErrorCode::InsufficientBalance);
+        // WSOL 账户中超出代币余额的 lamports 是用户包装时支付的 ATA 租金
+        let wsol_rent = ctx
+            .accounts
+            .wsol_account
+            .to_account_info()
+            .lamports()
+            .saturating_sub(wsol_held);
 
         msg!("验证通过，开始解包装 WSOL...");
 
         // 创建金库的签名种子
-        let vault_investor = ctx.accounts.vault.investor;
-        let vault_bump = ctx.accounts.vault.bump;
-        let seeds = &[b"vault".as_ref(), vault_investor.as_ref(), &[vault_bump]];
-        let _signer_seeds = &[&seeds[..]];
+        let (vault_investor, vault_bump) = {
+            let vault = ctx.accounts.vault.load()?;
+            (vault.investor, vault.bump)
+        };
+        let index_bytes = ctx.accounts.vault.load()?.vault_index.to_le_bytes();
+        let seeds = &[
+            b"vault".as_ref(),
+            vault_investor.as_ref(),
+            vault_index_seed(&index_bytes),
+            &[vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
 
-        // 关闭 WSOL 账户，将 SOL 转回金库
+        // 代币程序只能整体解包：关闭 WSOL 账户，全部 SOL 转回金库
         let token_program_key = ctx.accounts.token_program.key();
         let wsol_key = ctx.accounts.wsol_account.key();
         let vault_key = ctx.accounts.vault.key();
@@ -673,7 +3680,7 @@ pub mod instructions {
             &[],
         )?;
 
-        invoke(
+        invoke_signed(
             &close_account_instruction,
             &[
                 ctx.accounts.wsol_account.to_account_info(),
@@ -681,31 +3688,104 @@ pub mod instructions {
                 ctx.accounts.vault.to_account_info(),
                 ctx.accounts.token_program.to_account_info(),
             ],
+            signer_seeds,
         )?;
 
+        // ATA 租金退还给用户
+        **ctx
+            .accounts
+            .vault
+            .to_account_info()
+            .try_borrow_mut_lamports()? -= wsol_rent;
+        **ctx
+            .accounts
+            .user
+            .to_account_info()
+            .try_borrow_mut_lamports()? += wsol_rent;
+
+        // 部分解包：重新创建 WSOL 账户（由用户支付租金）并把剩余部分包装回去
+        let rewrap_amount = wsol_held - amount;
+        if rewrap_amount > 0 {
+            msg!("部分解包装，重新包装剩余 {} lamports", rewrap_amount);
+            associated_token::create(CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: ctx.accounts.user.to_account_info(),
+                    associated_token: ctx.accounts.wsol_account.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                    mint: ctx.accounts.wsol_mint.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                },
+            ))?;
+            **ctx
+                .accounts
+                .vault
+                .to_account_info()
+                .try_borrow_mut_lamports()? -= rewrap_amount;
+            **ctx
+                .accounts
+                .wsol_account
+                .to_account_info()
+                .try_borrow_mut_lamports()? += rewrap_amount;
+            let sync_native_instruction =
+                anchor_spl::token::spl_token::instruction::sync_native(&token_program_key, &wsol_key)?;
+            invoke(
+                &sync_native_instruction,
+                &[
+                    ctx.accounts.wsol_account.to_account_info(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+            )?;
+        }
+
         msg!("WSOL 解包装成功，更新余额记录...");
 
-        // 更新内部余额记录
-        set_token_balance(&mut ctx.accounts.vault, WSOL_MINT, wsol_balance - amount);
-        let sol_balance = get_token_balance(&ctx.accounts.vault, NATIVE_SOL_MINT);
-        set_token_balance(
-            &mut ctx.accounts.vault,
+        // SOL 条目可能是新增的（必要时扩容，由用户支付租金）
+        ensure_vault_capacity(
+            &ctx.accounts.vault,
             NATIVE_SOL_MINT,
-            sol_balance + amount,
-        );
+            DEFAULT_BUCKET,
+            &ctx.accounts.global_config,
+            &ctx.accounts.user.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        // 更新内部余额记录
+        {
+            let mut vault = load_vault_mut(&ctx.accounts.vault)?;
+            set_token_balance(&mut vault, WSOL_MINT, wsol_balance - amount)?;
+            let sol_balance = get_token_balance(vault.balances(), NATIVE_SOL_MINT);
+            set_token_balance(&mut vault, NATIVE_SOL_MINT, sol_balance + amount)?;
+            // 解包不改变持仓价值，成本随数量转回 SOL
+            move_cost(&mut vault, WSOL_MINT, DEFAULT_BUCKET, NATIVE_SOL_MINT, DEFAULT_BUCKET, amount, wsol_balance)?;
+        }
+
+        // 🔒 解锁重入保护
+        ctx.accounts.vault.load_mut()?.is_locked = 0;
+
+        // 账本不变量检查（剩余 WSOL 已重新包装）
+        check_ledger_invariant(&ctx.accounts.vault, WSOL_MINT, DEFAULT_BUCKET, rewrap_amount)?;
+        let available_lamports = vault_available_lamports(&ctx.accounts.vault.to_account_info())?;
+        check_ledger_invariant(&ctx.accounts.vault, NATIVE_SOL_MINT, DEFAULT_BUCKET, available_lamports)?;
 
         msg!("WSOL 解包装操作完成!");
         Ok(())
     }
 
     /// 发送交易信号并执行 DEX 交易
+    #[allow(clippy::too_many_arguments)]
     pub fn send_trade_signal(
         ctx: Context<SendTradeSignal>,
         token_in: Pubkey,
         token_out: Pubkey,
         amount_in: u64,
         slippage_bps: u16,
-        pool_type: u8,
+        route: DexRoute,
+        valid_until_slot: u64,
+        deadline_unix_ts: Option<i64>,
+        signal_id: u64,
+        bucket: u8,
     ) -> Result<u64> {
         msg!("🚀 开始发送交易信号操作...");
         msg!("执行者地址: {}", ctx.accounts.executor.key());
@@ -718,30 +3798,78 @@ pub mod instructions {
             slippage_bps,
             slippage_bps as f64 / 100.0
         );
-        msg!("池子类型: {} (0=AMM V4, 1=CLMM)", pool_type);
+        msg!("DEX 路由: {:?}", route);
 
         // 📋 现在直接使用结构体中的账户，不需要从 remaining_accounts 获取
         msg!("收到 {} 个额外账户", ctx.remaining_accounts.len());
 
-        // 🎯 直接访问结构体中的账户
-        let mut vault_data = ctx.accounts.vault.clone();
-        let global_config = ctx.accounts.global_config.clone();
-        
+        // 🎯 直接访问结构体中的账户（vault 通过 zero-copy 直接读写账户数据）
+        let vault_key = ctx.accounts.vault.key();
+        let global_config = &ctx.accounts.global_config;
+
         msg!("✅ 账户验证通过");
-        msg!("金库地址: {}", ctx.accounts.vault.key());
-        msg!("全局配置地址: {}", ctx.accounts.global_config.key());
+        msg!("金库地址: {}", vault_key);
+        msg!("全局配置地址: {}", global_config.key());
 
         // 🔐 验证 vault 已初始化
-        require!(vault_data.is_initialized, ErrorCode::VaultNotInitialized);
-        
+        require!(ctx.accounts.vault.load()?.is_initialized(), ErrorCode::VaultNotInitialized);
+
+        // 🚨 全局暂停检查（交易及路由对应的 DEX 适配器）
+        global_config.check_not_paused(PAUSE_TRADING)?;
+        global_config.check_dex_route_enabled(route)?;
+
+        // ⏸️ 投资者暂停交易期间拒绝交易信号
+        require!(
+            !ctx.accounts.vault.load()?.is_trading_paused(),
+            ErrorCode::VaultTradingPaused
+        );
+
         // 🔐 验证 global_config 已初始化
         require!(global_config.is_initialized, ErrorCode::GlobalConfigNotInitialized);
 
-        // 🔐 验证执行者是机器人或管理员
+        let clock = Clock::get()?;
+        msg!("当前槽位: {}, 当前时间: {}", clock.slot, clock.unix_timestamp);
+
+        // 🔐 验证执行者（全局机器人/管理员，或投资者授权的执行者）
+        authorize_trade_executor(
+            ctx.accounts.executor.key(),
+            global_config,
+            ctx.accounts.vault_executors.as_ref(),
+            clock.unix_timestamp,
+        )?;
+        let fee_recipient = global_config.admin;
+
+        // ⏰ 验证信号未过期（机器人决策到上链之间价格可能已变化）
+        // 有效截止槽位不超过 MAX_SIGNAL_VALIDITY_SLOTS，保证重放保护记录覆盖整个有效期
+        msg!("信号有效截止槽位: {}", valid_until_slot);
+        require!(
+            clock.slot <= valid_until_slot,
+            ErrorCode::TradeSignalExpired
+        );
+        require!(
+            valid_until_slot - clock.slot <= MAX_SIGNAL_VALIDITY_SLOTS,
+            ErrorCode::InvalidSignalValidity
+        );
+        if let Some(deadline_unix_ts) = deadline_unix_ts {
+            msg!("信号截止时间: {}", deadline_unix_ts);
+            require!(
+                clock.unix_timestamp <= deadline_unix_ts,
+                ErrorCode::TradeSignalExpired
+            );
+        }
+
+        // 🔁 重放保护：同一信号 ID 只能执行一次
+        msg!("信号 ID: {}", signal_id);
+        require!(signal_id != 0, ErrorCode::InvalidSignalId);
+        let signal_registry = &mut ctx.accounts.signal_registry;
+        if signal_registry.vault == Pubkey::default() {
+            // 首次使用，初始化记录账户
+            signal_registry.vault = vault_key;
+            signal_registry.bump = ctx.bumps.signal_registry;
+        }
         require!(
-            ctx.accounts.executor.key() == global_config.bot
-                || ctx.accounts.executor.key() == global_config.admin,
-            ErrorCode::OnlyBotOrAdmin
+            !is_signal_recorded(signal_registry, signal_id),
+            ErrorCode::DuplicateSignalId
         );
 
         require!(amount_in > 0, ErrorCode::InvalidAmount);
@@ -767,12 +3895,76 @@ pub mod instructions {
 
         require!(slippage_bps <= 10000, ErrorCode::InvalidSlippage); // 最大滑点100%
 
-        // 检查输入代币余额
-        let current_balance = get_token_balance(&vault_data, token_in);
+        // 只能动用指定分仓的余额
+        validate_bucket(bucket)?;
+        msg!("分仓: {}", bucket);
+
+        // 🔐 验证 dex_program 与路由对应的适配器一致
+        require_keys_eq!(
+            ctx.accounts.dex_program.key(),
+            route.adapter().program_id(),
+            ErrorCode::InvalidDexProgram
+        );
+
+        // 输出代币可能是新增的（必要时扩容，由执行者支付租金）
+        ensure_vault_capacity(
+            &ctx.accounts.vault,
+            token_out,
+            bucket,
+            &ctx.accounts.global_config,
+            &ctx.accounts.executor.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        // 🔒 重入保护：检查并设置锁定状态
+        let (current_balance, vault_investor, vault_bump) = {
+            let mut vault = load_vault_mut(&ctx.accounts.vault)?;
+            require!(!vault.header.is_locked(), ErrorCode::ReentrantCall);
+            vault.header.is_locked = 1;
+
+            // 检查输入代币在该分仓中的余额
+            let current_balance = get_bucket_balance(vault.balances(), token_in, bucket);
+            (current_balance, vault.header.investor, vault.header.bump)
+        };
         require!(current_balance >= amount_in, ErrorCode::InsufficientBalance);
 
         msg!("当前输入代币余额: {}", current_balance);
 
+        // 📐 投资者交易策略检查
+        let mut trading_policy = load_trading_policy(ctx.accounts.trading_policy.as_ref())?;
+        if let Some(policy) = trading_policy.as_ref() {
+            enforce_trading_policy(
+                policy,
+                token_in,
+                token_out,
+                amount_in,
+                slippage_bps,
+                route,
+                current_balance,
+                clock.unix_timestamp,
+            )?;
+        }
+
+        // 📊 投资者 24 小时交易限额检查
+        let mut trade_limits = load_trade_limits(ctx.accounts.trade_limits.as_ref())?;
+        if let Some(limits) = trade_limits.as_ref() {
+            enforce_trade_limits_before_swap(
+                limits,
+                token_in,
+                token_out,
+                amount_in,
+                clock.unix_timestamp,
+            )?;
+        }
+
+        // 🔁 全部参数与策略检查通过后才登记信号 ID
+        record_signal_id(
+            &mut ctx.accounts.signal_registry,
+            signal_id,
+            valid_until_slot,
+            clock.slot,
+        )?;
+
         msg!("✅ 验证通过，开始处理账户...");
 
         // 计算最小输出金额
@@ -783,23 +3975,20 @@ pub mod instructions {
         let fee_rate = crate::constants::get_default_fee_rate();
         msg!("使用的费率: {} (百万分之一)", fee_rate);
 
-        // 扣除输入代币
-        let new_token_in_balance = current_balance.checked_sub(amount_in)
-            .ok_or(ErrorCode::InsufficientBalance)?;
-        set_token_balance(
-            &mut vault_data,
-            token_in,
-            new_token_in_balance,
-        );
-
         // 🔄 执行 DEX 交换（使用抽象层）
         msg!("🔄 开始执行 DEX 交换...");
 
         // 🔐 构建 vault PDA 的签名种子
         msg!("🔐 构建 vault PDA 签名种子...");
-        msg!("  - investor: {}", vault_data.investor);
-        msg!("  - bump: {}", vault_data.bump);
-        let vault_seeds = &[b"vault".as_ref(), vault_data.investor.as_ref(), &[vault_data.bump]];
+        msg!("  - investor: {}", vault_investor);
+        msg!("  - bump: {}", vault_bump);
+        let index_bytes = ctx.accounts.vault.load()?.vault_index.to_le_bytes();
+        let vault_seeds = &[
+            b"vault".as_ref(),
+            vault_investor.as_ref(),
+            vault_index_seed(&index_bytes),
+            &[vault_bump],
+        ];
         let signer_seeds = &[&vault_seeds[..]];
 
         // 🎯 关键修复：直接传递 remaining_accounts，并在 DEX 集成中处理 executor
@@ -807,21 +3996,32 @@ pub mod instructions {
         msg!("  - executor (payer): {}", ctx.accounts.executor.key());
         msg!("  - remaining_accounts 数量: {}", ctx.remaining_accounts.len());
         
-        let amount_out = crate::dex_integration::execute_dex_swap(
+        let swap_result = crate::dex_integration::execute_dex_swap(
             ctx.remaining_accounts,  // ✅ 直接传递 remaining_accounts
+            &vault_key,
+            &token_in,
+            &token_out,
             amount_in,
             amount_out_minimum,
             slippage_bps,
-            pool_type,  // ✅ 传递池子类型参数
+            route,  // ✅ 传递 DEX 路由
             signer_seeds,  // ✅ 传递 vault PDA 签名种子
         )?;
+        let amount_out = swap_result.amount_out;
 
-        msg!("✅ 交换完成，输出金额: {}", amount_out);
+        msg!("交换完成，实际输入: {}, 实际输出: {}", swap_result.amount_in, amount_out);
         require!(
             amount_out >= amount_out_minimum,
             ErrorCode::InsufficientOutputAmount
         );
 
+        let mut vault = load_vault_mut(&ctx.accounts.vault)?;
+
+        // 按实际花费扣除输入代币
+        let new_token_in_balance = current_balance.checked_sub(swap_result.amount_in)
+            .ok_or(ErrorCode::InsufficientBalance)?;
+        set_bucket_balance(&mut vault, token_in, bucket, new_token_in_balance)?;
+
         // 计算费用
         let fee_amount = amount_out.checked_mul(fee_rate)
             .and_then(|v| v.checked_div(1000000))
@@ -833,39 +4033,303 @@ pub mod instructions {
         msg!("👤 用户获得金额: {}", user_amount);
 
         // 更新输出代币余额
-        let current_out_balance = get_token_balance(&vault_data, token_out);
+        let current_out_balance = get_bucket_balance(vault.balances(), token_out, bucket);
         let new_out_balance = current_out_balance.checked_add(user_amount)
             .ok_or(ErrorCode::MathOverflow)?;
-        set_token_balance(
-            &mut vault_data,
+        set_bucket_balance(&mut vault, token_out, bucket, new_out_balance)?;
+
+        // 按实际成交数量更新成本与已实现盈亏
+        let realized_pnl = record_trade(
+            &mut vault,
+            token_in,
             token_out,
-            new_out_balance,
-        );
+            bucket,
+            swap_result.amount_in,
+            current_balance,
+            user_amount,
+            ctx.accounts.global_config.quote_mint,
+        )?;
+        msg!("本次已实现盈亏: {}", realized_pnl);
+
+        // 🔒 解锁重入保护
+        vault.header.is_locked = 0;
+        drop(vault);
+
+        // 账本不变量检查
+        check_ledger_invariant(&ctx.accounts.vault, token_in, bucket, swap_result.input_balance)?;
+        check_ledger_invariant(&ctx.accounts.vault, token_out, bucket, swap_result.output_balance)?;
+
+        // 📜 追加交易历史记录
+        let recorded = append_trade_record(
+            ctx.accounts.trade_history.as_ref(),
+            TradeRecord {
+                slot: clock.slot,
+                signal_id,
+                amount_in: swap_result.amount_in,
+                amount_out,
+                fee_amount,
+                token_in,
+                token_out,
+                executor: ctx.accounts.executor.key(),
+                bucket,
+                padding: [0; 7],
+            },
+        )?;
+        if !recorded {
+            msg!("金库未创建交易历史，跳过记录");
+        }
+
+        // 记录本次交易时间（用于策略的最小交易间隔）
+        if let Some(policy) = trading_policy.as_mut() {
+            policy.last_trade_ts = clock.unix_timestamp;
+            store_trading_policy(ctx.accounts.trading_policy.as_ref(), policy)?;
+        }
+
+        // 计入 24 小时交易次数和交易额
+        if let Some(limits) = trade_limits.as_mut() {
+            record_trade_limits_after_swap(
+                limits,
+                token_in,
+                token_out,
+                swap_result.amount_in,
+                amount_out,
+                clock.unix_timestamp,
+            )?;
+            store_trade_limits(ctx.accounts.trade_limits.as_ref(), limits)?;
+        }
 
         // 发出事件
         emit!(TradeSignalEvent {
-            user: vault_data.investor,     // 金库所有者
+            user: vault_investor,     // 金库所有者
             executor: ctx.accounts.executor.key(), // 交易执行者
             token_in,
             token_out,
             from_asset_metadata: token_in,
             to_asset_metadata: token_out,
-            amount_in,
+            amount_in: swap_result.amount_in,
             amount_out_min: amount_out_minimum,
             amount_out,
             slippage_bps,
-            fee_recipient: global_config.admin, // 使用管理员作为费用接收者
+            fee_recipient, // 使用管理员作为费用接收者
             fee_amount,
-            timestamp: Clock::get()?.unix_timestamp,
-            timestamp_microseconds: Clock::get()?.unix_timestamp as u64 * 1_000_000,
+            signal_id,
+            slot: clock.slot,
+            valid_until_slot,
+            deadline_unix_ts,
+            timestamp: clock.unix_timestamp,
+            timestamp_microseconds: clock.unix_timestamp as u64 * 1_000_000,
+            bucket,
         });
 
-        // 保存更新后的 vault_data
-        let vault_account = &ctx.remaining_accounts[0];
-        let mut data = vault_account.try_borrow_mut_data()?;
-        vault_data.try_serialize(&mut &mut **data)?;  // try_serialize 会自动处理鉴别器
-
         msg!("🎉 交易信号发送完成!");
         Ok(amount_out)
     }
+
+    /// 创建交易历史（投资者）
+    pub fn init_trade_history(ctx: Context<InitTradeHistory>, capacity: u16) -> Result<()> {
+        msg!("开始创建交易历史...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+        msg!("容量: {}", capacity);
+
+        {
+            let vault = ctx.accounts.vault.load()?;
+            require!(
+                ctx.accounts.user.key() == vault.investor,
+                ErrorCode::OnlyInvestor
+            );
+            require!(vault.is_initialized(), ErrorCode::VaultNotInitialized);
+        }
+        validate_trade_history_capacity(0, capacity as usize)?;
+
+        let mut history = ctx.accounts.trade_history.load_init()?;
+        history.vault = ctx.accounts.vault.key();
+        history.investor = ctx.accounts.user.key();
+        history.bump = ctx.bumps.trade_history;
+
+        emit!(TradeHistoryResizedEvent {
+            user: history.investor,
+            vault: history.vault,
+            old_capacity: 0,
+            new_capacity: capacity,
+            record_count: 0,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("交易历史创建成功!");
+        Ok(())
+    }
+
+    /// 调整交易历史容量（投资者）
+    /// 缩容时只保留最新的记录，多余的租金退还给投资者；扩容时由投资者补足租金
+    pub fn resize_trade_history(ctx: Context<ResizeTradeHistory>, new_capacity: u16) -> Result<()> {
+        msg!("开始调整交易历史容量...");
+        msg!("用户地址: {}", ctx.accounts.user.key());
+
+        let history_info = ctx.accounts.trade_history.to_account_info();
+        let user_info = ctx.accounts.user.to_account_info();
+        let old_size = history_info.data_len();
+        let old_capacity = TradeHistory::capacity_for(old_size);
+        msg!("容量: {} -> {}", old_capacity, new_capacity);
+        validate_trade_history_capacity(old_capacity, new_capacity as usize)?;
+
+        // 先按时间顺序整理记录，再调整账户大小
+        let (vault, record_count) = {
+            let mut history = load_trade_history_mut(&history_info)?;
+            history.retain_latest(new_capacity as usize);
+            (history.header.vault, history.header.record_count)
+        };
+
+        let new_size = TradeHistory::space(new_capacity as usize);
+        let rent = Rent::get()?;
+        if new_size > old_size {
+            let extra_rent = rent
+                .minimum_balance(new_size)
+                .saturating_sub(rent.minimum_balance(old_size));
+            if extra_rent > 0 {
+                system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        system_program::Transfer {
+                            from: user_info.clone(),
+                            to: history_info.clone(),
+                        },
+                    ),
+                    extra_rent,
+                )?;
+            }
+            history_info.resize(new_size)?;
+            msg!("扩容交易历史: {} -> {} 字节, 新增租金 {} lamports", old_size, new_size, extra_rent);
+        } else if new_size < old_size {
+            let rent_reclaimed = rent
+                .minimum_balance(old_size)
+                .saturating_sub(rent.minimum_balance(new_size));
+            history_info.resize(new_size)?;
+            **history_info.try_borrow_mut_lamports()? -= rent_reclaimed;
+            **user_info.try_borrow_mut_lamports()? += rent_reclaimed;
+            msg!("缩小交易历史: {} -> {} 字节, 退还租金 {} lamports", old_size, new_size, rent_reclaimed);
+        }
+
+        emit!(TradeHistoryResizedEvent {
+            user: ctx.accounts.user.key(),
+            vault,
+            old_capacity: old_capacity as u16,
+            new_capacity,
+            record_count,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("交易历史容量调整完成, 保留 {} 条记录", record_count);
+        Ok(())
+    }
+
+    /// 关闭交易历史（投资者），租金退还给投资者
+    /// 关闭后交易不再记录，需要时可以重新创建
+    pub fn close_trade_history(ctx: Context<CloseTradeHistory>) -> Result<()> {
+        let history = ctx.accounts.trade_history.load()?;
+        msg!("关闭交易历史...");
+        msg!("金库地址: {}", history.vault);
+        msg!("累计记录交易: {} 笔", history.total_trades);
+        Ok(())
+    }
+
+    /// 金库净值快照（任何人可调用）
+    /// 按预言机价格把各代币余额折算为报价代币并求和，记录到快照账户，返回净值
+    pub fn snapshot_vault<'info>(ctx: Context<'_, '_, '_, 'info, SnapshotVault<'info>>) -> Result<u64> {
+        msg!("开始金库净值快照...");
+        msg!("调用者: {}", ctx.accounts.caller.key());
+        msg!("收到 {} 个价格账户", ctx.remaining_accounts.len());
+
+        let vault_key = ctx.accounts.vault.key();
+        let quote_mint = ctx.accounts.global_config.quote_mint;
+        require!(quote_mint != Pubkey::default(), ErrorCode::QuoteMintNotSet);
+        let oracle_config = &ctx.accounts.oracle_config;
+        let clock = Clock::get()?;
+
+        // 快照频率限制
+        let last_snapshot_ts = ctx.accounts.vault_snapshots.last_snapshot_ts;
+        if last_snapshot_ts != 0 {
+            let elapsed = clock.unix_timestamp.saturating_sub(last_snapshot_ts);
+            require!(
+                elapsed >= oracle_config.effective_snapshot_interval_secs() as i64,
+                ErrorCode::SnapshotTooFrequent
+            );
+        }
+
+        // 汇总各代币在所有分仓中的余额
+        let (investor, holdings) = {
+            let vault = load_vault(&ctx.accounts.vault)?;
+            require!(vault.header.is_initialized(), ErrorCode::VaultNotInitialized);
+            let mut holdings: Vec<(Pubkey, u64)> = Vec::new();
+            for balance in vault.balances.iter().filter(|balance| balance.amount > 0) {
+                if !holdings.iter().any(|(token, _)| *token == balance.token) {
+                    holdings.push((balance.token, get_token_total(&vault.balances, balance.token)));
+                }
+            }
+            (vault.header.investor, holdings)
+        };
+
+        let prices = ctx
+            .remaining_accounts
+            .iter()
+            .map(read_price_update)
+            .collect::<Result<Vec<_>>>()?;
+
+        // 报价代币按面值计入，其他代币按预言机价格折算
+        let mut nav: u64 = 0;
+        let mut quote: Option<(u8, &OraclePrice)> = None;
+        for (token, amount) in holdings.iter() {
+            let value = if *token == quote_mint {
+                *amount
+            } else {
+                let (decimals, price) =
+                    find_oracle_price(oracle_config, &prices, token, clock.unix_timestamp)?;
+                let (quote_decimals, quote_price) = match quote {
+                    Some(quote) => quote,
+                    None => {
+                        let found = find_oracle_price(
+                            oracle_config,
+                            &prices,
+                            &quote_mint,
+                            clock.unix_timestamp,
+                        )?;
+                        quote = Some(found);
+                        found
+                    }
+                };
+                quote_value(*amount, decimals, price, quote_decimals, quote_price)?
+            };
+            msg!("  代币: {}, 数量: {}, 价值: {}", token, amount, value);
+            nav = nav.checked_add(value).ok_or(ErrorCode::MathOverflow)?;
+        }
+        msg!("金库净值: {} (报价代币: {})", nav, quote_mint);
+
+        let snapshots = &mut ctx.accounts.vault_snapshots;
+        if snapshots.vault == Pubkey::default() {
+            // 首次快照，初始化记录账户
+            snapshots.vault = vault_key;
+            snapshots.bump = ctx.bumps.vault_snapshots;
+        }
+        record_snapshot(
+            snapshots,
+            NavSnapshot {
+                timestamp: clock.unix_timestamp,
+                slot: clock.slot,
+                nav,
+            },
+        );
+
+        emit!(VaultSnapshotEvent {
+            user: investor,
+            vault: vault_key,
+            caller: ctx.accounts.caller.key(),
+            quote_mint,
+            nav,
+            priced_tokens: holdings.len() as u16,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("净值快照完成!");
+        Ok(nav)
+    }
 }
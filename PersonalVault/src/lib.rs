@@ -1,3 +1,4 @@
+
 use anchor_lang::prelude::*;
 
 // 程序 ID 声明 - 必须在这里，不能在子模块中
@@ -8,88 +9,372 @@ mod constants;
 mod dex;
 mod dex_integration;
 mod instructions;
+mod oracle;
 mod structs;
 pub use constants::*;
+// 与 anchor_lang::prelude 中的同名类型区分，显式导出本程序的错误码
+pub use constants::ErrorCode;
 pub use structs::*;
 pub use dex::*;
 pub use dex_integration::*;
-pub use instructions::{get_token_balance, set_token_balance};
+pub use oracle::*;
+pub use instructions::{
+    get_bucket_balance, get_token_balance, get_token_total, set_bucket_balance, set_token_balance,
+};
 
 // 项目入口
-/// 个人金库程序
-#[program]
-pub mod personal_vault {
+// #[program] 生成的链上 IDL 指令调用了 anchor-lang 中已弃用的 realloc，
+// 这些代码生成在 #[program] 所在的模块中，因此把程序放进单独的模块并只对该模块放行
+#[allow(deprecated)]
+mod program_entry {
     use super::*;
-    use instructions::instructions;
-
-    /// 初始化全局配置
-    pub fn initialize_global_config(
-        ctx: Context<InitializeGlobalConfig>,
-        bot_address: Pubkey,
-    ) -> Result<()> {
-        instructions::initialize_global_config(ctx, bot_address)
-    }
 
-    /// 设置机器人地址
-    pub fn set_bot(ctx: Context<SetBot>, new_bot_address: Pubkey) -> Result<()> {
-        instructions::set_bot(ctx, new_bot_address)
-    }
+    /// 个人金库程序
+    #[program]
+    pub mod personal_vault {
+        use super::*;
+        use instructions::instructions;
 
-    /// 设置管理员
-    pub fn set_admin(ctx: Context<SetAdmin>, new_admin: Pubkey) -> Result<()> {
-        instructions::set_admin(ctx, new_admin)
-    }
+        /// 初始化全局配置
+        pub fn initialize_global_config(
+            ctx: Context<InitializeGlobalConfig>,
+            bot_address: Pubkey,
+        ) -> Result<()> {
+            instructions::initialize_global_config(ctx, bot_address)
+        }
 
-    /// 创建余额管理器
-    pub fn create_balance_manager(ctx: Context<CreateBalanceManager>) -> Result<()> {
-        instructions::create_balance_manager(ctx)
-    }
+        /// 迁移全局配置到当前布局（管理员，升级程序后执行一次）
+        pub fn migrate_global_config(ctx: Context<MigrateGlobalConfig>) -> Result<()> {
+            instructions::migrate_global_config(ctx)
+        }
 
-    /// 用户存款
-    pub fn user_deposit(ctx: Context<UserDeposit>, amount: u64) -> Result<()> {
-        instructions::user_deposit(ctx, amount)
-    }
+        /// 迁移信号记录账户到带有效截止槽位的布局（任何人可调用，升级程序后每个金库执行一次）
+        pub fn migrate_signal_registry(ctx: Context<MigrateSignalRegistry>) -> Result<()> {
+            instructions::migrate_signal_registry(ctx)
+        }
 
-    /// 用户存入原生 SOL
-    pub fn user_deposit_sol(ctx: Context<UserDepositSol>, amount: u64) -> Result<()> {
-        instructions::user_deposit_sol(ctx, amount)
-    }
+        /// 设置紧急暂停守护者
+        pub fn set_guardian(ctx: Context<SetGuardian>, new_guardian: Pubkey) -> Result<()> {
+            instructions::set_guardian(ctx, new_guardian)
+        }
 
-    /// 用户取款
-    pub fn user_withdraw(ctx: Context<UserWithdraw>, amount: u64) -> Result<()> {
-        instructions::user_withdraw(ctx, amount)
-    }
+        /// 设置全局暂停标志（管理员或守护者）
+        /// paused_operations: PAUSE_TRADING / PAUSE_DEPOSITS / PAUSE_WITHDRAWALS 位掩码
+        /// paused_dex_routes: 按 DEX 路由变体索引的位掩码
+        pub fn set_pause_flags(
+            ctx: Context<SetPauseFlags>,
+            paused_operations: u8,
+            paused_dex_routes: u8,
+        ) -> Result<()> {
+            instructions::set_pause_flags(ctx, paused_operations, paused_dex_routes)
+        }
 
-    /// 用户取出原生 SOL
-    pub fn user_withdraw_sol(ctx: Context<UserWithdrawSol>, amount: u64) -> Result<()> {
-        instructions::user_withdraw_sol(ctx, amount)
-    }
+        /// 设置机器人地址
+        pub fn set_bot(ctx: Context<SetBot>, new_bot_address: Pubkey) -> Result<()> {
+            instructions::set_bot(ctx, new_bot_address)
+        }
 
-    /// 获取代币余额
-    pub fn get_balance(ctx: Context<GetBalance>, token: Pubkey) -> Result<u64> {
-        instructions::get_balance(ctx, token)
-    }
+        /// 设置管理员
+        pub fn set_admin(ctx: Context<SetAdmin>, new_admin: Pubkey) -> Result<()> {
+            instructions::set_admin(ctx, new_admin)
+        }
 
-    /// 将 SOL 包装为 WSOL
-    pub fn wrap_sol(ctx: Context<WrapSol>, amount: u64) -> Result<()> {
-        instructions::wrap_sol(ctx, amount)
-    }
+        /// 设置每个金库最多持有的代币种类
+        pub fn set_max_vault_tokens(ctx: Context<SetMaxVaultTokens>, max_tokens: u16) -> Result<()> {
+            instructions::set_max_vault_tokens(ctx, max_tokens)
+        }
 
-    /// 将 WSOL 解包装为 SOL
-    pub fn unwrap_sol(ctx: Context<UnwrapSol>, amount: u64) -> Result<()> {
-        instructions::unwrap_sol(ctx, amount)
-    }
+        /// 设置成本与盈亏计价使用的报价代币
+        pub fn set_quote_mint(ctx: Context<SetQuoteMint>, quote_mint: Pubkey) -> Result<()> {
+            instructions::set_quote_mint(ctx, quote_mint)
+        }
+
+        /// 登记代币的价格源（Pyth feed ID 全零表示移除）
+        pub fn set_price_feed(ctx: Context<SetPriceFeed>, mint: Pubkey, feed_id: [u8; 32]) -> Result<()> {
+            instructions::set_price_feed(ctx, mint, feed_id)
+        }
+
+        /// 设置净值快照间隔和价格最大延迟
+        pub fn set_snapshot_config(
+            ctx: Context<SetSnapshotConfig>,
+            snapshot_interval_secs: u32,
+            max_price_age_secs: u32,
+        ) -> Result<()> {
+            instructions::set_snapshot_config(ctx, snapshot_interval_secs, max_price_age_secs)
+        }
+
+        /// 金库净值快照（任何人可调用），价格账户通过 remaining_accounts 传入
+        pub fn snapshot_vault<'info>(
+            ctx: Context<'_, '_, '_, 'info, SnapshotVault<'info>>,
+        ) -> Result<u64> {
+            instructions::snapshot_vault(ctx)
+        }
+
+        /// 创建余额管理器
+        /// vault_index 为 0 时创建旧版地址的金库；label 最长 32 字节
+        pub fn create_balance_manager(
+            ctx: Context<CreateBalanceManager>,
+            vault_index: u16,
+            label: Option<String>,
+        ) -> Result<()> {
+            instructions::create_balance_manager(ctx, vault_index, label)
+        }
+
+        /// 将已有金库登记到投资者的金库列表
+        pub fn register_vault(ctx: Context<RegisterVault>) -> Result<()> {
+            instructions::register_vault(ctx)
+        }
+
+        /// 将旧版金库迁移为 zero-copy 布局
+        pub fn migrate_vault(ctx: Context<MigrateVault>) -> Result<()> {
+            instructions::migrate_vault(ctx)
+        }
+
+        /// 账本对账（投资者或管理员）
+        pub fn reconcile_balances<'info>(
+            ctx: Context<'_, '_, '_, 'info, ReconcileBalances<'info>>,
+        ) -> Result<()> {
+            instructions::reconcile_balances(ctx)
+        }
+
+        /// 压缩金库（投资者）
+        pub fn compact_vault<'info>(ctx: Context<'_, '_, '_, 'info, CompactVault<'info>>) -> Result<()> {
+            instructions::compact_vault(ctx)
+        }
+
+        /// 关闭金库（投资者）
+        pub fn close_vault<'info>(ctx: Context<'_, '_, '_, 'info, CloseVault<'info>>) -> Result<()> {
+            instructions::close_vault(ctx)
+        }
+
+        /// 用户存款（只记入默认分仓 DEFAULT_BUCKET）
+        pub fn user_deposit<'info>(
+            ctx: Context<'_, '_, '_, 'info, UserDeposit<'info>>,
+            amount: u64,
+        ) -> Result<()> {
+            instructions::user_deposit(ctx, amount)
+        }
+
+        /// 用户存入原生 SOL（只记入默认分仓）
+        pub fn user_deposit_sol(ctx: Context<UserDepositSol>, amount: u64) -> Result<()> {
+            instructions::user_deposit_sol(ctx, amount)
+        }
+
+        /// 代存款（任何出资人 -> 指定投资者的金库，只记入默认分仓）
+        pub fn deposit_for<'info>(
+            ctx: Context<'_, '_, '_, 'info, DepositFor<'info>>,
+            amount: u64,
+        ) -> Result<()> {
+            instructions::deposit_for(ctx, amount)
+        }
+
+        /// 代存 SOL（任何出资人 -> 指定投资者的金库，只记入默认分仓）
+        pub fn deposit_sol_for(ctx: Context<DepositSolFor>, amount: u64) -> Result<()> {
+            instructions::deposit_sol_for(ctx, amount)
+        }
+
+        /// 用户取款（只动用默认分仓的余额）
+        pub fn user_withdraw<'info>(
+            ctx: Context<'_, '_, '_, 'info, UserWithdraw<'info>>,
+            amount: u64,
+        ) -> Result<()> {
+            instructions::user_withdraw(ctx, amount)
+        }
+
+        /// 用户取出原生 SOL（只动用默认分仓的余额）
+        pub fn user_withdraw_sol(ctx: Context<UserWithdrawSol>, amount: u64) -> Result<()> {
+            instructions::user_withdraw_sol(ctx, amount)
+        }
+
+        /// 取款到指定账户（收款 ATA 不存在时由投资者支付创建，只动用默认分仓的余额）
+        pub fn user_withdraw_to<'info>(
+            ctx: Context<'_, '_, '_, 'info, UserWithdrawTo<'info>>,
+            amount: u64,
+        ) -> Result<()> {
+            instructions::user_withdraw_to(ctx, amount)
+        }
+
+        /// 取出原生 SOL 到指定钱包（只动用默认分仓的余额）
+        pub fn user_withdraw_sol_to(ctx: Context<UserWithdrawSolTo>, amount: u64) -> Result<()> {
+            instructions::user_withdraw_sol_to(ctx, amount)
+        }
+
+        /// 批量存款（多种代币，账户通过 remaining_accounts 传入，只记入默认分仓）
+        pub fn batch_deposit<'info>(
+            ctx: Context<'_, '_, '_, 'info, BatchDeposit<'info>>,
+            transfers: Vec<TokenTransfer>,
+        ) -> Result<()> {
+            instructions::batch_deposit(ctx, transfers)
+        }
+
+        /// 批量取款（多种代币，账户通过 remaining_accounts 传入，只动用默认分仓的余额）
+        pub fn batch_withdraw<'info>(
+            ctx: Context<'_, '_, '_, 'info, BatchWithdraw<'info>>,
+            transfers: Vec<TokenTransfer>,
+        ) -> Result<()> {
+            instructions::batch_withdraw(ctx, transfers)
+        }
+
+        /// 获取代币余额
+        pub fn get_balance(ctx: Context<GetBalance>, token: Pubkey) -> Result<u64> {
+            instructions::get_balance(ctx, token)
+        }
+
+        /// 查询持仓（累计存取、成本、平均成本、已实现盈亏）
+        pub fn get_position(ctx: Context<GetPosition>, token: Pubkey, bucket: u8) -> Result<PositionInfo> {
+            instructions::get_position(ctx, token, bucket)
+        }
+
+        /// 将 SOL 包装为 WSOL（默认分仓）
+        pub fn wrap_sol(ctx: Context<WrapSol>, amount: u64) -> Result<()> {
+            instructions::wrap_sol(ctx, amount)
+        }
+
+        /// 将 WSOL 解包装为 SOL（默认分仓）
+        pub fn unwrap_sol(ctx: Context<UnwrapSol>, amount: u64) -> Result<()> {
+            instructions::unwrap_sol(ctx, amount)
+        }
+
+        /// 发送交易信号并执行 DEX 交易
+        /// route: DEX 路由（RaydiumAmmV4 / RaydiumClmm）
+        /// valid_until_slot: 信号有效截止槽位（距当前槽位不超过 MAX_SIGNAL_VALIDITY_SLOTS）
+        /// deadline_unix_ts: 信号截止时间（可选），过期的信号会被拒绝
+        /// signal_id: 信号 ID（非零），同一金库内重复的 ID 会被拒绝
+        #[allow(clippy::too_many_arguments)]
+        pub fn send_trade_signal(
+            ctx: Context<SendTradeSignal>,
+            token_in: Pubkey,
+            token_out: Pubkey,
+            amount_in: u64,
+            slippage_bps: u16,
+            route: DexRoute,
+            valid_until_slot: u64,
+            deadline_unix_ts: Option<i64>,
+            signal_id: u64,
+            bucket: u8,
+        ) -> Result<u64> {
+            instructions::send_trade_signal(
+                ctx,
+                token_in,
+                token_out,
+                amount_in,
+                slippage_bps,
+                route,
+                valid_until_slot,
+                deadline_unix_ts,
+                signal_id,
+                bucket,
+            )
+        }
+
+        /// 创建交易历史（投资者），capacity 为环形缓冲区容量
+        pub fn init_trade_history(ctx: Context<InitTradeHistory>, capacity: u16) -> Result<()> {
+            instructions::init_trade_history(ctx, capacity)
+        }
+
+        /// 调整交易历史容量（投资者），缩容时保留最新的记录
+        pub fn resize_trade_history(ctx: Context<ResizeTradeHistory>, new_capacity: u16) -> Result<()> {
+            instructions::resize_trade_history(ctx, new_capacity)
+        }
+
+        /// 关闭交易历史（投资者）
+        pub fn close_trade_history(ctx: Context<CloseTradeHistory>) -> Result<()> {
+            instructions::close_trade_history(ctx)
+        }
+
+        /// 设置交易策略（投资者）：允许的代币、单笔上限、滑点上限、DEX 适配器和最小交易间隔
+        pub fn set_trading_policy(
+            ctx: Context<SetTradingPolicy>,
+            params: TradingPolicyParams,
+        ) -> Result<()> {
+            instructions::set_trading_policy(ctx, params)
+        }
+
+        /// 移除交易策略（投资者）
+        pub fn close_trading_policy(ctx: Context<CloseTradingPolicy>) -> Result<()> {
+            instructions::close_trading_policy(ctx)
+        }
+
+        /// 授权执行者对金库发送交易信号（投资者），expires_at 为 None 时永不过期
+        pub fn authorize_vault_executor(
+            ctx: Context<ManageVaultExecutors>,
+            executor: Pubkey,
+            expires_at: Option<i64>,
+        ) -> Result<()> {
+            instructions::authorize_vault_executor(ctx, executor, expires_at)
+        }
+
+        /// 撤销执行者授权（投资者）
+        pub fn revoke_vault_executor(ctx: Context<RevokeVaultExecutor>, executor: Pubkey) -> Result<()> {
+            instructions::revoke_vault_executor(ctx, executor)
+        }
+
+        /// 设置是否接受全局机器人和管理员执行交易（投资者）
+        pub fn set_executor_preference(
+            ctx: Context<ManageVaultExecutors>,
+            allow_global_executors: bool,
+        ) -> Result<()> {
+            instructions::set_executor_preference(ctx, allow_global_executors)
+        }
+
+        /// 删除执行者授权（投资者），恢复为只接受全局机器人和管理员
+        pub fn close_vault_executors(ctx: Context<CloseVaultExecutors>) -> Result<()> {
+            instructions::close_vault_executors(ctx)
+        }
+
+        /// 设置 24 小时交易限额（投资者）：交易次数上限和以 quote_mint 计价的交易额上限
+        pub fn set_trade_limits(
+            ctx: Context<SetTradeLimits>,
+            quote_mint: Pubkey,
+            max_daily_trades: u32,
+            max_daily_volume: u64,
+            unpriced_trade_policy: UnpricedTradePolicy,
+        ) -> Result<()> {
+            instructions::set_trade_limits(
+                ctx,
+                quote_mint,
+                max_daily_trades,
+                max_daily_volume,
+                unpriced_trade_policy,
+            )
+        }
+
+        /// 移除交易限额（投资者）
+        pub fn close_trade_limits(ctx: Context<CloseTradeLimits>) -> Result<()> {
+            instructions::close_trade_limits(ctx)
+        }
+
+        /// 查询 24 小时交易限额的剩余额度
+        pub fn get_trade_allowance(ctx: Context<GetTradeAllowance>) -> Result<TradeAllowance> {
+            instructions::get_trade_allowance(ctx)
+        }
+
+        /// 暂停金库交易（投资者），暂停期间交易信号被拒绝，存取款不受影响
+        pub fn pause_trading(ctx: Context<PauseTrading>) -> Result<()> {
+            instructions::pause_trading(ctx)
+        }
+
+        /// 恢复金库交易（投资者）
+        pub fn resume_trading(ctx: Context<ResumeTrading>) -> Result<()> {
+            instructions::resume_trading(ctx)
+        }
+
+        /// 设置分仓名称（对应的策略）
+        pub fn set_bucket_label(ctx: Context<SetBucketLabel>, bucket: u8, label: String) -> Result<()> {
+            instructions::set_bucket_label(ctx, bucket, label)
+        }
 
-    /// 发送交易信号并执行 DEX 交易
-    /// pool_type: 0 = Raydium AMM V4, 1 = Raydium CLMM
-    pub fn send_trade_signal(
-        ctx: Context<SendTradeSignal>,
-        token_in: Pubkey,
-        token_out: Pubkey,
-        amount_in: u64,
-        slippage_bps: u16,
-        pool_type: u8,
-    ) -> Result<u64> {
-        instructions::send_trade_signal(ctx, token_in, token_out, amount_in, slippage_bps, pool_type)
+        /// 在分仓之间划转余额
+        /// 存取款、代存款、批量操作和 SOL 包装只使用默认分仓，其他分仓的余额只能通过本指令转入转出
+        pub fn move_between_buckets(
+            ctx: Context<MoveBetweenBuckets>,
+            token: Pubkey,
+            from_bucket: u8,
+            to_bucket: u8,
+            amount: u64,
+        ) -> Result<()> {
+            instructions::move_between_buckets(ctx, token, from_bucket, to_bucket, amount)
+        }
     }
 }
+pub use program_entry::*;
@@ -0,0 +1,76 @@
+use anchor_lang::prelude::*;
+
+use crate::constants::{ErrorCode, PYTH_PRICE_UPDATE_DISCRIMINATOR, PYTH_RECEIVER_PROGRAM_ID};
+
+/// PriceUpdateV2 中 verification_level = Full 的枚举标签
+const PYTH_VERIFICATION_FULL: u8 = 1;
+
+/// 预言机价格（来自 Pyth PriceUpdateV2 账户）
+/// 实际价格 = price * 10^exponent（以美元计）
+#[derive(Debug, Clone)]
+pub struct OraclePrice {
+    pub feed_id: [u8; 32],
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+}
+
+/// 读取 Pyth PriceUpdateV2 账户
+///
+/// 布局：鉴别器(8) + write_authority(32) + verification_level(Full 为 1 字节)
+///   + feed_id(32) + price(i64) + conf(u64) + exponent(i32) + publish_time(i64) + ...
+///
+/// 只接受完整验证（Full）的价格
+pub fn read_price_update(account: &AccountInfo) -> Result<OraclePrice> {
+    require_keys_eq!(*account.owner, PYTH_RECEIVER_PROGRAM_ID, ErrorCode::InvalidPriceAccount);
+    let data = account.try_borrow_data()?;
+
+    const MESSAGE_OFFSET: usize = 8 + 32 + 1;
+    require!(
+        data.len() >= MESSAGE_OFFSET + 32 + 8 + 8 + 4 + 8
+            && data[..8] == PYTH_PRICE_UPDATE_DISCRIMINATOR
+            && data[8 + 32] == PYTH_VERIFICATION_FULL,
+        ErrorCode::InvalidPriceAccount
+    );
+
+    let message = &data[MESSAGE_OFFSET..];
+    let mut feed_id = [0u8; 32];
+    feed_id.copy_from_slice(&message[..32]);
+    Ok(OraclePrice {
+        feed_id,
+        price: i64::from_le_bytes(message[32..40].try_into().unwrap()),
+        conf: u64::from_le_bytes(message[40..48].try_into().unwrap()),
+        exponent: i32::from_le_bytes(message[48..52].try_into().unwrap()),
+        publish_time: i64::from_le_bytes(message[52..60].try_into().unwrap()),
+    })
+}
+
+/// 将代币数量按预言机价格折算为报价代币数量（最小单位）
+///
+/// `value = amount / 10^decimals * price * 10^exponent / (quote_price * 10^quote_exponent) * 10^quote_decimals`
+pub fn quote_value(
+    amount: u64,
+    decimals: u8,
+    price: &OraclePrice,
+    quote_decimals: u8,
+    quote_price: &OraclePrice,
+) -> Result<u64> {
+    require!(price.price > 0 && quote_price.price > 0, ErrorCode::InvalidPriceAccount);
+
+    let scale = price.exponent - quote_price.exponent + quote_decimals as i32 - decimals as i32;
+    let mut numerator = (amount as u128)
+        .checked_mul(price.price as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let mut denominator = quote_price.price as u128;
+    let factor = 10u128
+        .checked_pow(scale.unsigned_abs())
+        .ok_or(ErrorCode::MathOverflow)?;
+    if scale >= 0 {
+        numerator = numerator.checked_mul(factor).ok_or(ErrorCode::MathOverflow)?;
+    } else {
+        denominator = denominator.checked_mul(factor).ok_or(ErrorCode::MathOverflow)?;
+    }
+
+    u64::try_from(numerator / denominator).map_err(|_| ErrorCode::MathOverflow.into())
+}
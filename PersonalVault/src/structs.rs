@@ -1,15 +1,121 @@
+use std::cell::{Ref, RefMut};
+
 use anchor_lang::prelude::*;
 use anchor_lang::system_program::System;
 use anchor_spl::associated_token::AssociatedToken;
 use anchor_spl::token::{Mint, Token, TokenAccount};
+use anchor_spl::token_2022::Token2022;
+use anchor_spl::token_interface::{self, TokenInterface};
 
-use crate::constants::{ErrorCode, WSOL_MINT};
+use crate::constants::{
+    ErrorCode, BUCKET_LABEL_LEN, DEFAULT_MAX_PRICE_AGE_SECS, DEFAULT_MAX_VAULT_TOKENS,
+    DEFAULT_SNAPSHOT_INTERVAL_SECS, LEGACY_PERSONAL_VAULT_DISCRIMINATOR, MAX_POLICY_MINTS,
+    MAX_PRICE_FEEDS, MAX_VAULTS_PER_INVESTOR, MAX_VAULT_BUCKETS, MAX_VAULT_EXECUTORS,
+    PAUSE_DEPOSITS, PAUSE_TRADING, PERSONAL_VAULT_DISCRIMINATOR, SIGNAL_HISTORY_SIZE,
+    SNAPSHOT_HISTORY_SIZE, TRADE_LIMIT_SEGMENTS, TRADE_LIMIT_SEGMENT_SECS, TRADE_LIMIT_WINDOW_SECS,
+    VAULT_INITIAL_TOKEN_CAPACITY, VAULT_LABEL_LEN, WSOL_MINT,
+};
+use crate::dex::DexRoute;
 
-/// 代币余额结构
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+/// 代币余额槽位（zero-copy）
+/// 账本条目按 (代币, 分仓) 区分，同一代币在不同分仓中各占一个槽位
+#[zero_copy]
 pub struct TokenBalance {
     pub token: Pubkey,
     pub amount: u64,
+    /// 所属分仓（0 为默认分仓）
+    pub bucket: u8,
+    /// 持仓中含有成本未知的部分（非报价代币按零成本存入或由此换入），余额清零时复位
+    pub basis_unknown: u8,
+    /// 对齐填充
+    pub padding: [u8; 6],
+    /// 累计存入数量（存款、代存、对账补记）
+    pub total_deposited: u64,
+    /// 累计取出数量
+    pub total_withdrawn: u64,
+    /// 当前持仓的总成本（以报价代币计）
+    pub cost_basis: u64,
+    /// 已实现盈亏（以报价代币计）
+    pub realized_pnl: i64,
+}
+
+impl TokenBalance {
+    /// 单个槽位大小
+    pub const SIZE: usize = std::mem::size_of::<TokenBalance>();
+
+    pub fn has_unknown_basis(&self) -> bool {
+        self.basis_unknown != 0
+    }
+
+    /// 条目是否带有记账历史（累计存取、持仓成本或已实现盈亏）
+    pub fn has_history(&self) -> bool {
+        self.total_deposited != 0
+            || self.total_withdrawn != 0
+            || self.cost_basis != 0
+            || self.realized_pnl != 0
+    }
+}
+
+/// 旧版代币余额结构（Borsh 布局，仅用于迁移）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LegacyTokenBalance {
+    pub token: Pubkey,
+    pub amount: u64,
+}
+
+/// 批量存取款中的单个代币条目
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TokenTransfer {
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+/// 交易策略参数（set_trading_policy 的参数）
+/// 各项为 0 或空列表时表示不限制
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TradingPolicyParams {
+    /// 允许交易的代币（输入和输出代币都必须在列表中）
+    pub allowed_mints: Vec<Pubkey>,
+    /// 单笔交易输入数量上限
+    pub max_amount_in: u64,
+    /// 单笔交易输入数量占该分仓余额的比例上限（基点）
+    pub max_amount_in_bps: u16,
+    /// 滑点上限（基点）
+    pub max_slippage_bps: u16,
+    /// 允许的 DEX 适配器（位定义同 DexRoute::pause_flag）
+    pub allowed_dex_routes: u8,
+    /// 两次交易之间的最小间隔（秒）
+    pub min_trade_interval_secs: u32,
+}
+
+/// 交易限额剩余额度（get_trade_allowance 的返回值）
+/// 上限为 0 时对应的剩余额度为 u32::MAX / u64::MAX
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TradeAllowance {
+    pub quote_mint: Pubkey,
+    pub max_daily_trades: u32,
+    pub trades_used: u32,
+    pub trades_remaining: u32,
+    pub max_daily_volume: u64,
+    pub volume_used: u64,
+    pub volume_remaining: u64,
+}
+
+/// 持仓信息（get_position 的返回值）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PositionInfo {
+    pub token: Pubkey,
+    pub bucket: u8,
+    pub quote_mint: Pubkey,
+    pub amount: u64,
+    pub total_deposited: u64,
+    pub total_withdrawn: u64,
+    pub cost_basis: u64,
+    /// 平均成本（每单位代币的报价代币数量，放大 AVERAGE_COST_SCALE 倍）
+    pub average_cost: u64,
+    pub realized_pnl: i64,
+    /// 持仓中含有成本未知的部分（cost_basis 和 average_cost 偏低，卖出时不计入已实现盈亏）
+    pub basis_unknown: bool,
 }
 
 /// 全局配置结构体 - 存储所有用户共享的配置
@@ -21,11 +127,239 @@ pub struct GlobalConfig {
     pub bot: Pubkey,
     /// 是否已初始化
     pub is_initialized: bool,
+    /// 每个金库最多持有的代币种类（0 表示使用默认值）
+    pub max_vault_tokens: u16,
+    /// 成本与盈亏计价使用的报价代币（默认值表示未设置）
+    pub quote_mint: Pubkey,
+    /// 紧急暂停守护者（默认值表示未设置），与管理员一样可以设置暂停标志
+    pub guardian: Pubkey,
+    /// 全局暂停的操作（PAUSE_TRADING / PAUSE_DEPOSITS / PAUSE_WITHDRAWALS 位掩码，
+    /// 各指令对应的标志见 constants.rs 中的说明）
+    pub paused_operations: u8,
+    /// 暂停的 DEX 适配器（按 DexRoute::pause_flag 的位掩码）
+    pub paused_dex_routes: u8,
 }
 
-/// 个人金库账户结构
-#[account]
+impl GlobalConfig {
+    /// 账户空间（末尾预留 64 字节供后续字段使用）
+    pub const SPACE: usize = 8 + 32 + 32 + 1 + 2 + 32 + 32 + 1 + 1 + 64;
+
+    /// 检查操作是否被全局暂停
+    pub fn check_not_paused(&self, operation: u8) -> Result<()> {
+        if self.paused_operations & operation == 0 {
+            return Ok(());
+        }
+        msg!("操作已被全局暂停 (标志: {:#04x})", self.paused_operations);
+        Err(match operation {
+            PAUSE_TRADING => ErrorCode::TradingPaused,
+            PAUSE_DEPOSITS => ErrorCode::DepositsPaused,
+            _ => ErrorCode::WithdrawalsPaused,
+        }
+        .into())
+    }
+
+    /// 检查 DEX 路由对应的适配器是否被暂停
+    pub fn check_dex_route_enabled(&self, route: DexRoute) -> Result<()> {
+        require!(
+            self.paused_dex_routes & route.pause_flag() == 0,
+            ErrorCode::DexAdapterPaused
+        );
+        Ok(())
+    }
+
+    /// 当前生效的金库代币种类上限
+    pub fn effective_max_vault_tokens(&self) -> u16 {
+        if self.max_vault_tokens == 0 {
+            DEFAULT_MAX_VAULT_TOKENS
+        } else {
+            self.max_vault_tokens
+        }
+    }
+}
+
+/// 个人金库账户结构（zero-copy）
+///
+/// 账户布局：8 字节鉴别器 + 头部（本结构）+ `TokenBalance` 槽位表。
+/// 槽位表紧跟在头部之后，容量由账户大小决定，新增代币时通过 realloc 扩容；
+/// 前 `token_count` 个槽位为已使用的余额条目，可按槽位索引 O(1) 访问。
+/// 通过 [`load_vault`] / [`load_vault_mut`] 同时访问头部和槽位表。
+#[account(zero_copy, discriminator = &PERSONAL_VAULT_DISCRIMINATOR)]
 pub struct PersonalVault {
+    /// 投资者地址
+    pub investor: Pubkey,
+    /// 已使用的余额槽位数量
+    pub token_count: u16,
+    /// 是否已初始化
+    pub is_initialized: u8,
+    /// 重入保护标志
+    pub is_locked: u8,
+    /// PDA bump seed
+    pub bump: u8,
+    /// 账户布局版本
+    pub layout_version: u8,
+    /// 金库编号（0 为旧版无编号金库）
+    pub vault_index: u16,
+    /// 金库标签（UTF-8，不足部分补零）
+    pub label: [u8; VAULT_LABEL_LEN],
+    /// 各分仓名称（对应的策略，UTF-8，不足部分补零）
+    pub bucket_labels: [[u8; BUCKET_LABEL_LEN]; MAX_VAULT_BUCKETS],
+    /// 投资者暂停交易标志（暂停期间拒绝交易信号，存取款不受影响）
+    pub trading_paused: u8,
+    /// 预留字段
+    pub reserved: [u8; 23],
+}
+
+impl PersonalVault {
+    /// 头部大小（不含鉴别器）
+    pub const HEADER_SIZE: usize = std::mem::size_of::<PersonalVault>();
+
+    /// 容纳 token_capacity 个余额槽位所需的账户空间
+    pub fn space(token_capacity: usize) -> usize {
+        8 + Self::HEADER_SIZE + TokenBalance::SIZE * token_capacity
+    }
+
+    /// 指定账户大小可容纳的余额槽位数量
+    pub fn capacity_for(data_len: usize) -> usize {
+        data_len.saturating_sub(8 + Self::HEADER_SIZE) / TokenBalance::SIZE
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.is_initialized != 0
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.is_locked != 0
+    }
+
+    pub fn is_trading_paused(&self) -> bool {
+        self.trading_paused != 0
+    }
+
+    /// 金库 PDA 的编号种子
+    pub fn index_seed(&self) -> &[u8] {
+        if self.vault_index == 0 {
+            &[]
+        } else {
+            bytemuck::bytes_of(&self.vault_index)
+        }
+    }
+}
+
+/// 金库编号对应的 PDA 种子
+/// 编号 0 使用空种子，推导结果与旧版 `[b"vault", investor]` 相同，旧版金库无需迁移
+pub fn vault_index_seed(index_bytes: &[u8; 2]) -> &[u8] {
+    if *index_bytes == [0, 0] {
+        &[]
+    } else {
+        index_bytes
+    }
+}
+
+/// 只读的金库视图：头部 + 已使用的余额槽位
+pub struct VaultRef<'a> {
+    pub header: Ref<'a, PersonalVault>,
+    pub balances: Ref<'a, [TokenBalance]>,
+}
+
+/// 可写的金库视图：头部 + 全部余额槽位（含未使用的容量）
+pub struct VaultMut<'a> {
+    pub header: RefMut<'a, PersonalVault>,
+    slots: RefMut<'a, [TokenBalance]>,
+}
+
+impl VaultMut<'_> {
+    /// 已使用的余额槽位
+    pub fn balances(&self) -> &[TokenBalance] {
+        &self.slots[..self.header.token_count as usize]
+    }
+
+    /// 已使用的余额槽位（可写）
+    pub fn balances_mut(&mut self) -> &mut [TokenBalance] {
+        let token_count = self.header.token_count as usize;
+        &mut self.slots[..token_count]
+    }
+
+    /// 槽位表容量
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// 移除余额为零且没有记账历史的槽位（保持其余条目的顺序），返回移除的数量
+    /// 带有累计存取、成本或已实现盈亏的零余额槽位保留，避免压缩后丢失持仓记录
+    pub fn remove_zero_balances(&mut self) -> usize {
+        let token_count = self.header.token_count as usize;
+        let mut kept = 0;
+        for index in 0..token_count {
+            if self.slots[index].amount > 0 || self.slots[index].has_history() {
+                self.slots[kept] = self.slots[index];
+                kept += 1;
+            }
+        }
+        for slot in self.slots[kept..token_count].iter_mut() {
+            *slot = bytemuck::Zeroable::zeroed();
+        }
+        self.header.token_count = kept as u16;
+        token_count - kept
+    }
+
+    /// 追加新的余额槽位，返回槽位索引
+    pub fn push(&mut self, token: Pubkey, bucket: u8, amount: u64) -> Result<usize> {
+        let index = self.header.token_count as usize;
+        require!(index < self.capacity(), ErrorCode::VaultCapacityExceeded);
+        self.slots[index] = TokenBalance {
+            token,
+            amount,
+            bucket,
+            ..bytemuck::Zeroable::zeroed()
+        };
+        self.header.token_count += 1;
+        Ok(index)
+    }
+}
+
+/// 以只读方式加载金库头部和余额槽位
+pub fn load_vault<'a>(vault: &'a AccountLoader<PersonalVault>) -> Result<VaultRef<'a>> {
+    // 校验鉴别器
+    drop(vault.load()?);
+
+    let data = vault.as_ref().try_borrow_data()?;
+    let (header, balances) = Ref::map_split(data, |data| {
+        let (header, slots) = data[8..].split_at(PersonalVault::HEADER_SIZE);
+        let header: &PersonalVault = bytemuck::from_bytes(header);
+        let token_count = header.token_count as usize;
+        let slots: &[TokenBalance] = bytemuck::cast_slice(
+            &slots[..PersonalVault::capacity_for(data.len()) * TokenBalance::SIZE],
+        );
+        (header, &slots[..token_count])
+    });
+    Ok(VaultRef { header, balances })
+}
+
+/// 以可写方式加载金库头部和余额槽位
+pub fn load_vault_mut<'a>(vault: &'a AccountLoader<PersonalVault>) -> Result<VaultMut<'a>> {
+    // 校验鉴别器和可写性
+    drop(vault.load_mut()?);
+    load_vault_slots_mut(vault.as_ref())
+}
+
+/// 直接按 zero-copy 布局映射金库账户数据（不校验鉴别器）
+/// 仅供已自行校验账户的场景使用，例如 migrate_vault 重写账户数据之后
+pub fn load_vault_slots_mut<'a>(vault_info: &'a AccountInfo) -> Result<VaultMut<'a>> {
+    let data = vault_info.try_borrow_mut_data()?;
+    let capacity = PersonalVault::capacity_for(data.len());
+    let (header, slots) = RefMut::map_split(data, |data| {
+        let (header, slots) = data[8..].split_at_mut(PersonalVault::HEADER_SIZE);
+        (
+            bytemuck::from_bytes_mut(header),
+            bytemuck::cast_slice_mut(&mut slots[..capacity * TokenBalance::SIZE]),
+        )
+    });
+    Ok(VaultMut { header, slots })
+}
+
+/// 旧版个人金库账户结构（Borsh 布局，仅用于 migrate_vault）
+#[account(discriminator = &LEGACY_PERSONAL_VAULT_DISCRIMINATOR)]
+pub struct LegacyPersonalVault {
     /// 投资者地址
     pub investor: Pubkey,
     /// 是否已初始化
@@ -33,18 +367,438 @@ pub struct PersonalVault {
     /// 重入保护标志
     pub is_locked: bool,
     /// 代币余额列表
-    pub balances: Vec<TokenBalance>,
+    pub balances: Vec<LegacyTokenBalance>,
+    /// PDA bump seed
+    pub bump: u8,
+}
+
+/// 交易信号 ID 记录账户（每个金库一个）
+/// 保存最近执行过的信号 ID 及其有效截止槽位，用于拒绝重复信号，使机器人重试幂等
+/// 记录在信号过期前不会被覆盖，因此被挤出的信号 ID 不可能再被执行
+#[account]
+pub struct SignalRegistry {
+    /// 所属金库地址
+    pub vault: Pubkey,
+    /// 最近的信号 ID（0 表示空槽）
+    pub signal_ids: [u64; SIGNAL_HISTORY_SIZE],
+    /// 下一个写入位置
+    pub next_index: u16,
+    /// PDA bump seed
+    pub bump: u8,
+    /// 每条记录对应信号的有效截止槽位（过期后该槽位才可被覆盖）
+    pub expiry_slots: [u64; SIGNAL_HISTORY_SIZE],
+}
+
+impl SignalRegistry {
+    /// 旧版布局（无有效截止槽位）的账户大小
+    pub const LEGACY_SPACE: usize = 8 + 32 + 8 * SIGNAL_HISTORY_SIZE + 2 + 1;
+    /// 账户总大小（包括 8 字节鉴别器）
+    pub const SPACE: usize = Self::LEGACY_SPACE + 8 * SIGNAL_HISTORY_SIZE;
+}
+
+/// 交易记录（zero-copy，TradeHistory 环形缓冲区中的一项）
+#[zero_copy]
+pub struct TradeRecord {
+    /// 执行时的槽位
+    pub slot: u64,
+    /// 交易信号 ID
+    pub signal_id: u64,
+    /// 实际花费的输入代币数量
+    pub amount_in: u64,
+    /// DEX 实际输出数量（含手续费）
+    pub amount_out: u64,
+    /// 手续费
+    pub fee_amount: u64,
+    pub token_in: Pubkey,
+    pub token_out: Pubkey,
+    /// 交易执行者（管理员或Bot）
+    pub executor: Pubkey,
+    /// 分仓编号
+    pub bucket: u8,
+    /// 对齐填充
+    pub padding: [u8; 7],
+}
+
+impl TradeRecord {
+    /// 单条记录大小
+    pub const SIZE: usize = std::mem::size_of::<TradeRecord>();
+}
+
+/// 交易历史账户（zero-copy，每个金库一个，由投资者创建）
+///
+/// 账户布局：8 字节鉴别器 + 头部（本结构）+ `TradeRecord` 环形缓冲区。
+/// 容量由账户大小决定，投资者可以扩容、缩容或关闭；
+/// 缓冲区写满后新记录覆盖最旧的记录。
+#[account(zero_copy)]
+pub struct TradeHistory {
+    /// 所属金库地址
+    pub vault: Pubkey,
+    /// 投资者地址
+    pub investor: Pubkey,
+    /// 累计记录过的交易数量（含已被覆盖的记录）
+    pub total_trades: u64,
+    /// 下一个写入位置
+    pub next_index: u16,
+    /// 当前保存的记录数量
+    pub record_count: u16,
+    /// PDA bump seed
+    pub bump: u8,
+    /// 对齐填充
+    pub padding: [u8; 3],
+}
+
+impl TradeHistory {
+    /// 头部大小（不含鉴别器）
+    pub const HEADER_SIZE: usize = std::mem::size_of::<TradeHistory>();
+
+    /// 容纳 capacity 条记录所需的账户空间
+    pub fn space(capacity: usize) -> usize {
+        8 + Self::HEADER_SIZE + TradeRecord::SIZE * capacity
+    }
+
+    /// 指定账户大小可容纳的记录数量
+    pub fn capacity_for(data_len: usize) -> usize {
+        data_len.saturating_sub(8 + Self::HEADER_SIZE) / TradeRecord::SIZE
+    }
+}
+
+/// 可写的交易历史视图：头部 + 全部记录槽位
+pub struct TradeHistoryMut<'a> {
+    pub header: RefMut<'a, TradeHistory>,
+    records: RefMut<'a, [TradeRecord]>,
+}
+
+impl TradeHistoryMut<'_> {
+    /// 缓冲区容量
+    pub fn capacity(&self) -> usize {
+        self.records.len()
+    }
+
+    /// 追加一条记录，缓冲区已满时覆盖最旧的记录
+    pub fn push(&mut self, record: TradeRecord) {
+        let capacity = self.capacity();
+        if capacity == 0 {
+            return;
+        }
+        let index = self.header.next_index as usize % capacity;
+        self.records[index] = record;
+        self.header.next_index = ((index + 1) % capacity) as u16;
+        if (self.header.record_count as usize) < capacity {
+            self.header.record_count += 1;
+        }
+        self.header.total_trades = self.header.total_trades.saturating_add(1);
+    }
+
+    /// 按从旧到新的顺序把记录移到缓冲区开头，只保留最新的 keep 条（keep > 0）
+    /// 调整容量之前调用，之后新记录从第 keep 条之后继续写入
+    pub fn retain_latest(&mut self, keep: usize) {
+        let capacity = self.capacity();
+        let mut count = self.header.record_count as usize;
+        if capacity > 0 && count == capacity {
+            // 缓冲区已满时最旧的记录位于写入位置
+            self.records.rotate_left(self.header.next_index as usize % capacity);
+        }
+        if count > keep {
+            self.records.copy_within(count - keep..count, 0);
+            count = keep;
+        }
+        for record in self.records[count..].iter_mut() {
+            *record = bytemuck::Zeroable::zeroed();
+        }
+        self.header.record_count = count as u16;
+        self.header.next_index = (count % keep) as u16;
+    }
+}
+
+/// 以可写方式映射交易历史账户（校验所有者和鉴别器）
+pub fn load_trade_history_mut<'a>(history_info: &'a AccountInfo) -> Result<TradeHistoryMut<'a>> {
+    require_keys_eq!(*history_info.owner, crate::ID, ErrorCode::InvalidTradeHistoryAccount);
+    let data = history_info.try_borrow_mut_data()?;
+    require!(
+        data.len() >= 8 + TradeHistory::HEADER_SIZE && data[..8] == *TradeHistory::DISCRIMINATOR,
+        ErrorCode::InvalidTradeHistoryAccount
+    );
+    let capacity = TradeHistory::capacity_for(data.len());
+    let (header, records) = RefMut::map_split(data, |data| {
+        let (header, records) = data[8..].split_at_mut(TradeHistory::HEADER_SIZE);
+        (
+            bytemuck::from_bytes_mut(header),
+            bytemuck::cast_slice_mut(&mut records[..capacity * TradeRecord::SIZE]),
+        )
+    });
+    Ok(TradeHistoryMut { header, records })
+}
+
+/// 交易策略账户（每个金库一个，由投资者设置）
+/// 约束机器人可以对该金库执行的交易，未设置时不做额外限制
+#[account]
+pub struct TradingPolicy {
+    /// 所属金库地址
+    pub vault: Pubkey,
+    /// PDA bump seed
+    pub bump: u8,
+    /// 允许交易的代币（空列表表示不限制）
+    pub allowed_mints: Vec<Pubkey>,
+    /// 单笔交易输入数量上限（0 表示不限制）
+    pub max_amount_in: u64,
+    /// 单笔交易输入数量占该分仓余额的比例上限（基点，0 表示不限制）
+    pub max_amount_in_bps: u16,
+    /// 滑点上限（基点，0 表示不限制）
+    pub max_slippage_bps: u16,
+    /// 允许的 DEX 适配器（位掩码，0 表示不限制）
+    pub allowed_dex_routes: u8,
+    /// 两次交易之间的最小间隔（秒，0 表示不限制）
+    pub min_trade_interval_secs: u32,
+    /// 最近一次交易的时间（由 send_trade_signal 更新）
+    pub last_trade_ts: i64,
+}
+
+impl TradingPolicy {
+    /// 账户空间（按最大代币数量预分配）
+    pub const SPACE: usize = 8 + 32 + 1 + 4 + 32 * MAX_POLICY_MINTS + 8 + 2 + 2 + 1 + 4 + 8;
+}
+
+/// 金库授权的执行者
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct VaultExecutorEntry {
+    pub executor: Pubkey,
+    /// 授权过期时间（unix 时间，0 表示永不过期）
+    pub expires_at: i64,
+}
+
+impl VaultExecutorEntry {
+    pub const SIZE: usize = 32 + 8;
+}
+
+/// 执行者授权账户（每个金库一个，由投资者设置）
+/// 投资者可授权自己选择的执行者（例如第三方策略方）对金库发送交易信号，
+/// 并决定是否继续接受全局机器人和管理员
+#[account]
+pub struct VaultExecutors {
+    /// 所属金库地址
+    pub vault: Pubkey,
+    /// PDA bump seed
+    pub bump: u8,
+    /// 是否接受全局机器人和管理员执行交易（账户创建时默认为 true）
+    pub allow_global_executors: bool,
+    /// 金库授权的执行者
+    pub executors: Vec<VaultExecutorEntry>,
+}
+
+impl VaultExecutors {
+    /// 账户空间（按最大执行者数量预分配）
+    pub const SPACE: usize = 8 + 32 + 1 + 1 + 4 + VaultExecutorEntry::SIZE * MAX_VAULT_EXECUTORS;
+
+    /// 查找未过期的执行者授权
+    pub fn find_active(&self, executor: &Pubkey, now: i64) -> Option<&VaultExecutorEntry> {
+        self.executors.iter().find(|entry| {
+            entry.executor == *executor && (entry.expires_at == 0 || now < entry.expires_at)
+        })
+    }
+}
+
+/// 交易限额的一个统计时段（一小时）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct TradeLimitSegment {
+    /// 时段编号（unix 时间 / 3600）
+    pub hour: i64,
+    /// 该时段内的交易次数
+    pub trades: u32,
+    /// 该时段内的交易额（以限额计价代币计）
+    pub volume: u64,
+}
+
+impl TradeLimitSegment {
+    pub const SIZE: usize = 8 + 4 + 8;
+}
+
+/// 设置了交易额上限时，两侧都不是限额计价代币的交易如何处理（这类交易的交易额无法计量）
+/// Borsh 序列化为 1 字节的变体索引，只能在末尾追加
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnpricedTradePolicy {
+    /// 拒绝交易（TradeNotPricedInLimitMint）
+    #[default]
+    Reject,
+    /// 允许交易，只计入交易次数，不计入交易额
+    CountOnly,
+}
+
+/// 交易限额账户（每个金库一个，由投资者设置）
+/// 按小时分段统计最近 24 小时的交易次数和交易额，防止机器人失控时频繁交易
+#[account]
+pub struct TradeLimits {
+    /// 所属金库地址
+    pub vault: Pubkey,
+    /// PDA bump seed
+    pub bump: u8,
+    /// 交易额的计价代币
+    pub quote_mint: Pubkey,
+    /// 24 小时内最多交易次数（0 表示不限制）
+    pub max_daily_trades: u32,
+    /// 24 小时内最大交易额（0 表示不限制）
+    pub max_daily_volume: u64,
+    /// 按小时分段的统计（按 hour % 24 存放）
+    pub segments: [TradeLimitSegment; TRADE_LIMIT_SEGMENTS],
+    /// 两侧都不是计价代币的交易的处理方式
+    pub unpriced_trade_policy: UnpricedTradePolicy,
+}
+
+impl TradeLimits {
+    pub const SPACE: usize =
+        8 + 32 + 1 + 32 + 4 + 8 + TradeLimitSegment::SIZE * TRADE_LIMIT_SEGMENTS + 1;
+
+    /// 统计滚动窗口内（最近 24 个小时段）的交易次数和交易额
+    pub fn usage(&self, now: i64) -> (u32, u64) {
+        let current_hour = now.div_euclid(TRADE_LIMIT_SEGMENT_SECS);
+        let oldest_hour = current_hour - TRADE_LIMIT_WINDOW_SECS / TRADE_LIMIT_SEGMENT_SECS;
+        self.segments
+            .iter()
+            .filter(|segment| segment.hour > oldest_hour && segment.hour <= current_hour)
+            .fold((0u32, 0u64), |(trades, volume), segment| {
+                (
+                    trades.saturating_add(segment.trades),
+                    volume.saturating_add(segment.volume),
+                )
+            })
+    }
+
+    /// 记录一笔交易到当前小时段（过期的时段先清零）
+    pub fn record(&mut self, now: i64, volume: u64) {
+        let current_hour = now.div_euclid(TRADE_LIMIT_SEGMENT_SECS);
+        let segment = &mut self.segments[current_hour.rem_euclid(TRADE_LIMIT_SEGMENTS as i64) as usize];
+        if segment.hour != current_hour {
+            *segment = TradeLimitSegment {
+                hour: current_hour,
+                ..Default::default()
+            };
+        }
+        segment.trades = segment.trades.saturating_add(1);
+        segment.volume = segment.volume.saturating_add(volume);
+    }
+}
+
+/// 投资者金库登记项
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct VaultRegistryEntry {
+    pub vault_index: u16,
+    pub label: [u8; VAULT_LABEL_LEN],
+    pub vault: Pubkey,
+}
+
+impl VaultRegistryEntry {
+    pub const SIZE: usize = 2 + VAULT_LABEL_LEN + 32;
+}
+
+/// 投资者金库登记账户（每个投资者一个）
+/// 列出投资者名下的全部金库，创建金库时登记，关闭金库时移除
+#[account]
+pub struct VaultRegistry {
+    /// 投资者地址
+    pub investor: Pubkey,
+    /// PDA bump seed
+    pub bump: u8,
+    /// 已登记的金库
+    pub vaults: Vec<VaultRegistryEntry>,
+}
+
+impl VaultRegistry {
+    /// 账户空间（按最大金库数量预分配）
+    pub const SPACE: usize = 8 + 32 + 1 + 4 + VaultRegistryEntry::SIZE * MAX_VAULTS_PER_INVESTOR;
+}
+
+/// 价格源登记项（代币 -> Pyth 价格源）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PriceFeedEntry {
+    pub mint: Pubkey,
+    /// Pyth 价格源 ID
+    pub feed_id: [u8; 32],
+    /// 代币精度（登记时从 mint 账户读取，原生 SOL 取 WSOL 的精度）
+    pub decimals: u8,
+}
+
+impl PriceFeedEntry {
+    pub const SIZE: usize = 32 + 32 + 1;
+}
+
+/// 预言机配置账户（全局唯一，由管理员维护）
+/// 登记每种代币对应的价格源，以及净值快照的频率和价格时效要求
+#[account]
+pub struct OracleConfig {
+    /// PDA bump seed
+    pub bump: u8,
+    /// 同一金库两次快照之间的最小间隔（秒，0 表示使用默认值）
+    pub snapshot_interval_secs: u32,
+    /// 价格允许的最大延迟（秒，0 表示使用默认值）
+    pub max_price_age_secs: u32,
+    /// 已登记的价格源
+    pub feeds: Vec<PriceFeedEntry>,
+}
+
+impl OracleConfig {
+    /// 账户空间（按最大价格源数量预分配）
+    pub const SPACE: usize = 8 + 1 + 4 + 4 + 4 + PriceFeedEntry::SIZE * MAX_PRICE_FEEDS;
+
+    /// 当前生效的快照间隔
+    pub fn effective_snapshot_interval_secs(&self) -> u32 {
+        if self.snapshot_interval_secs == 0 {
+            DEFAULT_SNAPSHOT_INTERVAL_SECS
+        } else {
+            self.snapshot_interval_secs
+        }
+    }
+
+    /// 当前生效的价格最大延迟
+    pub fn effective_max_price_age_secs(&self) -> u32 {
+        if self.max_price_age_secs == 0 {
+            DEFAULT_MAX_PRICE_AGE_SECS
+        } else {
+            self.max_price_age_secs
+        }
+    }
+
+    /// 查找代币的价格源
+    pub fn feed_for(&self, mint: &Pubkey) -> Option<&PriceFeedEntry> {
+        self.feeds.iter().find(|feed| feed.mint == *mint)
+    }
+}
+
+/// 金库净值快照
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct NavSnapshot {
+    pub timestamp: i64,
+    pub slot: u64,
+    /// 金库总价值（报价代币最小单位）
+    pub nav: u64,
+}
+
+/// 金库净值快照记录账户（每个金库一个）
+/// 以环形缓冲区保存最近的快照，首次快照时由调用者创建
+#[account]
+pub struct VaultSnapshots {
+    /// 所属金库地址
+    pub vault: Pubkey,
+    /// 最近一次快照的时间（0 表示尚未快照）
+    pub last_snapshot_ts: i64,
+    /// 最近的快照
+    pub snapshots: [NavSnapshot; SNAPSHOT_HISTORY_SIZE],
+    /// 下一个写入位置
+    pub next_index: u16,
     /// PDA bump seed
     pub bump: u8,
 }
 
+impl VaultSnapshots {
+    /// 账户空间
+    pub const SPACE: usize = 8 + 32 + 8 + (8 + 8 + 8) * SNAPSHOT_HISTORY_SIZE + 2 + 1;
+}
+
 /// 初始化全局配置上下文
 #[derive(Accounts)]
 pub struct InitializeGlobalConfig<'info> {
     #[account(
         init,
         payer = admin,
-        space = 8 + 32 + 32 + 32 + 32 + 1,
+        space = GlobalConfig::SPACE,
         seeds = [b"global_config"],
         bump
     )]
@@ -57,16 +811,28 @@ pub struct InitializeGlobalConfig<'info> {
 }
 
 /// 创建余额管理器上下文
+/// vault_index 为 0 时创建旧版地址的金库（种子为 `[b"vault", user]`）
 #[derive(Accounts)]
+#[instruction(vault_index: u16)]
 pub struct CreateBalanceManager<'info> {
     #[account(
         init,
         payer = user,
-        space = 8 + 32 + 1 + 1 + 4 + 40 * 10 + 1,
-        seeds = [b"vault", user.key().as_ref()],
+        space = PersonalVault::space(VAULT_INITIAL_TOKEN_CAPACITY),
+        seeds = [b"vault", user.key().as_ref(), vault_index_seed(&vault_index.to_le_bytes())],
         bump
     )]
-    pub vault: Account<'info, PersonalVault>,
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    /// 投资者金库登记账户（首次创建金库时初始化）
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = VaultRegistry::SPACE,
+        seeds = [b"vault_registry", user.key().as_ref()],
+        bump
+    )]
+    pub vault_registry: Account<'info, VaultRegistry>,
 
     #[account(
         seeds = [b"global_config"],
@@ -81,244 +847,1253 @@ pub struct CreateBalanceManager<'info> {
     pub system_program: Program<'info, System>,
 }
 
-/// 设置机器人地址上下文
+/// 登记已有金库上下文（用于登记账户出现之前创建的金库）
 #[derive(Accounts)]
-pub struct SetBot<'info> {
+pub struct RegisterVault<'info> {
+    #[account(
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = VaultRegistry::SPACE,
+        seeds = [b"vault_registry", user.key().as_ref()],
+        bump
+    )]
+    pub vault_registry: Account<'info, VaultRegistry>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// 设置分仓名称上下文
+#[derive(Accounts)]
+pub struct SetBucketLabel<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    pub user: Signer<'info>,
+}
+
+/// 暂停交易上下文（投资者）
+#[derive(Accounts)]
+pub struct PauseTrading<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    pub user: Signer<'info>,
+}
+
+/// 恢复交易上下文（投资者）
+#[derive(Accounts)]
+pub struct ResumeTrading<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    pub user: Signer<'info>,
+}
+
+/// 分仓间划转上下文
+#[derive(Accounts)]
+pub struct MoveBetweenBuckets<'info> {
     #[account(
         mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    /// 投资者（新增账本条目时支付扩容租金）
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// 全局配置账户（读取金库代币种类上限、检查全局暂停标志）
+    #[account(
         seeds = [b"global_config"],
-        bump,
-        constraint = global_config.is_initialized @ ErrorCode::GlobalConfigNotInitialized
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// 金库迁移上下文（旧版 Borsh 布局 -> zero-copy 布局）
+#[derive(Accounts)]
+pub struct MigrateVault<'info> {
+    /// 旧版金库账户
+    /// CHECK: 旧版布局无法用 AccountLoader 加载，在指令中校验 owner 与鉴别器
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref()],
+        bump
+    )]
+    pub vault: UncheckedAccount<'info>,
+
+    /// 金库所有者（用于账户推导）
+    /// CHECK: 这个账户仅用于推导金库地址
+    pub user: UncheckedAccount<'info>,
+
+    /// 支付新增租金的账户（投资者或管理员）
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump,
+        constraint = global_config.is_initialized @ ErrorCode::GlobalConfigNotInitialized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// 全局配置迁移上下文（管理员）
+/// 将旧版全局配置账户扩容到当前布局，新增字段为默认值
+#[derive(Accounts)]
+pub struct MigrateGlobalConfig<'info> {
+    /// CHECK: 旧版账户长度不足以反序列化为 GlobalConfig，在指令中校验 owner、鉴别器和管理员
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: UncheckedAccount<'info>,
+
+    /// 管理员（支付新增租金）
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// 信号记录账户迁移上下文（任何人可调用，调用者支付新增租金）
+/// 将旧版信号记录账户扩容到带有效截止槽位的布局
+#[derive(Accounts)]
+pub struct MigrateSignalRegistry<'info> {
+    /// CHECK: 旧版账户长度不足以反序列化为 SignalRegistry，在指令中校验 owner 和鉴别器
+    #[account(
+        mut,
+        seeds = [b"signal_registry", vault.key().as_ref()],
+        bump
+    )]
+    pub signal_registry: UncheckedAccount<'info>,
+
+    /// CHECK: 只用于推导信号记录账户地址
+    pub vault: UncheckedAccount<'info>,
+
+    /// 调用者（支付新增租金）
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// 设置守护者上下文（管理员）
+#[derive(Accounts)]
+pub struct SetGuardian<'info> {
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump,
+        constraint = global_config.is_initialized @ ErrorCode::GlobalConfigNotInitialized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+/// 设置全局暂停标志上下文（管理员或守护者）
+#[derive(Accounts)]
+pub struct SetPauseFlags<'info> {
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump,
+        constraint = global_config.is_initialized @ ErrorCode::GlobalConfigNotInitialized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub authority: Signer<'info>,
+}
+
+/// 设置机器人地址上下文
+#[derive(Accounts)]
+pub struct SetBot<'info> {
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump,
+        constraint = global_config.is_initialized @ ErrorCode::GlobalConfigNotInitialized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+/// 设置管理员上下文
+#[derive(Accounts)]
+pub struct SetAdmin<'info> {
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump,
+        constraint = global_config.is_initialized @ ErrorCode::GlobalConfigNotInitialized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+/// 设置金库代币种类上限上下文
+#[derive(Accounts)]
+pub struct SetMaxVaultTokens<'info> {
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump,
+        constraint = global_config.is_initialized @ ErrorCode::GlobalConfigNotInitialized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+/// 设置报价代币上下文
+#[derive(Accounts)]
+pub struct SetQuoteMint<'info> {
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump,
+        constraint = global_config.is_initialized @ ErrorCode::GlobalConfigNotInitialized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+/// 登记价格源上下文（管理员）
+#[derive(Accounts)]
+pub struct SetPriceFeed<'info> {
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = OracleConfig::SPACE,
+        seeds = [b"oracle_config"],
+        bump
+    )]
+    pub oracle_config: Account<'info, OracleConfig>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump,
+        constraint = global_config.is_initialized @ ErrorCode::GlobalConfigNotInitialized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// 登记代币的 mint（精度从这里读取；原生 SOL 传入 WSOL mint）
+    pub mint_account: InterfaceAccount<'info, token_interface::Mint>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// 设置快照参数上下文（管理员）
+#[derive(Accounts)]
+pub struct SetSnapshotConfig<'info> {
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = OracleConfig::SPACE,
+        seeds = [b"oracle_config"],
+        bump
+    )]
+    pub oracle_config: Account<'info, OracleConfig>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump,
+        constraint = global_config.is_initialized @ ErrorCode::GlobalConfigNotInitialized
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// 金库净值快照上下文（任何人可调用）
+/// remaining_accounts: 金库持有的各代币（及报价代币）的 Pyth PriceUpdateV2 账户，顺序不限
+#[derive(Accounts)]
+pub struct SnapshotVault<'info> {
+    /// 调用者，首次快照时支付快照记录账户的租金
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        seeds = [b"oracle_config"],
+        bump = oracle_config.bump
+    )]
+    pub oracle_config: Account<'info, OracleConfig>,
+
+    #[account(
+        init_if_needed,
+        payer = caller,
+        space = VaultSnapshots::SPACE,
+        seeds = [b"vault_snapshots", vault.key().as_ref()],
+        bump
+    )]
+    pub vault_snapshots: Account<'info, VaultSnapshots>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// 用户存款上下文
+#[derive(Accounts)]
+pub struct UserDeposit<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// 用户的代币账户
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    /// 金库的代币账户
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+        associated_token::token_program = token_program
+    )]
+    pub vault_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    /// 代币 mint（SPL Token 或 Token-2022）
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+    /// 全局配置账户（读取金库代币种类上限）
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+/// 代存款上下文（任何出资人都可以向指定投资者的金库存入代币，代币须已在账本中或在策略白名单中）
+#[derive(Accounts)]
+pub struct DepositFor<'info> {
+    /// 受益投资者（金库所有者）
+    /// CHECK: 仅用于推导金库地址，金库中记录的投资者必须与之一致
+    pub beneficiary: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", beneficiary.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    /// 出资人
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    /// 出资人的代币账户
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = funder,
+        associated_token::token_program = token_program
+    )]
+    pub funder_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    /// 金库的代币账户（不存在时由出资人支付创建）
+    #[account(
+        init_if_needed,
+        payer = funder,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+        associated_token::token_program = token_program
+    )]
+    pub vault_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    /// 代币 mint（SPL Token 或 Token-2022）
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+    /// 全局配置账户（读取金库代币种类上限）
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// 交易策略账户（代存只接受账本中已有或策略白名单中的代币）
+    /// CHECK: 地址由种子约束，存在时在指令中校验所有者和鉴别器
+    #[account(
+        seeds = [b"trading_policy", vault.key().as_ref()],
+        bump
+    )]
+    pub trading_policy: UncheckedAccount<'info>,
+}
+
+/// 代存 SOL 上下文
+#[derive(Accounts)]
+pub struct DepositSolFor<'info> {
+    /// 受益投资者（金库所有者）
+    /// CHECK: 仅用于推导金库地址，金库中记录的投资者必须与之一致
+    pub beneficiary: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", beneficiary.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    /// 出资人
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// 全局配置账户（读取金库代币种类上限）
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// 交易策略账户（代存只接受账本中已有或策略白名单中的代币）
+    /// CHECK: 地址由种子约束，存在时在指令中校验所有者和鉴别器
+    #[account(
+        seeds = [b"trading_policy", vault.key().as_ref()],
+        bump
+    )]
+    pub trading_policy: UncheckedAccount<'info>,
+}
+
+/// 用户取款上下文
+#[derive(Accounts)]
+pub struct UserWithdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// 用户的代币账户
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = user,
+        associated_token::token_program = token_program
+    )]
+    pub user_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    /// 金库的代币账户
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+        associated_token::token_program = token_program
+    )]
+    pub vault_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    /// 代币 mint（SPL Token 或 Token-2022）
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+    /// 全局配置（检查全局暂停标志）
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+/// 批量存款上下文
+/// remaining_accounts: 按 [用户代币账户, 金库代币账户, mint] 三个一组传入，顺序与参数列表一致
+/// （代币账户可写；金库代币账户不存在时由用户支付创建）
+#[derive(Accounts)]
+pub struct BatchDeposit<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+    /// 全局配置账户（读取金库代币种类上限）
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+/// 批量取款上下文
+/// remaining_accounts: 按 [用户代币账户, 金库代币账户, mint] 三个一组传入，顺序与参数列表一致
+#[derive(Accounts)]
+pub struct BatchWithdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+
+    /// 全局配置（检查全局暂停标志）
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+/// 用户存入 SOL 上下文
+#[derive(Accounts)]
+pub struct UserDepositSol<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// 全局配置账户（读取金库代币种类上限）
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+/// 用户取出 SOL 上下文
+#[derive(Accounts)]
+pub struct UserWithdrawSol<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// 全局配置（检查全局暂停标志）
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+/// 取款到指定账户上下文（收款方由投资者指定）
+#[derive(Accounts)]
+pub struct UserWithdrawTo<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// 收款钱包地址
+    /// CHECK: 任意地址，仅作为收款代币账户的所有者；不能是金库自身
+    #[account(constraint = destination_owner.key() != vault.key() @ ErrorCode::InvalidVaultAccount)]
+    pub destination_owner: UncheckedAccount<'info>,
+
+    /// 收款方的代币账户（不存在时由投资者支付创建）
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint,
+        associated_token::authority = destination_owner,
+        associated_token::token_program = token_program
+    )]
+    pub destination_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    /// 金库的代币账户
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault,
+        associated_token::token_program = token_program
+    )]
+    pub vault_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    /// 代币 mint（SPL Token 或 Token-2022）
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+    /// 全局配置（检查全局暂停标志）
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+/// 取出 SOL 到指定钱包上下文
+#[derive(Accounts)]
+pub struct UserWithdrawSolTo<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// 收款钱包
+    /// CHECK: 任意可写地址，只接收 lamports；不能是金库自身
+    #[account(mut, constraint = destination.key() != vault.key() @ ErrorCode::InvalidVaultAccount)]
+    pub destination: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// 全局配置（检查全局暂停标志）
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+/// SOL 包装为 WSOL 上下文
+#[derive(Accounts)]
+pub struct WrapSol<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// WSOL 代币账户
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = wsol_mint,
+        associated_token::authority = vault
+    )]
+    pub wsol_account: Account<'info, TokenAccount>,
+
+    /// WSOL mint 账户
+    #[account(address = WSOL_MINT)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+    /// 全局配置账户（读取金库代币种类上限、检查全局暂停标志）
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+/// WSOL 解包装为 SOL 上下文
+#[derive(Accounts)]
+pub struct UnwrapSol<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// WSOL 代币账户
+    #[account(
+        mut,
+        associated_token::mint = wsol_mint,
+        associated_token::authority = vault
+    )]
+    pub wsol_account: Account<'info, TokenAccount>,
+
+    /// WSOL mint 账户
+    #[account(address = WSOL_MINT)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+    /// 全局配置账户（读取金库代币种类上限、检查全局暂停标志）
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+/// 获取余额上下文
+#[derive(Accounts)]
+pub struct GetBalance<'info> {
+    pub vault: AccountLoader<'info, PersonalVault>,
+}
+
+/// 查询持仓上下文
+#[derive(Accounts)]
+pub struct GetPosition<'info> {
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+/// 发送交易信号上下文
+/// 优化版本：将常用账户移到结构体中，减少 remaining_accounts 的复杂性
+#[derive(Accounts)]
+#[instruction(
+    token_in: Pubkey,
+    token_out: Pubkey,
+    amount_in: u64,
+    slippage_bps: u16
+)]
+pub struct SendTradeSignal<'info> {
+    /// 交易执行者（管理员、Bot 或投资者授权的执行者），首次交易时支付信号记录账户的租金
+    #[account(mut)]
+    pub executor: Signer<'info>,
+
+    /// 金库所有者（用于账户推导）
+    /// CHECK: 这个账户仅用于推导其他账户，不需要验证
+    pub user: UncheckedAccount<'info>,
+
+    /// 个人金库账户
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    /// 全局配置账户
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// DEX 程序账户（用于 CPI 调用）
+    /// CHECK: 这个账户是 DEX 程序 ID，必须与 route 对应的适配器程序 ID 一致
+    pub dex_program: UncheckedAccount<'info>,
+
+    /// 交易信号 ID 记录账户（重放保护）
+    #[account(
+        init_if_needed,
+        payer = executor,
+        space = SignalRegistry::SPACE,
+        seeds = [b"signal_registry", vault.key().as_ref()],
+        bump
+    )]
+    pub signal_registry: Account<'info, SignalRegistry>,
+
+    /// 执行者授权账户（投资者设置后按授权检查执行者，未设置时只接受全局机器人和管理员）
+    /// CHECK: 地址由种子约束，存在时在指令中校验所有者和鉴别器
+    #[account(
+        seeds = [b"vault_executors", vault.key().as_ref()],
+        bump
+    )]
+    pub vault_executors: UncheckedAccount<'info>,
+
+    /// 交易策略账户（投资者设置后按策略检查交易，未设置时不做额外限制）
+    /// CHECK: 地址由种子约束，存在时在指令中校验所有者和鉴别器
+    #[account(
+        mut,
+        seeds = [b"trading_policy", vault.key().as_ref()],
+        bump
+    )]
+    pub trading_policy: UncheckedAccount<'info>,
+
+    /// 交易限额账户（投资者设置后统计并限制 24 小时内的交易次数和交易额）
+    /// CHECK: 地址由种子约束，存在时在指令中校验所有者和鉴别器
+    #[account(
+        mut,
+        seeds = [b"trade_limits", vault.key().as_ref()],
+        bump
+    )]
+    pub trade_limits: UncheckedAccount<'info>,
+
+    /// 交易历史账户（投资者创建后每笔交易自动追加记录，未创建时跳过）
+    /// CHECK: 地址由种子约束，存在时在指令中校验所有者和鉴别器
+    #[account(
+        mut,
+        seeds = [b"trade_history", vault.key().as_ref()],
+        bump
+    )]
+    pub trade_history: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// 授权执行者 / 设置执行者偏好上下文（投资者），首次设置时创建授权账户
+#[derive(Accounts)]
+pub struct ManageVaultExecutors<'info> {
+    #[account(
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    /// 投资者（首次设置时支付租金）
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = VaultExecutors::SPACE,
+        seeds = [b"vault_executors", vault.key().as_ref()],
+        bump
+    )]
+    pub vault_executors: Account<'info, VaultExecutors>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// 撤销执行者授权上下文（投资者）
+#[derive(Accounts)]
+pub struct RevokeVaultExecutor<'info> {
+    #[account(
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_executors", vault.key().as_ref()],
+        bump = vault_executors.bump
+    )]
+    pub vault_executors: Account<'info, VaultExecutors>,
+}
+
+/// 删除执行者授权账户上下文（投资者），之后只接受全局机器人和管理员，租金退还给投资者
+#[derive(Accounts)]
+pub struct CloseVaultExecutors<'info> {
+    #[account(
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_executors", vault.key().as_ref()],
+        bump = vault_executors.bump,
+        close = user
+    )]
+    pub vault_executors: Account<'info, VaultExecutors>,
+}
+
+/// 设置交易限额上下文（投资者）
+#[derive(Accounts)]
+pub struct SetTradeLimits<'info> {
+    #[account(
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    /// 投资者（首次设置时支付租金）
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = TradeLimits::SPACE,
+        seeds = [b"trade_limits", vault.key().as_ref()],
+        bump
+    )]
+    pub trade_limits: Account<'info, TradeLimits>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// 移除交易限额上下文（投资者），租金退还给投资者
+#[derive(Accounts)]
+pub struct CloseTradeLimits<'info> {
+    #[account(
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
+    )]
+    pub vault: AccountLoader<'info, PersonalVault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"trade_limits", vault.key().as_ref()],
+        bump = trade_limits.bump,
+        close = user
     )]
-    pub global_config: Account<'info, GlobalConfig>,
-
-    pub admin: Signer<'info>,
+    pub trade_limits: Account<'info, TradeLimits>,
 }
 
-/// 设置管理员上下文
+/// 查询交易限额剩余额度上下文
 #[derive(Accounts)]
-pub struct SetAdmin<'info> {
+pub struct GetTradeAllowance<'info> {
+    pub vault: AccountLoader<'info, PersonalVault>,
+
     #[account(
-        mut,
-        seeds = [b"global_config"],
-        bump,
-        constraint = global_config.is_initialized @ ErrorCode::GlobalConfigNotInitialized
+        seeds = [b"trade_limits", vault.key().as_ref()],
+        bump = trade_limits.bump
     )]
-    pub global_config: Account<'info, GlobalConfig>,
-
-    pub admin: Signer<'info>,
+    pub trade_limits: Account<'info, TradeLimits>,
 }
 
-/// 用户存款上下文
+/// 设置交易策略上下文（投资者）
 #[derive(Accounts)]
-pub struct UserDeposit<'info> {
+pub struct SetTradingPolicy<'info> {
     #[account(
-        mut,
-        seeds = [b"vault", user.key().as_ref()],
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
         bump
     )]
-    pub vault: Account<'info, PersonalVault>,
+    pub vault: AccountLoader<'info, PersonalVault>,
 
+    /// 投资者（首次设置时支付租金）
     #[account(mut)]
     pub user: Signer<'info>,
 
-    /// 用户的代币账户
-    #[account(
-        mut,
-        associated_token::mint = mint,
-        associated_token::authority = user
-    )]
-    pub user_token_account: Account<'info, TokenAccount>,
-
-    /// 金库的代币账户
     #[account(
         init_if_needed,
         payer = user,
-        associated_token::mint = mint,
-        associated_token::authority = vault
+        space = TradingPolicy::SPACE,
+        seeds = [b"trading_policy", vault.key().as_ref()],
+        bump
     )]
-    pub vault_token_account: Account<'info, TokenAccount>,
-
-    pub mint: Account<'info, Mint>,
+    pub trading_policy: Account<'info, TradingPolicy>,
 
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
-/// 用户取款上下文
+/// 移除交易策略上下文（投资者），租金退还给投资者
 #[derive(Accounts)]
-pub struct UserWithdraw<'info> {
+pub struct CloseTradingPolicy<'info> {
     #[account(
-        mut,
-        seeds = [b"vault", user.key().as_ref()],
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
         bump
     )]
-    pub vault: Account<'info, PersonalVault>,
+    pub vault: AccountLoader<'info, PersonalVault>,
 
     #[account(mut)]
     pub user: Signer<'info>,
 
-    /// 用户的代币账户
     #[account(
         mut,
-        associated_token::mint = mint,
-        associated_token::authority = user
+        seeds = [b"trading_policy", vault.key().as_ref()],
+        bump = trading_policy.bump,
+        close = user
     )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub trading_policy: Account<'info, TradingPolicy>,
+}
 
-    /// 金库的代币账户
+/// 创建交易历史上下文
+#[derive(Accounts)]
+#[instruction(capacity: u16)]
+pub struct InitTradeHistory<'info> {
     #[account(
-        mut,
-        associated_token::mint = mint,
-        associated_token::authority = vault
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump
     )]
-    pub vault_token_account: Account<'info, TokenAccount>,
+    pub vault: AccountLoader<'info, PersonalVault>,
 
-    pub mint: Account<'info, Mint>,
+    /// 投资者（支付租金）
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        init,
+        payer = user,
+        space = TradeHistory::space(capacity as usize),
+        seeds = [b"trade_history", vault.key().as_ref()],
+        bump
+    )]
+    pub trade_history: AccountLoader<'info, TradeHistory>,
 
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
-/// 用户存入 SOL 上下文
+/// 调整交易历史容量上下文
+/// 不要求金库仍然存在，金库关闭后投资者也可以管理其交易历史
 #[derive(Accounts)]
-pub struct UserDepositSol<'info> {
+pub struct ResizeTradeHistory<'info> {
     #[account(
         mut,
-        seeds = [b"vault", user.key().as_ref()],
-        bump
+        seeds = [b"trade_history", trade_history.load()?.vault.as_ref()],
+        bump = trade_history.load()?.bump,
+        constraint = trade_history.load()?.investor == user.key() @ ErrorCode::OnlyInvestor
     )]
-    pub vault: Account<'info, PersonalVault>,
+    pub trade_history: AccountLoader<'info, TradeHistory>,
 
+    /// 投资者（扩容时支付租金，缩容时接收退还的租金）
     #[account(mut)]
     pub user: Signer<'info>,
 
     pub system_program: Program<'info, System>,
 }
 
-/// 用户取出 SOL 上下文
+/// 关闭交易历史上下文
 #[derive(Accounts)]
-pub struct UserWithdrawSol<'info> {
+pub struct CloseTradeHistory<'info> {
     #[account(
         mut,
-        seeds = [b"vault", user.key().as_ref()],
-        bump
+        seeds = [b"trade_history", trade_history.load()?.vault.as_ref()],
+        bump = trade_history.load()?.bump,
+        constraint = trade_history.load()?.investor == user.key() @ ErrorCode::OnlyInvestor,
+        close = user
     )]
-    pub vault: Account<'info, PersonalVault>,
+    pub trade_history: AccountLoader<'info, TradeHistory>,
 
+    /// 投资者（接收租金）
     #[account(mut)]
     pub user: Signer<'info>,
-
-    pub system_program: Program<'info, System>,
 }
 
-/// SOL 包装为 WSOL 上下文
+/// 金库压缩上下文
+/// remaining_accounts: 需要关闭的金库空代币账户（可写，SPL Token 或 Token-2022）
 #[derive(Accounts)]
-pub struct WrapSol<'info> {
+pub struct CompactVault<'info> {
     #[account(
         mut,
-        seeds = [b"vault", user.key().as_ref()],
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
         bump
     )]
-    pub vault: Account<'info, PersonalVault>,
+    pub vault: AccountLoader<'info, PersonalVault>,
 
+    /// 投资者（接收回收的租金）
     #[account(mut)]
     pub user: Signer<'info>,
 
-    /// WSOL 代币账户
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+
+    /// 全局配置（检查全局暂停标志）
     #[account(
-        init_if_needed,
-        payer = user,
-        associated_token::mint = wsol_mint,
-        associated_token::authority = vault
+        seeds = [b"global_config"],
+        bump
     )]
-    pub wsol_account: Account<'info, TokenAccount>,
-
-    /// WSOL mint 账户
-    #[account(address = WSOL_MINT)]
-    pub wsol_mint: Account<'info, Mint>,
-
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
+    pub global_config: Account<'info, GlobalConfig>,
 }
 
-/// WSOL 解包装为 SOL 上下文
+/// 关闭金库上下文
+/// remaining_accounts: 按 [金库代币账户, 投资者代币账户, mint] 三个一组传入（代币账户可写）
 #[derive(Accounts)]
-pub struct UnwrapSol<'info> {
+pub struct CloseVault<'info> {
     #[account(
         mut,
-        seeds = [b"vault", user.key().as_ref()],
-        bump
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
+        bump,
+        close = user
     )]
-    pub vault: Account<'info, PersonalVault>,
+    pub vault: AccountLoader<'info, PersonalVault>,
 
+    /// 投资者（接收全部资产和租金）
     #[account(mut)]
     pub user: Signer<'info>,
 
-    /// WSOL 代币账户
+    /// 投资者金库登记账户（存在时移除该金库的登记项）
     #[account(
         mut,
-        associated_token::mint = wsol_mint,
-        associated_token::authority = vault
+        seeds = [b"vault_registry", user.key().as_ref()],
+        bump = vault_registry.bump
     )]
-    pub wsol_account: Account<'info, TokenAccount>,
-
-    /// WSOL mint 账户
-    #[account(address = WSOL_MINT)]
-    pub wsol_mint: Account<'info, Mint>,
+    pub vault_registry: Option<Account<'info, VaultRegistry>>,
 
     pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-}
+    pub token_2022_program: Program<'info, Token2022>,
 
-/// 获取余额上下文
-#[derive(Accounts)]
-pub struct GetBalance<'info> {
-    pub vault: Account<'info, PersonalVault>,
+    /// 全局配置（检查全局暂停标志）
+    #[account(
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// 以下为金库的附属 PDA：存在时随金库一并关闭（租金退还给投资者），
+    /// 避免同一编号重建的金库继承旧的策略、限额和执行者授权
+    /// CHECK: 地址由种子约束，由本程序拥有时关闭
+    #[account(
+        mut,
+        seeds = [b"signal_registry", vault.key().as_ref()],
+        bump
+    )]
+    pub signal_registry: UncheckedAccount<'info>,
+
+    /// CHECK: 地址由种子约束，由本程序拥有时关闭
+    #[account(
+        mut,
+        seeds = [b"trading_policy", vault.key().as_ref()],
+        bump
+    )]
+    pub trading_policy: UncheckedAccount<'info>,
+
+    /// CHECK: 地址由种子约束，由本程序拥有时关闭
+    #[account(
+        mut,
+        seeds = [b"trade_limits", vault.key().as_ref()],
+        bump
+    )]
+    pub trade_limits: UncheckedAccount<'info>,
+
+    /// CHECK: 地址由种子约束，由本程序拥有时关闭
+    #[account(
+        mut,
+        seeds = [b"vault_executors", vault.key().as_ref()],
+        bump
+    )]
+    pub vault_executors: UncheckedAccount<'info>,
+
+    /// CHECK: 地址由种子约束，由本程序拥有时关闭
+    #[account(
+        mut,
+        seeds = [b"trade_history", vault.key().as_ref()],
+        bump
+    )]
+    pub trade_history: UncheckedAccount<'info>,
+
+    /// CHECK: 地址由种子约束，由本程序拥有时关闭
+    #[account(
+        mut,
+        seeds = [b"vault_snapshots", vault.key().as_ref()],
+        bump
+    )]
+    pub vault_snapshots: UncheckedAccount<'info>,
 }
 
-/// 发送交易信号上下文
-/// 优化版本：将常用账户移到结构体中，减少 remaining_accounts 的复杂性
+/// 账本对账上下文
+/// remaining_accounts: 金库持有的代币账户（必须是金库的 ATA，SPL Token 或 Token-2022）
 #[derive(Accounts)]
-#[instruction(
-    token_in: Pubkey,
-    token_out: Pubkey,
-    amount_in: u64,
-    slippage_bps: u16
-)]
-pub struct SendTradeSignal<'info> {
-    /// 交易执行者（管理员或Bot）
-    pub executor: Signer<'info>,
+pub struct ReconcileBalances<'info> {
+    /// 调用者（投资者或管理员），新增代币条目时支付扩容租金
+    #[account(mut)]
+    pub caller: Signer<'info>,
 
     /// 金库所有者（用于账户推导）
-    /// CHECK: 这个账户仅用于推导其他账户，不需要验证
+    /// CHECK: 这个账户仅用于推导金库地址
     pub user: UncheckedAccount<'info>,
 
-    /// 个人金库账户
     #[account(
         mut,
-        seeds = [b"vault", user.key().as_ref()],
+        seeds = [b"vault", user.key().as_ref(), vault.load()?.index_seed()],
         bump
     )]
-    pub vault: Account<'info, PersonalVault>,
+    pub vault: AccountLoader<'info, PersonalVault>,
 
-    /// 全局配置账户
+    /// 全局配置（管理员可代为对账、检查全局暂停标志）
     #[account(
         seeds = [b"global_config"],
         bump
     )]
     pub global_config: Account<'info, GlobalConfig>,
 
-    /// DEX 程序账户（用于 CPI 调用）
-    /// CHECK: 这个账户是 DEX 程序 ID，将用于 invoke_signed
-    pub dex_program: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
 }
 
-/// 事件定义
+// 事件定义
 
 /// 余额管理器创建事件
 #[event]
@@ -327,6 +2102,7 @@ pub struct BalanceManagerCreatedEvent {
     pub vault: Pubkey,
     pub timestamp: i64,
     pub timestamp_microseconds: u64, // 微秒时间戳
+    pub vault_index: u16,
 }
 
 /// 用户存款事件
@@ -338,6 +2114,7 @@ pub struct UserDepositEvent {
     pub amount: u64,
     pub timestamp: i64,
     pub timestamp_microseconds: u64, // 微秒时间戳
+    pub bucket: u8, // 分仓编号
 }
 
 /// 用户取款事件
@@ -349,6 +2126,8 @@ pub struct UserWithdrawEvent {
     pub amount: u64,
     pub timestamp: i64,
     pub timestamp_microseconds: u64, // 微秒时间戳
+    pub destination: Pubkey, // 收款账户（代币账户或钱包地址）
+    pub bucket: u8, // 分仓编号
 }
 
 /// 交易信号事件
@@ -366,6 +2145,204 @@ pub struct TradeSignalEvent {
     pub slippage_bps: u16,
     pub fee_recipient: Pubkey,
     pub fee_amount: u64,
+    pub signal_id: u64,                // 交易信号 ID
+    pub slot: u64,                     // 执行时的槽位
+    pub valid_until_slot: u64,         // 信号有效截止槽位
+    pub deadline_unix_ts: Option<i64>, // 信号截止时间
+    pub timestamp: i64,
+    pub timestamp_microseconds: u64, // 微秒时间戳
+    pub bucket: u8, // 分仓编号
+}
+
+/// 账本不变量违反事件
+/// 账本余额超过金库实际持有量时发出（随后指令失败，事件保留在失败交易的日志中）
+#[event]
+pub struct LedgerInvariantViolatedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub mint: Pubkey,
+    pub bucket: u8,          // 本次操作涉及的分仓
+    pub ledger_amount: u64,  // 账本记录的余额（所有分仓合计）
+    pub actual_amount: u64,  // 代币账户（或扣除租金后的 lamports）实际余额
+    pub timestamp: i64,
+}
+
+/// 金库迁移事件
+#[event]
+pub struct VaultMigratedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub payer: Pubkey,
+    pub token_count: u16,
+    pub layout_version: u8,
+    pub timestamp: i64,
+}
+
+/// 账本对账事件
+/// 实际持有量超过账本余额时，差额记入账本并发出此事件
+#[event]
+pub struct BalanceReconciledEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub caller: Pubkey,
+    pub token: Pubkey,
+    pub token_account: Pubkey, // 对账的代币账户（原生 SOL 为金库地址）
+    pub ledger_amount: u64,    // 对账前的账本余额
+    pub actual_amount: u64,    // 实际持有量
+    pub delta: u64,            // 记入账本的差额
+    pub timestamp: i64,
+    pub bucket: u8, // 分仓编号
+}
+
+/// 金库压缩事件
+#[event]
+pub struct VaultCompactedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub removed_entries: u16, // 移除的零余额条目数量
+    pub token_count: u16,     // 压缩后的条目数量
+    pub old_size: u64,        // 压缩前的账户大小
+    pub new_size: u64,        // 压缩后的账户大小
+    pub rent_reclaimed: u64,  // 退还给投资者的租金
+    pub timestamp: i64,
+}
+
+/// 金库代币账户关闭事件
+#[event]
+pub struct VaultTokenAccountClosedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub token_account: Pubkey,
+    pub mint: Pubkey,
+    pub rent_reclaimed: u64,
+    pub timestamp: i64,
+}
+
+/// 金库关闭事件
+#[event]
+pub struct VaultClosedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub tokens_swept: u16,       // 转回投资者的代币种类数量
+    pub token_accounts_closed: u16,
+    pub sol_amount: u64,         // 转回投资者的 SOL（含租金）
+    pub timestamp: i64,
+}
+
+/// 代存款事件
+#[event]
+pub struct DepositForEvent {
+    pub funder: Pubkey,      // 出资人
+    pub beneficiary: Pubkey, // 受益投资者
+    pub vault: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64, // 金库实际收到的数量
     pub timestamp: i64,
     pub timestamp_microseconds: u64, // 微秒时间戳
+    pub bucket: u8, // 分仓编号
+}
+
+/// 分仓划转事件
+#[event]
+pub struct BucketTransferEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub token: Pubkey,
+    pub from_bucket: u8,
+    pub to_bucket: u8,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+/// 交易历史容量调整事件
+#[event]
+pub struct TradeHistoryResizedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub old_capacity: u16,
+    pub new_capacity: u16,
+    pub record_count: u16, // 调整后保留的记录数量
+    pub timestamp: i64,
+}
+
+/// 金库净值快照事件
+#[event]
+pub struct VaultSnapshotEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub caller: Pubkey,
+    pub quote_mint: Pubkey,
+    pub nav: u64,          // 金库总价值（报价代币最小单位）
+    pub priced_tokens: u16, // 参与估值的代币种类数量
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+/// 投资者暂停 / 恢复交易事件
+#[event]
+pub struct VaultTradingPausedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub paused: bool,
+    pub timestamp: i64,
+}
+
+/// 全局暂停标志变更事件（暂停与恢复）
+#[event]
+pub struct EmergencyPauseEvent {
+    pub authority: Pubkey, // 管理员或守护者
+    pub old_paused_operations: u8,
+    pub paused_operations: u8,
+    pub old_paused_dex_routes: u8,
+    pub paused_dex_routes: u8,
+    pub timestamp: i64,
+}
+
+/// 交易策略更新事件（移除策略时 removed 为 true）
+#[event]
+pub struct TradingPolicyUpdatedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub allowed_mints: u16, // 允许的代币数量（0 表示不限制）
+    pub max_amount_in: u64,
+    pub max_amount_in_bps: u16,
+    pub max_slippage_bps: u16,
+    pub allowed_dex_routes: u8,
+    pub min_trade_interval_secs: u32,
+    pub removed: bool,
+    pub timestamp: i64,
+}
+
+/// 交易限额更新事件（移除限额时 removed 为 true）
+#[event]
+pub struct TradeLimitsUpdatedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub quote_mint: Pubkey,
+    pub max_daily_trades: u32,
+    pub max_daily_volume: u64,
+    pub unpriced_trade_policy: UnpricedTradePolicy,
+    pub removed: bool,
+    pub timestamp: i64,
+}
+
+/// 执行者授权变更事件（撤销时 authorized 为 false）
+#[event]
+pub struct VaultExecutorUpdatedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub executor: Pubkey,
+    pub expires_at: i64, // 0 表示永不过期
+    pub authorized: bool,
+    pub timestamp: i64,
+}
+
+/// 执行者偏好变更事件（删除授权账户时 allow_global_executors 恢复为 true）
+#[event]
+pub struct ExecutorPreferenceUpdatedEvent {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub allow_global_executors: bool,
+    pub authorized_executors: u16,
+    pub timestamp: i64,
 }
@@ -1,6 +1,6 @@
 // Solana devnet 测试代码 - 更新版本匹配新的程序结构
 import { Connection, PublicKey, SystemProgram, Keypair, Transaction, TransactionInstruction, ComputeBudgetProgram } from '@solana/web3.js';
-import { TOKEN_PROGRAM_ID, ASSOCIATED_TOKEN_PROGRAM_ID, getAssociatedTokenAddress, createAssociatedTokenAccountInstruction, createInitializeAccountInstruction, createCloseAccountInstruction } from '@solana/spl-token';
+import { TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID, ASSOCIATED_TOKEN_PROGRAM_ID, getAssociatedTokenAddress, getMint, createTransferCheckedWithTransferHookInstruction, createAssociatedTokenAccountInstruction, createAssociatedTokenAccountIdempotentInstruction, createCloseAccountInstruction, createTransferInstruction, createSyncNativeInstruction, NATIVE_MINT } from '@solana/spl-token';
 import { BN } from '@coral-xyz/anchor';
 import * as fs from 'fs';
 import * as path from 'path';
@@ -108,24 +108,33 @@ function generateGlobalConfigPDA(): [PublicKey, number] {
   return [pda, bump];
 }
 
-// 生成金库 PDA
-function generateVaultPDA(userAddress: PublicKey): [PublicKey, number] {
-  const [pda, bump] = PublicKey.findProgramAddressSync(
-    [
-      Buffer.from("vault"),
-      userAddress.toBuffer()
-    ],
-    PROGRAM_ID
-  );
+// 生成金库 PDA（vaultIndex 为 0 时是旧版无编号金库）
+function generateVaultPDA(userAddress: PublicKey, vaultIndex: number = 0): [PublicKey, number] {
+  const seeds = [Buffer.from("vault"), userAddress.toBuffer()];
+  if (vaultIndex > 0) {
+    const indexBuffer = Buffer.alloc(2);
+    indexBuffer.writeUInt16LE(vaultIndex, 0);
+    seeds.push(indexBuffer);
+  }
+  const [pda, bump] = PublicKey.findProgramAddressSync(seeds, PROGRAM_ID);
 
   console.log("📝 生成金库 PDA:");
   console.log("  用户地址:", userAddress.toString());
+  console.log("  金库编号:", vaultIndex);
   console.log("  PDA 地址:", pda.toString());
   console.log("  Bump:", bump);
 
   return [pda, bump];
 }
 
+// 生成投资者金库登记 PDA
+function generateVaultRegistryPDA(userAddress: PublicKey): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("vault_registry"), userAddress.toBuffer()],
+    PROGRAM_ID
+  )[0];
+}
+
 // 生成Anchor指令discriminator
 function getInstructionDiscriminator(instructionName: string): Buffer {
   // 将camelCase转换为snake_case 
@@ -253,10 +262,25 @@ async function initializeGlobalConfig(
   }
 }
 
+// 序列化 create_balance_manager 参数: vault_index(u16) + label(Option<String>)
+function serializeVaultIndexAndLabel(vaultIndex: number, label?: string): Buffer {
+  const indexBuffer = Buffer.alloc(2);
+  indexBuffer.writeUInt16LE(vaultIndex, 0);
+  if (label === undefined) {
+    return Buffer.concat([indexBuffer, Buffer.from([0])]);
+  }
+  const labelBytes = Buffer.from(label, "utf8");
+  const lengthBuffer = Buffer.alloc(4);
+  lengthBuffer.writeUInt32LE(labelBytes.length, 0);
+  return Buffer.concat([indexBuffer, Buffer.from([1]), lengthBuffer, labelBytes]);
+}
+
 // 2. 创建余额管理器（金库）
 async function createBalanceManager(
   globalConfigPda: PublicKey,
-  userKeypair: Keypair = adminKeypair
+  userKeypair: Keypair = adminKeypair,
+  vaultIndex: number = 0, // 0 为旧版无编号金库地址
+  label?: string // 可选标签，最长 32 字节
 ): Promise<{ vaultPda: PublicKey, tx: string }> {
   try {
     console.log("\n🏦 开始创建余额管理器...");
@@ -269,7 +293,8 @@ async function createBalanceManager(
     }
 
     // 生成金库 PDA
-    const [vaultPda, vaultBump] = generateVaultPDA(userKeypair.publicKey);
+    const [vaultPda, vaultBump] = generateVaultPDA(userKeypair.publicKey, vaultIndex);
+    const vaultRegistryPda = generateVaultRegistryPDA(userKeypair.publicKey);
 
     // 检查金库是否已存在
     const vaultExists = await checkAccountExists(vaultPda);
@@ -294,11 +319,15 @@ async function createBalanceManager(
           programId: PROGRAM_ID,
           keys: [
             { pubkey: vaultPda, isSigner: false, isWritable: true },
+            { pubkey: vaultRegistryPda, isSigner: false, isWritable: true },
             { pubkey: globalConfigPda, isSigner: false, isWritable: false },
             { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
           ],
-          data: serializeInstructionData("createBalanceManager"),
+          data: Buffer.concat([
+            getInstructionDiscriminator("createBalanceManager"),
+            serializeVaultIndexAndLabel(vaultIndex, label),
+          ]),
         })
       ),
       [userKeypair]
@@ -374,12 +403,44 @@ async function ensureTokenAccount(
   }
 }
 
+// 获取 Token-2022 转账钩子所需的额外账户，顺序为 [声明的额外账户..., ExtraAccountMetaList PDA, 钩子程序]
+// 普通 mint 返回空数组；结果作为 remaining_accounts 追加到存款/取款/关闭金库/交易指令
+async function getTransferHookAccounts(
+  connection: Connection,
+  source: PublicKey,
+  mint: PublicKey,
+  destination: PublicKey,
+  owner: PublicKey,
+  amount: number,
+  tokenProgramId: PublicKey
+): Promise<{ pubkey: PublicKey; isSigner: boolean; isWritable: boolean }[]> {
+  if (!tokenProgramId.equals(TOKEN_2022_PROGRAM_ID)) {
+    return [];
+  }
+  const mintInfo = await getMint(connection, mint, "confirmed", tokenProgramId);
+  const transferInstruction = await createTransferCheckedWithTransferHookInstruction(
+    connection,
+    source,
+    mint,
+    destination,
+    owner,
+    BigInt(amount),
+    mintInfo.decimals,
+    [],
+    "confirmed",
+    tokenProgramId
+  );
+  // 前 4 个账户是 source / mint / destination / owner，其余为钩子账户
+  return transferInstruction.keys.slice(4).map((key) => ({ ...key, isSigner: false }));
+}
+
 // 3. 用户存款 (SPL 代币)
 async function userDeposit(
   vaultPda: PublicKey,
   mint: PublicKey,
   amount: number,
-  userKeypair: Keypair = user2Keypair // 默认使用 user2，可以传入其他用户
+  userKeypair: Keypair = user2Keypair, // 默认使用 user2，可以传入其他用户
+  tokenProgramId: PublicKey = TOKEN_PROGRAM_ID // Token-2022 mint 传入 TOKEN_2022_PROGRAM_ID
 ): Promise<string> {
   try {
     console.log("\n💰 用户存款...");
@@ -395,19 +456,26 @@ async function userDeposit(
     // 获取用户的代币账户地址
     const userTokenAccount = await getAssociatedTokenAddress(
       mint,
-      userKeypair.publicKey
+      userKeypair.publicKey,
+      false,
+      tokenProgramId
     );
 
     // 获取金库的代币账户地址
     const vaultTokenAccount = await getAssociatedTokenAddress(
       mint,
       vaultPda,
-      true // 允许PDA作为代币账户所有者
+      true, // 允许PDA作为代币账户所有者
+      tokenProgramId
     );
 
     console.log("  用户代币账户:", userTokenAccount.toString());
     console.log("  金库代币账户:", vaultTokenAccount.toString());
 
+    const hookAccounts = await getTransferHookAccounts(
+      connection, userTokenAccount, mint, vaultTokenAccount, userKeypair.publicKey, amount, tokenProgramId
+    );
+
     const tx = await connection.sendTransaction(
       new Transaction().add(
         new TransactionInstruction({
@@ -418,9 +486,11 @@ async function userDeposit(
             { pubkey: userTokenAccount, isSigner: false, isWritable: true },
             { pubkey: vaultTokenAccount, isSigner: false, isWritable: true },
             { pubkey: mint, isSigner: false, isWritable: false },
-            { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: tokenProgramId, isSigner: false, isWritable: false },
             { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false },
+            ...hookAccounts,
           ],
           data: serializeInstructionData("userDeposit", amount),
         })
@@ -468,6 +538,7 @@ async function userDepositSol(
             { pubkey: vaultPda, isSigner: false, isWritable: true },
             { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false },
           ],
           data: serializeInstructionData("userDepositSol", amount),
         })
@@ -490,12 +561,123 @@ async function userDepositSol(
   }
 }
 
+// 3.1 代存款：出资人向指定投资者的金库存入 SPL 代币
+async function depositFor(
+  beneficiary: PublicKey,
+  mint: PublicKey,
+  amount: number,
+  funderKeypair: Keypair = adminKeypair,
+  tokenProgramId: PublicKey = TOKEN_PROGRAM_ID
+): Promise<string> {
+  try {
+    console.log("\n🎁 代存款...");
+    console.log("  出资人地址:", funderKeypair.publicKey.toString());
+    console.log("  受益投资者地址:", beneficiary.toString());
+    console.log("  代币地址:", mint.toString());
+    console.log("  存款金额:", amount);
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const [vaultPda] = generateVaultPDA(beneficiary);
+    const funderTokenAccount = await getAssociatedTokenAddress(mint, funderKeypair.publicKey, false, tokenProgramId);
+    const vaultTokenAccount = await getAssociatedTokenAddress(mint, vaultPda, true, tokenProgramId);
+    const hookAccounts = await getTransferHookAccounts(
+      connection, funderTokenAccount, mint, vaultTokenAccount, funderKeypair.publicKey, amount, tokenProgramId
+    );
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: beneficiary, isSigner: false, isWritable: false },
+            { pubkey: vaultPda, isSigner: false, isWritable: true },
+            { pubkey: funderKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: funderTokenAccount, isSigner: false, isWritable: true },
+            { pubkey: vaultTokenAccount, isSigner: false, isWritable: true },
+            { pubkey: mint, isSigner: false, isWritable: false },
+            { pubkey: tokenProgramId, isSigner: false, isWritable: false },
+            { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false },
+            { pubkey: getTradingPolicyPda(vaultPda), isSigner: false, isWritable: false }, // trading_policy (代存只接受账本中已有或白名单中的代币)
+            ...hookAccounts,
+          ],
+          data: serializeInstructionData("depositFor", amount),
+        })
+      ),
+      [funderKeypair]
+    );
+
+    console.log("✅ 代存款成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 代存款失败:", error);
+    throw error;
+  }
+}
+
+// 3.2 代存 SOL：出资人向指定投资者的金库存入原生 SOL
+async function depositSolFor(
+  beneficiary: PublicKey,
+  amount: number,
+  funderKeypair: Keypair = adminKeypair
+): Promise<string> {
+  try {
+    console.log("\n🎁 代存 SOL...");
+    console.log("  出资人地址:", funderKeypair.publicKey.toString());
+    console.log("  受益投资者地址:", beneficiary.toString());
+    console.log("  存款金额:", amount.toLocaleString(), "lamports");
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const [vaultPda] = generateVaultPDA(beneficiary);
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: beneficiary, isSigner: false, isWritable: false },
+            { pubkey: vaultPda, isSigner: false, isWritable: true },
+            { pubkey: funderKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false },
+            { pubkey: getTradingPolicyPda(vaultPda), isSigner: false, isWritable: false }, // trading_policy (代存只接受账本中已有或白名单中的代币)
+          ],
+          data: serializeInstructionData("depositSolFor", amount),
+        })
+      ),
+      [funderKeypair]
+    );
+
+    console.log("✅ 代存 SOL 成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 代存 SOL 失败:", error);
+    throw error;
+  }
+}
+
 // 4. 用户取款 (SPL 代币)
 async function userWithdraw(
   vaultPda: PublicKey,
   mint: PublicKey,
   amount: number,
-  userKeypair: Keypair = user2Keypair // 默认使用 user2，可以传入其他用户
+  userKeypair: Keypair = user2Keypair, // 默认使用 user2，可以传入其他用户
+  tokenProgramId: PublicKey = TOKEN_PROGRAM_ID // Token-2022 mint 传入 TOKEN_2022_PROGRAM_ID
 ): Promise<string> {
   try {
     console.log("\n💸 用户取款...");
@@ -511,19 +693,26 @@ async function userWithdraw(
     // 获取用户的代币账户地址
     const userTokenAccount = await getAssociatedTokenAddress(
       mint,
-      userKeypair.publicKey
+      userKeypair.publicKey,
+      false,
+      tokenProgramId
     );
 
     // 获取金库的代币账户地址
     const vaultTokenAccount = await getAssociatedTokenAddress(
       mint,
       vaultPda,
-      true // 允许PDA作为代币账户所有者
+      true, // 允许PDA作为代币账户所有者
+      tokenProgramId
     );
 
     console.log("  用户代币账户:", userTokenAccount.toString());
     console.log("  金库代币账户:", vaultTokenAccount.toString());
 
+    const hookAccounts = await getTransferHookAccounts(
+      connection, vaultTokenAccount, mint, userTokenAccount, vaultPda, amount, tokenProgramId
+    );
+
     const tx = await connection.sendTransaction(
       new Transaction().add(
         new TransactionInstruction({
@@ -534,9 +723,11 @@ async function userWithdraw(
             { pubkey: userTokenAccount, isSigner: false, isWritable: true },
             { pubkey: vaultTokenAccount, isSigner: false, isWritable: true },
             { pubkey: mint, isSigner: false, isWritable: false },
-            { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: tokenProgramId, isSigner: false, isWritable: false },
             { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false }, // global_config (全局暂停标志)
+            ...hookAccounts,
           ],
           data: serializeInstructionData("userWithdraw", amount),
         })
@@ -583,6 +774,7 @@ async function userWithdrawSol(
             { pubkey: vaultPda, isSigner: false, isWritable: true },
             { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false }, // global_config (全局暂停标志)
           ],
           data: serializeInstructionData("userWithdrawSol", amount),
         })
@@ -604,146 +796,1621 @@ async function userWithdrawSol(
   }
 }
 
-// 5. 获取余额（View函数）
-async function getBalance(vaultPda: PublicKey, token: PublicKey): Promise<BN> {
+// 4b. 取款到指定钱包（收款 ATA 不存在时由投资者支付创建）
+async function userWithdrawTo(
+  vaultPda: PublicKey,
+  mint: PublicKey,
+  destinationOwner: PublicKey,
+  amount: number,
+  userKeypair: Keypair = user2Keypair,
+  tokenProgramId: PublicKey = TOKEN_PROGRAM_ID
+): Promise<string> {
   try {
-    console.log("\n📊 查询余额...");
-    console.log("  代币地址:", token.toString());
+    console.log("\n💸 取款到指定钱包...");
+    console.log("  用户地址:", userKeypair.publicKey.toString());
+    console.log("  收款地址:", destinationOwner.toString());
+    console.log("  代币地址:", mint.toString());
+    console.log("  取款金额:", amount);
 
     const connection = await checkConnection();
     if (!connection) {
       throw new Error("网络连接失败");
     }
 
-    // 注意：getBalance 是一个 view 函数，不会修改状态
-    // 我们需要从金库账户数据中解析余额
-    const vaultAccount = await connection.getAccountInfo(vaultPda);
+    const destinationTokenAccount = await getAssociatedTokenAddress(
+      mint,
+      destinationOwner,
+      true, // 收款方可以是 PDA
+      tokenProgramId
+    );
+    const vaultTokenAccount = await getAssociatedTokenAddress(
+      mint,
+      vaultPda,
+      true,
+      tokenProgramId
+    );
 
-    if (!vaultAccount) {
-      console.log("⚠️  金库账户不存在");
-      return new BN(0);
-    }
+    console.log("  收款代币账户:", destinationTokenAccount.toString());
+    console.log("  金库代币账户:", vaultTokenAccount.toString());
 
-    console.log("✅ 获取金库账户信息成功");
-    console.log("  账户数据长度:", vaultAccount.data.length);
-    console.log("  账户所有者:", vaultAccount.owner.toString());
+    const hookAccounts = await getTransferHookAccounts(
+      connection, vaultTokenAccount, mint, destinationTokenAccount, vaultPda, amount, tokenProgramId
+    );
 
-    // 解析账户数据
-    const vaultData = parsePersonalVaultAccount(vaultAccount.data);
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: vaultPda, isSigner: false, isWritable: true },
+            { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: destinationOwner, isSigner: false, isWritable: false },
+            { pubkey: destinationTokenAccount, isSigner: false, isWritable: true },
+            { pubkey: vaultTokenAccount, isSigner: false, isWritable: true },
+            { pubkey: mint, isSigner: false, isWritable: false },
+            { pubkey: tokenProgramId, isSigner: false, isWritable: false },
+            { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false }, // global_config (全局暂停标志)
+            ...hookAccounts,
+          ],
+          data: serializeInstructionData("userWithdrawTo", amount),
+        })
+      ),
+      [userKeypair]
+    );
 
-    if (!vaultData) {
-      console.log("⚠️  账户数据解析失败");
-      return new BN(0);
+    console.log("✅ 取款到指定钱包成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 取款到指定钱包失败:", error);
+    throw error;
+  }
+}
+
+// 4c. 取出原生 SOL 到指定钱包
+async function userWithdrawSolTo(
+  vaultPda: PublicKey,
+  destination: PublicKey,
+  amount: number,
+  userKeypair: Keypair = user2Keypair
+): Promise<string> {
+  try {
+    console.log("\n💸 取出 SOL 到指定钱包...");
+    console.log("  用户地址:", userKeypair.publicKey.toString());
+    console.log("  收款地址:", destination.toString());
+    console.log("  取款金额:", amount.toLocaleString(), "lamports");
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
     }
 
-    console.log("✅ 账户数据解析成功");
-    console.log("  投资者:", vaultData.investor);
-    console.log("  已初始化:", vaultData.isInitialized);
-    console.log("  代币余额数量:", vaultData.balances.length);
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: vaultPda, isSigner: false, isWritable: true },
+            { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: destination, isSigner: false, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false }, // global_config (全局暂停标志)
+          ],
+          data: serializeInstructionData("userWithdrawSolTo", amount),
+        })
+      ),
+      [userKeypair]
+    );
 
-    // 查找特定代币的余额
-    const tokenStr = token.toString();
-    console.log("🔍 查找代币:", tokenStr);
-    console.log("🔍 现有代币余额:");
-    vaultData.balances.forEach((balance: any, index: number) => {
-      console.log(`  ${index + 1}. 代币: ${balance.token}, 余额: ${balance.amount} lamports`);
-    });
+    console.log("✅ SOL 取款到指定钱包成功!");
+    console.log("  交易签名:", tx);
 
-    const balanceEntry = vaultData.balances.find((balance: any) =>
-      balance.token === tokenStr
+    return tx;
+
+  } catch (error) {
+    console.error("❌ SOL 取款到指定钱包失败:", error);
+    throw error;
+  }
+}
+
+// 序列化批量存取款参数 Vec<TokenTransfer>: [长度 u32] + 每项 [mint(32) + amount(u64)]
+function serializeTokenTransfers(transfers: { mint: PublicKey; amount: number }[]): Buffer {
+  const length = Buffer.alloc(4);
+  length.writeUInt32LE(transfers.length, 0);
+  const items = transfers.map(({ mint, amount }) => {
+    const amountBuffer = Buffer.alloc(8);
+    amountBuffer.writeBigUInt64LE(BigInt(amount), 0);
+    return Buffer.concat([mint.toBuffer(), amountBuffer]);
+  });
+  return Buffer.concat([length, ...items]);
+}
+
+// 构建批量存取款的 remaining_accounts: 每种代币 [用户代币账户, 金库代币账户, mint]
+async function getBatchTokenAccounts(
+  connection: Connection,
+  vaultPda: PublicKey,
+  owner: PublicKey,
+  transfers: { mint: PublicKey; amount: number }[]
+) {
+  const keys = [];
+  for (const { mint } of transfers) {
+    const tokenProgramId = await getMintTokenProgram(connection, mint);
+    const userTokenAccount = await getAssociatedTokenAddress(mint, owner, false, tokenProgramId);
+    const vaultTokenAccount = await getAssociatedTokenAddress(mint, vaultPda, true, tokenProgramId);
+    keys.push(
+      { pubkey: userTokenAccount, isSigner: false, isWritable: true },
+      { pubkey: vaultTokenAccount, isSigner: false, isWritable: true },
+      { pubkey: mint, isSigner: false, isWritable: false },
     );
+  }
+  return keys;
+}
 
-    if (balanceEntry) {
-      const balance = new BN(balanceEntry.amount);
-      console.log("✅ 找到代币余额:", balance.toNumber().toLocaleString(), "lamports (" + (balance.toNumber() / 1_000_000_000) + " SOL)");
-      return balance;
-    } else {
-      console.log("⚠️  未找到代币余额，返回0");
-      return new BN(0);
+// 4d. 批量存款（带转账钩子的 mint 请使用 userDeposit）
+async function batchDeposit(
+  vaultPda: PublicKey,
+  transfers: { mint: PublicKey; amount: number }[],
+  userKeypair: Keypair = user2Keypair
+): Promise<string> {
+  try {
+    console.log("\n💰 批量存款...");
+    console.log("  用户地址:", userKeypair.publicKey.toString());
+    transfers.forEach(({ mint, amount }) => console.log(`  ${mint.toString()}: ${amount}`));
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const tokenAccounts = await getBatchTokenAccounts(connection, vaultPda, userKeypair.publicKey, transfers);
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: vaultPda, isSigner: false, isWritable: true },
+            { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: TOKEN_2022_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false },
+            ...tokenAccounts,
+          ],
+          data: Buffer.concat([getInstructionDiscriminator("batchDeposit"), serializeTokenTransfers(transfers)]),
+        })
+      ),
+      [userKeypair]
+    );
+
+    console.log("✅ 批量存款成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 批量存款失败:", error);
+    throw error;
+  }
+}
+
+// 4e. 批量取款（带转账钩子的 mint 请使用 userWithdraw）
+async function batchWithdraw(
+  vaultPda: PublicKey,
+  transfers: { mint: PublicKey; amount: number }[],
+  userKeypair: Keypair = user2Keypair
+): Promise<string> {
+  try {
+    console.log("\n💸 批量取款...");
+    console.log("  用户地址:", userKeypair.publicKey.toString());
+    transfers.forEach(({ mint, amount }) => console.log(`  ${mint.toString()}: ${amount}`));
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
     }
 
-  } catch (error) {
-    console.error("❌ 查询余额失败:", error);
-    throw error;
+    const tokenAccounts = await getBatchTokenAccounts(connection, vaultPda, userKeypair.publicKey, transfers);
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: vaultPda, isSigner: false, isWritable: true },
+            { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: TOKEN_2022_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false }, // global_config (全局暂停标志)
+            ...tokenAccounts,
+          ],
+          data: Buffer.concat([getInstructionDiscriminator("batchWithdraw"), serializeTokenTransfers(transfers)]),
+        })
+      ),
+      [userKeypair]
+    );
+
+    console.log("✅ 批量取款成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 批量取款失败:", error);
+    throw error;
+  }
+}
+
+// 5a. 查询持仓（累计存取、成本、平均成本、已实现盈亏、成本是否未知），与链上 get_position 的计算一致
+async function getPosition(vaultPda: PublicKey, token: PublicKey, bucket: number = 0) {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const vaultAccount = await connection.getAccountInfo(vaultPda);
+  const vaultData = vaultAccount ? parsePersonalVaultAccount(vaultAccount.data) : null;
+  const entry = vaultData?.balances.find(
+    (balance: any) => balance.token === token.toString() && balance.bucket === bucket
+  );
+  if (!entry) {
+    return null;
+  }
+
+  const amount = BigInt(entry.amount);
+  const costBasis = BigInt(entry.costBasis);
+  // 平均成本放大 10^9 倍
+  const averageCost = amount === BigInt(0) ? BigInt(0) : costBasis * BigInt(1_000_000_000) / amount;
+
+  console.log("\n📈 持仓信息:");
+  console.log("  代币:", entry.token, "分仓:", bucket);
+  console.log("  余额:", entry.amount);
+  console.log("  累计存入:", entry.totalDeposited, "累计取出:", entry.totalWithdrawn);
+  console.log("  持仓成本:", entry.costBasis, "平均成本(x1e9):", averageCost.toString());
+  console.log("  已实现盈亏:", entry.realizedPnl);
+  if (entry.basisUnknown) {
+    console.log("  ⚠️ 持仓含有成本未知的部分（零成本存入），卖出时不计已实现盈亏");
+  }
+
+  return { ...entry, averageCost: averageCost.toString() };
+}
+
+// 5. 获取余额（View函数）
+async function getBalance(vaultPda: PublicKey, token: PublicKey): Promise<BN> {
+  try {
+    console.log("\n📊 查询余额...");
+    console.log("  代币地址:", token.toString());
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    // 注意：getBalance 是一个 view 函数，不会修改状态
+    // 我们需要从金库账户数据中解析余额
+    const vaultAccount = await connection.getAccountInfo(vaultPda);
+
+    if (!vaultAccount) {
+      console.log("⚠️  金库账户不存在");
+      return new BN(0);
+    }
+
+    console.log("✅ 获取金库账户信息成功");
+    console.log("  账户数据长度:", vaultAccount.data.length);
+    console.log("  账户所有者:", vaultAccount.owner.toString());
+
+    // 解析账户数据
+    const vaultData = parsePersonalVaultAccount(vaultAccount.data);
+
+    if (!vaultData) {
+      console.log("⚠️  账户数据解析失败");
+      return new BN(0);
+    }
+
+    console.log("✅ 账户数据解析成功");
+    console.log("  投资者:", vaultData.investor);
+    console.log("  已初始化:", vaultData.isInitialized);
+    console.log("  代币余额数量:", vaultData.balances.length);
+
+    // 查找特定代币的余额
+    const tokenStr = token.toString();
+    console.log("🔍 查找代币:", tokenStr);
+    console.log("🔍 现有代币余额:");
+    vaultData.balances.forEach((balance: any, index: number) => {
+      console.log(`  ${index + 1}. 代币: ${balance.token}, 余额: ${balance.amount} lamports`);
+    });
+
+    const balanceEntry = vaultData.balances.find((balance: any) =>
+      balance.token === tokenStr
+    );
+
+    if (balanceEntry) {
+      const balance = new BN(balanceEntry.amount);
+      console.log("✅ 找到代币余额:", balance.toNumber().toLocaleString(), "lamports (" + (balance.toNumber() / 1_000_000_000) + " SOL)");
+      return balance;
+    } else {
+      console.log("⚠️  未找到代币余额，返回0");
+      return new BN(0);
+    }
+
+  } catch (error) {
+    console.error("❌ 查询余额失败:", error);
+    throw error;
+  }
+}
+
+// 6. 设置机器人地址
+async function setBot(globalConfigPda: PublicKey, newBotAddress: PublicKey): Promise<string> {
+  try {
+    console.log("\n🤖 设置机器人地址...");
+    console.log("  新机器人地址:", newBotAddress.toString());
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: globalConfigPda, isSigner: false, isWritable: true },
+            { pubkey: adminKeypair.publicKey, isSigner: true, isWritable: false },
+          ],
+          data: serializeInstructionData("setBot", newBotAddress),
+        })
+      ),
+      [adminKeypair]
+    );
+
+    console.log("✅ 机器人地址设置成功!");
+    console.log("  交易签名:", tx);
+
+    // 打印交易查看链接
+    console.log("\n🔗 交易查看链接:");
+    console.log(`https://explorer.solana.com/tx/${tx}?cluster=devnet`);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 设置机器人失败:", error);
+    throw error;
+  }
+}
+
+// 6a. 全局紧急暂停
+// 暂停标志位：1 = 交易，2 = 存款，4 = 取款；DEX 适配器标志位按路由索引（1 = AMM V4，2 = CLMM）
+const PAUSE_TRADING = 1;
+const PAUSE_DEPOSITS = 2;
+const PAUSE_WITHDRAWALS = 4;
+
+// 迁移旧版全局配置账户到当前布局（升级程序后由管理员执行一次）
+async function migrateGlobalConfig(adminKp: Keypair = adminKeypair): Promise<string> {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const tx = await connection.sendTransaction(
+    new Transaction().add(
+      new TransactionInstruction({
+        programId: PROGRAM_ID,
+        keys: [
+          { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: true },
+          { pubkey: adminKp.publicKey, isSigner: true, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ],
+        data: getInstructionDiscriminator("migrateGlobalConfig"),
+      })
+    ),
+    [adminKp]
+  );
+  console.log("✅ 全局配置迁移完成:", tx);
+  return tx;
+}
+
+// 迁移信号记录账户到带有效截止槽位的布局（任何人可调用，调用者支付新增租金）
+async function migrateSignalRegistry(vaultPda: PublicKey, payerKp: Keypair = adminKeypair): Promise<string> {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const tx = await connection.sendTransaction(
+    new Transaction().add(
+      new TransactionInstruction({
+        programId: PROGRAM_ID,
+        keys: [
+          { pubkey: getSignalRegistryPda(vaultPda)[0], isSigner: false, isWritable: true },
+          { pubkey: vaultPda, isSigner: false, isWritable: false },
+          { pubkey: payerKp.publicKey, isSigner: true, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ],
+        data: getInstructionDiscriminator("migrateSignalRegistry"),
+      })
+    ),
+    [payerKp]
+  );
+  console.log("✅ 信号记录账户迁移完成:", tx);
+  return tx;
+}
+
+// 设置守护者（管理员），传入 PublicKey.default 取消守护者
+async function setGuardian(guardian: PublicKey, adminKp: Keypair = adminKeypair): Promise<string> {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const tx = await connection.sendTransaction(
+    new Transaction().add(
+      new TransactionInstruction({
+        programId: PROGRAM_ID,
+        keys: [
+          { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: true },
+          { pubkey: adminKp.publicKey, isSigner: true, isWritable: false },
+        ],
+        data: Buffer.concat([getInstructionDiscriminator("setGuardian"), guardian.toBuffer()]),
+      })
+    ),
+    [adminKp]
+  );
+  console.log("✅ 守护者设置完成:", tx);
+  return tx;
+}
+
+// 设置全局暂停标志（管理员或守护者），传入 0 恢复全部操作
+async function setPauseFlags(
+  pausedOperations: number,
+  pausedDexRoutes: number,
+  authorityKp: Keypair = adminKeypair
+): Promise<string> {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const tx = await connection.sendTransaction(
+    new Transaction().add(
+      new TransactionInstruction({
+        programId: PROGRAM_ID,
+        keys: [
+          { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: true },
+          { pubkey: authorityKp.publicKey, isSigner: true, isWritable: false },
+        ],
+        data: Buffer.concat([
+          getInstructionDiscriminator("setPauseFlags"),
+          Buffer.from([pausedOperations, pausedDexRoutes]),
+        ]),
+      })
+    ),
+    [authorityKp]
+  );
+  console.log("✅ 暂停标志设置完成:", tx);
+  return tx;
+}
+
+// 7. 设置管理员
+async function setAdmin(globalConfigPda: PublicKey, newAdmin: PublicKey): Promise<string> {
+  try {
+    console.log("\n👤 设置管理员...");
+    console.log("  新管理员地址:", newAdmin.toString());
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: globalConfigPda, isSigner: false, isWritable: true },
+            { pubkey: adminKeypair.publicKey, isSigner: true, isWritable: false },
+          ],
+          data: serializeInstructionData("setAdmin", newAdmin),
+        })
+      ),
+      [adminKeypair]
+    );
+
+    console.log("✅ 管理员设置成功!");
+    console.log("  交易签名:", tx);
+
+    // 打印交易查看链接
+    console.log("\n🔗 交易查看链接:");
+    console.log(`https://explorer.solana.com/tx/${tx}?cluster=devnet`);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 设置管理员失败:", error);
+    throw error;
+  }
+}
+
+// 根据 mint 账户的所有者获取对应的代币程序（SPL Token 或 Token-2022）
+async function getMintTokenProgram(connection: Connection, mint: PublicKey): Promise<PublicKey> {
+  const mintAccount = await connection.getAccountInfo(mint);
+  if (!mintAccount) {
+    throw new Error(`mint 账户不存在: ${mint.toString()}`);
+  }
+  return mintAccount.owner;
+}
+
+// 7.1 账本对账（将直接转入金库 ATA 的代币和捐赠给金库的 SOL 记入账本）
+async function reconcileBalances(
+  userAddress: PublicKey,
+  mints: PublicKey[],
+  callerKeypair: Keypair = user2Keypair
+): Promise<string> {
+  try {
+    console.log("\n🧾 账本对账...");
+    console.log("  调用者地址:", callerKeypair.publicKey.toString());
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const [vaultPda] = generateVaultPDA(userAddress);
+    const vaultTokenAccounts = await Promise.all(
+      mints.map(async (mint) =>
+        getAssociatedTokenAddress(mint, vaultPda, true, await getMintTokenProgram(connection, mint))
+      )
+    );
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: callerKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: userAddress, isSigner: false, isWritable: false },
+            { pubkey: vaultPda, isSigner: false, isWritable: true },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            ...vaultTokenAccounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false })),
+          ],
+          data: serializeInstructionData("reconcileBalances"),
+        })
+      ),
+      [callerKeypair]
+    );
+
+    console.log("✅ 账本对账成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 账本对账失败:", error);
+    throw error;
+  }
+}
+
+// 7.2 压缩金库（移除零余额条目，关闭空的金库代币账户，租金退还给投资者）
+async function compactVault(
+  emptyMints: PublicKey[] = [],
+  userKeypair: Keypair = user2Keypair
+): Promise<string> {
+  try {
+    console.log("\n🗜️  压缩金库...");
+    console.log("  用户地址:", userKeypair.publicKey.toString());
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const [vaultPda] = generateVaultPDA(userKeypair.publicKey);
+    const emptyTokenAccounts = await Promise.all(
+      emptyMints.map(async (mint) =>
+        getAssociatedTokenAddress(mint, vaultPda, true, await getMintTokenProgram(connection, mint))
+      )
+    );
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: vaultPda, isSigner: false, isWritable: true },
+            { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: TOKEN_2022_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false }, // global_config (全局暂停标志)
+            ...emptyTokenAccounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true })),
+          ],
+          data: serializeInstructionData("compactVault"),
+        })
+      ),
+      [userKeypair]
+    );
+
+    console.log("✅ 金库压缩成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 金库压缩失败:", error);
+    throw error;
+  }
+}
+
+// 7.3 关闭金库（全部代币和 SOL 转回投资者）
+async function closeVault(
+  mints: PublicKey[],
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  try {
+    console.log("\n🔚 关闭金库...");
+    console.log("  用户地址:", userKeypair.publicKey.toString());
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const [vaultPda] = generateVaultPDA(userKeypair.publicKey, vaultIndex);
+
+    // 登记账户不存在时传入程序 ID 表示可选账户为空
+    const vaultRegistryPda = generateVaultRegistryPDA(userKeypair.publicKey);
+    const registryExists = await checkAccountExists(vaultRegistryPda);
+
+    // 三个一组传入 [金库代币账户, 投资者代币账户, mint]；带转账钩子的 mint 紧接着附加
+    // 钩子账户 [额外账户..., ExtraAccountMetaList PDA, 钩子程序]
+    const tokenAccountGroups = [];
+    for (const mint of mints) {
+      const tokenProgramId = await getMintTokenProgram(connection, mint);
+      const vaultTokenAccount = await getAssociatedTokenAddress(mint, vaultPda, true, tokenProgramId);
+      const userTokenAccount = await getAssociatedTokenAddress(mint, userKeypair.publicKey, false, tokenProgramId);
+      const vaultBalance = await connection.getTokenAccountBalance(vaultTokenAccount);
+      const hookAccounts = await getTransferHookAccounts(
+        connection, vaultTokenAccount, mint, userTokenAccount, vaultPda,
+        Number(vaultBalance.value.amount), tokenProgramId
+      );
+      tokenAccountGroups.push(
+        { pubkey: vaultTokenAccount, isSigner: false, isWritable: true },
+        { pubkey: userTokenAccount, isSigner: false, isWritable: true },
+        { pubkey: mint, isSigner: false, isWritable: false },
+        ...hookAccounts,
+      );
+    }
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: vaultPda, isSigner: false, isWritable: true },
+            { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: registryExists ? vaultRegistryPda : PROGRAM_ID, isSigner: false, isWritable: registryExists },
+            { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: TOKEN_2022_PROGRAM_ID, isSigner: false, isWritable: false },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false }, // global_config (全局暂停标志)
+            // 附属 PDA：存在时随金库一并关闭，租金退还给投资者
+            { pubkey: getSignalRegistryPda(vaultPda)[0], isSigner: false, isWritable: true },
+            { pubkey: getTradingPolicyPda(vaultPda), isSigner: false, isWritable: true },
+            { pubkey: getTradeLimitsPda(vaultPda), isSigner: false, isWritable: true },
+            { pubkey: getVaultExecutorsPda(vaultPda), isSigner: false, isWritable: true },
+            { pubkey: getTradeHistoryPda(vaultPda), isSigner: false, isWritable: true },
+            {
+              pubkey: PublicKey.findProgramAddressSync([Buffer.from("vault_snapshots"), vaultPda.toBuffer()], PROGRAM_ID)[0],
+              isSigner: false,
+              isWritable: true,
+            },
+            ...tokenAccountGroups,
+          ],
+          data: serializeInstructionData("closeVault"),
+        })
+      ),
+      [userKeypair]
+    );
+
+    console.log("✅ 金库关闭成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 金库关闭失败:", error);
+    throw error;
+  }
+}
+
+// 7.6 设置分仓名称（对应的策略）
+async function setBucketLabel(
+  bucket: number,
+  label: string,
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  try {
+    console.log("\n🏷️  设置分仓名称...");
+    console.log("  分仓:", bucket, "名称:", label);
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const [vaultPda] = generateVaultPDA(userKeypair.publicKey, vaultIndex);
+    const labelBytes = Buffer.from(label, "utf8");
+    const lengthBuffer = Buffer.alloc(4);
+    lengthBuffer.writeUInt32LE(labelBytes.length, 0);
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: vaultPda, isSigner: false, isWritable: true },
+            { pubkey: userKeypair.publicKey, isSigner: true, isWritable: false },
+          ],
+          data: Buffer.concat([
+            getInstructionDiscriminator("setBucketLabel"),
+            Buffer.from([bucket]),
+            lengthBuffer,
+            labelBytes,
+          ]),
+        })
+      ),
+      [userKeypair]
+    );
+
+    console.log("✅ 分仓名称设置成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 分仓名称设置失败:", error);
+    throw error;
+  }
+}
+
+// 7.7 在分仓之间划转余额
+async function moveBetweenBuckets(
+  token: PublicKey,
+  fromBucket: number,
+  toBucket: number,
+  amount: number,
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  try {
+    console.log("\n🔀 分仓划转...");
+    console.log("  代币:", token.toString());
+    console.log(`  分仓 ${fromBucket} -> ${toBucket}, 数量: ${amount}`);
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const [vaultPda] = generateVaultPDA(userKeypair.publicKey, vaultIndex);
+    const amountBuffer = Buffer.alloc(8);
+    amountBuffer.writeBigUInt64LE(BigInt(amount), 0);
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: vaultPda, isSigner: false, isWritable: true },
+            { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+          ],
+          data: Buffer.concat([
+            getInstructionDiscriminator("moveBetweenBuckets"),
+            token.toBuffer(),
+            Buffer.from([fromBucket, toBucket]),
+            amountBuffer,
+          ]),
+        })
+      ),
+      [userKeypair]
+    );
+
+    console.log("✅ 分仓划转成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 分仓划转失败:", error);
+    throw error;
+  }
+}
+
+// 7.12 暂停 / 恢复金库交易（投资者），暂停期间存取款不受影响
+async function setTradingPaused(
+  paused: boolean,
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  try {
+    console.log(paused ? "\n⏸️  暂停金库交易..." : "\n▶️  恢复金库交易...");
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const [vaultPda] = generateVaultPDA(userKeypair.publicKey, vaultIndex);
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: vaultPda, isSigner: false, isWritable: true },
+            { pubkey: userKeypair.publicKey, isSigner: true, isWritable: false },
+          ],
+          data: getInstructionDiscriminator(paused ? "pauseTrading" : "resumeTrading"),
+        })
+      ),
+      [userKeypair]
+    );
+
+    console.log("✅ 操作成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 操作失败:", error);
+    throw error;
+  }
+}
+
+// 7.4 登记已有金库（登记账户出现之前创建的金库）
+async function registerVault(
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  try {
+    console.log("\n🗂️  登记金库...");
+    console.log("  用户地址:", userKeypair.publicKey.toString());
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const [vaultPda] = generateVaultPDA(userKeypair.publicKey, vaultIndex);
+    const vaultRegistryPda = generateVaultRegistryPDA(userKeypair.publicKey);
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: vaultPda, isSigner: false, isWritable: false },
+            { pubkey: vaultRegistryPda, isSigner: false, isWritable: true },
+            { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+          ],
+          data: serializeInstructionData("registerVault"),
+        })
+      ),
+      [userKeypair]
+    );
+
+    console.log("✅ 金库登记成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 金库登记失败:", error);
+    throw error;
+  }
+}
+
+// 7.5 查询投资者的全部金库
+async function getVaultRegistry(userAddress: PublicKey) {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const registryAccount = await connection.getAccountInfo(generateVaultRegistryPDA(userAddress));
+  if (!registryAccount) {
+    return [];
+  }
+
+  // 布局: 鉴别器(8) + investor(32) + bump(1) + vaults(Vec<VaultRegistryEntry>)
+  const data = registryAccount.data;
+  let offset = 8 + 32 + 1;
+  const count = data.readUInt32LE(offset);
+  offset += 4;
+  const vaults = [];
+  for (let i = 0; i < count; i++) {
+    const vaultIndex = data.readUInt16LE(offset);
+    const label = data.slice(offset + 2, offset + 34).toString("utf8").replace(/\0+$/, "");
+    const vault = new PublicKey(data.slice(offset + 34, offset + 66));
+    vaults.push({ vaultIndex, label, vault: vault.toString() });
+    offset += 66;
+  }
+  return vaults;
+}
+
+// 生成执行者授权账户 PDA
+function getVaultExecutorsPda(vaultPda: PublicKey): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("vault_executors"), vaultPda.toBuffer()],
+    PROGRAM_ID
+  )[0];
+}
+
+// 执行者授权相关指令的公共账户（投资者签名，首次设置时支付租金）
+function vaultExecutorsKeys(userKeypair: Keypair, vaultIndex: number, withSystemProgram: boolean = true) {
+  const [vaultPda] = generateVaultPDA(userKeypair.publicKey, vaultIndex);
+  const keys = [
+    { pubkey: vaultPda, isSigner: false, isWritable: false },
+    { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+    { pubkey: getVaultExecutorsPda(vaultPda), isSigner: false, isWritable: true },
+  ];
+  if (withSystemProgram) {
+    keys.push({ pubkey: SystemProgram.programId, isSigner: false, isWritable: false });
+  }
+  return keys;
+}
+
+// 7.18 授权执行者（投资者），expiresAt 为 unix 时间，null 表示永不过期
+async function authorizeVaultExecutor(
+  executor: PublicKey,
+  expiresAt: number | null = null,
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  try {
+    console.log("\n🤝 授权执行者...");
+    console.log("  执行者:", executor.toString(), "过期时间:", expiresAt ?? "永不过期");
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: vaultExecutorsKeys(userKeypair, vaultIndex),
+          data: Buffer.concat([
+            getInstructionDiscriminator("authorizeVaultExecutor"),
+            executor.toBuffer(),
+            serializeOptionalU64(expiresAt, true),
+          ]),
+        })
+      ),
+      [userKeypair]
+    );
+
+    console.log("✅ 执行者授权成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 执行者授权失败:", error);
+    throw error;
+  }
+}
+
+// 7.19 撤销执行者授权（投资者）
+async function revokeVaultExecutor(
+  executor: PublicKey,
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const keys = vaultExecutorsKeys(userKeypair, vaultIndex, false);
+  keys[1].isWritable = false;
+  const tx = await connection.sendTransaction(
+    new Transaction().add(
+      new TransactionInstruction({
+        programId: PROGRAM_ID,
+        keys,
+        data: Buffer.concat([getInstructionDiscriminator("revokeVaultExecutor"), executor.toBuffer()]),
+      })
+    ),
+    [userKeypair]
+  );
+  console.log("✅ 执行者授权已撤销:", tx);
+  return tx;
+}
+
+// 7.20 设置是否接受全局 bot/admin 执行交易（投资者），false 表示只接受金库授权的执行者
+async function setExecutorPreference(
+  allowGlobalExecutors: boolean,
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const tx = await connection.sendTransaction(
+    new Transaction().add(
+      new TransactionInstruction({
+        programId: PROGRAM_ID,
+        keys: vaultExecutorsKeys(userKeypair, vaultIndex),
+        data: Buffer.concat([
+          getInstructionDiscriminator("setExecutorPreference"),
+          Buffer.from([allowGlobalExecutors ? 1 : 0]),
+        ]),
+      })
+    ),
+    [userKeypair]
+  );
+  console.log("✅ 执行者偏好已更新:", tx);
+  return tx;
+}
+
+// 7.21 删除执行者授权（投资者），恢复为只接受全局 bot/admin
+async function closeVaultExecutors(
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const tx = await connection.sendTransaction(
+    new Transaction().add(
+      new TransactionInstruction({
+        programId: PROGRAM_ID,
+        keys: vaultExecutorsKeys(userKeypair, vaultIndex, false),
+        data: getInstructionDiscriminator("closeVaultExecutors"),
+      })
+    ),
+    [userKeypair]
+  );
+  console.log("✅ 执行者授权已删除:", tx);
+  return tx;
+}
+
+// 7.22 读取金库的执行者授权，未设置时返回 null
+async function getVaultExecutors(vaultPda: PublicKey) {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const account = await connection.getAccountInfo(getVaultExecutorsPda(vaultPda));
+  if (!account) {
+    return null;
+  }
+
+  // 布局: 鉴别器(8) + vault(32) + bump(1) + allow_global_executors(1) + executors(Vec<VaultExecutorEntry>)
+  const data = account.data;
+  const allowGlobalExecutors = data[8 + 32 + 1] !== 0;
+  let offset = 8 + 32 + 2;
+  const count = data.readUInt32LE(offset);
+  offset += 4;
+  const executors = [];
+  for (let i = 0; i < count; i++) {
+    executors.push({
+      executor: new PublicKey(data.slice(offset, offset + 32)).toString(),
+      expiresAt: data.readBigInt64LE(offset + 32).toString(),
+    });
+    offset += 40;
+  }
+  return { allowGlobalExecutors, executors };
+}
+
+// 生成交易策略账户 PDA
+function getTradingPolicyPda(vaultPda: PublicKey): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("trading_policy"), vaultPda.toBuffer()],
+    PROGRAM_ID
+  )[0];
+}
+
+// 交易策略参数（各项为 0 或空列表时表示不限制）
+interface TradingPolicyParams {
+  allowedMints: PublicKey[]; // 允许交易的代币（输入和输出都必须在列表中）
+  maxAmountIn: number; // 单笔输入数量上限
+  maxAmountInBps: number; // 单笔输入占该分仓余额的比例上限（基点）
+  maxSlippageBps: number; // 滑点上限（基点）
+  allowedDexRoutes: number; // 允许的 DEX 适配器位掩码（1 = AMM V4，2 = CLMM）
+  minTradeIntervalSecs: number; // 两次交易之间的最小间隔（秒）
+}
+
+// 7.13 设置交易策略（投资者）
+async function setTradingPolicy(
+  params: TradingPolicyParams,
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  try {
+    console.log("\n📐 设置交易策略...");
+    console.log("  参数:", { ...params, allowedMints: params.allowedMints.map((mint) => mint.toString()) });
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const [vaultPda] = generateVaultPDA(userKeypair.publicKey, vaultIndex);
+
+    // Borsh: Vec<Pubkey> + u64 + u16 + u16 + u8 + u32
+    const mintCount = Buffer.alloc(4);
+    mintCount.writeUInt32LE(params.allowedMints.length, 0);
+    const limits = Buffer.alloc(8 + 2 + 2 + 1 + 4);
+    limits.writeBigUInt64LE(BigInt(params.maxAmountIn), 0);
+    limits.writeUInt16LE(params.maxAmountInBps, 8);
+    limits.writeUInt16LE(params.maxSlippageBps, 10);
+    limits.writeUInt8(params.allowedDexRoutes, 12);
+    limits.writeUInt32LE(params.minTradeIntervalSecs, 13);
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: vaultPda, isSigner: false, isWritable: false },
+            { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: getTradingPolicyPda(vaultPda), isSigner: false, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+          ],
+          data: Buffer.concat([
+            getInstructionDiscriminator("setTradingPolicy"),
+            mintCount,
+            ...params.allowedMints.map((mint) => mint.toBuffer()),
+            limits,
+          ]),
+        })
+      ),
+      [userKeypair]
+    );
+
+    console.log("✅ 交易策略设置成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 交易策略设置失败:", error);
+    throw error;
+  }
+}
+
+// 7.14 移除交易策略（投资者）
+async function closeTradingPolicy(
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const [vaultPda] = generateVaultPDA(userKeypair.publicKey, vaultIndex);
+  const tx = await connection.sendTransaction(
+    new Transaction().add(
+      new TransactionInstruction({
+        programId: PROGRAM_ID,
+        keys: [
+          { pubkey: vaultPda, isSigner: false, isWritable: false },
+          { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+          { pubkey: getTradingPolicyPda(vaultPda), isSigner: false, isWritable: true },
+        ],
+        data: getInstructionDiscriminator("closeTradingPolicy"),
+      })
+    ),
+    [userKeypair]
+  );
+  console.log("✅ 交易策略已移除:", tx);
+  return tx;
+}
+
+// 生成交易限额账户 PDA
+function getTradeLimitsPda(vaultPda: PublicKey): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("trade_limits"), vaultPda.toBuffer()],
+    PROGRAM_ID
+  )[0];
+}
+
+// 7.15 设置 24 小时交易限额（投资者），上限为 0 表示不限制
+// unpricedTradePolicy：设置了交易额上限时，两侧都不是计价代币的交易 0 = 拒绝，1 = 只计次数
+async function setTradeLimits(
+  quoteMint: PublicKey,
+  maxDailyTrades: number,
+  maxDailyVolume: number,
+  unpricedTradePolicy: number = 0,
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  try {
+    console.log("\n📊 设置交易限额...");
+    console.log("  计价代币:", quoteMint.toString());
+    console.log("  交易次数上限:", maxDailyTrades, "交易额上限:", maxDailyVolume);
+    console.log("  未计价交易:", unpricedTradePolicy === 0 ? "拒绝" : "只计次数");
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const [vaultPda] = generateVaultPDA(userKeypair.publicKey, vaultIndex);
+
+    // Borsh: Pubkey + u32 + u64 + UnpricedTradePolicy(1 字节变体索引)
+    const limits = Buffer.alloc(4 + 8 + 1);
+    limits.writeUInt32LE(maxDailyTrades, 0);
+    limits.writeBigUInt64LE(BigInt(maxDailyVolume), 4);
+    limits.writeUInt8(unpricedTradePolicy, 12);
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: PROGRAM_ID,
+          keys: [
+            { pubkey: vaultPda, isSigner: false, isWritable: false },
+            { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: getTradeLimitsPda(vaultPda), isSigner: false, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+          ],
+          data: Buffer.concat([
+            getInstructionDiscriminator("setTradeLimits"),
+            quoteMint.toBuffer(),
+            limits,
+          ]),
+        })
+      ),
+      [userKeypair]
+    );
+
+    console.log("✅ 交易限额设置成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 交易限额设置失败:", error);
+    throw error;
+  }
+}
+
+// 7.16 移除交易限额（投资者）
+async function closeTradeLimits(
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const [vaultPda] = generateVaultPDA(userKeypair.publicKey, vaultIndex);
+  const tx = await connection.sendTransaction(
+    new Transaction().add(
+      new TransactionInstruction({
+        programId: PROGRAM_ID,
+        keys: [
+          { pubkey: vaultPda, isSigner: false, isWritable: false },
+          { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+          { pubkey: getTradeLimitsPda(vaultPda), isSigner: false, isWritable: true },
+        ],
+        data: getInstructionDiscriminator("closeTradeLimits"),
+      })
+    ),
+    [userKeypair]
+  );
+  console.log("✅ 交易限额已移除:", tx);
+  return tx;
+}
+
+// 7.17 查询 24 小时交易限额剩余额度，与链上 get_trade_allowance 的计算一致
+async function getTradeAllowance(vaultPda: PublicKey) {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const account = await connection.getAccountInfo(getTradeLimitsPda(vaultPda));
+  if (!account) {
+    console.log("金库未设置交易限额");
+    return null;
+  }
+
+  // 布局: 鉴别器(8) + vault(32) + bump(1) + quote_mint(32) + max_daily_trades(4) + max_daily_volume(8) + 24 个时段
+  //   + unpriced_trade_policy(1)
+  const data = account.data;
+  let offset = 8 + 32 + 1;
+  const quoteMint = new PublicKey(data.subarray(offset, offset + 32));
+  offset += 32;
+  const maxDailyTrades = data.readUInt32LE(offset);
+  offset += 4;
+  const maxDailyVolume = data.readBigUInt64LE(offset);
+  offset += 8;
+
+  // 按小时分段统计，窗口为最近 24 个小时段（含当前小时）
+  const currentHour = BigInt(Math.floor(Date.now() / 1000 / 3600));
+  let tradesUsed = 0;
+  let volumeUsed = BigInt(0);
+  for (let i = 0; i < 24; i++) {
+    const hour = data.readBigInt64LE(offset);
+    const trades = data.readUInt32LE(offset + 8);
+    const volume = data.readBigUInt64LE(offset + 12);
+    offset += 20;
+    if (hour > currentHour - BigInt(24) && hour <= currentHour) {
+      tradesUsed += trades;
+      volumeUsed += volume;
+    }
+  }
+
+  const allowance = {
+    quoteMint: quoteMint.toString(),
+    maxDailyTrades,
+    tradesUsed,
+    tradesRemaining: maxDailyTrades === 0 ? null : Math.max(maxDailyTrades - tradesUsed, 0),
+    maxDailyVolume: maxDailyVolume.toString(),
+    volumeUsed: volumeUsed.toString(),
+    volumeRemaining: maxDailyVolume === BigInt(0)
+      ? null
+      : (volumeUsed >= maxDailyVolume ? BigInt(0) : maxDailyVolume - volumeUsed).toString(),
+    // 两侧都不是计价代币的交易：0 = 拒绝，1 = 只计次数
+    unpricedTradePolicy: data[offset] === 1 ? "countOnly" : "reject",
+  };
+
+  console.log("\n📊 交易限额（null 表示不限制）:", allowance);
+  return allowance;
+}
+
+// 生成交易历史账户 PDA
+function getTradeHistoryPda(vaultPda: PublicKey): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("trade_history"), vaultPda.toBuffer()],
+    PROGRAM_ID
+  )[0];
+}
+
+// 7.8 创建 / 调整 / 关闭交易历史（投资者）
+// action: "init" 创建（capacity 为容量），"resize" 调整容量，"close" 关闭并取回租金
+async function manageTradeHistory(
+  action: "init" | "resize" | "close",
+  capacity: number = 32,
+  userKeypair: Keypair = user2Keypair,
+  vaultIndex: number = 0
+): Promise<string> {
+  try {
+    console.log("\n📜 管理交易历史:", action, action === "close" ? "" : `容量 ${capacity}`);
+
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+
+    const [vaultPda] = generateVaultPDA(userKeypair.publicKey, vaultIndex);
+    const tradeHistoryPda = getTradeHistoryPda(vaultPda);
+    const capacityBuffer = Buffer.alloc(2);
+    capacityBuffer.writeUInt16LE(capacity, 0);
+
+    let keys;
+    let data;
+    if (action === "init") {
+      keys = [
+        { pubkey: vaultPda, isSigner: false, isWritable: false },
+        { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+        { pubkey: tradeHistoryPda, isSigner: false, isWritable: true },
+        { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      ];
+      data = Buffer.concat([getInstructionDiscriminator("initTradeHistory"), capacityBuffer]);
+    } else if (action === "resize") {
+      keys = [
+        { pubkey: tradeHistoryPda, isSigner: false, isWritable: true },
+        { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+        { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+      ];
+      data = Buffer.concat([getInstructionDiscriminator("resizeTradeHistory"), capacityBuffer]);
+    } else {
+      keys = [
+        { pubkey: tradeHistoryPda, isSigner: false, isWritable: true },
+        { pubkey: userKeypair.publicKey, isSigner: true, isWritable: true },
+      ];
+      data = getInstructionDiscriminator("closeTradeHistory");
+    }
+
+    const tx = await connection.sendTransaction(
+      new Transaction().add(new TransactionInstruction({ programId: PROGRAM_ID, keys, data })),
+      [userKeypair]
+    );
+
+    console.log("✅ 交易历史操作成功!");
+    console.log("  交易签名:", tx);
+
+    return tx;
+
+  } catch (error) {
+    console.error("❌ 交易历史操作失败:", error);
+    throw error;
+  }
+}
+
+// 7.9 读取交易历史（按从旧到新的顺序）
+async function getTradeHistory(vaultPda: PublicKey) {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+
+  const historyAccount = await connection.getAccountInfo(getTradeHistoryPda(vaultPda));
+  if (!historyAccount) {
+    return [];
+  }
+
+  // 布局: 鉴别器(8) + vault(32) + investor(32) + total_trades(8) + next_index(2) + record_count(2) + bump(1) + padding(3)
+  //   + TradeRecord(144 字节) 环形缓冲区
+  const data = historyAccount.data;
+  const headerEnd = 8 + 80;
+  const recordSize = 144;
+  const capacity = Math.floor((data.length - headerEnd) / recordSize);
+  const nextIndex = data.readUInt16LE(8 + 72);
+  const recordCount = data.readUInt16LE(8 + 74);
+  // 缓冲区已满时最旧的记录位于写入位置
+  const start = recordCount === capacity ? nextIndex : 0;
+
+  const records = [];
+  for (let i = 0; i < recordCount; i++) {
+    const offset = headerEnd + ((start + i) % capacity) * recordSize;
+    records.push({
+      slot: data.readBigUInt64LE(offset).toString(),
+      signalId: data.readBigUInt64LE(offset + 8).toString(),
+      amountIn: data.readBigUInt64LE(offset + 16).toString(),
+      amountOut: data.readBigUInt64LE(offset + 24).toString(),
+      feeAmount: data.readBigUInt64LE(offset + 32).toString(),
+      tokenIn: new PublicKey(data.slice(offset + 40, offset + 72)).toString(),
+      tokenOut: new PublicKey(data.slice(offset + 72, offset + 104)).toString(),
+      executor: new PublicKey(data.slice(offset + 104, offset + 136)).toString(),
+      bucket: data[offset + 136],
+    });
   }
+  console.log(`📜 交易历史: ${recordCount}/${capacity} 条, 累计 ${data.readBigUInt64LE(8 + 64)} 笔`);
+  return records;
 }
 
-// 6. 设置机器人地址
-async function setBot(globalConfigPda: PublicKey, newBotAddress: PublicKey): Promise<string> {
+// 7.10 登记代币的 Pyth 价格源（管理员），feedIdHex 全零表示移除
+// 合约从 mint 账户读取精度；原生 SOL（系统程序地址）传入 WSOL mint
+async function setPriceFeed(
+  mint: PublicKey,
+  feedIdHex: string,
+  signerKeypair: Keypair = adminKeypair
+): Promise<string> {
   try {
-    console.log("\n🤖 设置机器人地址...");
-    console.log("  新机器人地址:", newBotAddress.toString());
+    console.log("\n🔮 登记价格源...");
+    console.log("  代币:", mint.toString());
+    console.log("  价格源 ID:", feedIdHex);
 
     const connection = await checkConnection();
     if (!connection) {
       throw new Error("网络连接失败");
     }
 
+    const [oracleConfigPda] = PublicKey.findProgramAddressSync([Buffer.from("oracle_config")], PROGRAM_ID);
+    const feedId = Buffer.from(feedIdHex.replace(/^0x/, ""), "hex");
+    if (feedId.length !== 32) {
+      throw new Error("价格源 ID 必须是 32 字节");
+    }
+    const mintAccount = mint.equals(SystemProgram.programId) ? NATIVE_MINT : mint;
+
     const tx = await connection.sendTransaction(
       new Transaction().add(
         new TransactionInstruction({
           programId: PROGRAM_ID,
           keys: [
-            { pubkey: globalConfigPda, isSigner: false, isWritable: true },
-            { pubkey: adminKeypair.publicKey, isSigner: true, isWritable: false },
+            { pubkey: oracleConfigPda, isSigner: false, isWritable: true },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false },
+            { pubkey: mintAccount, isSigner: false, isWritable: false },
+            { pubkey: signerKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
           ],
-          data: serializeInstructionData("setBot", newBotAddress),
+          data: Buffer.concat([
+            getInstructionDiscriminator("setPriceFeed"),
+            mint.toBuffer(),
+            feedId,
+          ]),
         })
       ),
-      [adminKeypair]
+      [signerKeypair]
     );
 
-    console.log("✅ 机器人地址设置成功!");
+    console.log("✅ 价格源登记成功!");
     console.log("  交易签名:", tx);
 
-    // 打印交易查看链接
-    console.log("\n🔗 交易查看链接:");
-    console.log(`https://explorer.solana.com/tx/${tx}?cluster=devnet`);
-
     return tx;
 
   } catch (error) {
-    console.error("❌ 设置机器人失败:", error);
+    console.error("❌ 价格源登记失败:", error);
     throw error;
   }
 }
 
-// 7. 设置管理员
-async function setAdmin(globalConfigPda: PublicKey, newAdmin: PublicKey): Promise<string> {
+// Pyth 赞助价格源：push oracle 以 [shard_id(u16), feed_id] 推导的 PriceUpdateV2 账户
+const PYTH_PUSH_ORACLE_PROGRAM_ID = new PublicKey("pythWSnswVUd12oZpeFP8e9CVaEqJg25g1Vtc2biRsT");
+const PYTH_FEED_IDS = {
+  solUsd: "ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d",
+  usdcUsd: "eaa020c61cc479712813461ce153894a96a6c00b21ed0cfc2798d1f9a9e9c94a",
+};
+
+function getPythPriceFeedAccount(feedIdHex: string, shardId: number = 0): PublicKey {
+  const shardBuffer = Buffer.alloc(2);
+  shardBuffer.writeUInt16LE(shardId, 0);
+  return PublicKey.findProgramAddressSync(
+    [shardBuffer, Buffer.from(feedIdHex, "hex")],
+    PYTH_PUSH_ORACLE_PROGRAM_ID
+  )[0];
+}
+
+// 7.11 金库净值快照（任何人可调用），priceAccounts 为金库持有代币及报价代币的 Pyth PriceUpdateV2 账户
+async function snapshotVault(
+  vaultPda: PublicKey,
+  priceAccounts: PublicKey[],
+  callerKeypair: Keypair = adminKeypair
+): Promise<string> {
   try {
-    console.log("\n👤 设置管理员...");
-    console.log("  新管理员地址:", newAdmin.toString());
+    console.log("\n📊 金库净值快照...");
+    console.log("  金库地址:", vaultPda.toString());
+    console.log("  价格账户数量:", priceAccounts.length);
 
     const connection = await checkConnection();
     if (!connection) {
       throw new Error("网络连接失败");
     }
 
+    const [oracleConfigPda] = PublicKey.findProgramAddressSync([Buffer.from("oracle_config")], PROGRAM_ID);
+    const [vaultSnapshotsPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault_snapshots"), vaultPda.toBuffer()],
+      PROGRAM_ID
+    );
+
     const tx = await connection.sendTransaction(
       new Transaction().add(
         new TransactionInstruction({
           programId: PROGRAM_ID,
           keys: [
-            { pubkey: globalConfigPda, isSigner: false, isWritable: true },
-            { pubkey: adminKeypair.publicKey, isSigner: true, isWritable: false },
+            { pubkey: callerKeypair.publicKey, isSigner: true, isWritable: true },
+            { pubkey: vaultPda, isSigner: false, isWritable: false },
+            { pubkey: generateGlobalConfigPDA()[0], isSigner: false, isWritable: false },
+            { pubkey: oracleConfigPda, isSigner: false, isWritable: false },
+            { pubkey: vaultSnapshotsPda, isSigner: false, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            ...priceAccounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false })),
           ],
-          data: serializeInstructionData("setAdmin", newAdmin),
+          data: getInstructionDiscriminator("snapshotVault"),
         })
       ),
-      [adminKeypair]
+      [callerKeypair]
     );
 
-    console.log("✅ 管理员设置成功!");
+    console.log("✅ 净值快照成功!");
     console.log("  交易签名:", tx);
 
-    // 打印交易查看链接
-    console.log("\n🔗 交易查看链接:");
-    console.log(`https://explorer.solana.com/tx/${tx}?cluster=devnet`);
-
     return tx;
 
   } catch (error) {
-    console.error("❌ 设置管理员失败:", error);
+    console.error("❌ 净值快照失败:", error);
     throw error;
   }
 }
@@ -756,7 +2423,7 @@ async function sendTradeSignal(
   amountIn: number,
   slippageBps: number,
   signerKeypair: Keypair = adminKeypair, // 默认使用管理员，也可以传入机器人
-  userKeypair: Keypair = user2Keypair // 金库所有者的 Keypair，用于签名
+  options: TradeSignalOptions = {} // 信号有效期等可选参数
 ): Promise<string> {
   try {
     console.log("\n🔄 发送交易信号...");
@@ -820,19 +2487,11 @@ async function sendTradeSignal(
     let remainingAccounts: any[] = [];
     let poolType: number; // 1=CLMM
 
-    // 🔧 检测是否需要创建临时 WSOL 账户（方案 1）
-    const WSOL_MINT = TEST_ADDRESSES.dwsolDevnet;
-    const needsTempWsolAccount = tokenIn.equals(WSOL_MINT);
-    let tempWsolKeypair: Keypair | null = null;
-    let actualInputTokenAccount: PublicKey | null = null;
-
-    // 如果需要临时账户，先创建它
-    if (needsTempWsolAccount) {
-      console.log("\n🔍 检测到 WSOL 输入，创建临时账户（模仿 SDK）...");
-      tempWsolKeypair = Keypair.generate();
-      actualInputTokenAccount = tempWsolKeypair.publicKey;
-      console.log("  临时 WSOL 账户地址:", actualInputTokenAccount.toString());
-    }
+    // 金库的输入/输出代币账户：必须是金库 PDA 在对应 mint 下的 ATA（合约会校验）
+    const tokenInProgram = (await connection.getAccountInfo(tokenIn))!.owner;
+    const tokenOutProgram = (await connection.getAccountInfo(tokenOut))!.owner;
+    const vaultInputTokenAccount = await getAssociatedTokenAddress(tokenIn, vaultPda, true, tokenInProgram);
+    const vaultOutputTokenAccount = await getAssociatedTokenAddress(tokenOut, vaultPda, true, tokenOutProgram);
 
     if (raydiumResult.poolType === 'CLMM') {
       console.log("  🎯 构建 CLMM 账户列表...");
@@ -856,39 +2515,19 @@ async function sendTradeSignal(
       });
       
 
-      // === 建议的正确账户顺序 ===
-      // 根据 Raydium 官方文档，正确的账户顺序应该是：
-      //   [00] executor (payer, 签名者)
-      //   [01] ammConfig
-      //   [02] poolState
-      //   [03] inputTokenAccount
-      //   [04] outputTokenAccount
-      //   [05] poolVaultA
-      //   [06] poolVaultB
-      //   [07] observationState
-      //   [08] TOKEN_PROGRAM_ID
-      //   [09] inputMint
-      //   [10] outputMint
-      //   [11+] tickArray[0]
-      //   [12+] tickArray[1]
-      //   [13+] tickArray[2]
-      //   [14+] exBitmapAccount
-
-      
-      // 构建 remaining_accounts 列表 - CLMM 版本（根据官方 SDK 分析修复）
-      // 🎯 根据官方 SDK 成功交易分析，正确的账户顺序应该是：
+      // 构建 remaining_accounts 列表 - CLMM swap_v2 账户顺序：
       // [8] TOKEN_PROGRAM_ID, [9] TOKEN_2022_PROGRAM_ID, [10] MEMO_PROGRAM_ID, [11] inputMint, [12] outputMint, [13+] tickArrays
       remainingAccounts = [
-        // [0] payer (签名者) - 应该是金库所有者，不是管理员
-        { pubkey: userAddress, isSigner: true, isWritable: false },
+        // [0] payer - 金库 PDA（代币账户 owner，由合约通过 invoke_signed 签名）
+        { pubkey: vaultPda, isSigner: false, isWritable: false },
         // [1] ammConfigId
         { pubkey: new PublicKey((swapAccounts as any).ammConfig), isSigner: false, isWritable: false },
         // [2] poolId
         { pubkey: new PublicKey(swapAccounts.poolState), isSigner: false, isWritable: true },
-        // [3] inputTokenAccount
-        { pubkey: tempWsolKeypair ? (tempWsolKeypair as Keypair).publicKey : new PublicKey(swapAccounts.userInputTokenAccount), isSigner: tempWsolKeypair ? true : false, isWritable: true },
-        // [4] outputTokenAccount
-        { pubkey: new PublicKey(swapAccounts.userOutputTokenAccount), isSigner: false, isWritable: true },
+        // [3] inputTokenAccount - 金库输入代币 ATA
+        { pubkey: vaultInputTokenAccount, isSigner: false, isWritable: true },
+        // [4] outputTokenAccount - 金库输出代币 ATA
+        { pubkey: vaultOutputTokenAccount, isSigner: false, isWritable: true },
         // [5] inputVault
         { pubkey: new PublicKey(swapAccounts.poolVaultA), isSigner: false, isWritable: true },
         // [6] outputVault
@@ -908,6 +2547,25 @@ async function sendTradeSignal(
         // [13+] remainingAccounts (exBitmapAccount + tickArrays) - 移动到最后
         ...correctTickArrayAccounts,
       ];
+
+      // 带转账钩子的 Token-2022 mint：在 tick_array 之后附加两条转账腿的钩子账户，
+      // 合约按 ExtraAccountMetaList 校验后原样转发给 Raydium（池子金库的 owner 是池状态账户）
+      const poolState = new PublicKey(swapAccounts.poolState);
+      const hookAccounts = [
+        ...(await getTransferHookAccounts(
+          connection, vaultInputTokenAccount, tokenIn, new PublicKey(swapAccounts.poolVaultA),
+          vaultPda, amountIn, tokenInProgram
+        )),
+        ...(await getTransferHookAccounts(
+          connection, new PublicKey(swapAccounts.poolVaultB), tokenOut, vaultOutputTokenAccount,
+          poolState, 0, tokenOutProgram
+        )),
+      ];
+      remainingAccounts.push(
+        ...hookAccounts.filter(
+          (account, index) => hookAccounts.findIndex((other) => other.pubkey.equals(account.pubkey)) === index
+        )
+      );
       
       // ⚠️ 注意：Raydium CLMM 程序账户现在作为单独的账户传递（不在 remainingAccounts 中）
 
@@ -932,75 +2590,35 @@ async function sendTradeSignal(
         ComputeBudgetProgram.setComputeUnitPrice({ microLamports: 1 })
       );
 
-    if (needsTempWsolAccount && tempWsolKeypair) {
-      console.log("\n🔧 添加临时 WSOL 账户创建指令...");
-
-      // 1. 计算租金（Token Account size = 165 bytes）
-      const rent = await connection.getMinimumBalanceForRentExemption(165);
-      console.log("  租金金额:", rent, "lamports");
-      console.log("  转账金额:", amountIn, "lamports");
-      console.log("  总金额:", rent + amountIn, "lamports");
-
-      // 2. 创建账户指令
-      transaction.add(
-        SystemProgram.createAccount({
-          fromPubkey: signerKeypair.publicKey,
-          newAccountPubkey: tempWsolKeypair.publicKey,
-          lamports: rent + amountIn, // 租金 + 要转账的 SOL
-          space: 165,
-          programId: TOKEN_PROGRAM_ID
-        })
-      );
-
-      // 3. 初始化代币账户指令
-      transaction.add(
-        createInitializeAccountInstruction(
-          tempWsolKeypair.publicKey,
-          WSOL_MINT,
-          signerKeypair.publicKey // owner 是管理员，这样管理员可以签名使用它
-        )
-      );
-
-      console.log("  ✅ 添加了临时 WSOL 账户创建指令");
-      console.log("  📝 架构说明：临时账户 owner = 管理员，交换后需要转移到 vault");
-    }
-
     // 添加主要的 swap 指令
     transaction.add(
       new TransactionInstruction({
         programId: PROGRAM_ID,
         keys: [
           // 基本账户（来自 IDL）
-          { pubkey: signerKeypair.publicKey, isSigner: true, isWritable: false },     // executor (管理员或Bot)
+          { pubkey: signerKeypair.publicKey, isSigner: true, isWritable: true },      // executor (管理员或Bot，支付信号记录账户租金)
           { pubkey: userAddress, isSigner: false, isWritable: false },                // user (金库所有者，用于账户推导)
           { pubkey: vaultPda, isSigner: false, isWritable: true },                    // vault (个人金库账户)
           { pubkey: globalConfigPda, isSigner: false, isWritable: false },            // global_config (全局配置账户)
           { pubkey: RAYDIUM_PROGRAMS.clmm, isSigner: false, isWritable: false },      // dex_program (Raydium CLMM 程序)
+          { pubkey: getSignalRegistryPda(vaultPda)[0], isSigner: false, isWritable: true }, // signal_registry (信号 ID 记录账户)
+          { pubkey: getVaultExecutorsPda(vaultPda), isSigner: false, isWritable: false }, // vault_executors (执行者授权，未设置时只接受全局 bot/admin)
+          { pubkey: getTradingPolicyPda(vaultPda), isSigner: false, isWritable: true }, // trading_policy (交易策略，未设置时不限制)
+          { pubkey: getTradeLimitsPda(vaultPda), isSigner: false, isWritable: true },   // trade_limits (24 小时交易限额，未设置时不限制)
+          { pubkey: getTradeHistoryPda(vaultPda), isSigner: false, isWritable: true },  // trade_history (交易历史，未创建时跳过记录)
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },    // system_program
           // 剩余账户（Raydium 交换相关）
           ...remainingAccounts
         ],
-        data: serializeTradeSignalData(tokenIn, tokenOut, amountIn, slippageBps, poolType),
+        data: serializeTradeSignalData(tokenIn, tokenOut, amountIn, slippageBps, poolType, {
+          ...options,
+          // 有效截止槽位必填：未指定时使用当前槽位 + DEFAULT_SIGNAL_VALIDITY_SLOTS
+          validUntilSlot: options.validUntilSlot ?? (await connection.getSlot()) + DEFAULT_SIGNAL_VALIDITY_SLOTS,
+        }),
       })
     );
 
-    // ✅ 新架构：临时账户 owner = 管理员
-    // 说明：临时账户属于管理员，交换后需要：
-    // 1. 将获得的代币转移到 vault 的正式代币账户
-    // 2. 关闭临时账户回收租金
-    if (tempWsolKeypair) {
-      console.log("\n✅ 创建了临时 WSOL 账户");
-      console.log("  临时账户地址:", tempWsolKeypair.publicKey.toString());
-      console.log("  临时账户 owner:", signerKeypair.publicKey.toString());
-      console.log("  ⚠️  交换后需要手动转移代币到 vault 并关闭临时账户");
-    }
-
-    // 🔧 准备签名者列表
-    const signers = [signerKeypair, userKeypair]; // 添加用户 Keypair 用于签名
-    if (tempWsolKeypair) {
-      signers.push(tempWsolKeypair);
-      console.log("  ✅ 添加临时账户到签名者列表");
-    }
-    console.log("  ✅ 添加用户 Keypair 到签名者列表:", userKeypair.publicKey.toString());
+    const signers = [signerKeypair];
 
     console.log("\n📤 发送交易...");
     console.log("  签名者数量:", signers.length);
@@ -1043,13 +2661,57 @@ async function sendTradeSignal(
   }
 }
 
+// 合约允许的信号有效期上限（槽位数，对应 MAX_SIGNAL_VALIDITY_SLOTS）
+const MAX_SIGNAL_VALIDITY_SLOTS = 150;
+// 未指定有效截止槽位时的默认有效期（槽位数）
+const DEFAULT_SIGNAL_VALIDITY_SLOTS = 100;
+
+// 交易信号可选参数
+interface TradeSignalOptions {
+  validUntilSlot?: number; // 信号有效截止槽位（不超过当前槽位 + MAX_SIGNAL_VALIDITY_SLOTS，未指定时自动填充）
+  deadlineUnixTs?: number | null; // 信号截止时间（Unix 秒）
+  signalId?: bigint; // 信号 ID（非零，重试时复用同一 ID 保证幂等）
+  bucket?: number; // 分仓编号（默认 0），交易只动用该分仓的余额
+}
+
+// 生成随机的非零信号 ID
+function generateSignalId(): bigint {
+  const bytes: Buffer = require('crypto').randomBytes(8);
+  const id = bytes.readBigUInt64LE(0);
+  return id === BigInt(0) ? BigInt(1) : id;
+}
+
+// 生成交易信号 ID 记录账户 PDA
+function getSignalRegistryPda(vaultPda: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("signal_registry"), vaultPda.toBuffer()],
+    PROGRAM_ID
+  );
+}
+
+// 序列化 Option<u64> / Option<i64>（Borsh: 1 字节标志 + 8 字节值）
+function serializeOptionalU64(value: number | null | undefined, signed: boolean = false): Buffer {
+  if (value === null || value === undefined) {
+    return Buffer.from([0]);
+  }
+  const buffer = Buffer.alloc(9);
+  buffer.writeUInt8(1, 0);
+  if (signed) {
+    buffer.writeBigInt64LE(BigInt(value), 1);
+  } else {
+    buffer.writeBigUInt64LE(BigInt(value), 1);
+  }
+  return buffer;
+}
+
 // 序列化交易信号指令数据的辅助函数
 function serializeTradeSignalData(
   tokenIn: PublicKey,
   tokenOut: PublicKey,
   amountIn: number,
   slippageBps: number,
-  poolType: number // 新增：池子类型参数 (0=AMM V4, 1=CLMM)
+  poolType: number, // DexRoute 变体索引 (0=RaydiumAmmV4, 1=RaydiumClmm)
+  options: TradeSignalOptions = {}
 ): Buffer {
   const discriminator = getSendTradeSignalDiscriminator();
   let data = Buffer.from(discriminator);
@@ -1084,7 +2746,7 @@ function serializeTradeSignalData(
   slippageBpsBuffer.copy(newData, data.length);
   data = newData;
 
-  // 添加 poolType (1字节)
+  // 添加 route (DexRoute 枚举，Borsh 编码为 1 字节变体索引)
   const poolTypeBuffer = Buffer.alloc(1);
   poolTypeBuffer.writeUInt8(poolType, 0);
   newData = Buffer.alloc(data.length + poolTypeBuffer.length);
@@ -1092,10 +2754,34 @@ function serializeTradeSignalData(
   poolTypeBuffer.copy(newData, data.length);
   data = newData;
 
+  // 添加 validUntilSlot (u64，必填) 和 deadlineUnixTs (Option<i64>)
+  if (options.validUntilSlot === undefined) {
+    throw new Error("validUntilSlot 必填");
+  }
+  const validUntilSlotBuffer = Buffer.alloc(8);
+  validUntilSlotBuffer.writeBigUInt64LE(BigInt(options.validUntilSlot), 0);
+  data = Buffer.concat([
+    data,
+    validUntilSlotBuffer,
+    serializeOptionalU64(options.deadlineUnixTs, true),
+  ]);
+
+  // 添加 signalId (8字节)
+  const signalIdBuffer = Buffer.alloc(8);
+  signalIdBuffer.writeBigUInt64LE(options.signalId ?? generateSignalId(), 0);
+  data = Buffer.concat([data, signalIdBuffer]);
+
+  // 添加 bucket (1字节)
+  data = Buffer.concat([data, Buffer.from([options.bucket ?? 0])]);
+
   return data;
 }
 
-// 解析PersonalVault账户数据的辅助函数（更新版本）
+// 解析PersonalVault账户数据的辅助函数（zero-copy 布局）
+// 布局：8 字节鉴别器 + 160 字节头部 + N 个 80 字节的 TokenBalance 槽位
+const PERSONAL_VAULT_HEADER_SIZE = 160;
+const TOKEN_BALANCE_SIZE = 80;
+
 function parsePersonalVaultAccount(data: Buffer): any {
   try {
     // 跳过8字节的账户标识符
@@ -1105,43 +2791,80 @@ function parsePersonalVaultAccount(data: Buffer): any {
     const investor = new PublicKey(data.slice(offset, offset + 32));
     offset += 32;
 
+    // 读取token_count (2字节)
+    const tokenCount = data.readUInt16LE(offset);
+    offset += 2;
+
     // 读取is_initialized (1字节)
     const isInitialized = data[offset] === 1;
     offset += 1;
 
-    // 读取is_locked (1字节) - 新增字段
+    // 读取is_locked (1字节)
     const isLocked = data[offset] === 1;
     offset += 1;
 
-    // 读取balances数组长度 (4字节)
-    const balancesLength = data.readUInt32LE(offset);
-    offset += 4;
+    // 读取bump (1字节)
+    const bump = data[offset];
+    offset += 1;
+
+    // 读取layout_version (1字节)
+    const layoutVersion = data[offset];
+    offset += 1;
+
+    // 读取vault_index (2字节)
+    const vaultIndex = data.readUInt16LE(offset);
+    offset += 2;
+
+    // 读取label (32字节，补零)
+    const label = data.slice(offset, offset + 32).toString("utf8").replace(/\0+$/, "");
+    offset += 32;
+
+    // 读取bucket_labels (4 x 16字节)
+    const bucketLabels = [];
+    for (let i = 0; i < 4; i++) {
+      bucketLabels.push(data.slice(offset, offset + 16).toString("utf8").replace(/\0+$/, ""));
+      offset += 16;
+    }
+
+    // 读取trading_paused (1字节)
+    const tradingPaused = data[offset] !== 0;
+    offset += 1;
 
-    // 读取balances数组
+    // 读取已使用的余额槽位
+    const capacity = Math.floor((data.length - 8 - PERSONAL_VAULT_HEADER_SIZE) / TOKEN_BALANCE_SIZE);
     const balances = [];
-    for (let i = 0; i < balancesLength; i++) {
-      // 每个TokenBalance: token(32字节) + amount(8字节)
-      const token = new PublicKey(data.slice(offset, offset + 32));
-      offset += 32;
-      const amount = data.readBigUInt64LE(offset);
-      offset += 8;
+    for (let i = 0; i < tokenCount; i++) {
+      // 每个TokenBalance: token(32字节) + amount(8字节) + bucket(1字节) + basis_unknown(1字节) + padding(6字节)
+      //   + total_deposited(8字节) + total_withdrawn(8字节) + cost_basis(8字节) + realized_pnl(i64, 8字节)
+      const slotOffset = 8 + PERSONAL_VAULT_HEADER_SIZE + i * TOKEN_BALANCE_SIZE;
+      const token = new PublicKey(data.slice(slotOffset, slotOffset + 32));
+      const amount = data.readBigUInt64LE(slotOffset + 32);
+      const bucket = data[slotOffset + 40];
 
       balances.push({
         token: token.toString(),
-        amount: amount.toString()
+        amount: amount.toString(),
+        bucket,
+        basisUnknown: data[slotOffset + 41] !== 0,
+        totalDeposited: data.readBigUInt64LE(slotOffset + 48).toString(),
+        totalWithdrawn: data.readBigUInt64LE(slotOffset + 56).toString(),
+        costBasis: data.readBigUInt64LE(slotOffset + 64).toString(),
+        realizedPnl: data.readBigInt64LE(slotOffset + 72).toString()
       });
     }
 
-    // 读取bump (1字节) - 在数组数据之后
-    const bump = data[offset];
-    offset += 1;
-
     return {
       investor: investor.toString(),
       isInitialized,
       isLocked,
       balances,
-      bump
+      bump,
+      layoutVersion,
+      vaultIndex,
+      label,
+      bucketLabels,
+      tradingPaused,
+      capacity
     };
   } catch (error) {
     console.error("❌ 解析账户数据失败:", error);
@@ -1167,10 +2890,34 @@ function parseGlobalConfigAccount(data: Buffer): any {
     const isInitialized = data[offset] === 1;
     offset += 1;
 
+    // 读取max_vault_tokens (2字节，0 表示使用默认值)
+    const maxVaultTokens = data.readUInt16LE(offset);
+    offset += 2;
+
+    // 读取quote_mint (32字节，全零表示未设置)
+    const quoteMint = new PublicKey(data.slice(offset, offset + 32));
+    offset += 32;
+
+    // 读取guardian (32字节) 和暂停标志 (迁移前的旧账户没有这些字段)
+    let guardian = PublicKey.default;
+    let pausedOperations = 0;
+    let pausedDexRoutes = 0;
+    if (data.length >= offset + 34) {
+      guardian = new PublicKey(data.slice(offset, offset + 32));
+      pausedOperations = data[offset + 32];
+      pausedDexRoutes = data[offset + 33];
+      offset += 34;
+    }
+
     return {
       admin: admin.toString(),
       bot: bot.toString(),
-      isInitialized
+      isInitialized,
+      maxVaultTokens,
+      quoteMint: quoteMint.toString(),
+      guardian: guardian.toString(),
+      pausedOperations,
+      pausedDexRoutes
     };
   } catch (error) {
     console.error("❌ 解析全局配置数据失败:", error);
@@ -1307,9 +3054,631 @@ async function verifyBalanceChange(
   }
 }
 
+// 等待交易确认，之后读取的链上状态才包含该交易的结果
+async function confirmTx(tx: string): Promise<void> {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+  await connection.confirmTransaction(tx, "confirmed");
+}
+
+// 读取账户数据长度，账户不存在时返回 null
+async function getAccountDataLength(account: PublicKey): Promise<number | null> {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+  const accountInfo = await connection.getAccountInfo(account);
+  return accountInfo ? accountInfo.data.length : null;
+}
+
+// 读取代币在指定分仓中的账本余额，没有条目时为 0
+async function ledgerAmount(vaultPda: PublicKey, token: PublicKey, bucket: number = 0): Promise<bigint> {
+  const position = await getPosition(vaultPda, token, bucket);
+  return BigInt(position?.amount ?? 0);
+}
+
+// 把钱包中的 SOL 包装到其 WSOL 关联账户（账户不存在时创建）
+async function wrapSolToAta(ownerKeypair: Keypair, lamports: number): Promise<PublicKey> {
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+  const wsolAccount = await getAssociatedTokenAddress(NATIVE_MINT, ownerKeypair.publicKey);
+  await confirmTx(await connection.sendTransaction(
+    new Transaction().add(
+      createAssociatedTokenAccountIdempotentInstruction(
+        ownerKeypair.publicKey, wsolAccount, ownerKeypair.publicKey, NATIVE_MINT
+      ),
+      SystemProgram.transfer({ fromPubkey: ownerKeypair.publicKey, toPubkey: wsolAccount, lamports }),
+      createSyncNativeInstruction(wsolAccount)
+    ),
+    [ownerKeypair]
+  ));
+  return wsolAccount;
+}
+
+// 断言相等（按字符串比较，兼容 number / bigint / BN），不相等时抛出错误，由所在测试步骤记录失败
+function assertEqual(actual: any, expected: any, what: string) {
+  if (String(actual) !== String(expected)) {
+    throw new Error(`${what}: 预期 ${expected}, 实际 ${actual}`);
+  }
+  console.log(`  ✅ ${what}: ${actual}`);
+}
+
+// 断言交易以指定的程序错误失败
+async function expectProgramError(action: Promise<any>, errorName: string, what: string) {
+  try {
+    await action;
+  } catch (error: any) {
+    const details = [String(error), ...(error?.logs ?? [])].join("\n");
+    if (!details.includes(errorName)) {
+      throw new Error(`${what}: 预期错误 ${errorName}, 实际: ${details}`);
+    }
+    console.log(`  ✅ ${what}: 按预期失败 (${errorName})`);
+    return;
+  }
+  throw new Error(`${what}: 预期失败 (${errorName})，但交易成功`);
+}
+
+// 各功能测试共用的全局配置与主金库（由 testComplete 的前两步创建）
+interface TestContext {
+  globalConfigPda: PublicKey;
+  vaultPda: PublicKey;
+}
+
+// 运行单个功能测试：失败时输出错误并把进程退出码置为 1，不影响后续测试
+async function runFeatureTest(title: string, test: (ctx: TestContext) => Promise<void>, ctx: TestContext) {
+  console.log(`\n=== ${title} ===`);
+  try {
+    await test(ctx);
+    console.log(`✅ ${title}测试成功`);
+  } catch (error) {
+    console.error(`❌ ${title}测试失败:`, error);
+    process.exitCode = 1;
+  }
+}
+
+// 交易信号：管理员对主金库发起 WSOL -> USDC 交易
+async function testTradeSignal({ vaultPda }: TestContext) {
+  console.log("\n🤖 测试机器人权限调用交易信号...");
+
+  // ✅ 使用 Wrapped SOL (dwsolDevnet) 而不是原生 SOL
+  // 因为存款时使用的是 dwsolDevnet，所以交换时也必须使用相同的 mint
+  const tokenIn = TEST_ADDRESSES.dwsolDevnet; // Wrapped SOL 作为输入代币
+  const tokenOut = TEST_ADDRESSES.usdcDevnet; // USDC Devnet 作为输出代币
+  const amountIn = 100000000; // 0.1 SOL
+  const slippageBps = 300; // 3% 滑点 (300 基点)
+
+  console.log("使用机器人身份调用交易信号...");
+
+  // 🔍 验证代币账户地址计算
+  console.log("\n🔍 验证代币账户地址计算...");
+
+  // 获取用户的代币账户地址
+  const userTokenAccount = await getAssociatedTokenAddress(
+    tokenIn,
+    user2Keypair.publicKey
+  );
+  console.log("  用户代币账户:", userTokenAccount.toString());
+
+  // 获取金库的代币账户地址（交换时将使用的账户）
+  const vaultTokenAccount = await getAssociatedTokenAddress(
+    tokenIn,
+    vaultPda,
+    true // 允许PDA作为代币账户所有者
+  );
+  console.log("  金库代币账户 (交换时):", vaultTokenAccount.toString());
+
+  // 对比存款时的金库代币账户
+  const depositTimeVaultAccount = "Cvjy63uiDC24anWJyF71tSefVNYntSkFLomqmjYeKAu8";
+  console.log("  金库代币账户 (存款时):", depositTimeVaultAccount);
+
+  if (vaultTokenAccount.toString() === depositTimeVaultAccount) {
+    console.log("  ✅ 地址匹配！交换和存款使用相同的金库代币账户");
+  } else {
+    console.log("  ❌ 地址不匹配！这可能导致交换失败");
+    console.log("  ⚠️  请检查代币 mint 是否一致");
+  }
+  console.log();
+
+  const botTradeTx = await sendTradeSignal(
+    vaultPda,
+    tokenIn,
+    tokenOut,
+    amountIn,
+    slippageBps,
+    adminKeypair
+  );
+
+  console.log("  交易签名:", botTradeTx);
+}
+
+// 信号记录账户迁移：迁移后数据长度不缩短，已是当前布局时再次迁移不做修改
+async function testSignalRegistryMigration({ vaultPda }: TestContext) {
+  const [signalRegistryPda] = getSignalRegistryPda(vaultPda);
+  const lengthBefore = await getAccountDataLength(signalRegistryPda);
+  console.log("  迁移前数据长度:", lengthBefore);
+
+  await confirmTx(await migrateSignalRegistry(vaultPda));
+  const lengthAfter = await getAccountDataLength(signalRegistryPda);
+  if (lengthAfter === null || (lengthBefore !== null && lengthAfter < lengthBefore)) {
+    throw new Error(`迁移后数据长度异常: ${lengthBefore} -> ${lengthAfter}`);
+  }
+
+  // 已是当前布局时再次迁移不做任何修改
+  await confirmTx(await migrateSignalRegistry(vaultPda));
+  assertEqual(await getAccountDataLength(signalRegistryPda), lengthAfter, "重复迁移后数据长度");
+}
+
+// 账本对账：绕过合约直接转入的资金在对账后计入账本
+async function testReconcileBalances({ vaultPda }: TestContext) {
+  const mint = TEST_ADDRESSES.usdcDevnet;
+  const amount = 1000;
+
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+  const userTokenAccount = await ensureTokenAccount(mint, user2Keypair.publicKey, user2Keypair);
+  const vaultTokenAccount = await getAssociatedTokenAddress(mint, vaultPda, true);
+  const ledgerBefore = await ledgerAmount(vaultPda, mint);
+
+  // 绕过合约直接转入金库代币账户，账本不会记录这笔资金
+  await confirmTx(await connection.sendTransaction(
+    new Transaction().add(
+      createTransferInstruction(userTokenAccount, vaultTokenAccount, user2Keypair.publicKey, amount)
+    ),
+    [user2Keypair]
+  ));
+  assertEqual(await ledgerAmount(vaultPda, mint), ledgerBefore, "直接转入后账本余额");
+
+  await confirmTx(await reconcileBalances(user2Keypair.publicKey, [mint]));
+  assertEqual(await ledgerAmount(vaultPda, mint), ledgerBefore + BigInt(amount), "对账后账本余额");
+}
+
+// 压缩金库：只移除没有余额和历史的条目，保留条目的余额不变
+async function testCompactVault({ vaultPda }: TestContext) {
+  const vaultBefore = parsePersonalVaultAccount((await getVaultInfo(vaultPda))!.data);
+  const entryKey = (balance: any) => `${balance.token}:${balance.bucket}`;
+  // 余额为零且没有任何历史（存取、成本、盈亏）的条目会被移除
+  const removable = (balance: any) => balance.amount === "0"
+    && balance.totalDeposited === "0"
+    && balance.totalWithdrawn === "0"
+    && balance.costBasis === "0"
+    && balance.realizedPnl === "0";
+  const kept = vaultBefore.balances.filter((balance: any) => !removable(balance));
+
+  await confirmTx(await compactVault());
+
+  const vaultAfter = parsePersonalVaultAccount((await getVaultInfo(vaultPda))!.data);
+  assertEqual(vaultAfter.balances.length, kept.length, "压缩后条目数量");
+  for (const balance of kept) {
+    const entry = vaultAfter.balances.find((after: any) => entryKey(after) === entryKey(balance));
+    assertEqual(entry?.amount, balance.amount, `条目 ${entryKey(balance)} 的余额`);
+  }
+}
+
+// 关闭金库：使用单独编号的金库，关闭后附属账户与登记条目一并移除，资产和租金退回投资者
+async function testCloseVault({ globalConfigPda }: TestContext) {
+  // 使用单独编号的金库，避免影响后续步骤使用的主金库
+  const closeIndex = 3;
+  const depositAmount = 10_000_000; // 0.01 SOL
+  const { vaultPda: closePda, tx: createTx } = await createBalanceManager(globalConfigPda, user2Keypair, closeIndex, "close-test");
+  if (createTx !== "已存在，无需创建") {
+    await confirmTx(createTx);
+  }
+  await confirmTx(await userDepositSol(closePda, depositAmount, user2Keypair));
+
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+  const children = [
+    getSignalRegistryPda(closePda)[0],
+    getTradingPolicyPda(closePda),
+    getTradeLimitsPda(closePda),
+    getVaultExecutorsPda(closePda),
+    getTradeHistoryPda(closePda),
+    PublicKey.findProgramAddressSync([Buffer.from("vault_snapshots"), closePda.toBuffer()], PROGRAM_ID)[0],
+  ];
+  let reclaimed = 0;
+  for (const account of [closePda, ...children]) {
+    reclaimed += await connection.getBalance(account);
+  }
+  const userLamportsBefore = await connection.getBalance(user2Keypair.publicKey);
+  const listed = async () =>
+    (await getVaultRegistry(user2Keypair.publicKey)).some((entry: any) => entry.vaultIndex === closeIndex);
+  assertEqual(await listed(), true, "关闭前登记账户包含该金库");
+
+  await confirmTx(await closeVault([], user2Keypair, closeIndex));
+
+  assertEqual(await checkAccountExists(closePda), false, "关闭后金库账户存在");
+  for (const account of children) {
+    assertEqual(await checkAccountExists(account), false, `关闭后附属账户 ${account.toString()} 存在`);
+  }
+  assertEqual(await listed(), false, "关闭后登记账户包含该金库");
+  // 投资者收回金库中的 SOL 和全部租金，扣除 5000 lamports 交易费
+  assertEqual(
+    await connection.getBalance(user2Keypair.publicKey) - userLamportsBefore,
+    reclaimed - 5000,
+    "投资者收回的 lamports"
+  );
+}
+
+// 代存款：出资人向投资者金库存入 SOL 与 WSOL（依赖主金库账本中已有这两种代币）
+async function testDepositFor({ vaultPda }: TestContext) {
+  const nativeSol = SystemProgram.programId; // 账本中原生 SOL 的代币标识
+  const wsol = TEST_ADDRESSES.dwsolDevnet;
+  const amount = 10_000_000; // 0.01 SOL
+
+  // 管理员作为出资人向 user2 的金库存入，只有投资者本人可以取出
+  // 代存只接受金库账本中已有的代币（前面的步骤已存入 SOL 并包装出 WSOL）
+  const solBefore = await ledgerAmount(vaultPda, nativeSol);
+  await confirmTx(await depositSolFor(user2Keypair.publicKey, amount, adminKeypair));
+  assertEqual(await ledgerAmount(vaultPda, nativeSol), solBefore + BigInt(amount), "代存 SOL 后账本余额");
+
+  await wrapSolToAta(adminKeypair, amount);
+  const wsolBefore = await ledgerAmount(vaultPda, wsol);
+  await confirmTx(await depositFor(user2Keypair.publicKey, wsol, amount, adminKeypair));
+  assertEqual(await ledgerAmount(vaultPda, wsol), wsolBefore + BigInt(amount), "代存 WSOL 后账本余额");
+}
+
+// 取款到指定钱包：WSOL 与 SOL 取到 user1，收款 ATA 不存在时由投资者创建
+async function testWithdrawTo({ vaultPda }: TestContext) {
+  const nativeSol = SystemProgram.programId;
+  const wsol = TEST_ADDRESSES.dwsolDevnet;
+  const amount = 5_000_000; // 0.005 SOL
+  const destination = user1Keypair.publicKey;
+
+  const connection = await checkConnection();
+  if (!connection) {
+    throw new Error("网络连接失败");
+  }
+  const destinationTokenAccount = await getAssociatedTokenAddress(wsol, destination);
+  const tokenBalanceOf = async (account: PublicKey) =>
+    (await checkAccountExists(account))
+      ? BigInt((await connection.getTokenAccountBalance(account)).value.amount)
+      : BigInt(0);
+
+  // 收款 ATA 不存在时由投资者创建
+  const wsolBefore = await ledgerAmount(vaultPda, wsol);
+  const destinationTokensBefore = await tokenBalanceOf(destinationTokenAccount);
+  await confirmTx(await userWithdrawTo(vaultPda, wsol, destination, amount, user2Keypair));
+  assertEqual(await ledgerAmount(vaultPda, wsol), wsolBefore - BigInt(amount), "取出 WSOL 后账本余额");
+  assertEqual(
+    await tokenBalanceOf(destinationTokenAccount),
+    destinationTokensBefore + BigInt(amount),
+    "收款钱包 WSOL 余额"
+  );
+
+  const solBefore = await ledgerAmount(vaultPda, nativeSol);
+  const destinationLamportsBefore = await connection.getBalance(destination);
+  await confirmTx(await userWithdrawSolTo(vaultPda, destination, amount, user2Keypair));
+  assertEqual(await ledgerAmount(vaultPda, nativeSol), solBefore - BigInt(amount), "取出 SOL 后账本余额");
+  assertEqual(
+    await connection.getBalance(destination),
+    destinationLamportsBefore + amount,
+    "收款钱包 lamports"
+  );
+}
+
+// 批量存取款：一笔交易存入再取出 WSOL 与 USDC，账本恢复原值
+async function testBatchTransfers({ vaultPda }: TestContext) {
+  const transfers = [
+    { mint: TEST_ADDRESSES.dwsolDevnet, amount: 5_000_000 }, // 0.005 SOL
+    { mint: TEST_ADDRESSES.usdcDevnet, amount: 1000 }, // 0.001 USDC
+  ];
+  await wrapSolToAta(user2Keypair, transfers[0].amount);
+
+  const ledgerBefore = await Promise.all(transfers.map(({ mint }) => ledgerAmount(vaultPda, mint)));
+  await confirmTx(await batchDeposit(vaultPda, transfers, user2Keypair));
+  for (const [i, { mint, amount }] of transfers.entries()) {
+    assertEqual(await ledgerAmount(vaultPda, mint), ledgerBefore[i] + BigInt(amount), `批量存款后 ${mint.toString()} 账本余额`);
+  }
+
+  await confirmTx(await batchWithdraw(vaultPda, transfers, user2Keypair));
+  for (const [i, { mint }] of transfers.entries()) {
+    assertEqual(await ledgerAmount(vaultPda, mint), ledgerBefore[i], `批量取款后 ${mint.toString()} 账本余额`);
+  }
+}
+
+// 多金库与金库登记：旧版金库手动登记不重复，新编号金库创建时自动登记
+async function testVaultRegistry({ globalConfigPda, vaultPda }: TestContext) {
+  const findEntry = async (index: number) =>
+    (await getVaultRegistry(user2Keypair.publicKey)).find((entry: any) => entry.vaultIndex === index);
+
+  // 登记账户出现之前创建的旧版金库需要手动登记，重复登记不会产生重复条目
+  await confirmTx(await registerVault(user2Keypair, 0));
+  await confirmTx(await registerVault(user2Keypair, 0));
+  const registry = await getVaultRegistry(user2Keypair.publicKey);
+  assertEqual(registry.filter((entry: any) => entry.vaultIndex === 0).length, 1, "登记账户中编号 0 的条目数量");
+  assertEqual((await findEntry(0))?.vault, vaultPda.toString(), "编号 0 的金库地址");
+
+  // 新编号的金库创建时自动登记
+  const secondIndex = 1;
+  const [secondPda] = generateVaultPDA(user2Keypair.publicKey, secondIndex);
+  const { tx } = await createBalanceManager(globalConfigPda, user2Keypair, secondIndex, "strategy-b");
+  if (tx !== "已存在，无需创建") {
+    await confirmTx(tx);
+  }
+  const secondEntry = await findEntry(secondIndex);
+  assertEqual(secondEntry?.vault, secondPda.toString(), "编号 1 的金库地址");
+  assertEqual(secondEntry?.label, "strategy-b", "编号 1 的金库标签");
+  assertEqual(parsePersonalVaultAccount((await getVaultInfo(secondPda))!.data).vaultIndex, secondIndex, "金库头部记录的编号");
+}
+
+// 分仓：命名分仓 1，在分仓 0 与 1 之间划转并划回
+async function testBuckets({ vaultPda }: TestContext) {
+  const mint = TEST_ADDRESSES.usdcDevnet;
+  const amount = 500;
+
+  await confirmTx(await setBucketLabel(1, "hedge", user2Keypair));
+  assertEqual(parsePersonalVaultAccount((await getVaultInfo(vaultPda))!.data).bucketLabels[1], "hedge", "分仓 1 名称");
+
+  const defaultBefore = await ledgerAmount(vaultPda, mint, 0);
+  const hedgeBefore = await ledgerAmount(vaultPda, mint, 1);
+  await confirmTx(await moveBetweenBuckets(mint, 0, 1, amount, user2Keypair));
+  assertEqual(await ledgerAmount(vaultPda, mint, 0), defaultBefore - BigInt(amount), "划转后分仓 0 余额");
+  assertEqual(await ledgerAmount(vaultPda, mint, 1), hedgeBefore + BigInt(amount), "划转后分仓 1 余额");
+
+  // 划回后两个分仓恢复原状，代币总额始终不变
+  await confirmTx(await moveBetweenBuckets(mint, 1, 0, amount, user2Keypair));
+  assertEqual(await ledgerAmount(vaultPda, mint, 0), defaultBefore, "划回后分仓 0 余额");
+  assertEqual(await ledgerAmount(vaultPda, mint, 1), hedgeBefore, "划回后分仓 1 余额");
+}
+
+// 持仓成本：存取款更新持仓数量、累计存取与成本
+async function testPositionCost({ globalConfigPda, vaultPda }: TestContext) {
+  const mint = TEST_ADDRESSES.usdcDevnet;
+  const amount = 1000;
+  const quoteMint = parseGlobalConfigAccount((await getGlobalConfigInfo(globalConfigPda))!.data).quoteMint;
+  const field = (position: any, name: string) => BigInt(position?.[name] ?? 0);
+
+  const before = await getPosition(vaultPda, mint);
+  await confirmTx(await userDeposit(vaultPda, mint, amount, user2Keypair));
+  const afterDeposit = await getPosition(vaultPda, mint);
+  assertEqual(field(afterDeposit, "amount"), field(before, "amount") + BigInt(amount), "存款后持仓数量");
+  assertEqual(field(afterDeposit, "totalDeposited"), field(before, "totalDeposited") + BigInt(amount), "存款后累计存入");
+  if (quoteMint === mint.toString()) {
+    // 报价代币按面值计入成本
+    assertEqual(field(afterDeposit, "costBasis"), field(before, "costBasis") + BigInt(amount), "存款后持仓成本");
+  } else {
+    // 其他代币存入时没有链上价格，成本记为零并标记为未知
+    assertEqual(afterDeposit?.basisUnknown, true, "存款后成本未知标记");
+  }
+
+  await confirmTx(await userWithdraw(vaultPda, mint, amount, user2Keypair));
+  const afterWithdraw = await getPosition(vaultPda, mint);
+  assertEqual(field(afterWithdraw, "amount"), field(before, "amount"), "取款后持仓数量");
+  assertEqual(field(afterWithdraw, "totalWithdrawn"), field(before, "totalWithdrawn") + BigInt(amount), "取款后累计取出");
+}
+
+// 交易历史：扩容保留全部记录，关闭后记录清空
+async function testTradeHistory({ vaultPda }: TestContext) {
+  const historyPda = getTradeHistoryPda(vaultPda);
+  // 头部 88 字节，每条记录 144 字节
+  const capacityOf = async () => {
+    const length = await getAccountDataLength(historyPda);
+    return length === null ? null : (length - 88) / 144;
+  };
+
+  if (!(await checkAccountExists(historyPda))) {
+    await confirmTx(await manageTradeHistory("init", 8, user2Keypair));
+  }
+  const capacity = (await capacityOf())!;
+  const recordsBefore = await getTradeHistory(vaultPda);
+
+  // 扩容保留全部记录
+  await confirmTx(await manageTradeHistory("resize", capacity + 8, user2Keypair));
+  assertEqual(await capacityOf(), capacity + 8, "扩容后容量");
+  assertEqual(JSON.stringify(await getTradeHistory(vaultPda)), JSON.stringify(recordsBefore), "扩容后交易记录");
+
+  await confirmTx(await manageTradeHistory("close", 0, user2Keypair));
+  assertEqual(await checkAccountExists(historyPda), false, "关闭后交易历史账户存在");
+  assertEqual((await getTradeHistory(vaultPda)).length, 0, "关闭后交易记录数量");
+}
+
+// 净值快照：设置 Pyth 价格源后写入一条快照（需要全局配置已设置报价代币）
+async function testSnapshot({ globalConfigPda, vaultPda }: TestContext) {
+  const quoteMint = parseGlobalConfigAccount((await getGlobalConfigInfo(globalConfigPda))!.data).quoteMint;
+  if (quoteMint === PublicKey.default.toString()) {
+    throw new Error("全局配置未设置报价代币，请先调用 set_quote_mint");
+  }
+
+  // 原生 SOL 与 WSOL 共用 SOL/USD 价格源
+  await confirmTx(await setPriceFeed(SystemProgram.programId, PYTH_FEED_IDS.solUsd, adminKeypair));
+  await confirmTx(await setPriceFeed(TEST_ADDRESSES.dwsolDevnet, PYTH_FEED_IDS.solUsd, adminKeypair));
+  await confirmTx(await setPriceFeed(TEST_ADDRESSES.usdcDevnet, PYTH_FEED_IDS.usdcUsd, adminKeypair));
+
+  // 布局: 鉴别器(8) + vault(32) + last_snapshot_ts(8) + 64 条快照(各 24 字节) + next_index(2) + bump(1)
+  const [snapshotsPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("vault_snapshots"), vaultPda.toBuffer()],
+    PROGRAM_ID
+  );
+  const readSnapshots = async () => {
+    const connection = await checkConnection();
+    if (!connection) {
+      throw new Error("网络连接失败");
+    }
+    const account = await connection.getAccountInfo(snapshotsPda);
+    if (!account) {
+      return { lastSnapshotTs: BigInt(0), nextIndex: 0 };
+    }
+    return {
+      lastSnapshotTs: account.data.readBigInt64LE(8 + 32),
+      nextIndex: account.data.readUInt16LE(8 + 32 + 8 + 24 * 64),
+    };
+  };
+
+  const before = await readSnapshots();
+  await confirmTx(await snapshotVault(
+    vaultPda,
+    [getPythPriceFeedAccount(PYTH_FEED_IDS.solUsd), getPythPriceFeedAccount(PYTH_FEED_IDS.usdcUsd)],
+    adminKeypair
+  ));
+  const after = await readSnapshots();
+  assertEqual(after.nextIndex, (before.nextIndex + 1) % 64, "快照写入位置");
+  if (after.lastSnapshotTs <= before.lastSnapshotTs) {
+    throw new Error(`快照时间没有前进: ${before.lastSnapshotTs} -> ${after.lastSnapshotTs}`);
+  }
+}
+
+// 投资者暂停交易：暂停期间交易信号被拒绝，恢复后状态复原
+async function testInvestorPause({ vaultPda }: TestContext) {
+  const tradingPaused = async () => parsePersonalVaultAccount((await getVaultInfo(vaultPda))!.data).tradingPaused;
+  assertEqual(await tradingPaused(), false, "暂停前交易状态");
+
+  await confirmTx(await setTradingPaused(true, user2Keypair));
+  assertEqual(await tradingPaused(), true, "暂停后交易状态");
+  await expectProgramError(
+    sendTradeSignal(vaultPda, TEST_ADDRESSES.dwsolDevnet, TEST_ADDRESSES.usdcDevnet, 1000, 300, adminKeypair),
+    "VaultTradingPaused",
+    "暂停期间的交易信号"
+  );
+
+  await confirmTx(await setTradingPaused(false, user2Keypair));
+  assertEqual(await tradingPaused(), false, "恢复后交易状态");
+}
+
+// 全局紧急暂停：守护者暂停存款后存款被拒绝，管理员恢复并取消守护者
+async function testEmergencyPause({ globalConfigPda, vaultPda }: TestContext) {
+  const readConfig = async () => parseGlobalConfigAccount((await getGlobalConfigInfo(globalConfigPda))!.data);
+  const PAUSE_DEPOSITS = 1 << 1;
+
+  // 旧布局的全局配置迁移后才有守护者和暂停标志，已是当前布局时不做修改
+  await confirmTx(await migrateGlobalConfig(adminKeypair));
+  assertEqual((await readConfig()).pausedOperations, 0, "迁移后暂停标志");
+
+  await confirmTx(await setGuardian(botKeypair.publicKey, adminKeypair));
+  assertEqual((await readConfig()).guardian, botKeypair.publicKey.toString(), "守护者地址");
+
+  // 守护者暂停存款
+  await confirmTx(await setPauseFlags(PAUSE_DEPOSITS, 0, botKeypair));
+  assertEqual((await readConfig()).pausedOperations, PAUSE_DEPOSITS, "暂停后暂停标志");
+  await expectProgramError(userDepositSol(vaultPda, 1_000_000, user2Keypair), "DepositsPaused", "暂停期间的存款");
+
+  await confirmTx(await setPauseFlags(0, 0, adminKeypair));
+  assertEqual((await readConfig()).pausedOperations, 0, "恢复后暂停标志");
+  await confirmTx(await setGuardian(PublicKey.default, adminKeypair));
+  assertEqual((await readConfig()).guardian, PublicKey.default.toString(), "取消后守护者地址");
+}
+
+// 交易策略：只允许 USDC 时 WSOL 输入的交易信号被拒绝，移除后策略账户关闭
+async function testTradingPolicy({ vaultPda }: TestContext) {
+  const policyPda = getTradingPolicyPda(vaultPda);
+  const tokenIn = TEST_ADDRESSES.dwsolDevnet;
+  const amountIn = 1000;
+
+  // 只允许 USDC，WSOL -> USDC 的交易应被策略拒绝
+  await confirmTx(await setTradingPolicy({
+    allowedMints: [TEST_ADDRESSES.usdcDevnet],
+    maxAmountIn: 0,
+    maxAmountInBps: 0,
+    maxSlippageBps: 0,
+    allowedDexRoutes: 0,
+    minTradeIntervalSecs: 0,
+  }, user2Keypair));
+  assertEqual(await checkAccountExists(policyPda), true, "设置后交易策略账户存在");
+
+  // 策略检查在余额检查之后执行，金库需要持有足够的输入代币
+  if (await ledgerAmount(vaultPda, tokenIn) >= BigInt(amountIn)) {
+    await expectProgramError(
+      sendTradeSignal(vaultPda, tokenIn, TEST_ADDRESSES.usdcDevnet, amountIn, 300, adminKeypair),
+      "TokenNotAllowedByPolicy",
+      "策略不允许的交易信号"
+    );
+  } else {
+    console.log("⚠️  金库 WSOL 余额不足，跳过策略拒绝交易的检查");
+  }
+
+  await confirmTx(await closeTradingPolicy(user2Keypair));
+  assertEqual(await checkAccountExists(policyPda), false, "移除后交易策略账户存在");
+}
+
+// 交易限额：修改上限不影响已记录的用量，移除后不再限制
+async function testTradeLimits({ vaultPda }: TestContext) {
+  const quoteMint = TEST_ADDRESSES.usdcDevnet;
+
+  await confirmTx(await setTradeLimits(quoteMint, 5, 0, 1, user2Keypair));
+  const allowance = (await getTradeAllowance(vaultPda))!;
+  assertEqual(allowance.quoteMint, quoteMint.toString(), "限额计价代币");
+  assertEqual(allowance.maxDailyTrades, 5, "每日交易次数上限");
+  assertEqual(allowance.volumeRemaining, null, "交易额上限为 0 时不限制");
+  assertEqual(allowance.unpricedTradePolicy, "countOnly", "无法计价交易的处理方式");
+
+  // 计价代币不变时修改限额只更新上限，不影响已记录的用量
+  await confirmTx(await setTradeLimits(quoteMint, 3, 1_000_000, 0, user2Keypair));
+  const updated = (await getTradeAllowance(vaultPda))!;
+  assertEqual(updated.maxDailyTrades, 3, "修改后每日交易次数上限");
+  assertEqual(updated.maxDailyVolume, 1_000_000, "修改后每日交易额上限");
+  assertEqual(updated.tradesUsed, allowance.tradesUsed, "修改后已用交易次数");
+  assertEqual(updated.unpricedTradePolicy, "reject", "修改后无法计价交易的处理方式");
+
+  await confirmTx(await closeTradeLimits(user2Keypair));
+  assertEqual(await getTradeAllowance(vaultPda), null, "移除后交易限额");
+}
+
+// 金库执行者授权：授权与撤销执行者，不接受全局执行者时管理员的交易信号被拒绝
+async function testVaultExecutors({ vaultPda }: TestContext) {
+  const executor = user1Keypair.publicKey;
+  const isAuthorized = async () =>
+    ((await getVaultExecutors(vaultPda))?.executors ?? []).some((entry: any) => entry.executor === executor.toString());
+
+  await confirmTx(await authorizeVaultExecutor(executor, null, user2Keypair));
+  assertEqual(await isAuthorized(), true, "授权后执行者在列表中");
+  assertEqual((await getVaultExecutors(vaultPda))!.allowGlobalExecutors, true, "默认接受全局执行者");
+
+  // 不再接受全局 bot/admin 后，管理员的交易信号被拒绝
+  await confirmTx(await setExecutorPreference(false, user2Keypair));
+  assertEqual((await getVaultExecutors(vaultPda))!.allowGlobalExecutors, false, "设置后接受全局执行者");
+  await expectProgramError(
+    sendTradeSignal(vaultPda, TEST_ADDRESSES.dwsolDevnet, TEST_ADDRESSES.usdcDevnet, 1000, 300, adminKeypair),
+    "ExecutorNotAuthorized",
+    "未授权管理员的交易信号"
+  );
+
+  await confirmTx(await revokeVaultExecutor(executor, user2Keypair));
+  assertEqual(await isAuthorized(), false, "撤销后执行者在列表中");
+
+  // 删除授权后恢复为只接受全局 bot/admin
+  await confirmTx(await closeVaultExecutors(user2Keypair));
+  assertEqual(await getVaultExecutors(vaultPda), null, "删除后执行者授权");
+}
+
+// 功能测试（按执行顺序）。命令行参数可指定只运行其中几项，例如 `npx ts-node test/test.ts reconcile compact`；
+// 部分测试依赖前面测试留下的链上状态（如代存款要求主金库账本中已有 SOL 与 WSOL）
+const FEATURE_TESTS: { name: string; title: string; run: (ctx: TestContext) => Promise<void> }[] = [
+  { name: "trade-signal", title: "交易信号", run: testTradeSignal },
+  { name: "signal-registry", title: "信号记录账户迁移", run: testSignalRegistryMigration },
+  { name: "reconcile", title: "账本对账", run: testReconcileBalances },
+  { name: "compact", title: "压缩金库", run: testCompactVault },
+  { name: "close-vault", title: "关闭金库", run: testCloseVault },
+  { name: "deposit-for", title: "代存款", run: testDepositFor },
+  { name: "withdraw-to", title: "取款到指定钱包", run: testWithdrawTo },
+  { name: "batch", title: "批量存取款", run: testBatchTransfers },
+  { name: "registry", title: "多金库与金库登记", run: testVaultRegistry },
+  { name: "buckets", title: "分仓", run: testBuckets },
+  { name: "position", title: "持仓成本", run: testPositionCost },
+  { name: "history", title: "交易历史", run: testTradeHistory },
+  { name: "snapshot", title: "净值快照", run: testSnapshot },
+  { name: "investor-pause", title: "投资者暂停交易", run: testInvestorPause },
+  { name: "emergency-pause", title: "全局紧急暂停", run: testEmergencyPause },
+  { name: "policy", title: "交易策略", run: testTradingPolicy },
+  { name: "limits", title: "交易限额", run: testTradeLimits },
+  { name: "executors", title: "金库执行者授权", run: testVaultExecutors },
+];
+
+// 主测试函数：创建全局配置与主金库后依次运行选中的功能测试
+async function testComplete(selected: string[] = process.argv.slice(2)) {
+  const unknown = selected.filter((name) => !FEATURE_TESTS.some((test) => test.name === name));
+  if (unknown.length > 0) {
+    console.error("❌ 未知的测试:", unknown.join(", "), "\n可选:", FEATURE_TESTS.map(({ name }) => name).join(", "));
+    process.exitCode = 1;
+    return;
+  }
 
-// 主测试函数
-async function testComplete() {
   try {
     console.log("🎯 开始完整测试流程...\n");
 
@@ -1333,6 +3702,7 @@ async function testComplete() {
 
     // 获取初始金库信息
     await getVaultInfo(vaultPda);
+    const ctx: TestContext = { globalConfigPda, vaultPda };
 
 
     // console.log("\n=== 步骤 3: SOL 存款 SOL 测试 ===");
@@ -1460,64 +3830,9 @@ async function testComplete() {
     // }
 
 
-    console.log("\n=== 步骤 7: 交易信号测试 ===");
-    try {
-      console.log("\n🤖 测试机器人权限调用交易信号...");
-
-      // ✅ 使用 Wrapped SOL (dwsolDevnet) 而不是原生 SOL
-      // 因为存款时使用的是 dwsolDevnet，所以交换时也必须使用相同的 mint
-      const tokenIn = TEST_ADDRESSES.dwsolDevnet; // Wrapped SOL 作为输入代币
-      const tokenOut = TEST_ADDRESSES.usdcDevnet; // USDC Devnet 作为输出代币
-      const amountIn = 100000000; // 0.1 SOL
-      const slippageBps = 300; // 3% 滑点 (300 基点)
-
-      console.log("使用机器人身份调用交易信号...");
-
-      // 🔍 验证代币账户地址计算
-      console.log("\n🔍 验证代币账户地址计算...");
-
-      // 获取用户的代币账户地址
-      const userTokenAccount = await getAssociatedTokenAddress(
-        tokenIn,
-        user2Keypair.publicKey
-      );
-      console.log("  用户代币账户:", userTokenAccount.toString());
-
-      // 获取金库的代币账户地址（交换时将使用的账户）
-      const vaultTokenAccount = await getAssociatedTokenAddress(
-        tokenIn,
-        vaultPda,
-        true // 允许PDA作为代币账户所有者
-      );
-      console.log("  金库代币账户 (交换时):", vaultTokenAccount.toString());
-
-      // 对比存款时的金库代币账户
-      const depositTimeVaultAccount = "Cvjy63uiDC24anWJyF71tSefVNYntSkFLomqmjYeKAu8";
-      console.log("  金库代币账户 (存款时):", depositTimeVaultAccount);
-
-      if (vaultTokenAccount.toString() === depositTimeVaultAccount) {
-        console.log("  ✅ 地址匹配！交换和存款使用相同的金库代币账户");
-      } else {
-        console.log("  ❌ 地址不匹配！这可能导致交换失败");
-        console.log("  ⚠️  请检查代币 mint 是否一致");
-      }
-      console.log();
-
-      const botTradeTx = await sendTradeSignal(
-        vaultPda,
-        tokenIn,
-        tokenOut,
-        amountIn,
-        slippageBps,
-        adminKeypair,
-        user2Keypair // 传入金库所有者的 Keypair
-      );
-
-      console.log("✅ 管理员交易信号测试成功");
-      console.log("  交易签名:", botTradeTx);
-
-    } catch (error) {
-      console.error("❌ 管理员交易信号测试失败:", error);
+    const tests = selected.length === 0 ? FEATURE_TESTS : FEATURE_TESTS.filter(({ name }) => selected.includes(name));
+    for (const { title, run } of tests) {
+      await runFeatureTest(title, run, ctx);
     }
 
     // 6b. 管理员权限测试
@@ -1602,6 +3917,7 @@ async function testComplete() {
 
   } catch (error) {
     console.error("❌ 测试流程失败:", error);
+    process.exitCode = 1;
   }
 }
 
@@ -0,0 +1,31 @@
+//! 分仓名称：超过 BUCKET_LABEL_LEN 字节的名称被拒绝
+
+mod common;
+
+use common::{vault_error, VaultFixture};
+use personal_vault::{ErrorCode, BUCKET_LABEL_LEN};
+
+/// 投资者设置分仓名称
+fn set_bucket_label(
+    fixture: &mut VaultFixture,
+    bucket: u8,
+    label: String,
+) -> Result<(), anchor_lang::prelude::ProgramError> {
+    let (vault, investor) = (fixture.vault, fixture.investor);
+    fixture.env.vault_ix(
+        personal_vault::accounts::SetBucketLabel { vault, user: investor },
+        personal_vault::instruction::SetBucketLabel { bucket, label },
+        vec![],
+    )
+}
+
+#[test]
+fn bucket_label_length_is_checked() {
+    let mut fixture = VaultFixture::new();
+
+    set_bucket_label(&mut fixture, 1, "h".repeat(BUCKET_LABEL_LEN)).unwrap();
+    assert_eq!(
+        set_bucket_label(&mut fixture, 1, "h".repeat(BUCKET_LABEL_LEN + 1)),
+        Err(vault_error(ErrorCode::InvalidBucketLabel))
+    );
+}
@@ -0,0 +1,165 @@
+//! 关闭金库：转回全部资产，关闭附属 PDA，同一编号重建的金库不继承旧配置
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::AccountMeta;
+use anchor_lang::system_program;
+use common::{ata, vault_child_pda, vault_error, VaultFixture};
+use personal_vault::{DexRoute, ErrorCode, TradingPolicyParams, UnpricedTradePolicy};
+
+const DEPOSIT: u64 = 10_000;
+
+/// 金库的全部附属 PDA
+const VAULT_CHILD_PREFIXES: [&[u8]; 6] = [
+    b"signal_registry",
+    b"trading_policy",
+    b"trade_limits",
+    b"vault_executors",
+    b"trade_history",
+    b"vault_snapshots",
+];
+
+/// 投资者为金库设置策略、限额、执行者授权和交易历史
+fn configure_vault(fixture: &mut VaultFixture, quote_mint: &Pubkey) {
+    let (vault, investor, bot) = (fixture.vault, fixture.investor, fixture.bot);
+    fixture
+        .env
+        .vault_ix(
+            personal_vault::accounts::SetTradingPolicy {
+                vault,
+                user: investor,
+                trading_policy: vault_child_pda(b"trading_policy", &vault),
+                system_program: system_program::ID,
+            },
+            personal_vault::instruction::SetTradingPolicy {
+                params: TradingPolicyParams {
+                    allowed_mints: vec![*quote_mint],
+                    max_amount_in: 0,
+                    max_amount_in_bps: 0,
+                    max_slippage_bps: 0,
+                    allowed_dex_routes: 0,
+                    min_trade_interval_secs: 0,
+                },
+            },
+            vec![],
+        )
+        .unwrap();
+    fixture
+        .env
+        .vault_ix(
+            personal_vault::accounts::SetTradeLimits {
+                vault,
+                user: investor,
+                trade_limits: vault_child_pda(b"trade_limits", &vault),
+                system_program: system_program::ID,
+            },
+            personal_vault::instruction::SetTradeLimits {
+                quote_mint: *quote_mint,
+                max_daily_trades: 1,
+                max_daily_volume: 0,
+                unpriced_trade_policy: UnpricedTradePolicy::Reject,
+            },
+            vec![],
+        )
+        .unwrap();
+    fixture
+        .env
+        .vault_ix(
+            personal_vault::accounts::ManageVaultExecutors {
+                vault,
+                user: investor,
+                vault_executors: vault_child_pda(b"vault_executors", &vault),
+                system_program: system_program::ID,
+            },
+            personal_vault::instruction::AuthorizeVaultExecutor {
+                executor: bot,
+                expires_at: None,
+            },
+            vec![],
+        )
+        .unwrap();
+    fixture
+        .env
+        .vault_ix(
+            personal_vault::accounts::InitTradeHistory {
+                vault,
+                user: investor,
+                trade_history: vault_child_pda(b"trade_history", &vault),
+                system_program: system_program::ID,
+            },
+            personal_vault::instruction::InitTradeHistory { capacity: 8 },
+            vec![],
+        )
+        .unwrap();
+}
+
+fn token_group(fixture: &VaultFixture, mint: &Pubkey) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(ata(&fixture.vault, mint, &spl_token::ID), false),
+        AccountMeta::new(ata(&fixture.investor, mint, &spl_token::ID), false),
+        AccountMeta::new_readonly(*mint, false),
+    ]
+}
+
+#[test]
+fn close_vault_returns_assets_and_closes_per_vault_accounts() {
+    let mut fixture = VaultFixture::new();
+    let mint = fixture.create_mint(&spl_token::ID);
+    fixture.deposit(&mint, DEPOSIT, vec![]).unwrap();
+    configure_vault(&mut fixture, &mint);
+
+    let vault = fixture.vault;
+    let children: Vec<Pubkey> = VAULT_CHILD_PREFIXES
+        .iter()
+        .map(|prefix| vault_child_pda(prefix, &vault))
+        .collect();
+    let vault_token_account = ata(&vault, &mint, &spl_token::ID);
+    let reclaimed: u64 = children
+        .iter()
+        .chain([&vault, &vault_token_account])
+        .map(|key| fixture.env.lamports(key))
+        .sum();
+    let investor_lamports = fixture.env.lamports(&fixture.investor);
+
+    let remaining = token_group(&fixture, &mint);
+    fixture.close_vault(remaining).unwrap();
+
+    let investor = fixture.investor;
+    assert_eq!(fixture.env.token_balance(&ata(&investor, &mint, &spl_token::ID)), DEPOSIT);
+    assert_eq!(fixture.env.lamports(&investor), investor_lamports + reclaimed);
+    for key in children.iter().chain([&vault, &vault_token_account]) {
+        assert!(!fixture.env.exists(key), "{key} was not closed");
+    }
+
+    // 同一编号重建的金库不继承旧的策略、限额、执行者授权和交易历史
+    fixture.create_vault(1).unwrap();
+    assert!(fixture.env.exists(&vault));
+    for key in &children {
+        assert!(!fixture.env.exists(key), "{key} survived the vault re-creation");
+    }
+}
+
+#[test]
+fn close_vault_rejects_ledger_tokens_without_token_accounts() {
+    let mut fixture = VaultFixture::new();
+    let mint = fixture.create_mint(&spl_token::ID);
+    fixture.deposit(&mint, DEPOSIT, vec![]).unwrap();
+    configure_vault(&mut fixture, &mint);
+
+    let err = fixture.close_vault(vec![]).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::VaultNotEmpty));
+    let vault = fixture.vault;
+    assert!(fixture.env.exists(&vault));
+    assert!(fixture.env.exists(&vault_child_pda(b"trading_policy", &vault)));
+    assert_eq!(fixture.ledger_balance(&mint), DEPOSIT);
+}
+
+#[test]
+fn registered_routes_leave_no_positions_behind() {
+    // close_vault 只能转回金库代币账户；路由声明会留下仓位时关闭被拒绝
+    for route in DexRoute::ALL {
+        assert!(!route.adapter().leaves_open_positions(), "{route:?}");
+    }
+    assert!(!DexRoute::any_leaves_open_positions());
+}
@@ -0,0 +1,288 @@
+//! 测试用的本地程序：System 程序、Token-2022 包装、转账钩子以及 Raydium AMM V4 / CLMM 模拟
+//!
+//! 模拟 DEX 按真实的账户布局读取账户，以固定汇率（1 输入换 MOCK_SWAP_RATE 输出）通过代币程序
+//! CPI 完成交换：金库 -> 池子由金库签名，池子 -> 金库由模拟程序的池子 PDA 签名。
+
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::AccountMeta;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::system_program;
+use spl_tlv_account_resolution::state::ExtraAccountMetaList;
+use spl_token_2022::extension::StateWithExtensions;
+use spl_transfer_hook_interface::get_extra_account_metas_address;
+use spl_transfer_hook_interface::instruction::{ExecuteInstruction, TransferHookInstruction};
+
+/// 模拟 DEX 的固定汇率
+pub const MOCK_SWAP_RATE: u64 = 2;
+
+/// 模拟 AMM 的池子权限 PDA 种子
+pub const AMM_AUTHORITY_SEED: &[u8] = b"amm authority";
+
+/// 模拟 CLMM 的池子状态 PDA 种子（同时作为池子金库的权限）
+pub const CLMM_POOL_SEED: &[u8] = b"pool";
+
+/// 测试转账钩子程序 ID
+pub const TEST_HOOK_PROGRAM_ID: Pubkey = Pubkey::new_from_array([0x48; 32]);
+
+/// 钩子计数账户的 PDA 种子（`[HOOK_COUNTER_SEED, mint]`）
+pub const HOOK_COUNTER_SEED: &[u8] = b"counter";
+
+/// 输出数量低于最小值时模拟 DEX 返回的错误
+pub const MOCK_SLIPPAGE_ERROR: u32 = 0x1771;
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, ProgramError> {
+    data.get(offset..offset + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ProgramError::InvalidInstructionData)
+}
+
+/// System 程序：create_account / assign / transfer / allocate
+pub fn process_system<'a>(_program_id: &Pubkey, accounts: &'a [AccountInfo<'a>], data: &[u8]) -> ProgramResult {
+    let tag = data
+        .get(..4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    let signer = |info: &AccountInfo| {
+        if info.is_signer {
+            Ok(())
+        } else {
+            Err(ProgramError::MissingRequiredSignature)
+        }
+    };
+    let transfer = |from: &AccountInfo, to: &AccountInfo, lamports: u64| {
+        signer(from)?;
+        if *from.owner != system_program::ID || !from.data_is_empty() {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let remaining = from
+            .lamports()
+            .checked_sub(lamports)
+            .ok_or(ProgramError::InsufficientFunds)?;
+        **from.try_borrow_mut_lamports()? = remaining;
+        **to.try_borrow_mut_lamports()? += lamports;
+        Ok(())
+    };
+    let allocate = |account: &AccountInfo, space: u64| {
+        signer(account)?;
+        if *account.owner != system_program::ID || !account.data_is_empty() {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+        account.resize(space as usize)
+    };
+    let assign = |account: &AccountInfo, owner: &[u8]| {
+        signer(account)?;
+        if *account.owner != system_program::ID {
+            return Err(ProgramError::IllegalOwner);
+        }
+        account.assign(&Pubkey::try_from(owner).map_err(|_| ProgramError::InvalidInstructionData)?);
+        Ok(())
+    };
+
+    match tag {
+        // CreateAccount { lamports, space, owner }
+        0 => {
+            let (from, to) = (&accounts[0], &accounts[1]);
+            if to.lamports() > 0 {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            transfer(from, to, read_u64(data, 4)?)?;
+            allocate(to, read_u64(data, 12)?)?;
+            assign(to, data.get(20..52).ok_or(ProgramError::InvalidInstructionData)?)
+        }
+        // Assign { owner }
+        1 => assign(&accounts[0], data.get(4..36).ok_or(ProgramError::InvalidInstructionData)?),
+        // Transfer { lamports }
+        2 => transfer(&accounts[0], &accounts[1], read_u64(data, 4)?),
+        // Allocate { space }
+        8 => allocate(&accounts[0], read_u64(data, 4)?),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+/// Token-2022 程序
+/// 主机构建的 Token-2022 通过 solana_cpi 调用转账钩子，在测试运行时中不会执行；
+/// TransferChecked 成功后按链上相同的方式解析额外账户并补做钩子的 Execute 调用
+pub fn process_token_2022<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>], data: &[u8]) -> ProgramResult {
+    spl_token_2022::processor::Processor::process(program_id, accounts, data)?;
+    // TransferChecked: [source, mint, destination, authority, 额外账户...]
+    if data.first() != Some(&12) {
+        return Ok(());
+    }
+    let mint = &accounts[1];
+    let hook_program_id = {
+        let mint_data = mint.try_borrow_data()?;
+        let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+        spl_token_2022::extension::transfer_hook::get_program_id(&mint_state)
+    };
+    let Some(hook_program_id) = hook_program_id else {
+        return Ok(());
+    };
+    let additional_accounts = &accounts[4..];
+    let mut execute = spl_transfer_hook_interface::instruction::execute(
+        &hook_program_id,
+        accounts[0].key,
+        mint.key,
+        accounts[2].key,
+        accounts[3].key,
+        read_u64(data, 1)?,
+    );
+    let mut execute_infos = accounts[..4].to_vec();
+    let validation_key = get_extra_account_metas_address(mint.key, &hook_program_id);
+    if let Some(validation_info) = additional_accounts.iter().find(|info| *info.key == validation_key) {
+        execute.accounts.push(AccountMeta::new_readonly(validation_key, false));
+        execute_infos.push(validation_info.clone());
+        ExtraAccountMetaList::add_to_cpi_instruction::<ExecuteInstruction>(
+            &mut execute,
+            &mut execute_infos,
+            &validation_info.try_borrow_data()?,
+            additional_accounts,
+        )?;
+    }
+    invoke(&execute, &execute_infos)
+}
+
+/// 测试转账钩子：按 ExtraAccountMetaList 校验 Execute 的账户，
+/// 并在计数账户中累计调用次数（[0..8]）和转账数量（[8..16]）
+pub fn process_test_hook<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>], data: &[u8]) -> ProgramResult {
+    let TransferHookInstruction::Execute { amount } = TransferHookInstruction::unpack(data)? else {
+        return Err(ProgramError::InvalidInstructionData);
+    };
+    let validation = accounts.get(4).ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if *validation.key != get_extra_account_metas_address(accounts[1].key, program_id) || validation.owner != program_id {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    ExtraAccountMetaList::check_account_infos::<ExecuteInstruction>(
+        accounts,
+        data,
+        program_id,
+        &validation.try_borrow_data()?,
+    )?;
+
+    let counter = accounts.get(5).ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let mut counter_data = counter.try_borrow_mut_data()?;
+    let calls = read_u64(&counter_data, 0)? + 1;
+    let total = read_u64(&counter_data, 8)? + amount;
+    counter_data[..8].copy_from_slice(&calls.to_le_bytes());
+    counter_data[8..16].copy_from_slice(&total.to_le_bytes());
+    Ok(())
+}
+
+/// 读取代币账户的 mint
+pub fn token_account_mint(info: &AccountInfo) -> Result<Pubkey, ProgramError> {
+    let data = info.try_borrow_data()?;
+    Ok(StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?.base.mint)
+}
+
+/// 读取 mint 的小数位数
+pub fn mint_decimals(info: &AccountInfo) -> Result<u8, ProgramError> {
+    let data = info.try_borrow_data()?;
+    Ok(StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?.base.decimals)
+}
+
+/// Raydium AMM V4 swap_base_in 模拟（指令 9，18 个账户）
+/// [0] token program, [2] 池子权限, [5]/[6] 池子代币账户, [15] 输入, [16] 输出, [17] 输入账户 owner
+pub fn process_mock_amm<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>], data: &[u8]) -> ProgramResult {
+    if data.len() != 17 || data[0] != 9 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if accounts.len() < 18 {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let amount_in = read_u64(data, 1)?;
+    let minimum_out = read_u64(data, 9)?;
+    let token_program = &accounts[0];
+    let authority = &accounts[2];
+    let (source, destination, owner) = (&accounts[15], &accounts[16], &accounts[17]);
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let (expected_authority, bump) = Pubkey::find_program_address(&[AMM_AUTHORITY_SEED], program_id);
+    if *authority.key != expected_authority {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let (pool_in, pool_out) = if token_account_mint(&accounts[5])? == token_account_mint(source)? {
+        (&accounts[5], &accounts[6])
+    } else {
+        (&accounts[6], &accounts[5])
+    };
+    let amount_out = amount_in * MOCK_SWAP_RATE;
+    if amount_out < minimum_out {
+        return Err(ProgramError::Custom(MOCK_SLIPPAGE_ERROR));
+    }
+
+    invoke(
+        &spl_token::instruction::transfer(token_program.key, source.key, pool_in.key, owner.key, &[], amount_in)?,
+        &[source.clone(), pool_in.clone(), owner.clone()],
+    )?;
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            pool_out.key,
+            destination.key,
+            authority.key,
+            &[],
+            amount_out,
+        )?,
+        &[pool_out.clone(), destination.clone(), authority.clone()],
+        &[&[AMM_AUTHORITY_SEED, &[bump]]],
+    )
+}
+
+/// Raydium CLMM swap_v2 模拟
+/// [0] payer, [2] 池子状态, [3]/[4] 输入/输出代币账户, [5]/[6] 池子金库, [11]/[12] 输入/输出 mint,
+/// [13+] tick array 和转账钩子额外账户
+pub fn process_mock_clmm<'a>(program_id: &Pubkey, accounts: &'a [AccountInfo<'a>], data: &[u8]) -> ProgramResult {
+    let discriminator = &anchor_lang::solana_program::hash::hash(b"global:swap_v2").to_bytes()[..8];
+    if data.len() != 41 || data[..8] != *discriminator || data[40] != 1 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if accounts.len() < 13 {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let amount_in = read_u64(data, 8)?;
+    let minimum_out = read_u64(data, 16)?;
+    let payer = &accounts[0];
+    let pool_state = &accounts[2];
+    let (input_account, output_account) = (&accounts[3], &accounts[4]);
+    let (input_vault, output_vault) = (&accounts[5], &accounts[6]);
+    let (input_mint, output_mint) = (&accounts[11], &accounts[12]);
+    let extra_accounts = &accounts[13..];
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let (expected_pool, bump) = Pubkey::find_program_address(&[CLMM_POOL_SEED], program_id);
+    if *pool_state.key != expected_pool {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let amount_out = amount_in * MOCK_SWAP_RATE;
+    if amount_out < minimum_out {
+        return Err(ProgramError::Custom(MOCK_SLIPPAGE_ERROR));
+    }
+
+    spl_token_2022::onchain::invoke_transfer_checked(
+        input_account.owner,
+        input_account.clone(),
+        input_mint.clone(),
+        input_vault.clone(),
+        payer.clone(),
+        extra_accounts,
+        amount_in,
+        mint_decimals(input_mint)?,
+        &[],
+    )?;
+    spl_token_2022::onchain::invoke_transfer_checked(
+        output_account.owner,
+        output_vault.clone(),
+        output_mint.clone(),
+        output_account.clone(),
+        pool_state.clone(),
+        extra_accounts,
+        amount_out,
+        mint_decimals(output_mint)?,
+        &[&[CLMM_POOL_SEED, &[bump]]],
+    )
+}
@@ -0,0 +1,1155 @@
+//! 本地测试运行时：在主机上直接执行合约与 SPL 程序的处理函数
+//!
+//! 账户按加载器的对齐输入格式序列化后交给 `entrypoint::deserialize`，因此 `resize`、`assign`
+//! 与链上行为一致；CPI 通过 syscall stub 分发到本地处理函数（personal_vault、SPL Token、
+//! Token-2022、ATA 程序、模拟 System 程序、测试转账钩子和模拟 DEX），并按调用方程序 ID 校验 PDA 签名。
+//! 顶层指令成功后才把账户写回，失败时丢弃全部修改。每次调用（含 CPI）返回后按链上规则校验
+//! 账户修改：只读账户不可改动，只有所有者程序可以改数据、扣 lamports 或转移所有权；
+//! 顶层指令还校验 lamports 守恒。
+//!
+//! 为什么不用 solana-program-test / litesvm：本仓库的构建环境只能离线使用已缓存的 crate，
+//! 其中只有 solana-program-test 1.18，它依赖已撤回的 solana_rbpf 0.8.0 无法解析，且与本合约
+//! 使用的 solana-program 2.x 类型不兼容；缓存里没有 program-test 2.x 和 litesvm，也没有
+//! cargo-build-sbf，无法产出 .so。因此这里直接在主机上运行各程序的原生处理函数。
+//!
+//! 不覆盖的部分：计算单元计量、SBF 栈/堆限制、交易签名校验、除 Clock/Rent 外的 sysvar；
+//! Token-2022 在主机上不发起钩子 CPI，由本运行时在每次带钩子的 `TransferChecked` 后重放 `Execute`。
+//! 除 PDA 与错误码辅助函数外，测试只通过 `TestEnv` 与 `VaultFixture` 的接口访问运行时，换成 program-test 或 litesvm 时
+//! 只需替换这两个类型的实现。
+
+#![allow(dead_code)]
+
+pub mod mock_programs;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Once;
+
+use anchor_lang::prelude::{AccountInfo, Clock, Pubkey, Rent};
+use anchor_lang::solana_program::entrypoint::{self, ProgramResult, MAX_PERMITTED_DATA_INCREASE};
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::program_stubs::{self, SyscallStubs};
+use anchor_lang::{pubkey, system_program, AnchorDeserialize, InstructionData, ToAccountMetas};
+use spl_tlv_account_resolution::account::ExtraAccountMeta;
+use spl_tlv_account_resolution::seeds::Seed;
+use spl_tlv_account_resolution::state::ExtraAccountMetaList;
+use spl_token_2022::extension::{ExtensionType, StateWithExtensions, StateWithExtensionsMut};
+use spl_transfer_hook_interface::get_extra_account_metas_address;
+use spl_transfer_hook_interface::instruction::ExecuteInstruction;
+
+use mock_programs::{HOOK_COUNTER_SEED, TEST_HOOK_PROGRAM_ID};
+
+/// 原生处理函数签名
+pub type Processor = for<'a> fn(&Pubkey, &'a [AccountInfo<'a>], &[u8]) -> ProgramResult;
+
+/// BPF 加载器（程序账户的 owner）
+const BPF_LOADER_ID: Pubkey = anchor_lang::solana_program::bpf_loader_upgradeable::ID;
+
+/// 原生加载器（System 程序账户的 owner）
+const NATIVE_LOADER_ID: Pubkey = pubkey!("NativeLoader1111111111111111111111111111111");
+
+/// 测试账户状态
+#[derive(Clone, Debug, Default)]
+pub struct TestAccount {
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub owner: Pubkey,
+    pub executable: bool,
+}
+
+thread_local! {
+    static PROGRAM_STACK: RefCell<Vec<Pubkey>> = const { RefCell::new(Vec::new()) };
+    static RETURN_DATA: RefCell<Option<(Pubkey, Vec<u8>)>> = const { RefCell::new(None) };
+    static LOG_DATA: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+    static PRE_ACCOUNTS: RefCell<Vec<Vec<PreAccount>>> = const { RefCell::new(Vec::new()) };
+    static CLOCK: RefCell<Clock> = RefCell::new(Clock::default());
+}
+
+/// 根据程序 ID 查找本地处理函数
+fn processor_for(program_id: &Pubkey) -> Option<Processor> {
+    let processor: Processor = if *program_id == personal_vault::ID {
+        |program_id, accounts, data| personal_vault::entry(program_id, accounts, data)
+    } else if *program_id == spl_token::ID {
+        |program_id, accounts, data| spl_token::processor::Processor::process(program_id, accounts, data)
+    } else if *program_id == spl_token_2022::ID {
+        mock_programs::process_token_2022
+    } else if *program_id == spl_associated_token_account::ID {
+        spl_associated_token_account::processor::process_instruction
+    } else if *program_id == system_program::ID {
+        mock_programs::process_system
+    } else if *program_id == personal_vault::RAYDIUM_AMM_PROGRAM_ID {
+        mock_programs::process_mock_amm
+    } else if *program_id == personal_vault::RAYDIUM_CLMM_PROGRAM_ID {
+        mock_programs::process_mock_clmm
+    } else if *program_id == TEST_HOOK_PROGRAM_ID {
+        mock_programs::process_test_hook
+    } else {
+        return None;
+    };
+    Some(processor)
+}
+
+/// 调用前的账户状态，用于按链上规则校验被调用程序对账户的修改
+#[derive(Clone)]
+struct PreAccount {
+    key: Pubkey,
+    is_writable: bool,
+    lamports: u64,
+    data: Vec<u8>,
+    owner: Pubkey,
+}
+
+impl PreAccount {
+    fn capture(accounts: &[AccountInfo]) -> Vec<PreAccount> {
+        let mut pre: Vec<PreAccount> = Vec::new();
+        for info in accounts {
+            match pre.iter_mut().find(|account| account.key == *info.key) {
+                Some(account) => account.is_writable |= info.is_writable,
+                None => pre.push(PreAccount {
+                    key: *info.key,
+                    is_writable: info.is_writable,
+                    lamports: info.lamports(),
+                    data: info.data.borrow().to_vec(),
+                    owner: *info.owner,
+                }),
+            }
+        }
+        pre
+    }
+
+    /// 链上运行时的账户修改规则：只读账户不可修改；只有所有者程序可以修改数据、扣减 lamports
+    /// 或转移所有权，转移所有权时数据必须清零
+    fn verify(&self, program_id: &Pubkey, info: &AccountInfo) {
+        let key = self.key;
+        let data = info.data.borrow();
+        let data_changed = data[..] != self.data[..];
+        let owner_changed = *info.owner != self.owner;
+        let lamports_changed = info.lamports() != self.lamports;
+        if !self.is_writable {
+            assert!(
+                !data_changed && !owner_changed && !lamports_changed,
+                "{program_id} modified read-only account {key}"
+            );
+            return;
+        }
+        let owned = self.owner == *program_id;
+        assert!(owned || !data_changed, "{program_id} modified data of {key} owned by {}", self.owner);
+        assert!(
+            owned || info.lamports() >= self.lamports,
+            "{program_id} debited lamports of {key} owned by {}",
+            self.owner
+        );
+        assert!(
+            !owner_changed || (owned && data.iter().all(|byte| *byte == 0)),
+            "{program_id} reassigned {key} (owner {}, data zeroed: {})",
+            self.owner,
+            data.iter().all(|byte| *byte == 0)
+        );
+    }
+}
+
+/// 调用本地处理函数（维护调用栈，供 PDA 签名校验和返回数据使用）
+/// 成功返回后按链上规则校验账户修改，并把修改后的状态同步给调用方的调用前快照
+pub fn invoke_program(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let processor = processor_for(program_id).ok_or(ProgramError::IncorrectProgramId)?;
+    // AccountInfo 对生命周期不变，处理函数要求切片与账户同一生命周期；调用期间账户均有效
+    let accounts: &[AccountInfo<'static>] = unsafe { std::mem::transmute(accounts) };
+    PROGRAM_STACK.with(|stack| stack.borrow_mut().push(*program_id));
+    PRE_ACCOUNTS.with(|pre| pre.borrow_mut().push(PreAccount::capture(accounts)));
+    let result = processor(program_id, accounts, data);
+    PROGRAM_STACK.with(|stack| stack.borrow_mut().pop());
+    let pre_accounts = PRE_ACCOUNTS.with(|pre| pre.borrow_mut().pop().unwrap());
+    result?;
+    // SPL 程序的主机构建关闭账户时只清零数据，链上会把数据长度缩为 0
+    for info in accounts {
+        if info.is_writable && info.lamports() == 0 && *info.owner == system_program::ID && !info.data_is_empty() {
+            info.resize(0)?;
+        }
+    }
+    for pre in &pre_accounts {
+        let info = accounts.iter().find(|info| *info.key == pre.key).unwrap();
+        pre.verify(program_id, info);
+    }
+    // 被调用程序的修改已通过校验，调用方继续以修改后的状态为基准
+    PRE_ACCOUNTS.with(|pre| {
+        if let Some(caller) = pre.borrow_mut().last_mut() {
+            for account in caller.iter_mut() {
+                if let Some(info) = accounts.iter().find(|info| *info.key == account.key) {
+                    account.lamports = info.lamports();
+                    account.data = info.data.borrow().to_vec();
+                    account.owner = *info.owner;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+fn current_program() -> Pubkey {
+    PROGRAM_STACK.with(|stack| *stack.borrow().last().expect("no program is executing"))
+}
+
+/// syscall stub：CPI、系统变量、返回数据和日志
+struct TestSyscallStubs;
+
+impl SyscallStubs for TestSyscallStubs {
+    fn sol_log(&self, message: &str) {
+        println!("Program log: {message}");
+    }
+
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        LOG_DATA.with(|log| log.borrow_mut().push(fields.concat()));
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let caller = current_program();
+        let pda_signers = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &caller))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| ProgramError::InvalidSeeds)?;
+
+        let mut callee_accounts = Vec::with_capacity(instruction.accounts.len());
+        for meta in &instruction.accounts {
+            let info = account_infos
+                .iter()
+                .find(|info| *info.key == meta.pubkey)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            if meta.is_signer && !info.is_signer && !pda_signers.contains(&meta.pubkey) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if meta.is_writable && !info.is_writable {
+                return Err(ProgramError::InvalidArgument);
+            }
+            let mut info = info.clone();
+            info.is_signer = meta.is_signer;
+            info.is_writable = meta.is_writable;
+            callee_accounts.push(info);
+        }
+        invoke_program(&instruction.program_id, &callee_accounts, &instruction.data)
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = CLOCK.with(|clock| clock.borrow().clone());
+        unsafe { *(var_addr as *mut Clock) = clock };
+        entrypoint::SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        entrypoint::SUCCESS
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        RETURN_DATA.with(|data| data.borrow().clone())
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        let program_id = current_program();
+        RETURN_DATA.with(|slot| {
+            *slot.borrow_mut() = (!data.is_empty()).then(|| (program_id, data.to_vec()));
+        });
+    }
+}
+
+fn install_stubs() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        program_stubs::set_syscall_stubs(Box::new(TestSyscallStubs));
+    });
+}
+
+/// 按加载器的对齐输入格式序列化账户与指令数据
+fn serialize_input(
+    keys: &[Pubkey],
+    metas: &[(bool, bool)],
+    dup_of: &[Option<usize>],
+    accounts: &HashMap<Pubkey, TestAccount>,
+    data: &[u8],
+    program_id: &Pubkey,
+) -> Vec<u64> {
+    let mut buf: Vec<u8> = Vec::new();
+    buf.extend_from_slice(&(keys.len() as u64).to_le_bytes());
+    for (index, key) in keys.iter().enumerate() {
+        if let Some(original) = dup_of[index] {
+            buf.push(original as u8);
+            buf.extend_from_slice(&[0u8; 7]);
+            continue;
+        }
+        let account = accounts.get(key).cloned().unwrap_or_else(|| TestAccount {
+            owner: system_program::ID,
+            ..TestAccount::default()
+        });
+        let (is_signer, is_writable) = metas[index];
+        buf.push(u8::MAX);
+        buf.push(is_signer as u8);
+        buf.push(is_writable as u8);
+        buf.push(account.executable as u8);
+        buf.extend_from_slice(&[0u8; 4]);
+        buf.extend_from_slice(key.as_ref());
+        buf.extend_from_slice(account.owner.as_ref());
+        buf.extend_from_slice(&account.lamports.to_le_bytes());
+        buf.extend_from_slice(&(account.data.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&account.data);
+        buf.resize(buf.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+        buf.resize(buf.len().next_multiple_of(8), 0);
+        buf.extend_from_slice(&u64::MAX.to_le_bytes());
+    }
+    buf.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    buf.extend_from_slice(data);
+    buf.extend_from_slice(program_id.as_ref());
+
+    let mut aligned = vec![0u64; buf.len().div_ceil(8)];
+    bytemuck::cast_slice_mut::<u64, u8>(&mut aligned)[..buf.len()].copy_from_slice(&buf);
+    aligned
+}
+
+/// 本地测试环境
+pub struct TestEnv {
+    pub accounts: HashMap<Pubkey, TestAccount>,
+    pub payer: Pubkey,
+}
+
+impl TestEnv {
+    pub fn new() -> Self {
+        install_stubs();
+        let mut env = TestEnv {
+            accounts: HashMap::new(),
+            payer: Pubkey::new_unique(),
+        };
+        for program_id in [
+            personal_vault::ID,
+            spl_token::ID,
+            spl_token_2022::ID,
+            spl_associated_token_account::ID,
+            personal_vault::RAYDIUM_AMM_PROGRAM_ID,
+            personal_vault::RAYDIUM_CLMM_PROGRAM_ID,
+            TEST_HOOK_PROGRAM_ID,
+        ] {
+            env.add_program(program_id);
+        }
+        env.accounts.insert(
+            system_program::ID,
+            TestAccount {
+                lamports: 1,
+                owner: NATIVE_LOADER_ID,
+                executable: true,
+                ..TestAccount::default()
+            },
+        );
+        env.fund(env.payer, 1_000 * 1_000_000_000);
+        env.set_clock(1_000, 1_700_000_000);
+        env
+    }
+
+    /// 注册一个可执行的程序账户
+    pub fn add_program(&mut self, program_id: Pubkey) {
+        self.accounts.insert(
+            program_id,
+            TestAccount {
+                lamports: 1,
+                owner: BPF_LOADER_ID,
+                executable: true,
+                ..TestAccount::default()
+            },
+        );
+    }
+
+    pub fn set_clock(&mut self, slot: u64, unix_timestamp: i64) {
+        CLOCK.with(|clock| {
+            *clock.borrow_mut() = Clock {
+                slot,
+                unix_timestamp,
+                ..Clock::default()
+            }
+        });
+    }
+
+    pub fn clock(&self) -> Clock {
+        CLOCK.with(|clock| clock.borrow().clone())
+    }
+
+    /// 给系统账户充值
+    pub fn fund(&mut self, key: Pubkey, lamports: u64) {
+        let account = self.accounts.entry(key).or_insert_with(|| TestAccount {
+            owner: system_program::ID,
+            ..TestAccount::default()
+        });
+        account.lamports += lamports;
+    }
+
+    /// 新建一个有 SOL 的钱包
+    pub fn new_wallet(&mut self) -> Pubkey {
+        let wallet = Pubkey::new_unique();
+        self.fund(wallet, 100 * 1_000_000_000);
+        wallet
+    }
+
+    pub fn account(&self, key: &Pubkey) -> Option<&TestAccount> {
+        self.accounts.get(key)
+    }
+
+    pub fn lamports(&self, key: &Pubkey) -> u64 {
+        self.accounts.get(key).map_or(0, |account| account.lamports)
+    }
+
+    /// 账户存在（有 lamports 或数据）
+    pub fn exists(&self, key: &Pubkey) -> bool {
+        self.accounts
+            .get(key)
+            .is_some_and(|account| account.lamports > 0 || !account.data.is_empty())
+    }
+
+    /// 最近一条顶层指令设置的返回数据
+    pub fn return_data(&self) -> Option<Vec<u8>> {
+        RETURN_DATA.with(|data| data.borrow().as_ref().map(|(_, data)| data.clone()))
+    }
+
+    /// 最近一条顶层指令发出的 E 类型事件（与链上一致，指令失败时同样保留）
+    pub fn events<E: anchor_lang::Event + AnchorDeserialize>(&self) -> Vec<E> {
+        LOG_DATA.with(|log| {
+            log.borrow()
+                .iter()
+                .filter_map(|data| data.strip_prefix(E::DISCRIMINATOR))
+                .map(|mut payload| E::deserialize(&mut payload).unwrap())
+                .collect()
+        })
+    }
+
+    /// 执行一条顶层指令；签名者由指令的 AccountMeta 标记（测试中不校验私钥签名）
+    pub fn process(&mut self, instruction: &Instruction) -> Result<(), ProgramError> {
+        let mut keys: Vec<Pubkey> = Vec::new();
+        let mut flags: Vec<(bool, bool)> = Vec::new();
+        let mut dup_of: Vec<Option<usize>> = Vec::new();
+        for meta in &instruction.accounts {
+            match keys.iter().position(|key| *key == meta.pubkey) {
+                Some(first) => {
+                    let first = dup_of[first].unwrap_or(first);
+                    flags[first].0 |= meta.is_signer;
+                    flags[first].1 |= meta.is_writable;
+                    dup_of.push(Some(first));
+                    flags.push((false, false));
+                }
+                None => {
+                    dup_of.push(None);
+                    flags.push((meta.is_signer, meta.is_writable));
+                }
+            }
+            keys.push(meta.pubkey);
+        }
+        require_program(self, &instruction.program_id)?;
+
+        let mut input = serialize_input(
+            &keys,
+            &flags,
+            &dup_of,
+            &self.accounts,
+            &instruction.data,
+            &instruction.program_id,
+        );
+        let lamports_before: u64 = unique_keys(&keys).iter().map(|key| self.lamports(key)).sum();
+
+        RETURN_DATA.with(|data| *data.borrow_mut() = None);
+        LOG_DATA.with(|log| log.borrow_mut().clear());
+        let (program_id, account_infos, data) =
+            unsafe { entrypoint::deserialize(input.as_mut_ptr() as *mut u8) };
+        invoke_program(program_id, &account_infos, data)?;
+
+        let mut updated = Vec::new();
+        for info in account_infos.iter() {
+            if updated.iter().any(|(key, _): &(Pubkey, TestAccount)| key == info.key) {
+                continue;
+            }
+            let previous = self.accounts.get(info.key);
+            updated.push((
+                *info.key,
+                TestAccount {
+                    lamports: info.lamports(),
+                    data: info.data.borrow().to_vec(),
+                    owner: *info.owner,
+                    executable: previous.is_some_and(|account| account.executable),
+                },
+            ));
+        }
+        let lamports_after: u64 = updated.iter().map(|(_, account)| account.lamports).sum();
+        assert_eq!(lamports_before, lamports_after, "instruction changed total lamports");
+        for (key, account) in updated {
+            self.accounts.insert(key, account);
+        }
+        Ok(())
+    }
+
+    /// 执行一条顶层指令，失败时 panic
+    pub fn execute(&mut self, instruction: &Instruction) {
+        if let Err(err) = self.process(instruction) {
+            panic!("instruction to {} failed: {err:?}", instruction.program_id);
+        }
+    }
+
+    // ---------- SPL Token 辅助 ----------
+
+    /// 创建 mint（decimals 位小数，mint 权限为 authority）
+    pub fn create_mint(&mut self, authority: &Pubkey, decimals: u8, token_program: &Pubkey) -> Pubkey {
+        let mint = Pubkey::new_unique();
+        self.create_mint_at(&mint, authority, decimals, token_program);
+        mint
+    }
+
+    /// 在指定地址创建 mint（如 WSOL 原生 mint）
+    pub fn create_mint_at(&mut self, mint: &Pubkey, authority: &Pubkey, decimals: u8, token_program: &Pubkey) {
+        let space = if *token_program == spl_token::ID {
+            spl_token::state::Mint::LEN
+        } else {
+            spl_token_2022::state::Mint::LEN
+        };
+        self.create_account(mint, space, token_program);
+        self.execute(
+            &spl_token_2022::instruction::initialize_mint2(token_program, mint, authority, None, decimals)
+                .unwrap(),
+        );
+    }
+
+    /// 创建配置了测试转账钩子的 Token-2022 mint，并写入 ExtraAccountMetaList
+    /// （唯一的额外账户是按 mint 推导的可写计数账户）
+    pub fn create_hooked_mint(&mut self, authority: &Pubkey, decimals: u8) -> Pubkey {
+        let mint = Pubkey::new_unique();
+        let space = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&[
+            ExtensionType::TransferHook,
+        ])
+        .unwrap();
+        self.create_account(&mint, space, &spl_token_2022::ID);
+        self.execute(
+            &spl_token_2022::extension::transfer_hook::instruction::initialize(
+                &spl_token_2022::ID,
+                &mint,
+                Some(*authority),
+                Some(TEST_HOOK_PROGRAM_ID),
+            )
+            .unwrap(),
+        );
+        self.execute(
+            &spl_token_2022::instruction::initialize_mint2(&spl_token_2022::ID, &mint, authority, None, decimals)
+                .unwrap(),
+        );
+
+        let extra_metas = [ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal {
+                    bytes: HOOK_COUNTER_SEED.to_vec(),
+                },
+                Seed::AccountKey { index: 1 },
+            ],
+            false,
+            true,
+        )
+        .unwrap()];
+        let mut validation_data = vec![0; ExtraAccountMetaList::size_of(extra_metas.len()).unwrap()];
+        ExtraAccountMetaList::init::<ExecuteInstruction>(&mut validation_data, &extra_metas).unwrap();
+        self.insert_program_account(
+            get_extra_account_metas_address(&mint, &TEST_HOOK_PROGRAM_ID),
+            validation_data,
+            TEST_HOOK_PROGRAM_ID,
+        );
+        self.insert_program_account(hook_counter_pda(&mint), vec![0; 16], TEST_HOOK_PROGRAM_ID);
+        mint
+    }
+
+    /// 带钩子的 mint 转账时需要附加的账户（与 spl-token 客户端解析的顺序一致）：
+    /// [计数账户, ExtraAccountMetaList PDA, 钩子程序]
+    pub fn hook_accounts(&self, mint: &Pubkey) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(hook_counter_pda(mint), false),
+            AccountMeta::new_readonly(get_extra_account_metas_address(mint, &TEST_HOOK_PROGRAM_ID), false),
+            AccountMeta::new_readonly(TEST_HOOK_PROGRAM_ID, false),
+        ]
+    }
+
+    /// 测试钩子对该 mint 的 (调用次数, 累计转账数量)
+    pub fn hook_counter(&self, mint: &Pubkey) -> (u64, u64) {
+        let data = &self.accounts[&hook_counter_pda(mint)].data;
+        (
+            u64::from_le_bytes(data[..8].try_into().unwrap()),
+            u64::from_le_bytes(data[8..16].try_into().unwrap()),
+        )
+    }
+
+    /// 直接写入一个租金豁免的程序账户
+    pub fn insert_program_account(&mut self, address: Pubkey, data: Vec<u8>, owner: Pubkey) {
+        self.accounts.insert(
+            address,
+            TestAccount {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner,
+                executable: false,
+            },
+        );
+    }
+
+    /// 由测试付款账户创建一个归属 owner 程序的空账户
+    pub fn create_account(&mut self, address: &Pubkey, space: usize, owner: &Pubkey) {
+        let lamports = Rent::default().minimum_balance(space);
+        let mut data = 0u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&lamports.to_le_bytes());
+        data.extend_from_slice(&(space as u64).to_le_bytes());
+        data.extend_from_slice(owner.as_ref());
+        self.execute(&Instruction {
+            program_id: system_program::ID,
+            accounts: vec![AccountMeta::new(self.payer, true), AccountMeta::new(*address, true)],
+            data,
+        });
+    }
+
+    /// 创建 ATA
+    pub fn create_ata(&mut self, owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+        self.execute(
+            &spl_associated_token_account::instruction::create_associated_token_account(
+                &self.payer,
+                owner,
+                mint,
+                token_program,
+            ),
+        );
+        ata(owner, mint, token_program)
+    }
+
+    /// 创建非 ATA 的代币账户
+    pub fn create_token_account(&mut self, owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+        let address = Pubkey::new_unique();
+        let space = if *token_program == spl_token::ID {
+            spl_token::state::Account::LEN
+        } else {
+            let mint_data = self.accounts[mint].data.clone();
+            let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data).unwrap();
+            let mint_extensions = spl_token_2022::extension::BaseStateWithExtensions::get_extension_types(&mint_state).unwrap();
+            let account_extensions =
+                spl_token_2022::extension::ExtensionType::get_required_init_account_extensions(&mint_extensions);
+            spl_token_2022::extension::ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(
+                &account_extensions,
+            )
+            .unwrap()
+        };
+        self.create_account(&address, space, token_program);
+        self.execute(
+            &spl_token_2022::instruction::initialize_account3(token_program, &address, mint, owner).unwrap(),
+        );
+        address
+    }
+
+    pub fn mint_to(&mut self, mint: &Pubkey, destination: &Pubkey, authority: &Pubkey, amount: u64) {
+        let token_program = self.accounts[mint].owner;
+        self.execute(
+            &spl_token_2022::instruction::mint_to(&token_program, mint, destination, authority, &[], amount)
+                .unwrap(),
+        );
+    }
+
+    /// 代币账户余额
+    pub fn token_balance(&self, token_account: &Pubkey) -> u64 {
+        let data = &self.accounts[token_account].data;
+        StateWithExtensions::<spl_token_2022::state::Account>::unpack(data)
+            .unwrap()
+            .base
+            .amount
+    }
+
+    /// 直接改写代币账户余额（模拟资产在程序之外流出，例如被永久代理转走）
+    pub fn set_token_balance(&mut self, token_account: &Pubkey, amount: u64) {
+        let data = &mut self.accounts.get_mut(token_account).unwrap().data;
+        let mut state =
+            StateWithExtensionsMut::<spl_token_2022::state::Account>::unpack(data).unwrap();
+        state.base.amount = amount;
+        state.pack_base();
+    }
+
+    // ---------- personal_vault 辅助 ----------
+
+    /// 发送 personal_vault 指令
+    pub fn vault_ix(
+        &mut self,
+        accounts: impl ToAccountMetas,
+        args: impl InstructionData,
+        remaining: Vec<AccountMeta>,
+    ) -> Result<(), ProgramError> {
+        let mut metas = accounts.to_account_metas(None);
+        metas.extend(remaining);
+        self.process(&Instruction {
+            program_id: personal_vault::ID,
+            accounts: metas,
+            data: args.data(),
+        })
+    }
+}
+
+/// 已初始化全局配置的测试场景（管理员、机器人和一个投资者金库）
+pub struct VaultFixture {
+    pub env: TestEnv,
+    pub admin: Pubkey,
+    pub bot: Pubkey,
+    pub investor: Pubkey,
+    pub vault: Pubkey,
+}
+
+/// 全局配置 PDA
+pub fn global_config_pda() -> Pubkey {
+    Pubkey::find_program_address(&[b"global_config"], &personal_vault::ID).0
+}
+
+/// 金库 PDA
+pub fn vault_pda(investor: &Pubkey, vault_index: u16) -> Pubkey {
+    let index_bytes = vault_index.to_le_bytes();
+    Pubkey::find_program_address(
+        &[
+            b"vault",
+            investor.as_ref(),
+            personal_vault::vault_index_seed(&index_bytes),
+        ],
+        &personal_vault::ID,
+    )
+    .0
+}
+
+/// 测试钩子的计数账户 PDA
+pub fn hook_counter_pda(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[HOOK_COUNTER_SEED, mint.as_ref()], &TEST_HOOK_PROGRAM_ID).0
+}
+
+/// 投资者金库登记 PDA
+pub fn vault_registry_pda(investor: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"vault_registry", investor.as_ref()], &personal_vault::ID).0
+}
+
+/// 金库附属 PDA（种子为 `[prefix, vault]`）
+pub fn vault_child_pda(prefix: &[u8], vault: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[prefix, vault.as_ref()], &personal_vault::ID).0
+}
+
+impl VaultFixture {
+    /// 初始化全局配置并为新投资者创建 1 号金库
+    pub fn new() -> Self {
+        let mut env = TestEnv::new();
+        let admin = env.new_wallet();
+        let bot = env.new_wallet();
+        let investor = env.new_wallet();
+        env.vault_ix(
+            personal_vault::accounts::InitializeGlobalConfig {
+                global_config: global_config_pda(),
+                admin,
+                system_program: system_program::ID,
+            },
+            personal_vault::instruction::InitializeGlobalConfig { bot_address: bot },
+            vec![],
+        )
+        .unwrap();
+        let mut fixture = VaultFixture {
+            env,
+            admin,
+            bot,
+            investor,
+            vault: vault_pda(&investor, 1),
+        };
+        fixture.create_vault(1).unwrap();
+        fixture
+    }
+
+    /// 投资者创建指定编号的金库
+    pub fn create_vault(&mut self, vault_index: u16) -> Result<(), ProgramError> {
+        let investor = self.investor;
+        self.env.vault_ix(
+            personal_vault::accounts::CreateBalanceManager {
+                vault: vault_pda(&investor, vault_index),
+                vault_registry: vault_registry_pda(&investor),
+                global_config: global_config_pda(),
+                user: investor,
+                system_program: system_program::ID,
+            },
+            personal_vault::instruction::CreateBalanceManager {
+                vault_index,
+                label: None,
+            },
+            vec![],
+        )
+    }
+
+    /// 新建 mint（投资者为 mint 权限）
+    pub fn create_mint(&mut self, token_program: &Pubkey) -> Pubkey {
+        let investor = self.investor;
+        self.env.create_mint(&investor, 6, token_program)
+    }
+
+    /// 给投资者铸币并存入金库；remaining 为转账钩子的额外账户
+    pub fn deposit(&mut self, mint: &Pubkey, amount: u64, remaining: Vec<AccountMeta>) -> Result<(), ProgramError> {
+        let token_program = self.env.accounts[mint].owner;
+        let investor = self.investor;
+        let user_token_account = ata(&investor, mint, &token_program);
+        if !self.env.exists(&user_token_account) {
+            self.env.create_ata(&investor, mint, &token_program);
+        }
+        self.env.mint_to(mint, &user_token_account, &investor, amount);
+        self.env.vault_ix(
+            personal_vault::accounts::UserDeposit {
+                vault: self.vault,
+                user: investor,
+                user_token_account,
+                vault_token_account: ata(&self.vault, mint, &token_program),
+                mint: *mint,
+                token_program,
+                associated_token_program: spl_associated_token_account::ID,
+                system_program: system_program::ID,
+                global_config: global_config_pda(),
+            },
+            personal_vault::instruction::UserDeposit { amount },
+            remaining,
+        )
+    }
+
+    /// 投资者从金库取回代币；remaining 为转账钩子的额外账户
+    pub fn withdraw(&mut self, mint: &Pubkey, amount: u64, remaining: Vec<AccountMeta>) -> Result<(), ProgramError> {
+        let token_program = self.env.accounts[mint].owner;
+        let (vault, investor) = (self.vault, self.investor);
+        self.env.vault_ix(
+            personal_vault::accounts::UserWithdraw {
+                vault,
+                user: investor,
+                user_token_account: ata(&investor, mint, &token_program),
+                vault_token_account: ata(&vault, mint, &token_program),
+                mint: *mint,
+                token_program,
+                associated_token_program: spl_associated_token_account::ID,
+                system_program: system_program::ID,
+                global_config: global_config_pda(),
+            },
+            personal_vault::instruction::UserWithdraw { amount },
+            remaining,
+        )
+    }
+
+    /// 投资者向金库存入 SOL
+    pub fn deposit_sol(&mut self, amount: u64) -> Result<(), ProgramError> {
+        let (vault, investor) = (self.vault, self.investor);
+        self.env.vault_ix(
+            personal_vault::accounts::UserDepositSol {
+                vault,
+                user: investor,
+                system_program: system_program::ID,
+                global_config: global_config_pda(),
+            },
+            personal_vault::instruction::UserDepositSol { amount },
+            vec![],
+        )
+    }
+
+    /// 出资人代投资者存入代币（先给出资人铸币）
+    pub fn deposit_for(&mut self, funder: &Pubkey, mint: &Pubkey, amount: u64) -> Result<(), ProgramError> {
+        let token_program = self.env.accounts[mint].owner;
+        let (vault, investor) = (self.vault, self.investor);
+        let funder_token_account = ata(funder, mint, &token_program);
+        if !self.env.exists(&funder_token_account) {
+            self.env.create_ata(funder, mint, &token_program);
+        }
+        self.env.mint_to(mint, &funder_token_account, &investor, amount);
+        self.env.vault_ix(
+            personal_vault::accounts::DepositFor {
+                beneficiary: investor,
+                vault,
+                funder: *funder,
+                funder_token_account,
+                vault_token_account: ata(&vault, mint, &token_program),
+                mint: *mint,
+                token_program,
+                associated_token_program: spl_associated_token_account::ID,
+                system_program: system_program::ID,
+                global_config: global_config_pda(),
+                trading_policy: vault_child_pda(b"trading_policy", &vault),
+            },
+            personal_vault::instruction::DepositFor { amount },
+            vec![],
+        )
+    }
+
+    /// 出资人代投资者存入 SOL
+    pub fn deposit_sol_for(&mut self, funder: &Pubkey, amount: u64) -> Result<(), ProgramError> {
+        let (vault, investor) = (self.vault, self.investor);
+        self.env.vault_ix(
+            personal_vault::accounts::DepositSolFor {
+                beneficiary: investor,
+                vault,
+                funder: *funder,
+                system_program: system_program::ID,
+                global_config: global_config_pda(),
+                trading_policy: vault_child_pda(b"trading_policy", &vault),
+            },
+            personal_vault::instruction::DepositSolFor { amount },
+            vec![],
+        )
+    }
+
+    /// 投资者设置只限制代币白名单的交易策略
+    pub fn set_allowed_mints(&mut self, allowed_mints: Vec<Pubkey>) -> Result<(), ProgramError> {
+        let (vault, investor) = (self.vault, self.investor);
+        self.env.vault_ix(
+            personal_vault::accounts::SetTradingPolicy {
+                vault,
+                user: investor,
+                trading_policy: vault_child_pda(b"trading_policy", &vault),
+                system_program: system_program::ID,
+            },
+            personal_vault::instruction::SetTradingPolicy {
+                params: personal_vault::TradingPolicyParams {
+                    allowed_mints,
+                    max_amount_in: 0,
+                    max_amount_in_bps: 0,
+                    max_slippage_bps: 0,
+                    allowed_dex_routes: 0,
+                    min_trade_interval_secs: 0,
+                },
+            },
+            vec![],
+        )
+    }
+
+    /// 投资者把金库中的 SOL 包装为 WSOL（WSOL mint 需已创建）
+    pub fn wrap_sol(&mut self, amount: u64) -> Result<(), ProgramError> {
+        let (vault, investor) = (self.vault, self.investor);
+        self.env.vault_ix(
+            personal_vault::accounts::WrapSol {
+                vault,
+                user: investor,
+                wsol_account: ata(&vault, &personal_vault::WSOL_MINT, &spl_token::ID),
+                wsol_mint: personal_vault::WSOL_MINT,
+                token_program: spl_token::ID,
+                associated_token_program: spl_associated_token_account::ID,
+                system_program: system_program::ID,
+                global_config: global_config_pda(),
+            },
+            personal_vault::instruction::WrapSol { amount },
+            vec![],
+        )
+    }
+
+    /// 投资者把金库中的 WSOL 解包装为 SOL
+    pub fn unwrap_sol(&mut self, amount: u64) -> Result<(), ProgramError> {
+        let (vault, investor) = (self.vault, self.investor);
+        self.env.vault_ix(
+            personal_vault::accounts::UnwrapSol {
+                vault,
+                user: investor,
+                wsol_account: ata(&vault, &personal_vault::WSOL_MINT, &spl_token::ID),
+                wsol_mint: personal_vault::WSOL_MINT,
+                token_program: spl_token::ID,
+                associated_token_program: spl_associated_token_account::ID,
+                system_program: system_program::ID,
+                global_config: global_config_pda(),
+            },
+            personal_vault::instruction::UnwrapSol { amount },
+            vec![],
+        )
+    }
+
+    /// 投资者在分仓之间划转代币
+    pub fn move_between_buckets(&mut self, mint: &Pubkey, from_bucket: u8, to_bucket: u8, amount: u64) -> Result<(), ProgramError> {
+        let (vault, investor) = (self.vault, self.investor);
+        self.env.vault_ix(
+            personal_vault::accounts::MoveBetweenBuckets {
+                vault,
+                user: investor,
+                global_config: global_config_pda(),
+                system_program: system_program::ID,
+            },
+            personal_vault::instruction::MoveBetweenBuckets {
+                token: *mint,
+                from_bucket,
+                to_bucket,
+                amount,
+            },
+            vec![],
+        )
+    }
+
+    /// 管理员设置全局暂停标志
+    pub fn set_pause_flags(&mut self, paused_operations: u8, paused_dex_routes: u8) {
+        let admin = self.admin;
+        self.env
+            .vault_ix(
+                personal_vault::accounts::SetPauseFlags {
+                    global_config: global_config_pda(),
+                    authority: admin,
+                },
+                personal_vault::instruction::SetPauseFlags {
+                    paused_operations,
+                    paused_dex_routes,
+                },
+                vec![],
+            )
+            .unwrap();
+    }
+
+    /// 管理员设置计算成本和盈亏的报价代币
+    pub fn set_quote_mint(&mut self, quote_mint: &Pubkey) {
+        let admin = self.admin;
+        self.env
+            .vault_ix(
+                personal_vault::accounts::SetQuoteMint {
+                    global_config: global_config_pda(),
+                    admin,
+                },
+                personal_vault::instruction::SetQuoteMint { quote_mint: *quote_mint },
+                vec![],
+            )
+            .unwrap();
+    }
+
+    /// 投资者压缩金库；remaining 为待关闭的空金库代币账户
+    pub fn compact_vault(&mut self, remaining: Vec<AccountMeta>) -> Result<(), ProgramError> {
+        let (vault, investor) = (self.vault, self.investor);
+        self.env.vault_ix(
+            personal_vault::accounts::CompactVault {
+                vault,
+                user: investor,
+                token_program: spl_token::ID,
+                token_2022_program: spl_token_2022::ID,
+                global_config: global_config_pda(),
+            },
+            personal_vault::instruction::CompactVault {},
+            remaining,
+        )
+    }
+
+    /// 投资者关闭金库；remaining 为 [金库代币账户, 投资者代币账户, mint] 三个一组
+    pub fn close_vault(&mut self, remaining: Vec<AccountMeta>) -> Result<(), ProgramError> {
+        let (vault, investor) = (self.vault, self.investor);
+        self.env.vault_ix(
+            personal_vault::accounts::CloseVault {
+                vault,
+                user: investor,
+                vault_registry: Some(vault_registry_pda(&investor)),
+                token_program: spl_token::ID,
+                token_2022_program: spl_token_2022::ID,
+                global_config: global_config_pda(),
+                signal_registry: vault_child_pda(b"signal_registry", &vault),
+                trading_policy: vault_child_pda(b"trading_policy", &vault),
+                trade_limits: vault_child_pda(b"trade_limits", &vault),
+                vault_executors: vault_child_pda(b"vault_executors", &vault),
+                trade_history: vault_child_pda(b"trade_history", &vault),
+                vault_snapshots: vault_child_pda(b"vault_snapshots", &vault),
+            },
+            personal_vault::instruction::CloseVault {},
+            remaining,
+        )
+    }
+
+    /// 金库账本中该代币的余额（所有分仓合计，通过 get_balance 的返回数据读取）
+    pub fn ledger_balance(&mut self, mint: &Pubkey) -> u64 {
+        self.env
+            .vault_ix(
+                personal_vault::accounts::GetBalance { vault: self.vault },
+                personal_vault::instruction::GetBalance { token: *mint },
+                vec![],
+            )
+            .unwrap();
+        u64::from_le_bytes(self.env.return_data().unwrap().try_into().unwrap())
+    }
+
+    /// (代币, 分仓) 条目的持仓信息（通过 get_position 的返回数据读取）
+    pub fn position(&mut self, mint: &Pubkey, bucket: u8) -> personal_vault::PositionInfo {
+        self.env
+            .vault_ix(
+                personal_vault::accounts::GetPosition {
+                    vault: self.vault,
+                    global_config: global_config_pda(),
+                },
+                personal_vault::instruction::GetPosition { token: *mint, bucket },
+                vec![],
+            )
+            .unwrap();
+        AnchorDeserialize::deserialize(&mut self.env.return_data().unwrap().as_slice()).unwrap()
+    }
+
+    /// 机器人发送交易信号（remaining 为 DEX 账户列表）
+    #[allow(clippy::too_many_arguments)]
+    pub fn send_trade_signal(
+        &mut self,
+        route: personal_vault::DexRoute,
+        token_in: &Pubkey,
+        token_out: &Pubkey,
+        amount_in: u64,
+        signal_id: u64,
+        remaining: Vec<AccountMeta>,
+    ) -> Result<(), ProgramError> {
+        let dex_program = route.adapter().program_id();
+        let slot = self.env.clock().slot;
+        self.env.vault_ix(
+            personal_vault::accounts::SendTradeSignal {
+                executor: self.bot,
+                user: self.investor,
+                vault: self.vault,
+                global_config: global_config_pda(),
+                dex_program,
+                signal_registry: vault_child_pda(b"signal_registry", &self.vault),
+                vault_executors: vault_child_pda(b"vault_executors", &self.vault),
+                trading_policy: vault_child_pda(b"trading_policy", &self.vault),
+                trade_limits: vault_child_pda(b"trade_limits", &self.vault),
+                trade_history: vault_child_pda(b"trade_history", &self.vault),
+                system_program: system_program::ID,
+            },
+            personal_vault::instruction::SendTradeSignal {
+                token_in: *token_in,
+                token_out: *token_out,
+                amount_in,
+                slippage_bps: 100,
+                route,
+                valid_until_slot: slot + 10,
+                deadline_unix_ts: None,
+                signal_id,
+                bucket: 0,
+            },
+            remaining,
+        )
+    }
+}
+
+impl Default for VaultFixture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for TestEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn require_program(env: &TestEnv, program_id: &Pubkey) -> Result<(), ProgramError> {
+    match env.accounts.get(program_id) {
+        Some(account) if account.executable => Ok(()),
+        _ => Err(ProgramError::IncorrectProgramId),
+    }
+}
+
+fn unique_keys(keys: &[Pubkey]) -> Vec<Pubkey> {
+    let mut unique = Vec::new();
+    for key in keys {
+        if !unique.contains(key) {
+            unique.push(*key);
+        }
+    }
+    unique
+}
+
+/// ATA 地址
+pub fn ata(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    spl_associated_token_account::get_associated_token_address_with_program_id(owner, mint, token_program)
+}
+
+/// 合约错误码对应的 ProgramError
+pub fn vault_error(code: personal_vault::ErrorCode) -> ProgramError {
+    ProgramError::Custom(code.into())
+}
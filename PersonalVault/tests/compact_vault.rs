@@ -0,0 +1,24 @@
+//! 压缩金库：零余额但带有记账历史的条目不被移除
+
+mod common;
+
+use common::VaultFixture;
+
+const DEPOSIT: u64 = 10_000;
+
+#[test]
+fn compact_vault_keeps_zero_balance_entries_with_history() {
+    let mut fixture = VaultFixture::new();
+    let mint = fixture.create_mint(&spl_token::ID);
+    fixture.set_quote_mint(&mint);
+    fixture.deposit(&mint, DEPOSIT, vec![]).unwrap();
+    fixture.withdraw(&mint, DEPOSIT, vec![]).unwrap();
+
+    fixture.compact_vault(vec![]).unwrap();
+
+    let position = fixture.position(&mint, 0);
+    assert_eq!(position.amount, 0);
+    assert_eq!(position.total_deposited, DEPOSIT);
+    assert_eq!(position.total_withdrawn, DEPOSIT);
+    assert_eq!(position.cost_basis, 0);
+}
@@ -0,0 +1,59 @@
+//! 代存款：出资人只能存入金库已持有或投资者在交易策略白名单中列出的代币
+
+mod common;
+
+use common::{vault_error, VaultFixture};
+use personal_vault::{ErrorCode, NATIVE_SOL_MINT};
+
+const DEPOSIT: u64 = 10_000;
+
+#[test]
+fn deposit_for_rejects_mints_the_investor_never_accepted() {
+    let mut fixture = VaultFixture::new();
+    let funder = fixture.env.new_wallet();
+    let dust = fixture.create_mint(&spl_token::ID);
+
+    assert_eq!(
+        fixture.deposit_for(&funder, &dust, 1),
+        Err(vault_error(ErrorCode::DepositForMintNotAccepted))
+    );
+    assert_eq!(
+        fixture.deposit_sol_for(&funder, 1),
+        Err(vault_error(ErrorCode::DepositForMintNotAccepted))
+    );
+    assert_eq!(fixture.ledger_balance(&dust), 0);
+}
+
+#[test]
+fn deposit_for_accepts_held_mints() {
+    let mut fixture = VaultFixture::new();
+    let funder = fixture.env.new_wallet();
+    let mint = fixture.create_mint(&spl_token::ID);
+    fixture.deposit(&mint, DEPOSIT, vec![]).unwrap();
+    fixture.deposit_sol(DEPOSIT).unwrap();
+
+    fixture.deposit_for(&funder, &mint, DEPOSIT).unwrap();
+    fixture.deposit_sol_for(&funder, DEPOSIT).unwrap();
+
+    assert_eq!(fixture.ledger_balance(&mint), 2 * DEPOSIT);
+    assert_eq!(fixture.ledger_balance(&NATIVE_SOL_MINT), 2 * DEPOSIT);
+}
+
+#[test]
+fn deposit_for_accepts_policy_allowed_mints() {
+    let mut fixture = VaultFixture::new();
+    let funder = fixture.env.new_wallet();
+    let allowed = fixture.create_mint(&spl_token::ID);
+    let other = fixture.create_mint(&spl_token::ID);
+    fixture.set_allowed_mints(vec![allowed, NATIVE_SOL_MINT]).unwrap();
+
+    fixture.deposit_for(&funder, &allowed, DEPOSIT).unwrap();
+    fixture.deposit_sol_for(&funder, DEPOSIT).unwrap();
+    assert_eq!(
+        fixture.deposit_for(&funder, &other, DEPOSIT),
+        Err(vault_error(ErrorCode::DepositForMintNotAccepted))
+    );
+
+    assert_eq!(fixture.ledger_balance(&allowed), DEPOSIT);
+    assert_eq!(fixture.ledger_balance(&NATIVE_SOL_MINT), DEPOSIT);
+}
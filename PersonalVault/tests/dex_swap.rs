@@ -0,0 +1,398 @@
+//! 模拟 DEX 交换：校验金库账本与代币账户余额，以及交换账户布局的校验
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AnchorDeserialize;
+use anchor_lang::solana_program::instruction::AccountMeta;
+use anchor_lang::solana_program::program_error::ProgramError;
+use common::mock_programs::{AMM_AUTHORITY_SEED, CLMM_POOL_SEED, MOCK_SWAP_RATE};
+use anchor_lang::system_program;
+use common::{ata, vault_child_pda, vault_error, VaultFixture};
+use personal_vault::{
+    get_default_fee_rate, DexRoute, ErrorCode, TradeAllowance, UnpricedTradePolicy, RAYDIUM_AMM_PROGRAM_ID,
+    RAYDIUM_CLMM_PROGRAM_ID,
+};
+
+const DEPOSIT: u64 = 10_000;
+const AMOUNT_IN: u64 = 1_000;
+const POOL_LIQUIDITY: u64 = 1_000_000;
+
+/// 交换场景：金库持有 mint_in，输出 ATA 已创建，模拟池子两侧都有流动性
+struct SwapScenario {
+    fixture: VaultFixture,
+    mint_in: Pubkey,
+    mint_out: Pubkey,
+    vault_in: Pubkey,
+    vault_out: Pubkey,
+    pool_in: Pubkey,
+    pool_out: Pubkey,
+}
+
+impl SwapScenario {
+    fn new(token_program_in: &Pubkey, token_program_out: &Pubkey, pool_authority: &Pubkey) -> Self {
+        let mut fixture = VaultFixture::new();
+        let mint_in = fixture.create_mint(token_program_in);
+        let mint_out = fixture.create_mint(token_program_out);
+        Self::with_mints(fixture, mint_in, mint_out, pool_authority, vec![])
+    }
+
+    /// 输入代币为报价代币（存款前设置，存款按面值计入成本）
+    fn with_quote_input(pool_authority: &Pubkey) -> Self {
+        let mut fixture = VaultFixture::new();
+        let mint_in = fixture.create_mint(&spl_token::ID);
+        let mint_out = fixture.create_mint(&spl_token::ID);
+        fixture.set_quote_mint(&mint_in);
+        Self::with_mints(fixture, mint_in, mint_out, pool_authority, vec![])
+    }
+
+    /// 输入代币为带测试转账钩子的 Token-2022 mint，输出代币为 SPL Token
+    fn with_hooked_input(pool_authority: &Pubkey) -> Self {
+        let mut fixture = VaultFixture::new();
+        let investor = fixture.investor;
+        let mint_in = fixture.env.create_hooked_mint(&investor, 6);
+        let mint_out = fixture.create_mint(&spl_token::ID);
+        let hook_accounts = fixture.env.hook_accounts(&mint_in);
+        Self::with_mints(fixture, mint_in, mint_out, pool_authority, hook_accounts)
+    }
+
+    fn with_mints(
+        mut fixture: VaultFixture,
+        mint_in: Pubkey,
+        mint_out: Pubkey,
+        pool_authority: &Pubkey,
+        deposit_hook_accounts: Vec<AccountMeta>,
+    ) -> Self {
+        let token_program_in = fixture.env.accounts[&mint_in].owner;
+        let token_program_out = fixture.env.accounts[&mint_out].owner;
+        fixture.deposit(&mint_in, DEPOSIT, deposit_hook_accounts).unwrap();
+        let vault = fixture.vault;
+        let vault_in = ata(&vault, &mint_in, &token_program_in);
+        let vault_out = fixture.env.create_ata(&vault, &mint_out, &token_program_out);
+
+        let pool_in = fixture.env.create_token_account(pool_authority, &mint_in, &token_program_in);
+        let pool_out = fixture.env.create_token_account(pool_authority, &mint_out, &token_program_out);
+        let investor = fixture.investor;
+        fixture.env.mint_to(&mint_out, &pool_out, &investor, POOL_LIQUIDITY);
+
+        SwapScenario {
+            fixture,
+            mint_in,
+            mint_out,
+            vault_in,
+            vault_out,
+            pool_in,
+            pool_out,
+        }
+    }
+
+    /// 校验一笔 AMOUNT_IN 的交换已按实际成交数量入账
+    fn assert_swapped(&mut self) {
+        let amount_out = AMOUNT_IN * MOCK_SWAP_RATE;
+        let fee = amount_out * get_default_fee_rate() / 1_000_000;
+        let env = &self.fixture.env;
+        assert_eq!(env.token_balance(&self.vault_in), DEPOSIT - AMOUNT_IN);
+        assert_eq!(env.token_balance(&self.vault_out), amount_out);
+        assert_eq!(env.token_balance(&self.pool_in), AMOUNT_IN);
+        assert_eq!(env.token_balance(&self.pool_out), POOL_LIQUIDITY - amount_out);
+        let (mint_in, mint_out) = (self.mint_in, self.mint_out);
+        assert_eq!(self.fixture.ledger_balance(&mint_in), DEPOSIT - AMOUNT_IN);
+        assert_eq!(self.fixture.ledger_balance(&mint_out), amount_out - fee);
+    }
+
+    /// 校验交换被拒绝后余额与账本均未变化
+    fn assert_untouched(&mut self) {
+        let env = &self.fixture.env;
+        assert_eq!(env.token_balance(&self.vault_in), DEPOSIT);
+        assert_eq!(env.token_balance(&self.vault_out), 0);
+        let (mint_in, mint_out) = (self.mint_in, self.mint_out);
+        assert_eq!(self.fixture.ledger_balance(&mint_in), DEPOSIT);
+        assert_eq!(self.fixture.ledger_balance(&mint_out), 0);
+    }
+
+    /// Raydium AMM V4 的 18 个账户
+    fn amm_accounts(&self) -> Vec<AccountMeta> {
+        let authority = amm_authority();
+        let mut metas = vec![
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new_readonly(authority, false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new(self.pool_in, false),
+            AccountMeta::new(self.pool_out, false),
+        ];
+        metas.extend((7..15).map(|_| AccountMeta::new(Pubkey::new_unique(), false)));
+        metas.push(AccountMeta::new(self.vault_in, false));
+        metas.push(AccountMeta::new(self.vault_out, false));
+        metas.push(AccountMeta::new_readonly(self.fixture.vault, false));
+        metas
+    }
+
+    /// Raydium CLMM swap_v2 的账户（1 个 tick array）
+    fn clmm_accounts(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new_readonly(self.fixture.vault, false),
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),
+            AccountMeta::new(clmm_pool(), false),
+            AccountMeta::new(self.vault_in, false),
+            AccountMeta::new(self.vault_out, false),
+            AccountMeta::new(self.pool_in, false),
+            AccountMeta::new(self.pool_out, false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(spl_token_2022::ID, false),
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),
+            AccountMeta::new_readonly(self.mint_in, false),
+            AccountMeta::new_readonly(self.mint_out, false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+        ]
+    }
+
+    fn swap(&mut self, route: DexRoute, signal_id: u64, accounts: Vec<AccountMeta>) -> Result<(), ProgramError> {
+        let (mint_in, mint_out) = (self.mint_in, self.mint_out);
+        self.fixture
+            .send_trade_signal(route, &mint_in, &mint_out, AMOUNT_IN, signal_id, accounts)
+    }
+}
+
+/// 投资者设置交易额上限，计价代币不是交换的任何一侧
+fn set_unrelated_volume_cap(fixture: &mut VaultFixture, policy: UnpricedTradePolicy) {
+    let (vault, investor) = (fixture.vault, fixture.investor);
+    fixture
+        .env
+        .vault_ix(
+            personal_vault::accounts::SetTradeLimits {
+                vault,
+                user: investor,
+                trade_limits: vault_child_pda(b"trade_limits", &vault),
+                system_program: system_program::ID,
+            },
+            personal_vault::instruction::SetTradeLimits {
+                quote_mint: Pubkey::new_unique(),
+                max_daily_trades: 0,
+                max_daily_volume: 1_000_000,
+                unpriced_trade_policy: policy,
+            },
+            vec![],
+        )
+        .unwrap();
+}
+
+fn trade_allowance(fixture: &mut VaultFixture) -> TradeAllowance {
+    let vault = fixture.vault;
+    fixture
+        .env
+        .vault_ix(
+            personal_vault::accounts::GetTradeAllowance {
+                vault,
+                trade_limits: vault_child_pda(b"trade_limits", &vault),
+            },
+            personal_vault::instruction::GetTradeAllowance {},
+            vec![],
+        )
+        .unwrap();
+    AnchorDeserialize::deserialize(&mut fixture.env.return_data().unwrap().as_slice()).unwrap()
+}
+
+fn amm_authority() -> Pubkey {
+    Pubkey::find_program_address(&[AMM_AUTHORITY_SEED], &RAYDIUM_AMM_PROGRAM_ID).0
+}
+
+fn clmm_pool() -> Pubkey {
+    Pubkey::find_program_address(&[CLMM_POOL_SEED], &RAYDIUM_CLMM_PROGRAM_ID).0
+}
+
+#[test]
+fn amm_swap_updates_ledger_by_measured_amounts() {
+    let mut scenario = SwapScenario::new(&spl_token::ID, &spl_token::ID, &amm_authority());
+    let accounts = scenario.amm_accounts();
+    scenario.swap(DexRoute::RaydiumAmmV4, 1, accounts).unwrap();
+    scenario.assert_swapped();
+}
+
+#[test]
+fn clmm_swap_updates_ledger_by_measured_amounts() {
+    let mut scenario = SwapScenario::new(&spl_token_2022::ID, &spl_token::ID, &clmm_pool());
+    let accounts = scenario.clmm_accounts();
+    scenario.swap(DexRoute::RaydiumClmm, 1, accounts).unwrap();
+    scenario.assert_swapped();
+}
+
+#[test]
+fn swap_rejects_token_account_that_is_not_the_vault_ata() {
+    let mut scenario = SwapScenario::new(&spl_token::ID, &spl_token::ID, &amm_authority());
+    // 金库拥有、mint 正确但不是 ATA 的输出代币账户
+    let vault = scenario.fixture.vault;
+    let mint_out = scenario.mint_out;
+    let stray = scenario
+        .fixture
+        .env
+        .create_token_account(&vault, &mint_out, &spl_token::ID);
+    let mut accounts = scenario.amm_accounts();
+    accounts[16] = AccountMeta::new(stray, false);
+
+    let err = scenario.swap(DexRoute::RaydiumAmmV4, 1, accounts).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::InvalidSwapTokenAccount));
+    assert_eq!(scenario.fixture.env.token_balance(&stray), 0);
+    scenario.assert_untouched();
+}
+
+#[test]
+fn swap_rejects_accounts_where_the_vault_is_not_the_authority() {
+    let mut scenario = SwapScenario::new(&spl_token_2022::ID, &spl_token::ID, &clmm_pool());
+    let mut accounts = scenario.clmm_accounts();
+    accounts[0] = AccountMeta::new_readonly(scenario.fixture.investor, false);
+
+    let err = scenario.swap(DexRoute::RaydiumClmm, 1, accounts).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::InvalidSwapAuthority));
+    scenario.assert_untouched();
+}
+
+#[test]
+fn swap_rejects_too_few_accounts_for_the_route() {
+    let mut scenario = SwapScenario::new(&spl_token::ID, &spl_token::ID, &amm_authority());
+    let mut accounts = scenario.amm_accounts();
+    accounts.truncate(17);
+
+    let err = scenario.swap(DexRoute::RaydiumAmmV4, 1, accounts).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::InsufficientAccounts));
+    scenario.assert_untouched();
+}
+
+#[test]
+fn clmm_swap_forwards_validated_hook_accounts() {
+    let mut scenario = SwapScenario::with_hooked_input(&clmm_pool());
+    let mint_in = scenario.mint_in;
+    let mut accounts = scenario.clmm_accounts();
+    accounts.extend(scenario.fixture.env.hook_accounts(&mint_in));
+
+    scenario.swap(DexRoute::RaydiumClmm, 1, accounts).unwrap();
+    scenario.assert_swapped();
+    // 存款和交换各触发一次钩子
+    assert_eq!(scenario.fixture.env.hook_counter(&mint_in), (2, DEPOSIT + AMOUNT_IN));
+}
+
+#[test]
+fn clmm_swap_rejects_missing_or_mismatched_hook_accounts() {
+    let mut scenario = SwapScenario::with_hooked_input(&clmm_pool());
+    let mint_in = scenario.mint_in;
+
+    let accounts = scenario.clmm_accounts();
+    let err = scenario.swap(DexRoute::RaydiumClmm, 1, accounts).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::InvalidTransferHookAccounts));
+
+    let mut accounts = scenario.clmm_accounts();
+    let mut hook_accounts = scenario.fixture.env.hook_accounts(&mint_in);
+    hook_accounts[0] = AccountMeta::new(Pubkey::new_unique(), false);
+    accounts.extend(hook_accounts);
+    let err = scenario.swap(DexRoute::RaydiumClmm, 2, accounts).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::InvalidTransferHookAccounts));
+
+    scenario.assert_untouched();
+    assert_eq!(scenario.fixture.env.hook_counter(&mint_in), (1, DEPOSIT));
+}
+
+#[test]
+fn amm_swap_rejects_token_2022_accounts() {
+    let mut scenario = SwapScenario::new(&spl_token_2022::ID, &spl_token::ID, &amm_authority());
+    let accounts = scenario.amm_accounts();
+
+    let err = scenario.swap(DexRoute::RaydiumAmmV4, 1, accounts).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::UnsupportedRouteTokenProgram));
+    scenario.assert_untouched();
+}
+
+#[test]
+fn selling_a_zero_cost_deposit_books_no_realized_pnl() {
+    let mut scenario = SwapScenario::new(&spl_token::ID, &spl_token::ID, &amm_authority());
+    let (mint_in, mint_out) = (scenario.mint_in, scenario.mint_out);
+    scenario.fixture.set_quote_mint(&mint_out);
+    // 非报价代币的存款没有价格，按零成本计入并标记为成本未知
+    let position = scenario.fixture.position(&mint_in, 0);
+    assert_eq!(position.cost_basis, 0);
+    assert!(position.basis_unknown);
+
+    let accounts = scenario.amm_accounts();
+    scenario.swap(DexRoute::RaydiumAmmV4, 1, accounts).unwrap();
+    scenario.assert_swapped();
+
+    let sold = scenario.fixture.position(&mint_in, 0);
+    assert_eq!(sold.realized_pnl, 0);
+    assert!(sold.basis_unknown);
+    let received = scenario.fixture.position(&mint_out, 0);
+    assert_eq!(received.cost_basis, received.amount);
+    assert!(!received.basis_unknown);
+}
+
+#[test]
+fn unknown_basis_follows_the_swapped_position() {
+    let mut scenario = SwapScenario::new(&spl_token::ID, &spl_token::ID, &amm_authority());
+    let (mint_in, mint_out) = (scenario.mint_in, scenario.mint_out);
+    scenario.fixture.set_quote_mint(&Pubkey::new_unique());
+
+    let accounts = scenario.amm_accounts();
+    scenario.swap(DexRoute::RaydiumAmmV4, 1, accounts).unwrap();
+
+    let bought = scenario.fixture.position(&mint_out, 0);
+    assert_eq!(bought.cost_basis, 0);
+    assert!(bought.basis_unknown);
+    assert!(scenario.fixture.position(&mint_in, 0).basis_unknown);
+}
+
+#[test]
+fn selling_a_position_bought_with_the_quote_token_books_realized_pnl() {
+    let mut scenario = SwapScenario::with_quote_input(&amm_authority());
+    let (mint_in, mint_out) = (scenario.mint_in, scenario.mint_out);
+    let deposited = scenario.fixture.position(&mint_in, 0);
+    assert_eq!(deposited.cost_basis, DEPOSIT);
+    assert!(!deposited.basis_unknown);
+
+    let accounts = scenario.amm_accounts();
+    scenario.swap(DexRoute::RaydiumAmmV4, 1, accounts).unwrap();
+    let bought = scenario.fixture.position(&mint_out, 0);
+    assert_eq!(bought.cost_basis, AMOUNT_IN);
+    assert!(!bought.basis_unknown);
+
+    // 反向卖出全部持仓：池子补足报价代币的流动性
+    let (investor, pool_in) = (scenario.fixture.investor, scenario.pool_in);
+    scenario.fixture.env.mint_to(&mint_in, &pool_in, &investor, POOL_LIQUIDITY);
+    let mut accounts = scenario.amm_accounts();
+    accounts.swap(5, 6);
+    accounts.swap(15, 16);
+    scenario
+        .fixture
+        .send_trade_signal(DexRoute::RaydiumAmmV4, &mint_out, &mint_in, bought.amount, 2, accounts)
+        .unwrap();
+
+    let amount_out = bought.amount * MOCK_SWAP_RATE;
+    let received = amount_out - amount_out * get_default_fee_rate() / 1_000_000;
+    let sold = scenario.fixture.position(&mint_out, 0);
+    assert_eq!(sold.amount, 0);
+    assert_eq!(sold.cost_basis, 0);
+    assert_eq!(sold.realized_pnl, (received - AMOUNT_IN) as i64);
+}
+
+#[test]
+fn volume_cap_rejects_unpriced_trades_by_default() {
+    let mut scenario = SwapScenario::new(&spl_token::ID, &spl_token::ID, &amm_authority());
+    set_unrelated_volume_cap(&mut scenario.fixture, UnpricedTradePolicy::Reject);
+
+    let accounts = scenario.amm_accounts();
+    let err = scenario.swap(DexRoute::RaydiumAmmV4, 1, accounts).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::TradeNotPricedInLimitMint));
+    scenario.assert_untouched();
+}
+
+#[test]
+fn volume_cap_counts_unpriced_trades_when_allowed() {
+    let mut scenario = SwapScenario::new(&spl_token::ID, &spl_token::ID, &amm_authority());
+    set_unrelated_volume_cap(&mut scenario.fixture, UnpricedTradePolicy::CountOnly);
+
+    let accounts = scenario.amm_accounts();
+    scenario.swap(DexRoute::RaydiumAmmV4, 1, accounts).unwrap();
+    scenario.assert_swapped();
+    let allowance = trade_allowance(&mut scenario.fixture);
+    assert_eq!(allowance.trades_used, 1);
+    assert_eq!(allowance.volume_used, 0);
+}
@@ -0,0 +1,86 @@
+//! 全局暂停：包装 / 解包装、分仓划转、对账和压缩金库都检查对应的暂停标志
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::AccountMeta;
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::system_program;
+use common::{ata, global_config_pda, vault_error, VaultFixture};
+use personal_vault::{ErrorCode, PAUSE_DEPOSITS, PAUSE_TRADING, PAUSE_WITHDRAWALS, WSOL_MINT};
+
+const DEPOSIT: u64 = 5_000_000_000;
+const WRAP: u64 = 2_000_000_000;
+
+/// 存入 SOL 并包装其中一部分的金库
+fn wrapped_vault() -> VaultFixture {
+    let mut fixture = VaultFixture::new();
+    let payer = fixture.env.payer;
+    fixture.env.create_mint_at(&WSOL_MINT, &payer, 9, &spl_token::ID);
+    fixture.deposit_sol(DEPOSIT).unwrap();
+    fixture.wrap_sol(WRAP).unwrap();
+    fixture
+}
+
+/// 投资者对账，把直接转入代币账户的代币记入账本
+fn reconcile(fixture: &mut VaultFixture, token_account: Pubkey) -> Result<(), ProgramError> {
+    let (vault, investor) = (fixture.vault, fixture.investor);
+    fixture.env.vault_ix(
+        personal_vault::accounts::ReconcileBalances {
+            caller: investor,
+            user: investor,
+            vault,
+            global_config: global_config_pda(),
+            system_program: system_program::ID,
+        },
+        personal_vault::instruction::ReconcileBalances {},
+        vec![AccountMeta::new_readonly(token_account, false)],
+    )
+}
+
+#[test]
+fn trading_pause_blocks_wrapping_and_bucket_moves() {
+    let mut fixture = wrapped_vault();
+    fixture.set_pause_flags(PAUSE_TRADING, 0);
+
+    let paused = vault_error(ErrorCode::TradingPaused);
+    assert_eq!(fixture.wrap_sol(WRAP).unwrap_err(), paused);
+    assert_eq!(fixture.unwrap_sol(WRAP).unwrap_err(), paused);
+    assert_eq!(fixture.move_between_buckets(&WSOL_MINT, 0, 1, WRAP).unwrap_err(), paused);
+    assert_eq!(fixture.ledger_balance(&WSOL_MINT), WRAP);
+
+    fixture.set_pause_flags(0, 0);
+    fixture.move_between_buckets(&WSOL_MINT, 0, 1, WRAP).unwrap();
+    assert_eq!(fixture.position(&WSOL_MINT, 1).amount, WRAP);
+}
+
+#[test]
+fn deposit_pause_blocks_reconciliation() {
+    let mut fixture = VaultFixture::new();
+    let mint = fixture.create_mint(&spl_token::ID);
+    fixture.deposit(&mint, 1_000, vec![]).unwrap();
+    // 直接转入金库代币账户、尚未记入账本的代币
+    let (vault, investor) = (fixture.vault, fixture.investor);
+    let vault_token_account = ata(&vault, &mint, &spl_token::ID);
+    fixture.env.mint_to(&mint, &vault_token_account, &investor, 500);
+    fixture.set_pause_flags(PAUSE_DEPOSITS, 0);
+
+    assert_eq!(reconcile(&mut fixture, vault_token_account).unwrap_err(), vault_error(ErrorCode::DepositsPaused));
+    assert_eq!(fixture.ledger_balance(&mint), 1_000);
+
+    fixture.set_pause_flags(0, 0);
+    reconcile(&mut fixture, vault_token_account).unwrap();
+    assert_eq!(fixture.ledger_balance(&mint), 1_500);
+}
+
+#[test]
+fn withdrawal_pause_blocks_compaction() {
+    let mut fixture = VaultFixture::new();
+    fixture.set_pause_flags(PAUSE_WITHDRAWALS, 0);
+
+    let err = fixture.compact_vault(vec![]).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::WithdrawalsPaused));
+
+    fixture.set_pause_flags(0, 0);
+    fixture.compact_vault(vec![]).unwrap();
+}
@@ -0,0 +1,38 @@
+//! 账本不变量：账本余额超过实际持有量时指令失败，并在失败交易的日志中留下事件
+
+mod common;
+
+use common::{ata, vault_error, VaultFixture};
+use personal_vault::{ErrorCode, LedgerInvariantViolatedEvent, DEFAULT_BUCKET};
+
+const DEPOSIT: u64 = 10_000;
+const MISSING: u64 = 4_000;
+
+#[test]
+fn violation_emits_event_before_failing() {
+    let mut fixture = VaultFixture::new();
+    let mint = fixture.create_mint(&spl_token::ID);
+    fixture.deposit(&mint, DEPOSIT, vec![]).unwrap();
+    assert!(fixture.env.events::<LedgerInvariantViolatedEvent>().is_empty());
+
+    // 金库代币账户被程序之外的操作扣减，账本仍记录 DEPOSIT
+    let vault_token_account = ata(&fixture.vault, &mint, &spl_token::ID);
+    fixture.env.set_token_balance(&vault_token_account, DEPOSIT - MISSING);
+
+    assert_eq!(
+        fixture.deposit(&mint, 1, vec![]),
+        Err(vault_error(ErrorCode::LedgerInvariantViolation))
+    );
+    let events = fixture.env.events::<LedgerInvariantViolatedEvent>();
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event.user, fixture.investor);
+    assert_eq!(event.vault, fixture.vault);
+    assert_eq!(event.mint, mint);
+    assert_eq!(event.bucket, DEFAULT_BUCKET);
+    assert_eq!(event.ledger_amount, DEPOSIT + 1);
+    assert_eq!(event.actual_amount, DEPOSIT - MISSING + 1);
+
+    // 失败指令的账户修改被丢弃
+    assert_eq!(fixture.ledger_balance(&mint), DEPOSIT);
+}
@@ -0,0 +1,115 @@
+//! 净值快照：价格源精度取自 mint 账户，置信区间过宽的价格被拒绝
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::AccountMeta;
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::system_program;
+use common::{global_config_pda, vault_child_pda, vault_error, VaultFixture};
+use personal_vault::{ErrorCode, PYTH_PRICE_UPDATE_DISCRIMINATOR, PYTH_RECEIVER_PROGRAM_ID};
+
+const NOW: i64 = 1_700_000_000;
+/// 存入 2 个精度为 9 的代币
+const DEPOSIT: u64 = 2_000_000_000;
+
+fn oracle_config_pda() -> Pubkey {
+    Pubkey::find_program_address(&[b"oracle_config"], &personal_vault::ID).0
+}
+
+/// 写入一个完整验证的 Pyth PriceUpdateV2 账户，返回其地址
+fn price_update(fixture: &mut VaultFixture, feed_id: [u8; 32], price: i64, conf: u64, exponent: i32) -> Pubkey {
+    let mut data = PYTH_PRICE_UPDATE_DISCRIMINATOR.to_vec();
+    data.extend([0u8; 32]);
+    data.push(1);
+    data.extend(feed_id);
+    data.extend(price.to_le_bytes());
+    data.extend(conf.to_le_bytes());
+    data.extend(exponent.to_le_bytes());
+    data.extend(NOW.to_le_bytes());
+    let address = Pubkey::new_unique();
+    fixture.env.insert_program_account(address, data, PYTH_RECEIVER_PROGRAM_ID);
+    address
+}
+
+fn set_price_feed(
+    fixture: &mut VaultFixture,
+    mint: &Pubkey,
+    mint_account: &Pubkey,
+    feed_id: [u8; 32],
+) -> Result<(), ProgramError> {
+    let admin = fixture.admin;
+    fixture.env.vault_ix(
+        personal_vault::accounts::SetPriceFeed {
+            oracle_config: oracle_config_pda(),
+            global_config: global_config_pda(),
+            mint_account: *mint_account,
+            admin,
+            system_program: system_program::ID,
+        },
+        personal_vault::instruction::SetPriceFeed { mint: *mint, feed_id },
+        vec![],
+    )
+}
+
+fn snapshot(fixture: &mut VaultFixture, prices: &[Pubkey]) -> Result<u64, ProgramError> {
+    let (vault, investor) = (fixture.vault, fixture.investor);
+    fixture.env.vault_ix(
+        personal_vault::accounts::SnapshotVault {
+            caller: investor,
+            vault,
+            global_config: global_config_pda(),
+            oracle_config: oracle_config_pda(),
+            vault_snapshots: vault_child_pda(b"vault_snapshots", &vault),
+            system_program: system_program::ID,
+        },
+        personal_vault::instruction::SnapshotVault {},
+        prices.iter().map(|price| AccountMeta::new_readonly(*price, false)).collect(),
+    )?;
+    Ok(u64::from_le_bytes(fixture.env.return_data().unwrap().try_into().unwrap()))
+}
+
+/// 报价代币（精度 6）和金库持有的代币（精度 9）都登记了价格源
+fn priced_vault() -> (VaultFixture, Pubkey, Pubkey) {
+    let mut fixture = VaultFixture::new();
+    fixture.env.set_clock(1, NOW);
+    let investor = fixture.investor;
+    let quote = fixture.env.create_mint(&investor, 6, &spl_token::ID);
+    let token = fixture.env.create_mint(&investor, 9, &spl_token::ID);
+    fixture.set_quote_mint(&quote);
+    fixture.deposit(&token, DEPOSIT, vec![]).unwrap();
+    set_price_feed(&mut fixture, &quote, &quote, [1; 32]).unwrap();
+    set_price_feed(&mut fixture, &token, &token, [2; 32]).unwrap();
+    (fixture, quote, token)
+}
+
+#[test]
+fn snapshot_values_holdings_with_the_mint_decimals() {
+    let (mut fixture, _, _) = priced_vault();
+    // 报价代币 1.00 美元，持有代币 3.00 美元：2 个代币价值 6 个报价代币
+    let quote_price = price_update(&mut fixture, [1; 32], 100, 1, -2);
+    let token_price = price_update(&mut fixture, [2; 32], 300, 1, -2);
+
+    let nav = snapshot(&mut fixture, &[quote_price, token_price]).unwrap();
+    assert_eq!(nav, 6_000_000);
+}
+
+#[test]
+fn set_price_feed_rejects_a_mint_account_for_another_token() {
+    let (mut fixture, quote, token) = priced_vault();
+
+    let err = set_price_feed(&mut fixture, &token, &quote, [3; 32]).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::InvalidTokenMintAddress));
+}
+
+#[test]
+fn snapshot_rejects_prices_with_a_wide_confidence_interval() {
+    let (mut fixture, _, _) = priced_vault();
+    let quote_price = price_update(&mut fixture, [1; 32], 100, 1, -2);
+    // 置信区间为价格的 3%，超过 MAX_PRICE_CONF_BPS
+    let token_price = price_update(&mut fixture, [2; 32], 300, 9, -2);
+
+    let err = snapshot(&mut fixture, &[quote_price, token_price]).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::PriceConfidenceTooWide));
+    assert!(!fixture.env.exists(&vault_child_pda(b"vault_snapshots", &fixture.vault)));
+}
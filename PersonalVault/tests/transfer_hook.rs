@@ -0,0 +1,107 @@
+//! Token-2022 转账钩子：存取款和关闭金库按 ExtraAccountMetaList 校验并转发钩子账户
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::AccountMeta;
+use common::{ata, vault_error, VaultFixture};
+use personal_vault::ErrorCode;
+
+const DEPOSIT: u64 = 10_000;
+const WITHDRAW: u64 = 4_000;
+
+fn hooked_fixture() -> (VaultFixture, Pubkey) {
+    let mut fixture = VaultFixture::new();
+    let investor = fixture.investor;
+    let mint = fixture.env.create_hooked_mint(&investor, 6);
+    (fixture, mint)
+}
+
+#[test]
+fn deposit_and_withdraw_run_the_hook() {
+    let (mut fixture, mint) = hooked_fixture();
+    let hook_accounts = fixture.env.hook_accounts(&mint);
+
+    fixture.deposit(&mint, DEPOSIT, hook_accounts.clone()).unwrap();
+    assert_eq!(fixture.env.hook_counter(&mint), (1, DEPOSIT));
+    assert_eq!(fixture.ledger_balance(&mint), DEPOSIT);
+
+    fixture.withdraw(&mint, WITHDRAW, hook_accounts).unwrap();
+    assert_eq!(fixture.env.hook_counter(&mint), (2, DEPOSIT + WITHDRAW));
+    assert_eq!(fixture.ledger_balance(&mint), DEPOSIT - WITHDRAW);
+    let investor = fixture.investor;
+    assert_eq!(
+        fixture.env.token_balance(&ata(&investor, &mint, &spl_token_2022::ID)),
+        WITHDRAW
+    );
+}
+
+#[test]
+fn deposit_rejects_missing_hook_accounts() {
+    let (mut fixture, mint) = hooked_fixture();
+
+    let err = fixture.deposit(&mint, DEPOSIT, vec![]).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::InvalidTransferHookAccounts));
+    assert_eq!(fixture.env.hook_counter(&mint), (0, 0));
+    assert_eq!(fixture.ledger_balance(&mint), 0);
+}
+
+#[test]
+fn deposit_rejects_hook_accounts_that_do_not_match_the_meta_list() {
+    let (mut fixture, mint) = hooked_fixture();
+
+    // 额外账户不是列表中按 mint 推导的计数账户
+    let mut wrong_extra = fixture.env.hook_accounts(&mint);
+    wrong_extra[0] = AccountMeta::new(Pubkey::new_unique(), false);
+    let err = fixture.deposit(&mint, DEPOSIT, wrong_extra).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::InvalidTransferHookAccounts));
+
+    // 列表要求计数账户可写
+    let mut readonly_extra = fixture.env.hook_accounts(&mint);
+    readonly_extra[0].is_writable = false;
+    let err = fixture.deposit(&mint, DEPOSIT, readonly_extra).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::InvalidTransferHookAccounts));
+
+    assert_eq!(fixture.env.hook_counter(&mint), (0, 0));
+}
+
+#[test]
+fn withdraw_rejects_missing_hook_accounts() {
+    let (mut fixture, mint) = hooked_fixture();
+    let hook_accounts = fixture.env.hook_accounts(&mint);
+    fixture.deposit(&mint, DEPOSIT, hook_accounts).unwrap();
+
+    let err = fixture.withdraw(&mint, WITHDRAW, vec![]).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::InvalidTransferHookAccounts));
+    assert_eq!(fixture.env.hook_counter(&mint), (1, DEPOSIT));
+    assert_eq!(fixture.ledger_balance(&mint), DEPOSIT);
+}
+
+#[test]
+fn close_vault_sweeps_hooked_mints_with_their_hook_accounts() {
+    let (mut fixture, mint) = hooked_fixture();
+    let hook_accounts = fixture.env.hook_accounts(&mint);
+    fixture.deposit(&mint, DEPOSIT, hook_accounts.clone()).unwrap();
+
+    let (vault, investor) = (fixture.vault, fixture.investor);
+    let group = vec![
+        AccountMeta::new(ata(&vault, &mint, &spl_token_2022::ID), false),
+        AccountMeta::new(ata(&investor, &mint, &spl_token_2022::ID), false),
+        AccountMeta::new_readonly(mint, false),
+    ];
+
+    // 带钩子的 mint 必须在三个一组的账户后附带钩子账户
+    let err = fixture.close_vault(group.clone()).unwrap_err();
+    assert_eq!(err, vault_error(ErrorCode::InvalidTransferHookAccounts));
+    assert!(fixture.env.exists(&vault));
+
+    let mut remaining = group;
+    remaining.extend(hook_accounts);
+    fixture.close_vault(remaining).unwrap();
+    assert_eq!(fixture.env.hook_counter(&mint), (2, 2 * DEPOSIT));
+    assert_eq!(
+        fixture.env.token_balance(&ata(&investor, &mint, &spl_token_2022::ID)),
+        DEPOSIT
+    );
+    assert!(!fixture.env.exists(&vault));
+}
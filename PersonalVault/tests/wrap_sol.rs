@@ -0,0 +1,82 @@
+//! SOL 包装 / 解包装：账本与金库 lamports、WSOL 账户余额保持一致（含部分解包装）
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::Discriminator;
+use common::{ata, vault_error, VaultFixture};
+use personal_vault::{ErrorCode, PersonalVault, NATIVE_SOL_MINT, WSOL_MINT};
+
+const DEPOSIT: u64 = 5_000_000_000;
+const WRAP: u64 = 2_000_000_000;
+
+/// 存入 DEPOSIT lamports 并包装其中 WRAP 的金库
+fn wrapped_vault() -> (VaultFixture, Pubkey) {
+    let mut fixture = VaultFixture::new();
+    let payer = fixture.env.payer;
+    fixture.env.create_mint_at(&WSOL_MINT, &payer, 9, &spl_token::ID);
+    fixture.deposit_sol(DEPOSIT).unwrap();
+
+    let vault = fixture.vault;
+    let wsol_account = ata(&vault, &WSOL_MINT, &spl_token::ID);
+    fixture.wrap_sol(WRAP).unwrap();
+    assert_eq!(fixture.env.token_balance(&wsol_account), WRAP);
+    assert_eq!(fixture.ledger_balance(&WSOL_MINT), WRAP);
+    assert_eq!(fixture.ledger_balance(&NATIVE_SOL_MINT), DEPOSIT - WRAP);
+    (fixture, wsol_account)
+}
+
+#[test]
+fn full_unwrap_closes_the_wsol_account() {
+    let (mut fixture, wsol_account) = wrapped_vault();
+    let vault_lamports = fixture.env.lamports(&fixture.vault);
+
+    fixture.unwrap_sol(WRAP).unwrap();
+
+    assert!(!fixture.env.exists(&wsol_account));
+    assert_eq!(fixture.env.lamports(&fixture.vault), vault_lamports + WRAP);
+    assert_eq!(fixture.ledger_balance(&WSOL_MINT), 0);
+    assert_eq!(fixture.ledger_balance(&NATIVE_SOL_MINT), DEPOSIT);
+}
+
+#[test]
+fn partial_unwrap_rewraps_the_remainder() {
+    let (mut fixture, wsol_account) = wrapped_vault();
+    let vault_lamports = fixture.env.lamports(&fixture.vault);
+    let investor_lamports = fixture.env.lamports(&fixture.investor);
+    let unwrap = WRAP / 4;
+
+    fixture.unwrap_sol(unwrap).unwrap();
+
+    assert_eq!(fixture.env.token_balance(&wsol_account), WRAP - unwrap);
+    assert_eq!(fixture.env.lamports(&fixture.vault), vault_lamports + unwrap);
+    // 旧 ATA 的租金退还给用户，新 ATA 的租金由用户支付
+    assert_eq!(fixture.env.lamports(&fixture.investor), investor_lamports);
+    assert_eq!(fixture.ledger_balance(&WSOL_MINT), WRAP - unwrap);
+    assert_eq!(fixture.ledger_balance(&NATIVE_SOL_MINT), DEPOSIT - WRAP + unwrap);
+
+    // 剩余部分仍可继续解包装
+    fixture.unwrap_sol(WRAP - unwrap).unwrap();
+    assert!(!fixture.env.exists(&wsol_account));
+    assert_eq!(fixture.ledger_balance(&WSOL_MINT), 0);
+    assert_eq!(fixture.ledger_balance(&NATIVE_SOL_MINT), DEPOSIT);
+}
+
+#[test]
+fn wrap_and_unwrap_respect_the_reentrancy_lock() {
+    let (mut fixture, wsol_account) = wrapped_vault();
+    let vault = fixture.vault;
+    let lock_offset =
+        PersonalVault::DISCRIMINATOR.len() + std::mem::offset_of!(PersonalVault, is_locked);
+    fixture.env.accounts.get_mut(&vault).unwrap().data[lock_offset] = 1;
+
+    assert_eq!(fixture.wrap_sol(WRAP), Err(vault_error(ErrorCode::ReentrantCall)));
+    assert_eq!(fixture.unwrap_sol(WRAP), Err(vault_error(ErrorCode::ReentrantCall)));
+
+    // 成功的包装 / 解包装结束后释放锁
+    fixture.env.accounts.get_mut(&vault).unwrap().data[lock_offset] = 0;
+    fixture.unwrap_sol(WRAP / 2).unwrap();
+    fixture.wrap_sol(WRAP / 2).unwrap();
+    assert_eq!(fixture.env.accounts[&vault].data[lock_offset], 0);
+    assert_eq!(fixture.env.token_balance(&wsol_account), WRAP);
+}